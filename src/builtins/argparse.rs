@@ -0,0 +1,230 @@
+//! The `argparse` builtin, which parses the arguments of a script or function against the options
+//! that it declares, so that each script need not walk through its arguments by hand.
+
+use super::print_usage;
+use shell::Shell;
+use shell::status::*;
+use types::Array;
+
+/// An option which was declared as `[s/]name[=[default]]`.
+#[derive(Debug, PartialEq)]
+struct Spec<'a> {
+    short: Option<char>,
+    long: Option<&'a str>,
+    /// Whether the option takes a value, which is its default value if it has one.
+    value: Option<&'a str>,
+}
+
+impl<'a> Spec<'a> {
+    /// The variable that the option is stored within, which is named after its long name, if it
+    /// has one, with any `-` replaced by `_`.
+    fn variable(&self) -> String {
+        let name = match (self.long, self.short) {
+            (Some(long), _) => long.replace('-', "_"),
+            (None, Some(short)) => short.to_string(),
+            (None, None) => String::new(),
+        };
+        ["flag_", &name].concat()
+    }
+
+    /// How the option is written within the usage, such as `-o|--output VALUE`.
+    fn usage(&self) -> String {
+        let mut usage = match (self.short, self.long) {
+            (Some(short), Some(long)) => format!("-{}|--{}", short, long),
+            (Some(short), None) => format!("-{}", short),
+            (None, Some(long)) => format!("--{}", long),
+            (None, None) => String::new(),
+        };
+        if self.value.is_some() {
+            usage.push_str(" VALUE");
+        }
+        usage
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('-')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+fn parse_spec(spec: &str) -> Option<Spec> {
+    let (names, value) = match spec.find('=') {
+        Some(pos) => (&spec[..pos], Some(&spec[pos + 1..])),
+        None => (spec, None),
+    };
+    let (short, long) = match names.find('/') {
+        Some(pos) => (&names[..pos], Some(&names[pos + 1..])),
+        None if names.chars().count() == 1 => (names, None),
+        None => ("", Some(names)),
+    };
+
+    let short = match short.chars().count() {
+        0 => None,
+        1 if is_valid_name(short) => short.chars().next(),
+        _ => return None,
+    };
+    match long {
+        Some(long) if !is_valid_name(long) || long.len() == 1 => return None,
+        _ => (),
+    }
+    Some(Spec { short: short, long: long, value: value })
+}
+
+/// Parses the arguments against the options, returning the value of each option that was given,
+/// in the order that they were given, along with the arguments which were not options. Arguments
+/// which follow `--` are never options.
+fn parse_args<'a, 'b, 's>(specs: &'s [Spec<'b>], args: &[&'a str])
+    -> Result<(Vec<(&'s Spec<'b>, &'a str)>, Vec<&'a str>), String>
+{
+    let mut values = Vec::new();
+    let mut positionals = Vec::new();
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        if arg == "--" {
+            positionals.extend(args);
+            break;
+        } else if arg.starts_with("--") {
+            let (name, value) = match arg.find('=') {
+                Some(pos) => (&arg[2..pos], Some(&arg[pos + 1..])),
+                None => (&arg[2..], None),
+            };
+            let spec = match specs.iter().find(|spec| spec.long == Some(name)) {
+                Some(spec) => spec,
+                None => return Err(format!("--{}: unknown option", name)),
+            };
+            match (spec.value.is_some(), value) {
+                (true, Some(value)) => values.push((spec, value)),
+                (true, None) => match args.next() {
+                    Some(&value) => values.push((spec, value)),
+                    None => return Err(format!("--{}: option requires a value", name)),
+                },
+                (false, Some(_)) => return Err(format!("--{}: option does not take a value", name)),
+                (false, None) => values.push((spec, "1")),
+            }
+        } else if arg.starts_with('-') && arg.len() > 1 {
+            // Short options may be grouped, where the remainder of the group is the value of the
+            // first option that takes one.
+            for (pos, short) in arg.char_indices().skip(1) {
+                let spec = match specs.iter().find(|spec| spec.short == Some(short)) {
+                    Some(spec) => spec,
+                    None => return Err(format!("-{}: unknown option", short)),
+                };
+                if spec.value.is_none() {
+                    values.push((spec, "1"));
+                    continue;
+                }
+                let rest = &arg[pos + short.len_utf8()..];
+                if !rest.is_empty() {
+                    values.push((spec, rest));
+                } else {
+                    match args.next() {
+                        Some(&value) => values.push((spec, value)),
+                        None => return Err(format!("-{}: option requires a value", short)),
+                    }
+                }
+                break;
+            }
+        } else {
+            positionals.push(arg);
+        }
+    }
+    Ok((values, positionals))
+}
+
+/// Parses the arguments which follow `--` against the options which precede it. The value of each
+/// option is stored within `$flag_NAME`, where flags are set to `1` only when they were given, and
+/// the remaining arguments replace `@args`.
+pub fn argparse(args: &[&str], shell: &mut Shell) -> i32 {
+    let (name, args) = match args.split_first() {
+        Some((&"-n", rest)) if !rest.is_empty() => (Some(rest[0]), &rest[1..]),
+        _ => (None, args),
+    };
+    let (specs, args) = match args.iter().position(|&arg| arg == "--") {
+        Some(pos) => (&args[..pos], &args[pos + 1..]),
+        None => {
+            print_usage(shell, "argparse");
+            return BAD_ARG;
+        }
+    };
+
+    let mut parsed = Vec::with_capacity(specs.len());
+    for &spec in specs {
+        match parse_spec(spec) {
+            Some(spec) => parsed.push(spec),
+            None => {
+                eprintln!("ion: argparse: {}: invalid option specification", spec);
+                return BAD_ARG;
+            }
+        }
+    }
+
+    let command = match name {
+        Some(name) => name.to_owned(),
+        None => shell.variables.get_var("FUNCNAME")
+            .or_else(|| shell.variables.get_array("args").and_then(|args| args.first().cloned()))
+            .unwrap_or_else(|| "argparse".into()),
+    };
+
+    let (values, positionals) = match parse_args(&parsed, args) {
+        Ok(parsed) => parsed,
+        Err(why) => {
+            let usage = parsed.iter().fold(command.clone(), |acc, spec| {
+                acc + " [" + &spec.usage() + "]"
+            });
+            eprintln!("ion: {}: {}\nusage: {} [ARG...]", command, why, usage);
+            return FAILURE;
+        }
+    };
+
+    // Options which were not given are reset to their defaults, while flags are unset.
+    for spec in &parsed {
+        match spec.value {
+            Some(default) => {
+                shell.variables.set_var(&spec.variable(), default);
+            },
+            None => {
+                shell.variables.unset_var(&spec.variable());
+            }
+        }
+    }
+    for (spec, value) in values {
+        shell.variables.set_var(&spec.variable(), value);
+    }
+
+    let array: Array = positionals.into_iter().map(Into::into).collect();
+    shell.variables.set_array("args", array);
+    SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn specs() {
+        assert_eq!(parse_spec("h/help"), Some(Spec { short: Some('h'), long: Some("help"), value: None }));
+        assert_eq!(parse_spec("v"), Some(Spec { short: Some('v'), long: None, value: None }));
+        assert_eq!(parse_spec("dry-run"), Some(Spec { short: None, long: Some("dry-run"), value: None }));
+        assert_eq!(parse_spec("o/output="), Some(Spec { short: Some('o'), long: Some("output"), value: Some("") }));
+        assert_eq!(parse_spec("n=3"), Some(Spec { short: Some('n'), long: None, value: Some("3") }));
+        assert_eq!(parse_spec("ab/output"), None);
+        assert_eq!(parse_spec("-x"), None);
+    }
+
+    #[test]
+    fn arguments() {
+        let specs = [parse_spec("v/verbose").unwrap(), parse_spec("o/output=").unwrap()];
+        let (values, positionals) = parse_args(&specs, &["-vofile", "a", "--output=x", "--", "-v"]).unwrap();
+        let values: Vec<_> = values.iter().map(|&(spec, value)| (spec.variable(), value)).collect();
+        assert_eq!(values, vec![
+            ("flag_verbose".to_owned(), "1"),
+            ("flag_output".to_owned(), "file"),
+            ("flag_output".to_owned(), "x"),
+        ]);
+        assert_eq!(positionals, vec!["a", "-v"]);
+
+        assert!(parse_args(&specs, &["-x"]).is_err());
+        assert!(parse_args(&specs, &["--output"]).is_err());
+        assert!(parse_args(&specs, &["--verbose=1"]).is_err());
+    }
+}
@@ -1,20 +1,18 @@
-extern crate calc;
-
 use std::io::{self, Write};
-use calc::{eval, CalcError};
+use parser::arithmetic::eval;
 
 pub fn calc(args: &[&str]) -> Result<(), String> {
     let stdout = io::stdout();
     let mut stdout = stdout.lock();
     if !args.is_empty() {
         let result = eval(&args.join(""))?;
-        writeln!(stdout, "{}", result).map_err(CalcError::IO)?;
+        writeln!(stdout, "{}", result).map_err(|why| why.to_string())?;
     } else {
         let prompt = b"[]> ";
         loop {
-            let _ = stdout.write(prompt).map_err(CalcError::IO)?;
+            let _ = stdout.write(prompt).map_err(|why| why.to_string())?;
             let mut input = String::new();
-            io::stdin().read_line(&mut input).map_err(CalcError::IO)?;
+            io::stdin().read_line(&mut input).map_err(|why| why.to_string())?;
             if input.is_empty() {
                 break;
             } else {
@@ -22,7 +20,7 @@ pub fn calc(args: &[&str]) -> Result<(), String> {
                     "" => (),
                     "exit" => break,
                     s => {
-                        writeln!(stdout, "{}", eval(s)?).map_err(CalcError::IO)?;
+                        writeln!(stdout, "{}", eval(s)?).map_err(|why| why.to_string())?;
                     },
                 }
             }
@@ -0,0 +1,91 @@
+//! The `complete` builtin, which registers how the arguments of a command are completed when tab
+//! is pressed, either by a function which prints the completions, or by a list of words.
+
+use super::print_usage;
+use shell::Shell;
+use shell::completer::Completion;
+use shell::status::*;
+use std::io::{self, Write};
+
+fn print_completion<W: Write>(stdout: &mut W, command: &str, completion: &Completion) {
+    let _ = match *completion {
+        Completion::Function(ref function) => writeln!(stdout, "complete -c {} -f {}", command, function),
+        Completion::Words(ref words) => writeln!(stdout, "complete -c {} -w {}", command, words.join(" ")),
+    };
+}
+
+/// Registers the completions of a command with `-c command`, which are either printed by the
+/// function given with `-f`, or are the words which follow `-w`. The completions of a command are
+/// removed with `-r`, and are listed when neither a function nor words are given.
+pub fn complete(args: &[&str], shell: &mut Shell) -> i32 {
+    let mut command = None;
+    let mut completion = None;
+    let mut remove = false;
+    let mut index = 0;
+    while index < args.len() {
+        match args[index] {
+            "-c" if index + 1 < args.len() => {
+                command = Some(args[index + 1]);
+                index += 1;
+            }
+            "-f" if index + 1 < args.len() => {
+                completion = Some(Completion::Function(args[index + 1].into()));
+                index += 1;
+            }
+            "-w" => {
+                let words = args[index + 1..].iter().map(|&word| word.to_owned()).collect();
+                completion = Some(Completion::Words(words));
+                break;
+            }
+            "-r" => remove = true,
+            _ => {
+                print_usage(shell, "complete");
+                return BAD_ARG;
+            }
+        }
+        index += 1;
+    }
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let command = match command {
+        Some(command) => command,
+        None if !remove && completion.is_none() => {
+            let mut commands: Vec<_> = shell.completions.iter().collect();
+            commands.sort_by(|a, b| a.0.cmp(b.0));
+            for (command, completion) in commands {
+                print_completion(&mut stdout, command, completion);
+            }
+            return SUCCESS;
+        }
+        None => {
+            print_usage(shell, "complete");
+            return BAD_ARG;
+        }
+    };
+
+    if remove {
+        if shell.completions.remove(command).is_none() {
+            eprintln!("ion: complete: {}: no completions are registered", command);
+            return FAILURE;
+        }
+        return SUCCESS;
+    }
+
+    match completion {
+        Some(completion) => {
+            shell.completions.insert(command.into(), completion);
+            SUCCESS
+        }
+        None => match shell.completions.get(command) {
+            Some(completion) => {
+                print_completion(&mut stdout, command, completion);
+                SUCCESS
+            }
+            None => {
+                eprintln!("ion: complete: {}: no completions are registered", command);
+                FAILURE
+            }
+        },
+    }
+}
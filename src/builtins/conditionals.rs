@@ -25,3 +25,12 @@ string_function!(starts_with);
 string_function!(ends_with);
 string_function!(contains);
 
+
+/// Succeeds if the first argument is equal to any of the arguments that follow it.
+pub fn in_(args: &[&str], _: &mut Shell) -> i32 {
+    if args.len() < 2 {
+        eprintln!("ion: in: a value must be supplied");
+        return BAD_ARG
+    }
+    if args[2..].contains(&args[1]) { SUCCESS } else { FAILURE }
+}
@@ -0,0 +1,50 @@
+//! The `exec` builtin, which replaces the shell with the given command. Without a command, its
+//! redirections are instead applied to the shell itself, which is handled by the pipeline
+//! execution, as the redirections of other builtins are restored once they have completed.
+
+use shell::Shell;
+use shell::signals;
+use shell::status::*;
+use std::io::{self, Write};
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+/// Replaces the shell with the given command, which only returns if the command could not be
+/// executed. The `-c` flag executes the command with an empty environment.
+pub fn exec(args: &[&str], shell: &mut Shell) -> i32 {
+    let (clear_env, args) = match args.first() {
+        Some(&"-c") => (true, &args[1..]),
+        Some(&"--") => (false, &args[1..]),
+        _ => (false, args),
+    };
+
+    let (name, args) = match args.split_first() {
+        Some((&name, args)) => (name, args),
+        None => return SUCCESS,
+    };
+
+    let mut command = Command::new(name);
+    command.args(args);
+    if clear_env {
+        command.env_clear();
+    }
+
+    // The shell will not get another chance to write its history, or its buffered output.
+    if let Some(context) = shell.context.as_mut() {
+        context.history.commit_history();
+    }
+    let _ = io::stdout().flush();
+
+    // The signals which the shell blocks would otherwise remain blocked within the command.
+    signals::unblock();
+    let why = command.exec();
+    signals::block();
+
+    if why.kind() == io::ErrorKind::NotFound {
+        eprintln!("ion: exec: {}: command not found", name);
+        NO_SUCH_COMMAND
+    } else {
+        eprintln!("ion: exec: {}: {}", name, why);
+        COULD_NOT_EXEC
+    }
+}
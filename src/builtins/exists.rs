@@ -0,0 +1,41 @@
+//! The `exists` builtin, which checks for files, variables, functions, and builtins, so that
+//! conditions on the state of the shell need not be written in terms of `test`.
+
+use super::print_usage;
+use shell::Shell;
+use shell::status::*;
+use std::path::Path;
+
+/// Succeeds if everything that was given exists: either as the kind of item given by the flag,
+/// or as non-empty strings when no flag was given.
+pub fn exists(args: &[&str], shell: &mut Shell) -> i32 {
+    let (flag, names) = match args.split_first() {
+        Some((&flag, names)) if flag.starts_with('-') && flag.len() > 1 => (flag, names),
+        Some(_) => ("", args),
+        None => return FAILURE,
+    };
+    match flag {
+        "" | "-a" | "-b" | "-d" | "-f" | "-fn" | "-v" => (),
+        _ => {
+            eprintln!("ion: exists: {}: invalid option", flag);
+            print_usage(shell, "exists");
+            return BAD_ARG;
+        }
+    }
+    if !flag.is_empty() && names.is_empty() {
+        print_usage(shell, "exists");
+        return BAD_ARG;
+    }
+
+    let found = |name: &str| match flag {
+        "" => !name.is_empty(),
+        "-a" => shell.variables.get_array(name).is_some(),
+        "-b" => shell.builtins.contains_key(name),
+        "-d" => Path::new(name).is_dir(),
+        "-f" => Path::new(name).is_file(),
+        "-fn" => shell.resolve_function(name).is_some(),
+        "-v" => shell.variables.get_var(name).is_some(),
+        _ => false,
+    };
+    if names.iter().all(|&name| found(name)) { SUCCESS } else { FAILURE }
+}
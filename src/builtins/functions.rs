@@ -1,6 +1,7 @@
-use shell::flow_control::Function;
+use shell::flow_control::{EXPORTED_FUNCTION_PREFIX, Function};
 use fnv::FnvHashMap;
 use shell::status::*;
+use std::env;
 use std::io::{self, Write};
 use types::Identifier;
 
@@ -18,8 +19,72 @@ fn print_functions(functions: &FnvHashMap<Identifier, Function>) {
     }
 }
 
-pub fn fn_(functions: &mut FnvHashMap<Identifier, Function>) -> i32
+pub fn fn_(args: &[&str], functions: &mut FnvHashMap<Identifier, Function>) -> i32
 {
-    print_functions(functions);
+    let stdout = io::stdout();
+    let stdout = &mut stdout.lock();
+    let stderr = io::stderr();
+    let stderr = &mut stderr.lock();
+
+    match args.get(1).cloned() {
+        None => print_functions(functions),
+        Some("--list") => {
+            let mut names: Vec<&str> = functions.keys().map(|name| &**name).collect();
+            names.sort();
+            for name in names {
+                let _ = writeln!(stdout, "{}", name);
+            }
+        },
+        Some(option @ "--show") | Some(option @ "--describe") | Some(option @ "--trace")
+            | Some(option @ "--untrace") =>
+        {
+            let name = match args.get(2) {
+                Some(name) => name,
+                None => {
+                    let _ = writeln!(stderr, "ion: fn: {} requires a function name", option);
+                    return BAD_ARG;
+                }
+            };
+            match functions.get_mut::<str>(name) {
+                Some(function) => match option {
+                    "--show" => { let _ = write!(stdout, "{}", function); },
+                    "--describe" => { let _ = writeln!(stdout, "{}", function.description); },
+                    _ => function.traced = option == "--trace",
+                },
+                None => {
+                    let _ = writeln!(stderr, "ion: fn: function not found: {}", name);
+                    return FAILURE;
+                }
+            }
+        },
+        Some(option) => {
+            let _ = writeln!(stderr, "ion: fn: unknown option: {}", option);
+            return BAD_ARG;
+        }
+    }
     SUCCESS
 }
+
+/// Serializes the source of each supplied function into the environment, so that child ion
+/// processes will define the function on startup.
+pub fn export_functions(args: &[&str], functions: &FnvHashMap<Identifier, Function>) -> i32 {
+    let stderr = io::stderr();
+    let stderr = &mut stderr.lock();
+
+    if args.get(1) != Some(&"-f") || args.len() < 3 {
+        let _ = writeln!(stderr, "ion: export: usage: export -f <function>...");
+        return BAD_ARG;
+    }
+
+    let mut status = SUCCESS;
+    for name in &args[2..] {
+        match functions.get::<str>(name) {
+            Some(function) => env::set_var([EXPORTED_FUNCTION_PREFIX, name].concat(), function.to_string()),
+            None => {
+                let _ = writeln!(stderr, "ion: export: function not found: {}", name);
+                status = FAILURE;
+            }
+        }
+    }
+    status
+}
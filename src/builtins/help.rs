@@ -0,0 +1,65 @@
+//! The `help` builtin, which prints the help of the builtins from the metadata that each builtin
+//! declares when it is inserted into the map of builtins.
+
+use super::Builtin;
+use fnv::FnvHashMap;
+use shell::status::*;
+use std::io::{self, Write};
+
+/// Prints the description, usage, options, and examples of a builtin.
+fn print_help<W: Write>(stdout: &mut W, builtin: &Builtin) {
+    let _ = writeln!(stdout, "{} - {}", builtin.name, builtin.description());
+
+    let usage = builtin.usage();
+    if !usage.is_empty() {
+        let _ = writeln!(stdout, "\nUSAGE");
+        for form in usage {
+            let _ = writeln!(stdout, "    {}", form);
+        }
+    }
+
+    if !builtin.options.is_empty() {
+        let width = builtin.options.iter().map(|&(option, _)| option.len()).max().unwrap_or(0);
+        let _ = writeln!(stdout, "\nOPTIONS");
+        for &(option, description) in builtin.options {
+            let _ = writeln!(stdout, "    {:width$}  {}", option, description, width = width);
+        }
+    }
+
+    if !builtin.examples.is_empty() {
+        let _ = writeln!(stdout, "\nEXAMPLES");
+        for example in builtin.examples {
+            let _ = writeln!(stdout, "    {}", example);
+        }
+    }
+}
+
+/// Lists every builtin along with its description, or prints the help of the given builtin.
+pub fn help(args: &[&str], builtins: &FnvHashMap<&'static str, Builtin>) -> i32 {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    match args.get(1) {
+        Some(name) => match builtins.get(name) {
+            Some(builtin) => {
+                print_help(&mut stdout, builtin);
+                SUCCESS
+            }
+            None => {
+                eprintln!("ion: help: {}: no such builtin", name);
+                FAILURE
+            }
+        },
+        None => {
+            let mut commands = builtins.values().collect::<Vec<&Builtin>>();
+            commands.sort_by_key(|builtin| builtin.name);
+            let width = commands.iter().map(|builtin| builtin.name.len()).max().unwrap_or(0);
+
+            let mut buffer: Vec<u8> = Vec::new();
+            for builtin in commands {
+                let _ = writeln!(buffer, "{:width$}  {}", builtin.name, builtin.description(), width = width);
+            }
+            let _ = stdout.write_all(&buffer);
+            SUCCESS
+        }
+    }
+}
@@ -1,12 +1,20 @@
-//! Contains the `jobs`, `disown`, `bg`, and `fg` commands that manage job control in the shell.
+//! Contains the `jobs`, `disown`, `bg`, `fg`, `wait`, `kill`, and `suspend` commands that manage
+//! job control in the shell.
+use super::print_usage;
+use super::status::is_login_shell;
 use shell::Shell;
-use shell::job_control::{JobControl, ProcessState};
+use shell::flags::MONITOR;
+use shell::job_control::{job_id, parse_jobspec, JobControl, ProcessState};
 use shell::status::*;
 use shell::signals;
-use std::io::{stderr, Write};
+use std::io::{stderr, stdout, Write};
+use std::thread::sleep;
+use std::time::Duration;
+use sys;
 
-/// Disowns given process job IDs, and optionally marks jobs to not receive SIGHUP signals.
-/// The `-a` flag selects all jobs, `-r` selects all running jobs, and `-h` specifies to mark SIGHUP ignoral.
+/// Disowns the given jobs, or the current job if none were given, so that they are no longer
+/// listed, reported, or signaled when the shell exits. The `-a` flag selects all jobs, `-r` selects
+/// all running jobs, and `-h` instead keeps the jobs, but marks them to not receive a `SIGHUP`.
 pub fn disown(shell: &mut Shell, args: &[&str]) -> i32 {
     let stderr = stderr();
     let mut stderr = stderr.lock();
@@ -21,9 +29,9 @@ pub fn disown(shell: &mut Shell, args: &[&str]) -> i32 {
             "-a" => flags |= ALL_JOBS,
             "-h" => flags |= NO_SIGHUP,
             "-r" => flags |= RUN_JOBS,
-            _    => match arg.parse::<u32>() {
-                Ok(jobspec) => jobspecs.push(jobspec),
-                Err(_) => {
+            _    => match parse_jobspec(shell, arg) {
+                Some(jobspec) => jobspecs.push(jobspec),
+                None => {
                     let _ = writeln!(stderr, "ion: disown: invalid jobspec: '{}'", arg);
                     return FAILURE
                 },
@@ -31,54 +39,90 @@ pub fn disown(shell: &mut Shell, args: &[&str]) -> i32 {
         }
     }
 
+    if jobspecs.is_empty() && flags & (ALL_JOBS | RUN_JOBS) == 0 {
+        if shell.previous_job == !0 {
+            let _ = writeln!(stderr, "ion: disown: no current job");
+            return FAILURE;
+        }
+        jobspecs.push(shell.previous_job);
+    }
+
     let mut processes = shell.background.lock().unwrap();
-    if jobspecs.is_empty() && flags & ALL_JOBS != 0 {
-        if flags & NO_SIGHUP != 0 {
-            for process in processes.iter_mut() {
-                process.ignore_sighup = true;
-            }
-        } else {
-            for process in processes.iter_mut() {
-                process.state = ProcessState::Empty;
+    for &jobspec in &jobspecs {
+        match processes.get(jobspec as usize) {
+            Some(process) if process.state != ProcessState::Empty && !process.disowned => (),
+            _ => {
+                let _ = writeln!(stderr, "ion: disown: job {} does not exist", job_id(jobspec));
+                return FAILURE;
             }
         }
-    } else {
-        jobspecs.sort();
-
-        let mut jobspecs = jobspecs.into_iter();
-        let mut current_jobspec = jobspecs.next().unwrap();
-        for (id, process) in processes.iter_mut().enumerate() {
-            if id == current_jobspec as usize {
-                if flags & NO_SIGHUP != 0 { process.ignore_sighup = true; }
-                process.state = ProcessState::Empty;
-                match jobspecs.next() {
-                    Some(jobspec) => current_jobspec = jobspec,
-                    None          => break
-                }
-            }
+    }
+
+    for (id, process) in processes.iter_mut().enumerate() {
+        let selected = if jobspecs.is_empty() {
+            flags & RUN_JOBS == 0 || process.state == ProcessState::Running
+        } else {
+            jobspecs.contains(&(id as u32))
+        };
+        if !selected || process.disowned {
+            continue
         }
 
-        if flags & RUN_JOBS != 0 {
-            for process in processes.iter_mut() {
-                if process.state == ProcessState::Running {
-                    process.state = ProcessState::Empty;
-                }
-            }
+        if flags & NO_SIGHUP != 0 {
+            process.ignore_sighup = true;
+        } else if let ProcessState::Done(_) = process.state {
+            // The job has already been reaped, so its place in the list is freed immediately.
+            process.state = ProcessState::Empty;
+        } else if process.state.is_alive() {
+            process.disowned = true;
         }
     }
 
+    if flags & NO_SIGHUP == 0 && processes.get(shell.previous_job as usize).map_or(false, |job| !job.is_active()) {
+        shell.previous_job = !0;
+    }
+
     SUCCESS
 }
 
-/// Display a list of all jobs running in the background.
-pub fn jobs(shell: &mut Shell) {
-    let stderr = stderr();
-    let mut stderr = stderr.lock();
-    for (id, process) in shell.background.lock().unwrap().iter().enumerate() {
-        if process.state != ProcessState::Empty {
-            let _ = writeln!(stderr, "[{}] {} {}\t{}", id, process.pid, process.state, process.name);
+/// Display a list of all jobs in the background, along with their process IDs, their states, and
+/// their commands, where the current job is marked with a `+`. The `-p` flag lists only the
+/// process IDs, while `-r` and `-s` list only the running or stopped jobs. Jobs which have exited
+/// are removed from the list once they have been displayed.
+pub fn jobs(shell: &mut Shell, args: &[&str]) -> i32 {
+    let (mut pids_only, mut state) = (false, None);
+    for &arg in args {
+        match arg {
+            "-p" => pids_only = true,
+            "-r" => state = Some(ProcessState::Running),
+            "-s" => state = Some(ProcessState::Stopped),
+            _ => {
+                eprintln!("ion: jobs: invalid option: '{}'", arg);
+                return BAD_ARG;
+            }
         }
     }
+
+    let stdout = stdout();
+    let mut stdout = stdout.lock();
+    let current = shell.previous_job;
+    for (id, process) in shell.background.lock().unwrap().iter_mut().enumerate() {
+        if process.state == ProcessState::Empty || process.disowned
+            || state.map_or(false, |state| state != process.state)
+        {
+            continue
+        }
+        let _ = if pids_only {
+            writeln!(stdout, "{}", process.pid)
+        } else {
+            let mark = if id as u32 == current { '+' } else { ' ' };
+            writeln!(stdout, "[{}]{} {} {}\t{}", job_id(id as u32), mark, process.pid, process.state, process.name)
+        };
+        if let ProcessState::Done(_) = process.state {
+            process.state = ProcessState::Empty;
+        }
+    }
+    SUCCESS
 }
 
 /// Hands control of the foreground process to the specified jobs, recording their exit status.
@@ -87,29 +131,38 @@ pub fn jobs(shell: &mut Shell) {
 pub fn fg(shell: &mut Shell, args: &[&str]) -> i32 {
     fn fg_job(shell: &mut Shell, njob: u32) -> i32 {
         let job;
-        if let Some(borrowed_job) = shell.background.lock().unwrap().iter().nth(njob as usize) {
-            job = borrowed_job.clone();
-        } else {
-            let stderr = stderr();
-            let _ = writeln!(stderr.lock(), "ion: fg: job {} does not exist", njob);
-            return FAILURE;
+        match shell.background.lock().unwrap().iter().nth(njob as usize) {
+            Some(borrowed_job) if !borrowed_job.disowned => job = borrowed_job.clone(),
+            _ => {
+                let stderr = stderr();
+                let _ = writeln!(stderr.lock(), "ion: fg: job {} does not exist", job_id(njob));
+                return FAILURE;
+            }
         }
 
         // Bring the process into the foreground and wait for it to finish.
+        if job.state.is_alive() {
+            println!("{}", job.name);
+        }
         match job.state {
             // Give the bg task the foreground, and wait for it to finish.
             ProcessState::Running => shell.set_bg_task_in_foreground(job.pid, false),
             // Same as above, but also resumes the stopped process in advance.
             ProcessState::Stopped => shell.set_bg_task_in_foreground(job.pid, true),
             // Informs the user that the specified job ID no longer exists.
-            ProcessState::Empty => {
+            ProcessState::Done(_) | ProcessState::Empty => {
                 let stderr = stderr();
-                let _ = writeln!(stderr.lock(), "ion: fg: job {} does not exist", njob);
+                let _ = writeln!(stderr.lock(), "ion: fg: job {} does not exist", job_id(njob));
                 FAILURE
             }
         }
     }
 
+    if shell.flags & MONITOR == 0 {
+        eprintln!("ion: fg: no job control, which `set -m` enables");
+        return FAILURE;
+    }
+
     let mut status = 0;
     if args.is_empty() {
         if shell.previous_job == !0 {
@@ -121,9 +174,9 @@ pub fn fg(shell: &mut Shell, args: &[&str]) -> i32 {
         }
     } else {
         for arg in args {
-            match arg.parse::<u32>() {
-                Ok(njob) => status = fg_job(shell, njob),
-                Err(_) => {
+            match parse_jobspec(shell, arg) {
+                Some(njob) => status = fg_job(shell, njob),
+                None => {
                     let stderr = stderr();
                     let _ = writeln!(stderr.lock(), "ion: fg: {} is not a valid job number", arg);
                     status = FAILURE;
@@ -137,25 +190,31 @@ pub fn fg(shell: &mut Shell, args: &[&str]) -> i32 {
 /// Resumes a stopped background process, if it was stopped.
 pub fn bg(shell: &mut Shell, args: &[&str]) -> i32 {
     fn bg_job(shell: &mut Shell, njob: u32) -> bool {
-        if let Some(job) = shell.background.lock().unwrap().iter_mut().nth(njob as usize) {
-            match job.state {
+        match shell.background.lock().unwrap().iter().nth(njob as usize) {
+            Some(job) if !job.disowned => match job.state {
                 ProcessState::Running => {
-                    eprintln!("ion: bg: job {} is already running", njob);
+                    eprintln!("ion: bg: job {} is already running", job_id(njob));
                     return true
                 },
                 ProcessState::Stopped => signals::resume(job.pid),
-                ProcessState::Empty => {
-                    eprintln!("ion: bg: job {} does not exist", njob);
+                ProcessState::Done(_) | ProcessState::Empty => {
+                    eprintln!("ion: bg: job {} does not exist", job_id(njob));
                     return true
                 }
+            },
+            _ => {
+                eprintln!("ion: bg: job {} does not exist", job_id(njob));
+                return true
             }
-        } else {
-            eprintln!("ion: bg: job {} does not exist", njob);
-            return true
         }
         false
     }
 
+    if shell.flags & MONITOR == 0 {
+        eprintln!("ion: bg: no job control, which `set -m` enables");
+        return FAILURE;
+    }
+
     let mut error = false;
     if args.is_empty() {
         if shell.previous_job == !0 {
@@ -167,7 +226,7 @@ pub fn bg(shell: &mut Shell, args: &[&str]) -> i32 {
         }
     } else {
         for arg in args {
-            error = if let Ok(njob) = arg.parse::<u32>() {
+            error = if let Some(njob) = parse_jobspec(shell, arg) {
                 bg_job(shell, njob)
             } else {
                 eprintln!("ion: bg: {} is not a valid job number", arg);
@@ -177,3 +236,207 @@ pub fn bg(shell: &mut Shell, args: &[&str]) -> i32 {
     }
     if error { FAILURE } else { SUCCESS }
 }
+
+/// Waits for the given jobs, which are given either by their job specifications or by their process
+/// IDs, returning the exit status of the last job, or waits for every running job if none were
+/// given. Jobs which have already exited report the status that they exited with, and are then
+/// removed from the list. A `SIGINT` interrupts the wait, leaving the jobs running.
+pub fn wait(shell: &mut Shell, args: &[&str]) -> i32 {
+    let mut jobs = Vec::with_capacity(args.len());
+    for &arg in args {
+        let job = if arg.starts_with('%') {
+            parse_jobspec(shell, arg)
+        } else {
+            arg.parse::<u32>().ok().and_then(|pid| {
+                shell.background.lock().unwrap().iter()
+                    .position(|process| {
+                        process.pid == pid && process.state != ProcessState::Empty && !process.disowned
+                    })
+                    .map(|id| id as u32)
+            })
+        };
+
+        match job {
+            Some(job) => jobs.push(job),
+            None => {
+                eprintln!("ion: wait: {} is not a job of this shell", arg);
+                return NO_SUCH_COMMAND;
+            }
+        }
+    }
+
+    let (mut status, mut next) = (SUCCESS, 0);
+    loop {
+        if let Some(signal) = shell.next_signal() {
+            // A trapped signal interrupts the wait once its trap has been run.
+            if shell.run_trap(signal) || signal == sys::SIGINT {
+                return get_signal_code(signal);
+            } else if shell.handle_signal(signal) {
+                shell.exit(get_signal_code(signal));
+            }
+        }
+
+        {
+            let mut processes = shell.background.lock().unwrap();
+            if jobs.is_empty() {
+                if !processes.iter().any(|process| process.state == ProcessState::Running && !process.disowned) {
+                    return SUCCESS;
+                }
+            } else {
+                while next < jobs.len() {
+                    let process = &mut processes[jobs[next] as usize];
+                    status = match process.state {
+                        ProcessState::Running => break,
+                        ProcessState::Done(status) => {
+                            process.state = ProcessState::Empty;
+                            status
+                        }
+                        ProcessState::Stopped => get_signal_code(sys::SIGTSTP),
+                        // The job was reaped elsewhere, such as by `fg`, or `jobs`.
+                        ProcessState::Empty => NO_SUCH_COMMAND,
+                    };
+                    next += 1;
+                }
+                if next == jobs.len() {
+                    return status;
+                }
+            }
+        }
+
+        sleep(Duration::from_millis(25));
+    }
+}
+
+/// Sends a signal, which is `SIGTERM` by default, to each of the given process IDs, or to the process
+/// group of each job that is given by its job specification. The signal may be given by its name or
+/// number, as in `-TERM`, `-9`, or `-s HUP`, whereas `-l` lists the signals, or converts the given
+/// signals and exit statuses to their names, and the names of signals to their numbers.
+pub fn kill(shell: &mut Shell, args: &[&str]) -> i32 {
+    let mut signal = sys::SIGTERM;
+    let mut targets = args;
+    if let Some(&first) = args.first() {
+        if first == "-l" || first == "-L" {
+            return list_signals(&args[1..]);
+        } else if first == "-s" || first == "-n" {
+            match args.get(1).and_then(|name| signals::parse(name)) {
+                Some(number) => signal = number,
+                None => {
+                    eprintln!("ion: kill: {}: invalid signal specification", args.get(1).unwrap_or(&""));
+                    return BAD_ARG;
+                }
+            }
+            targets = &args[2..];
+        } else if first == "--" {
+            targets = &args[1..];
+        } else if first.starts_with('-') {
+            match signals::parse(&first[1..]) {
+                Some(number) => signal = number,
+                None => {
+                    eprintln!("ion: kill: {}: invalid signal specification", &first[1..]);
+                    return BAD_ARG;
+                }
+            }
+            targets = &args[1..];
+        }
+    }
+
+    if targets.is_empty() {
+        print_usage(shell, "kill");
+        return BAD_ARG;
+    }
+
+    let mut status = SUCCESS;
+    for &target in targets {
+        let result = if target.starts_with('%') {
+            let job = parse_jobspec(shell, target)
+                .and_then(|njob| shell.background.lock().unwrap().get(njob as usize).cloned());
+            match job {
+                Some(ref job) if job.is_active() => {
+                    let result = sys::killpg(job.pid, signal);
+                    // A stopped job must be continued before it is able to terminate.
+                    let terminates = signal == sys::SIGTERM || signal == sys::SIGHUP;
+                    if result.is_ok() && terminates && job.state == ProcessState::Stopped {
+                        signals::resume(job.pid);
+                    }
+                    result
+                }
+                _ => {
+                    eprintln!("ion: kill: {}: no such job", target);
+                    status = FAILURE;
+                    continue
+                }
+            }
+        } else {
+            match target.parse::<u32>() {
+                Ok(pid) => sys::kill(pid, signal),
+                Err(_) => {
+                    eprintln!("ion: kill: {}: arguments must be process or job IDs", target);
+                    status = FAILURE;
+                    continue
+                }
+            }
+        };
+
+        if let Err(why) = result {
+            eprintln!("ion: kill: ({}) - {}", target, why);
+            status = FAILURE;
+        }
+    }
+    status
+}
+
+/// Lists each signal along with its number, or converts each of the given signals, or exit statuses
+/// of processes which were ended by a signal, to their names, and the names of signals to their
+/// numbers.
+fn list_signals(args: &[&str]) -> i32 {
+    let stdout = stdout();
+    let mut stdout = stdout.lock();
+    if args.is_empty() {
+        for &(name, number) in sys::SIGNALS {
+            let _ = writeln!(stdout, "{:2}) SIG{}", number, name);
+        }
+        return SUCCESS;
+    }
+
+    let mut status = SUCCESS;
+    for &arg in args {
+        let converted = match arg.parse::<i32>() {
+            Ok(number) => signals::name(if number > 128 { number - 128 } else { number }).map(String::from),
+            Err(_) => signals::parse(arg).map(|number| number.to_string()),
+        };
+        match converted {
+            Some(converted) => {
+                let _ = writeln!(stdout, "{}", converted);
+            }
+            None => {
+                eprintln!("ion: kill: {}: invalid signal specification", arg);
+                status = FAILURE;
+            }
+        }
+    }
+    status
+}
+
+/// Suspends the shell, so that a shell which was started from another shell may be sent to the
+/// background of that shell. A login shell has no shell to return to, and so is only suspended
+/// when it is forced to be with `-f`.
+pub fn suspend(args: &[&str]) -> i32 {
+    let mut force = false;
+    for &arg in args {
+        match arg {
+            "-f" => force = true,
+            _ => {
+                eprintln!("ion: suspend: invalid option: '{}'", arg);
+                return BAD_ARG;
+            }
+        }
+    }
+
+    if !force && is_login_shell() {
+        eprintln!("ion: suspend: cannot suspend a login shell");
+        return FAILURE;
+    }
+
+    signals::suspend_shell();
+    SUCCESS
+}
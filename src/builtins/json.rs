@@ -0,0 +1,110 @@
+use std::io::{self, Read, Write};
+
+use json::{from_json as parse_json, to_json as serialize};
+use shell::status::*;
+use shell::variables::Variables;
+use types::Data;
+
+/// Parses JSON text into the given variable, where the text is read from the remaining arguments,
+/// or from the standard input if there are none. Objects are stored as maps, sorted by key if
+/// the `-s` flag was supplied, arrays as arrays, and everything else as a string.
+///
+/// ```ignore
+/// from-json [-s] <variable> [json...]
+/// ```
+pub fn from_json(args: &[&str], vars: &mut Variables) -> i32 {
+    let stderr = io::stderr();
+    let mut stderr = stderr.lock();
+
+    let mut args = args.iter().skip(1).peekable();
+    let sorted = args.peek().map_or(false, |&&arg| arg == "-s" || arg == "--sorted");
+    if sorted { args.next(); }
+
+    let name = match args.next() {
+        Some(name) if Variables::is_valid_variable_name(name) => *name,
+        Some(name) => {
+            let _ = writeln!(stderr, "ion: from-json: variable name, '{}', is invalid", name);
+            return BAD_ARG;
+        },
+        None => {
+            let _ = writeln!(stderr, "ion: from-json: you must specify a variable name");
+            return BAD_ARG;
+        }
+    };
+
+    let text = args.cloned().collect::<Vec<&str>>().join(" ");
+    let text = if text.is_empty() {
+        let mut text = String::new();
+        if let Err(why) = io::stdin().read_to_string(&mut text) {
+            let _ = writeln!(stderr, "ion: from-json: unable to read standard input: {}", why);
+            return FAILURE;
+        }
+        text
+    } else {
+        text
+    };
+
+    if vars.is_readonly(name) {
+        let _ = writeln!(stderr, "ion: cannot assign to readonly variable '{}'", name);
+        return FAILURE;
+    }
+
+    match parse_json(&text, sorted) {
+        Ok(Data::Str(value)) => { vars.set_var(name, &value); },
+        Ok(Data::HashMap(map)) => vars.set_hashmap(name, map),
+        Ok(Data::BTreeMap(map)) => vars.set_btreemap(name, map),
+        Ok(data) => { vars.set_array(name, data.flatten()); },
+        Err(why) => {
+            let _ = writeln!(stderr, "ion: from-json: {}", why);
+            return FAILURE;
+        }
+    }
+
+    SUCCESS
+}
+
+/// Prints the value of each of the given variables as JSON, one per line.
+pub fn to_json(args: &[&str], vars: &Variables) -> i32 {
+    if args.len() <= 1 {
+        let stderr = io::stderr();
+        let _ = writeln!(stderr.lock(), "ion: to-json: you must specify a variable name");
+        return BAD_ARG;
+    }
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for name in &args[1..] {
+        let data = match vars.get_data(name) {
+            Some(data) => data,
+            None => match vars.get_var(name) {
+                Some(value) => Data::Str(value),
+                None => {
+                    let stderr = io::stderr();
+                    let _ = writeln!(stderr.lock(), "ion: to-json: undefined variable: {}", name);
+                    return FAILURE;
+                }
+            }
+        };
+        let _ = writeln!(stdout, "{}", serialize(&data));
+    }
+
+    SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_json_arguments() {
+        let mut variables = Variables::default();
+        let status = from_json(&["from-json", "config", r#"{"ports": [80, 443]}"#], &mut variables);
+        assert_eq!(SUCCESS, status);
+        let ports = variables.get_data("config").and_then(|data| data.get("ports").map(Data::flatten));
+        assert_eq!(Some(array!["80", "443"]), ports);
+        assert_eq!(SUCCESS, from_json(&["from-json", "name", "\"ion\""], &mut variables));
+        assert_eq!(Some("ion".into()), variables.get_var("name"));
+        assert_eq!(FAILURE, from_json(&["from-json", "name", "[1,"], &mut variables));
+        assert_eq!(BAD_ARG, from_json(&["from-json"], &mut variables));
+    }
+}
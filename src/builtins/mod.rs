@@ -3,40 +3,90 @@ pub mod variables;
 pub mod functions;
 pub mod calc;
 
+mod argparse;
+mod complete;
 mod conditionals;
 mod job_control;
+mod printf;
+mod random;
+mod read;
 mod test;
 mod time;
+mod trap;
+mod ulimit;
+mod umask;
+mod which;
 mod echo;
+mod exec;
+mod exists;
+mod help;
+mod json;
 mod set;
-
-use self::conditionals::{starts_with, ends_with, contains};
-use self::variables::{alias, drop_alias, drop_variable, drop_array};
-use self::functions::fn_;
+mod sleep;
+mod status;
+
+use self::argparse::argparse;
+use self::complete::complete;
+use self::conditionals::{starts_with, ends_with, contains, in_};
+use self::variables::{alias, drop_alias, print_aliases, drop_variable, drop_array, hash, readonly, unhash};
+use self::functions::{export_functions, fn_};
+use self::sleep::sleep;
 use self::source::source;
+use self::status::status;
 use self::echo::echo;
-use self::test::test;
+use self::exec::exec;
+use self::exists::exists;
+use self::help::help;
+use self::json::{from_json, to_json};
+use self::printf::printf;
+use self::random::random;
+use self::read::read;
+use self::test::{bracket, test};
+use self::trap::trap;
+use self::ulimit::ulimit;
+use self::umask::umask;
+use self::which::{type_, which};
 
 use fnv::FnvHashMap;
 use std::io::{self, Write};
 use std::error::Error;
 
-use parser::QuoteTerminator;
-use shell::job_control::{JobControl, ProcessState};
-use shell::{self, Shell, FlowLogic, ShellHistory};
+use parser::assignments::{parse_assignment, Binding};
+use shell::{Shell, FlowLogic, ShellHistory, VariableStore};
 use shell::status::*;
 use sys;
+use types::Array;
 
 /// Structure which represents a Terminal's command.
 /// This command structure contains a name, and the code which run the
 /// functionnality associated to this one, with zero, one or several argument(s).
 pub struct Builtin {
     pub name: &'static str,
+    /// A description of the builtin on the first line, followed by an indented line for each
+    /// form in which the builtin may be used.
     pub help: &'static str,
+    /// Each option of the builtin, along with a description of the option.
+    pub options: &'static [(&'static str, &'static str)],
+    /// Commands which demonstrate how the builtin is used.
+    pub examples: &'static [&'static str],
     pub main: fn(&[&str], &mut Shell) -> i32,
 }
 
 impl Builtin {
+    /// The description of the builtin, which is the first line of its help.
+    pub fn description(&self) -> &'static str { self.help.lines().next().unwrap_or("") }
+
+    /// Each form in which the builtin may be used, which follow the description within its help.
+    pub fn usage(&self) -> Vec<&'static str> { self.help.lines().skip(1).map(str::trim).collect() }
+
+    /// Prints each form of the builtin's usage to the standard error, for when the builtin was
+    /// given invalid arguments.
+    pub fn print_usage(&self) {
+        for form in self.usage() {
+            eprintln!("ion: {}: usage: {}", self.name, form);
+        }
+    }
+
     /// Return the map from command names to commands
     pub fn map() -> FnvHashMap<&'static str, Self> {
         let mut commands: FnvHashMap<&str, Self> =
@@ -56,15 +106,27 @@ impl Builtin {
         }
 
         insert_builtin!("not", builtin_not, "Reverses the exit status value of the given command.");
+
+        The options and examples which `help` prints for a builtin may also be given:
+
+        insert_builtin!("not", builtin_not, "Reverses the exit status value of the given command.",
+            options: [], examples: ["not test -e file"]);
         */
 
         macro_rules! insert_builtin {
             ($name:expr, $func:ident, $help:expr) => {
+                insert_builtin!($name, $func, $help, options: [], examples: [])
+            };
+            ($name:expr, $func:ident, $help:expr,
+                options: [$(($option:expr, $description:expr)),* $(,)*],
+                examples: [$($example:expr),* $(,)*]) => {
                 commands.insert(
                     $name,
                     Builtin {
                         name: $name,
                         help: $help,
+                        options: &[$(($option, $description)),*],
+                        examples: &[$($example),*],
                         main: $func,
                     }
                 );
@@ -78,28 +140,140 @@ impl Builtin {
             "Change the current directory\n    cd <path>"
         );
 
-        insert_builtin!("dirs", builtin_dirs, "Display the current directory stack");
-        insert_builtin!("pushd", builtin_pushd, "Push a directory to the stack");
-        insert_builtin!("popd", builtin_popd, "Pop a directory from the stack");
+        insert_builtin!(
+            "dirs",
+            builtin_dirs,
+            "Display the current directory stack\n    dirs [-c] [-l] [-p] [-v] [+N | -N]",
+            options: [
+                ("-c", "Clear the directory stack"),
+                ("-l", "Print the full paths of the directories"),
+                ("-p", "Print each directory on its own line"),
+                ("-v", "Print each directory on its own line, along with its position"),
+            ],
+            examples: ["dirs -v", "dirs +1"]
+        );
+        insert_builtin!(
+            "pushd",
+            builtin_pushd,
+            "Push a directory to the stack, or rotate the stack\n    pushd [-n] [dir | +N | -N]",
+            options: [("-n", "Modify the stack without changing the current directory")],
+            examples: ["pushd /tmp", "pushd +2"]
+        );
+        insert_builtin!(
+            "popd",
+            builtin_popd,
+            "Pop a directory from the stack\n    popd [-n] [+N | -N]",
+            options: [("-n", "Modify the stack without changing the current directory")],
+            examples: ["popd", "popd +1"]
+        );
+        insert_builtin!(
+            "hash",
+            builtin_hash,
+            "Cache the executables of commands, or name a directory, which ~name will expand to\n    hash [-r] [-p path name] [name...]\n    hash -d [name=path...]",
+            options: [
+                ("-r", "Forget every cached executable"),
+                ("-p path", "Cache the path as the executable of the command"),
+                ("-d", "Name directories, or list the named directories"),
+            ],
+            examples: ["hash", "hash git cargo", "hash -d proj=~/src/project"]
+        );
+        insert_builtin!(
+            "unhash",
+            builtin_unhash,
+            "Forget the cached executables of commands, or delete named directories\n    unhash [-d] name..."
+        );
 
         /* Aliases */
-        insert_builtin!("alias", builtin_alias, "View, set or unset aliases");
-        insert_builtin!("unalias", builtin_unalias, "Delete an alias");
+        insert_builtin!("alias", builtin_alias, "View or set aliases\n    alias [name[=value]...]");
+        insert_builtin!("unalias", builtin_unalias, "Delete aliases\n    unalias [-a] name...");
+        insert_builtin!(
+            "type",
+            builtin_type,
+            "Describe how commands are resolved\n    type [-a] name...",
+            options: [("-a", "List every way in which each command may be resolved")],
+            examples: ["type echo", "type -a ls"]
+        );
+        insert_builtin!(
+            "which",
+            builtin_which,
+            "Print the executables of commands\n    which [-a] name...",
+            options: [("-a", "List every executable of each command within the PATH")],
+            examples: ["which ls", "which -a ion"]
+        );
 
         /* Variables */
-        insert_builtin!("fn", builtin_fn, "Print list of functions");
+        insert_builtin!(
+            "fn",
+            builtin_fn,
+            "Print list of functions, or inspect a function\n    fn [--list | --show <name> | --describe <name> | --trace <name> | --untrace <name>]"
+        );
+        insert_builtin!(
+            "export",
+            builtin_export,
+            "Export functions to child ion processes\n    export -f <function>..."
+        );
+        insert_builtin!(
+            "argparse",
+            builtin_argparse,
+            "Parse arguments into variables\n    argparse [-n name] [s/]long[=[default]]... -- args...",
+            options: [("-n name", "The name to print within errors, which is the function or script by default")],
+            examples: ["argparse h/help o/output=out.txt -- @args"]
+        );
         insert_builtin!(
             "read",
             builtin_read,
-            "Read some variables\n    read <variable>"
+            "Read a line into variables\n    read [-s] [-p prompt] [-a array] [-t seconds] [-n count] [variable...]",
+            options: [
+                ("-p prompt", "Print the prompt first, when reading from a terminal"),
+                ("-a array", "Assign each word to the array, rather than to variables"),
+                ("-t seconds", "Fail with a status of 124 if the line is not read in time"),
+                ("-n count", "Read no more than the given number of characters"),
+                ("-s", "Do not echo the input, when reading from a terminal"),
+            ],
+            examples: [
+                "read -p 'name: ' first last",
+                "read -a words <<< 'one two three'",
+                "read -t 5 -n 1 answer || echo 'no answer'",
+            ]
         );
         insert_builtin!("drop", builtin_drop, "Delete a variable");
+        insert_builtin!(
+            "from-json",
+            builtin_from_json,
+            "Parse JSON into a variable\n    from-json [-s] <variable> [json...]"
+        );
+        insert_builtin!(
+            "to-json",
+            builtin_to_json,
+            "Print variables as JSON\n    to-json <variable>..."
+        );
+        insert_builtin!(
+            "readonly",
+            builtin_readonly,
+            "Prevent variables from being modified\n    readonly [<variable>[=<value>]...]"
+        );
 
         /* Misc */
+        insert_builtin!(
+            "exists",
+            builtin_exists,
+            "Checks whether files, variables, functions, or builtins exist\n    exists [-a | -b | -d | -f | -fn | -v] name...\n    exists string...",
+            options: [
+                ("-a", "Check for arrays"),
+                ("-b", "Check for builtins"),
+                ("-d", "Check for directories"),
+                ("-f", "Check for regular files"),
+                ("-fn", "Check for functions"),
+                ("-v", "Check for string variables"),
+            ],
+            examples: ["exists -f ~/.config/ion/initrc", "exists -v EDITOR || export EDITOR=vi", "exists $answer"]
+        );
         insert_builtin!(
             "matches",
             builtin_matches,
-            "Checks if a string matches a given regex"
+            "Checks if a string matches a given regex, storing its captures within @MATCHES\n    matches string regex",
+            options: [],
+            examples: ["matches $version '^(\\d+)\\.(\\d+)$' && echo @MATCHES[1]"]
         );
         insert_builtin!(
             "not",
@@ -111,17 +285,28 @@ impl Builtin {
             builtin_set,
             "Set or unset values of shell options and positional parameters."
         );
-        insert_builtin!("eval", builtin_eval, "evaluates the evaluated expression");
+        insert_builtin!(
+            "eval",
+            builtin_eval,
+            "Evaluate the arguments as statements\n    eval [arguments...]"
+        );
         insert_builtin!("exit", builtin_exit, "Exits the current session");
+        insert_builtin!(
+            "exec",
+            builtin_exec,
+            "Replaces the shell with a command, or redirects the shell itself\n    exec [-c] [command [arguments...]]",
+            options: [("-c", "Execute the command with an empty environment")],
+            examples: ["exec vim notes.txt", "exec 3> trace.log", "exec < input.txt"]
+        );
         insert_builtin!(
             "wait",
             builtin_wait,
-            "Waits until all running background processes have completed"
+            "Waits for the given jobs, or all running background jobs, to complete"
         );
         insert_builtin!(
             "jobs",
             builtin_jobs,
-            "Displays all jobs that are attached to the background"
+            "Displays all jobs that are attached to the background\n    jobs [-p | -r | -s]"
         );
         insert_builtin!("bg", builtin_bg, "Resumes a stopped background process");
         insert_builtin!(
@@ -129,28 +314,127 @@ impl Builtin {
             builtin_fg,
             "Resumes and sets a background process as the active process"
         );
+        insert_builtin!(
+            "status",
+            builtin_status,
+            "Describe the context that the shell is executing within\n    status is-interactive\n    status is-login\n    status current-function\n    status line-number",
+            options: [],
+            examples: ["status is-interactive", "status current-function"]
+        );
+        insert_builtin!(
+            "sleep",
+            builtin_sleep,
+            "Wait for the given time, in seconds by default\n    sleep duration...",
+            options: [],
+            examples: ["sleep 0.25", "sleep 1m 30s"]
+        );
+        insert_builtin!(
+            "ulimit",
+            builtin_ulimit,
+            "Display or set the resource limits of the shell\n    ulimit [-H | -S] -a\n    ulimit [-H | -S] [-c | -d | -f | -l | -m | -n | -s | -t | -u | -v] [limit]",
+            options: [
+                ("-H", "Display or set the hard limit"),
+                ("-S", "Display or set the soft limit"),
+                ("-a", "Display every limit"),
+                ("-c", "The size of core files, in kilobytes"),
+                ("-d", "The size of the data segment of a process, in kilobytes"),
+                ("-f", "The size of files written by the shell and its children, in kilobytes"),
+                ("-l", "The size of memory which may be locked, in kilobytes"),
+                ("-m", "The resident set size, in kilobytes"),
+                ("-n", "The number of open files"),
+                ("-s", "The size of the stack, in kilobytes"),
+                ("-t", "The CPU time of a process, in seconds"),
+                ("-u", "The number of processes of the user"),
+                ("-v", "The size of virtual memory, in kilobytes"),
+            ],
+            examples: ["ulimit -a", "ulimit -n 4096", "ulimit -S -c unlimited"]
+        );
+        insert_builtin!(
+            "umask",
+            builtin_umask,
+            "Display or set the file creation mask\n    umask [-p] [-S] [mode]",
+            options: [
+                ("-S", "Display the permissions which the mask allows, such as u=rwx,g=rx,o=rx"),
+                ("-p", "Display the mask as the command which sets it"),
+            ],
+            examples: ["umask", "umask 077", "umask u=rwx,g=rx,o=", "umask go-w"]
+        );
         insert_builtin!(
             "suspend",
             builtin_suspend,
-            "Suspends the shell with a SIGTSTOP signal"
+            "Suspends the shell with a SIGTSTP signal\n    suspend [-f]"
         );
         insert_builtin!(
             "disown",
             builtin_disown,
             "Disowning a process removes that process from the shell's background process table."
         );
+        insert_builtin!(
+            "kill",
+            builtin_kill,
+            "Sends a signal to processes or jobs\n    kill [-s SIGNAL | -SIGNAL] PID | %JOB...\n    kill -l [SIGNAL]",
+            options: [
+                ("-s SIGNAL", "Send the given signal, rather than SIGTERM"),
+                ("-l", "List the signals, or convert signals between names and numbers"),
+            ],
+            examples: ["kill %1", "kill -s HUP 1234", "kill -l 15"]
+        );
+        insert_builtin!(
+            "trap",
+            builtin_trap,
+            "Runs a command when the shell receives a signal, or exits\n    trap [-lp] [[COMMAND] SIGNAL...]",
+            options: [
+                ("-l", "List the signals which may be trapped"),
+                ("-p", "Print the traps of the given signals, or of every signal"),
+            ],
+            examples: ["trap 'rm -f $tmpfile' EXIT", "trap '' HUP", "trap - INT"]
+        );
         insert_builtin!(
             "history",
             builtin_history,
-            "Display a log of all commands previously executed"
+            "Display a log of all commands previously executed\n    history [-c | -d N]",
+            options: [
+                ("-c", "Clear the history"),
+                ("-d N", "Delete the command numbered N"),
+            ],
+            examples: ["history", "history -d 12"]
+        );
+        insert_builtin!(
+            "complete",
+            builtin_complete,
+            "Register how the arguments of a command are completed\n    complete [-c command]\n    complete -c command -f function\n    complete -c command -w word...\n    complete -r -c command",
+            options: [
+                ("-c command", "The command whose arguments are completed"),
+                ("-f function", "The function which prints the completions, one per line"),
+                ("-w word...", "The words which are completed"),
+                ("-r", "Remove the completions of the command"),
+            ],
+            examples: ["complete -c git -f __git_complete", "complete -c make -w all clean install"]
         );
         insert_builtin!(
             "source",
             builtin_source,
-            "Evaluate the file following the command or re-initialize the init file"
+            "Evaluate the file following the command or re-initialize the init file\n    source [file [args...]]",
+            options: [],
+            examples: ["source ~/.config/ion/initrc", "source script.ion arg1 arg2"]
         );
         insert_builtin!("echo", builtin_echo, "Display a line of text");
+        insert_builtin!(
+            "random",
+            builtin_random,
+            "Print a random number, or one of the arguments\n    random [START END]\n    random element...\n    random -s SEED",
+            options: [("-s SEED", "Seed the generator, which $RANDOM shares, to repeat its numbers")],
+            examples: ["random 1 6", "random @colors", "random -s 42"]
+        );
+        insert_builtin!(
+            "printf",
+            builtin_printf,
+            "Format and print text\n    printf <format> [arguments...]",
+            options: [],
+            examples: ["printf '%-10s %5.2f\\n' apples 1.5 pears 0.25", "printf '%x\\n' 255"]
+        );
         insert_builtin!("test", builtin_test, "Performs tests on files and text");
+        insert_builtin!("[", builtin_bracket, "Performs tests on files and text, until a closing ]");
         insert_builtin!("calc", builtin_calc, "Calculate a mathematical expression");
         insert_builtin!(
             "time",
@@ -189,6 +473,11 @@ impl Builtin {
             contains,
             "Evaluates if the supplied argument contains a given string"
         );
+        insert_builtin!(
+            "in",
+            in_,
+            "Evaluates if the supplied value is equal to any of the following elements\n    in <value> <elements>..."
+        );
 
         commands
     }
@@ -236,7 +525,15 @@ fn builtin_popd(args: &[&str], shell: &mut Shell) -> i32 {
     }
 }
 
+fn builtin_type(args: &[&str], shell: &mut Shell) -> i32 { type_(args, shell) }
+
+fn builtin_which(args: &[&str], shell: &mut Shell) -> i32 { which(args, shell) }
+
 fn builtin_alias(args: &[&str], shell: &mut Shell) -> i32 {
+    // Aliases which are only given by their names are printed.
+    if args.len() > 1 && args[1..].iter().all(|arg| !arg.contains('=')) {
+        return print_aliases(&shell.variables, &args[1..]);
+    }
     let args_str = args[1..].join(" ");
     alias(&mut shell.variables, &args_str)
 }
@@ -245,14 +542,26 @@ fn builtin_unalias(args: &[&str], shell: &mut Shell) -> i32 {
     drop_alias(&mut shell.variables, args)
 }
 
-fn builtin_fn(_: &[&str], shell: &mut Shell) -> i32 {
-    fn_(&mut shell.functions)
+fn builtin_hash(args: &[&str], shell: &mut Shell) -> i32 {
+    hash(&mut shell.variables, shell.command_cache.get_mut(), args)
+}
+
+fn builtin_unhash(args: &[&str], shell: &mut Shell) -> i32 {
+    unhash(&mut shell.variables, shell.command_cache.get_mut(), args)
 }
 
-fn builtin_read(args: &[&str], shell: &mut Shell) -> i32 {
-    shell.variables.read(args)
+fn builtin_fn(args: &[&str], shell: &mut Shell) -> i32 {
+    fn_(args, &mut shell.functions)
 }
 
+fn builtin_export(args: &[&str], shell: &mut Shell) -> i32 {
+    export_functions(args, &shell.functions)
+}
+
+fn builtin_argparse(args: &[&str], shell: &mut Shell) -> i32 { argparse(&args[1..], shell) }
+
+fn builtin_read(args: &[&str], shell: &mut Shell) -> i32 { read(args, shell) }
+
 fn builtin_drop(args: &[&str], shell: &mut Shell) -> i32 {
     if args.len() >= 2 && args[1] == "-a" {
         drop_array(&mut shell.variables, args)
@@ -261,6 +570,31 @@ fn builtin_drop(args: &[&str], shell: &mut Shell) -> i32 {
     }
 }
 
+fn builtin_from_json(args: &[&str], shell: &mut Shell) -> i32 {
+    from_json(args, &mut shell.variables)
+}
+
+fn builtin_to_json(args: &[&str], shell: &mut Shell) -> i32 {
+    to_json(args, &shell.variables)
+}
+
+fn builtin_readonly(args: &[&str], shell: &mut Shell) -> i32 {
+    if args.len() <= 1 {
+        return readonly(&mut shell.variables, args);
+    }
+
+    let mut status = SUCCESS;
+    for arg in &args[1..] {
+        if arg.contains('=') {
+            let binding = Binding::Readonly(Box::new(parse_assignment(arg)));
+            if shell.local(binding) != SUCCESS { status = FAILURE; }
+        } else {
+            shell.variables.set_readonly(arg);
+        }
+    }
+    status
+}
+
 fn builtin_not(args: &[&str], shell: &mut Shell) -> i32 {
     let cmd = args[1..].join(" ");
     shell.on_command(&cmd);
@@ -276,21 +610,33 @@ fn builtin_set(args: &[&str], shell: &mut Shell) -> i32 {
 }
 fn builtin_eval(args: &[&str], shell: &mut Shell) -> i32 {
     let evaluated_command = args[1..].join(" ");
-    let mut buffer = QuoteTerminator::new(evaluated_command);
-    if buffer.check_termination() {
-        shell.on_command(&buffer.consume());
-        shell.previous_status
-    } else {
-        let stderr = io::stderr();
-        let mut stderr = stderr.lock();
-        let _ = writeln!(stderr, "ion: supplied eval expression was not terminted");
-        FAILURE
+    if evaluated_command.trim().is_empty() {
+        return SUCCESS;
+    }
+    match shell.evaluate(&evaluated_command) {
+        Ok(()) => shell.previous_status,
+        Err(why) => {
+            let stderr = io::stderr();
+            let mut stderr = stderr.lock();
+            let _ = writeln!(stderr, "ion: eval: {}", why);
+            FAILURE
+        }
     }
 }
 fn builtin_history(args: &[&str], shell: &mut Shell) -> i32 {
-    shell.print_history(args)
+    shell.history(args)
 }
 
+fn builtin_sleep(args: &[&str], shell: &mut Shell) -> i32 { sleep(&args[1..], shell) }
+
+fn builtin_ulimit(args: &[&str], shell: &mut Shell) -> i32 { ulimit(&args[1..], shell) }
+
+fn builtin_umask(args: &[&str], shell: &mut Shell) -> i32 { umask(&args[1..], shell) }
+
+fn builtin_status(args: &[&str], shell: &mut Shell) -> i32 { status(&args[1..], shell) }
+
+fn builtin_complete(args: &[&str], shell: &mut Shell) -> i32 { complete(&args[1..], shell) }
+
 fn builtin_source(args: &[&str], shell: &mut Shell) -> i32 {
     match source(shell, args) {
         Ok(()) => SUCCESS,
@@ -316,19 +662,38 @@ fn builtin_echo(args: &[&str], _: &mut Shell) -> i32 {
     }
 }
 
-fn builtin_test(args: &[&str], _: &mut Shell) -> i32 {
-    match test(args) {
+fn test_status(result: Result<bool, String>) -> i32 {
+    match result {
         Ok(true) => SUCCESS,
         Ok(false) => FAILURE,
         Err(why) => {
             let stderr = io::stderr();
             let mut stderr = stderr.lock();
             let _ = writeln!(stderr, "{}", why);
-            FAILURE
+            BAD_ARG
         }
     }
 }
 
+fn builtin_test(args: &[&str], _: &mut Shell) -> i32 { test_status(test(args)) }
+
+fn builtin_printf(args: &[&str], _: &mut Shell) -> i32 {
+    match printf(args) {
+        Ok(true) => SUCCESS,
+        Ok(false) => FAILURE,
+        Err(why) => {
+            eprintln!("ion: printf: {}", why);
+            BAD_ARG
+        }
+    }
+}
+
+fn builtin_exists(args: &[&str], shell: &mut Shell) -> i32 { exists(&args[1..], shell) }
+
+fn builtin_random(args: &[&str], shell: &mut Shell) -> i32 { random(&args[1..], shell) }
+
+fn builtin_bracket(args: &[&str], _: &mut Shell) -> i32 { test_status(bracket(args)) }
+
 fn builtin_calc(args: &[&str], _: &mut Shell) -> i32 {
     match calc::calc(&args[1..]) {
         Ok(()) => SUCCESS,
@@ -361,14 +726,12 @@ fn builtin_false(_: &[&str], _: &mut Shell) -> i32 {
     FAILURE
 }
 
-fn builtin_wait(_: &[&str], shell: &mut Shell) -> i32 {
-    shell.wait_for_background();
-    SUCCESS
+fn builtin_wait(args: &[&str], shell: &mut Shell) -> i32 {
+    job_control::wait(shell, &args[1..])
 }
 
-fn builtin_jobs(_: &[&str], shell: &mut Shell) -> i32 {
-    job_control::jobs(shell);
-    SUCCESS
+fn builtin_jobs(args: &[&str], shell: &mut Shell) -> i32 {
+    job_control::jobs(shell, &args[1..])
 }
 
 fn builtin_bg(args: &[&str], shell: &mut Shell) -> i32 {
@@ -379,46 +742,35 @@ fn builtin_fg(args: &[&str], shell: &mut Shell) -> i32 {
     job_control::fg(shell, &args[1..])
 }
 
-fn builtin_suspend(_: &[&str], _: &mut Shell) -> i32 {
-    shell::signals::suspend(0);
-    SUCCESS
+fn builtin_suspend(args: &[&str], _: &mut Shell) -> i32 {
+    job_control::suspend(&args[1..])
 }
 
 fn builtin_disown(args: &[&str], shell: &mut Shell) -> i32 {
     job_control::disown(shell, &args[1..])
 }
 
-fn builtin_help(args: &[&str], shell: &mut Shell) -> i32 {
-    let builtins = shell.builtins;
-    let stdout = io::stdout();
-    let mut stdout = stdout.lock();
-    if let Some(command) = args.get(1) {
-        if builtins.contains_key(command) {
-            if let Some(bltin) = builtins.get(command) {
-                let _ = stdout.write_all(bltin.help.as_bytes());
-                let _ = stdout.write_all(b"\n");
-            }
-        } else {
-            let _ = stdout.write_all(b"Command helper not found [run 'help']...");
-            let _ = stdout.write_all(b"\n");
-        }
-    } else {
-        let mut commands = builtins.keys().cloned().collect::<Vec<&str>>();
-        commands.sort();
+fn builtin_kill(args: &[&str], shell: &mut Shell) -> i32 {
+    job_control::kill(shell, &args[1..])
+}
 
-        let mut buffer: Vec<u8> = Vec::new();
-        for command in commands {
-            let _ = writeln!(buffer, "{}", command);
-        }
-        let _ = stdout.write_all(&buffer);
+fn builtin_trap(args: &[&str], shell: &mut Shell) -> i32 { trap(&args[1..], shell) }
+
+fn builtin_help(args: &[&str], shell: &mut Shell) -> i32 { help(args, shell.builtins) }
+
+/// Prints the usage of the named builtin, for when it was given invalid arguments.
+pub fn print_usage(shell: &Shell, name: &str) {
+    if let Some(builtin) = shell.builtins.get(name) {
+        builtin.print_usage();
     }
-    SUCCESS
 }
 
+fn builtin_exec(args: &[&str], shell: &mut Shell) -> i32 { exec(&args[1..], shell) }
+
 fn builtin_exit(args: &[&str], shell: &mut Shell) -> i32 {
-    // Kill all active background tasks before exiting the shell.
+    // Kill all active background tasks before exiting the shell, except for disowned jobs.
     for process in shell.background.lock().unwrap().iter() {
-        if process.state != ProcessState::Empty {
+        if process.is_active() {
             let _ = sys::kill(process.pid, sys::SIGTERM);
         }
     }
@@ -431,11 +783,11 @@ fn builtin_exit(args: &[&str], shell: &mut Shell) -> i32 {
 }
 
 use regex::Regex;
-fn builtin_matches(args: &[&str], _: &mut Shell) -> i32 {
+/// Matches the string against the regex, storing the text of the match, followed by the text of
+/// each of its capture groups, within `@MATCHES`.
+fn builtin_matches(args: &[&str], shell: &mut Shell) -> i32 {
     if args[1..].len() != 2 {
-        let stderr = io::stderr();
-        let mut stderr = stderr.lock();
-        let _ = stderr.write_all(b"match takes two arguments\n");
+        print_usage(shell, "matches");
         return BAD_ARG;
     }
     let input = args[1];
@@ -449,7 +801,20 @@ fn builtin_matches(args: &[&str], _: &mut Shell) -> i32 {
         }
     };
 
-    if re.is_match(input) { SUCCESS } else { FAILURE }
+    match re.captures(input) {
+        Some(captures) => {
+            // A group which did not participate in the match captures an empty string.
+            let matches = captures.iter()
+                .map(|group| group.map_or("", |group| group.as_str()).to_owned())
+                .collect::<Array>();
+            shell.variables.set_array("MATCHES", matches);
+            SUCCESS
+        }
+        None => {
+            shell.variables.set_array("MATCHES", Array::new());
+            FAILURE
+        }
+    }
 }
 
 fn builtin_and(args: &[&str], shell: &mut Shell) -> i32 {
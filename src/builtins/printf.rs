@@ -0,0 +1,357 @@
+//! Formats its arguments according to a format string, as the POSIX `printf` does, so that
+//! formatted output does not depend upon an external command.
+
+use std::io::{self, Write};
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// The flags, width, and precision of a conversion, such as the `-08.3` of `%-08.3f`.
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    zero: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Spec {
+    /// Pads text to the width of the conversion.
+    fn pad(&self, output: &mut String, text: &str) {
+        let fill = self.width.saturating_sub(text.chars().count());
+        if !self.left {
+            output.extend((0..fill).map(|_| ' '));
+        }
+        output.push_str(text);
+        if self.left {
+            output.extend((0..fill).map(|_| ' '));
+        }
+    }
+
+    /// Pads a number to the width of the conversion, where zeros are placed after the sign.
+    fn pad_number(&self, output: &mut String, sign: &str, prefix: &str, digits: &str, zero: bool) {
+        let length = sign.len() + prefix.len() + digits.len();
+        let fill = self.width.saturating_sub(length);
+        if self.zero && zero && !self.left {
+            output.push_str(sign);
+            output.push_str(prefix);
+            output.extend((0..fill).map(|_| '0'));
+            output.push_str(digits);
+        } else {
+            let number = [sign, prefix, digits].concat();
+            self.pad(output, &number);
+        }
+    }
+
+    fn sign(&self, negative: bool) -> &'static str {
+        if negative {
+            "-"
+        } else if self.plus {
+            "+"
+        } else if self.space {
+            " "
+        } else {
+            ""
+        }
+    }
+}
+
+/// The state of the formatting of the arguments, which are consumed by each conversion.
+struct Formatter<'a> {
+    args: &'a [&'a str],
+    next: usize,
+    output: String,
+    errors: Vec<String>,
+    /// Set by a `\c` escape, which stops the output.
+    stopped: bool,
+}
+
+impl<'a> Formatter<'a> {
+    fn arg(&mut self) -> Option<&'a str> {
+        let arg = self.args.get(self.next).cloned();
+        self.next += 1;
+        arg
+    }
+
+    /// Parses an integer, where a leading quote gives the value of the character which follows.
+    fn integer(&mut self, arg: Option<&str>) -> i64 {
+        let arg = match arg {
+            Some(arg) => arg.trim(),
+            None => return 0,
+        };
+        if arg.starts_with('\'') || arg.starts_with('"') {
+            return arg[1..].chars().next().map_or(0, |character| character as i64);
+        }
+        let (negative, digits) = match arg.chars().next() {
+            Some('-') => (true, &arg[1..]),
+            Some('+') => (false, &arg[1..]),
+            _ => (false, arg),
+        };
+        let value = if digits.starts_with("0x") || digits.starts_with("0X") {
+            i64::from_str_radix(&digits[2..], 16)
+        } else {
+            digits.parse::<i64>()
+        };
+        match value {
+            Ok(value) if negative => -value,
+            Ok(value) => value,
+            Err(_) => {
+                self.errors.push(format!("invalid number: {}", arg));
+                0
+            }
+        }
+    }
+
+    fn float(&mut self, arg: Option<&str>) -> f64 {
+        match arg.map(str::trim) {
+            Some(arg) => arg.parse::<f64>().unwrap_or_else(|_| {
+                self.errors.push(format!("invalid number: {}", arg));
+                0.0
+            }),
+            None => 0.0,
+        }
+    }
+
+    /// Reads the width or precision of a conversion, which is taken from an argument for `*`.
+    fn number(&mut self, chars: &mut Peekable<Chars>) -> usize {
+        if chars.peek() == Some(&'*') {
+            chars.next();
+            let arg = self.arg();
+            return self.integer(arg).max(0) as usize;
+        }
+        let mut number = 0;
+        while let Some(digit) = chars.peek().and_then(|character| character.to_digit(10)) {
+            number = number * 10 + digit as usize;
+            chars.next();
+        }
+        number
+    }
+
+    /// Formats a single conversion, after the `%` which started it.
+    fn conversion(&mut self, chars: &mut Peekable<Chars>) -> Result<(), String> {
+        let mut spec = Spec::default();
+        while let Some(&flag) = chars.peek() {
+            match flag {
+                '-' => spec.left = true,
+                '0' => spec.zero = true,
+                '+' => spec.plus = true,
+                ' ' => spec.space = true,
+                '#' => spec.alternate = true,
+                _ => break,
+            }
+            chars.next();
+        }
+        spec.width = self.number(chars);
+        if chars.peek() == Some(&'.') {
+            chars.next();
+            spec.precision = Some(self.number(chars));
+        }
+
+        match chars.next() {
+            Some('%') => self.output.push('%'),
+            Some('s') => {
+                let arg = self.arg().unwrap_or("");
+                let text: String = match spec.precision {
+                    Some(precision) => arg.chars().take(precision).collect(),
+                    None => arg.into(),
+                };
+                spec.pad(&mut self.output, &text);
+            }
+            Some('b') => {
+                let mut text = String::new();
+                let arg = self.arg().unwrap_or("");
+                let mut letters = arg.chars().peekable();
+                while let Some(character) = letters.next() {
+                    if character != '\\' {
+                        text.push(character);
+                    } else if !escape(&mut letters, &mut text) {
+                        self.stopped = true;
+                        break;
+                    }
+                }
+                spec.pad(&mut self.output, &text);
+            }
+            Some('c') => {
+                let text: String = self.arg().and_then(|arg| arg.chars().next()).into_iter().collect();
+                spec.pad(&mut self.output, &text);
+            }
+            Some(conversion @ 'd') | Some(conversion @ 'i') | Some(conversion @ 'u') |
+                Some(conversion @ 'x') | Some(conversion @ 'X') | Some(conversion @ 'o') =>
+            {
+                let arg = self.arg();
+                let value = self.integer(arg);
+                let signed = conversion == 'd' || conversion == 'i';
+                // Negative values are shown in two's complement by the unsigned conversions.
+                let (negative, magnitude) = if signed {
+                    (value < 0, value.wrapping_abs() as u64)
+                } else {
+                    (false, value as u64)
+                };
+                let mut digits = match conversion {
+                    'x' => format!("{:x}", magnitude),
+                    'X' => format!("{:X}", magnitude),
+                    'o' => format!("{:o}", magnitude),
+                    _ => magnitude.to_string(),
+                };
+                if let Some(precision) = spec.precision {
+                    if digits.len() < precision {
+                        digits = "0".repeat(precision - digits.len()) + &digits;
+                    }
+                }
+                let prefix = match conversion {
+                    'x' if spec.alternate && magnitude != 0 => "0x",
+                    'X' if spec.alternate && magnitude != 0 => "0X",
+                    'o' if spec.alternate && !digits.starts_with('0') => "0",
+                    _ => "",
+                };
+                let sign = if signed { spec.sign(negative) } else { "" };
+                spec.pad_number(&mut self.output, sign, prefix, &digits, spec.precision.is_none());
+            }
+            Some('f') | Some('F') => {
+                let arg = self.arg();
+                let value = self.float(arg);
+                let digits = format!("{:.*}", spec.precision.unwrap_or(6), value.abs());
+                let sign = spec.sign(value.is_sign_negative() && value != 0.0);
+                spec.pad_number(&mut self.output, sign, "", &digits, value.is_finite());
+            }
+            Some(conversion) => return Err(format!("invalid conversion: %{}", conversion)),
+            None => return Err("missing conversion after %".into()),
+        }
+        Ok(())
+    }
+}
+
+/// Appends the character of an escape sequence, after the backslash which started it. Returns
+/// `false` for `\c`, after which nothing more is to be printed.
+fn escape(chars: &mut Peekable<Chars>, output: &mut String) -> bool {
+    let character = match chars.next() {
+        Some('a') => '\u{7}',
+        Some('b') => '\u{8}',
+        Some('c') => return false,
+        Some('e') | Some('E') => '\u{1b}',
+        Some('f') => '\u{c}',
+        Some('n') => '\n',
+        Some('r') => '\r',
+        Some('t') => '\t',
+        Some('v') => '\u{b}',
+        Some(digit @ '0'...'7') => {
+            // Up to three octal digits may follow the leading digit if it is a zero.
+            let mut value = digit.to_digit(8).unwrap_or(0);
+            let length = if digit == '0' { 3 } else { 2 };
+            for _ in 0..length {
+                match chars.peek().and_then(|character| character.to_digit(8)) {
+                    Some(digit) => value = value * 8 + digit,
+                    None => break,
+                }
+                chars.next();
+            }
+            ::std::char::from_u32(value).unwrap_or('\u{fffd}')
+        }
+        Some('x') => {
+            let mut value = 0;
+            let mut read = 0;
+            while let Some(digit) = chars.peek().and_then(|character| character.to_digit(16)) {
+                value = value * 16 + digit;
+                chars.next();
+                read += 1;
+                if read == 2 { break }
+            }
+            if read == 0 {
+                output.push_str("\\x");
+                return true;
+            }
+            ::std::char::from_u32(value).unwrap_or('\u{fffd}')
+        }
+        Some(character @ '\\') | Some(character @ '"') | Some(character @ '\'') => character,
+        Some(character) => {
+            output.push('\\');
+            character
+        }
+        None => '\\',
+    };
+    output.push(character);
+    true
+}
+
+/// Formats the arguments, where the format is reused for as long as arguments remain. Returns the
+/// output, along with the errors of any arguments which were not valid numbers.
+fn format(format: &str, args: &[&str]) -> Result<(String, Vec<String>), String> {
+    let mut formatter = Formatter { args, next: 0, output: String::new(), errors: Vec::new(), stopped: false };
+    loop {
+        let start = formatter.next;
+        let mut chars = format.chars().peekable();
+        while let Some(character) = chars.next() {
+            match character {
+                '\\' => if !escape(&mut chars, &mut formatter.output) {
+                    formatter.stopped = true;
+                },
+                '%' => formatter.conversion(&mut chars)?,
+                _ => formatter.output.push(character),
+            }
+            if formatter.stopped {
+                return Ok((formatter.output, formatter.errors));
+            }
+        }
+        // The format is only repeated if it consumed some of the arguments.
+        if formatter.next >= args.len() || formatter.next == start {
+            return Ok((formatter.output, formatter.errors));
+        }
+    }
+}
+
+pub fn printf(args: &[&str]) -> Result<bool, String> {
+    let format_string = args.get(1).ok_or_else(|| String::from("missing format"))?;
+    let (output, errors) = format(format_string, &args[2..])?;
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    stdout.write_all(output.as_bytes()).and_then(|_| stdout.flush()).map_err(|why| why.to_string())?;
+
+    for error in &errors {
+        eprintln!("ion: printf: {}", error);
+    }
+    Ok(errors.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format;
+
+    fn output(format_string: &str, args: &[&str]) -> String {
+        format(format_string, args).unwrap().0
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!("a 1 1.50 ff\n", output("%s %d %.2f %x\\n", &["a", "1", "1.5", "255"]));
+        assert_eq!("tab\there", output("%b", &["tab\\there"]));
+        assert_eq!("100%", output("%d%%", &["100"]));
+        assert_eq!("A 65", output("%c %d", &["A", "'A"]));
+        assert_eq!("17 0x1f 1F", output("%o %#x %X", &["15", "31", "31"]));
+    }
+
+    #[test]
+    fn widths() {
+        assert_eq!("[  ab][ab  ][a]", output("[%4s][%-4s][%.1s]", &["ab", "ab", "abc"]));
+        assert_eq!("[-0042][  +42][007]", output("[%05d][%+5d][%.3d]", &["-42", "42", "7"]));
+        assert_eq!("[   3.14]", output("[%*.*f]", &["7", "2", "3.14159"]));
+    }
+
+    #[test]
+    fn cycling() {
+        assert_eq!("a=1\nb=2\nc=\n", output("%s=%s\\n", &["a", "1", "b", "2", "c"]));
+        assert_eq!("no args\n", output("no args\\n", &["ignored"]));
+        assert_eq!("one", output("%b%s", &["one\\c", "two"]));
+    }
+
+    #[test]
+    fn errors() {
+        let (text, errors) = format("%d", &["x"]).unwrap();
+        assert_eq!("0", text);
+        assert_eq!(1, errors.len());
+        assert!(format("%z", &[]).is_err());
+        assert!(format("%", &[]).is_err());
+    }
+}
@@ -0,0 +1,45 @@
+//! The `random` builtin, which draws numbers from the same generator as `$RANDOM`, and so is
+//! seeded differently for each shell, unless a seed is given with `-s`.
+
+use super::print_usage;
+use shell::Shell;
+use shell::status::*;
+
+/// Draws a number within the inclusive range, using 64 bits of the generator so that every
+/// range of integers may be drawn from.
+fn within(shell: &Shell, start: i64, end: i64) -> i64 {
+    let number = (shell.variables.random() as u64) << 32 | shell.variables.random() as u64;
+    let span = (end.wrapping_sub(start) as u64).wrapping_add(1);
+    // A span of zero means that the range covers every integer.
+    let offset = if span == 0 { number } else { number % span };
+    start.wrapping_add(offset as i64)
+}
+
+/// Prints a number between 0 and 32767, a number between `START` and `END`, or one of the
+/// arguments, such as the elements of an array.
+pub fn random(args: &[&str], shell: &mut Shell) -> i32 {
+    match args.len() {
+        0 => println!("{}", shell.variables.random() % 32768),
+        2 if args[0] == "-s" => match args[1].parse::<u32>() {
+            Ok(seed) => shell.variables.seed_random(seed),
+            Err(_) => {
+                eprintln!("ion: random: {}: the seed must be a positive integer", args[1]);
+                return BAD_ARG;
+            }
+        },
+        _ if args[0] == "-s" => {
+            print_usage(shell, "random");
+            return BAD_ARG;
+        }
+        2 => match (args[0].parse::<i64>(), args[1].parse::<i64>()) {
+            (Ok(start), Ok(end)) if start > end => {
+                eprintln!("ion: random: {} is greater than {}", start, end);
+                return FAILURE;
+            }
+            (Ok(start), Ok(end)) => println!("{}", within(shell, start, end)),
+            _ => println!("{}", args[within(shell, 0, 1) as usize]),
+        },
+        length => println!("{}", args[within(shell, 0, length as i64 - 1) as usize]),
+    }
+    SUCCESS
+}
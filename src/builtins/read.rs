@@ -0,0 +1,216 @@
+//! Reads a line from the standard input into variables, optionally with a prompt, a timeout, a
+//! limit on the number of characters, or without echoing the input to the terminal.
+
+use super::print_usage;
+use liner::Context;
+use shell::Shell;
+use shell::status::*;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{FromRawFd, IntoRawFd};
+use std::time::{Duration, Instant};
+use sys;
+use types::Array;
+
+#[derive(Default)]
+struct Options<'a> {
+    prompt: Option<&'a str>,
+    array: Option<&'a str>,
+    timeout: Option<Duration>,
+    count: Option<usize>,
+    silent: bool,
+}
+
+/// The reason that a line was not read in its entirety.
+enum Interruption {
+    EndOfFile,
+    TimedOut,
+    Failed(io::Error),
+}
+
+fn parse_options<'a>(args: &[&'a str]) -> Result<(Options<'a>, Vec<&'a str>), String> {
+    let mut options = Options::default();
+    let mut variables = Vec::new();
+    let mut index = 1;
+    while index < args.len() {
+        let arg = args[index];
+        index += 1;
+        let value = args.get(index).cloned().ok_or_else(|| format!("{} requires an argument", arg));
+        match arg {
+            "-p" => options.prompt = Some(value?),
+            "-a" => options.array = Some(value?),
+            "-t" => {
+                let timeout = value?;
+                match timeout.parse::<f64>() {
+                    Ok(seconds) if seconds >= 0.0 => {
+                        options.timeout = Some(Duration::from_millis((seconds * 1000.0) as u64));
+                    }
+                    _ => return Err(format!("invalid timeout: {}", timeout)),
+                }
+            }
+            "-n" => {
+                let count = value?;
+                options.count = Some(count.parse::<usize>().map_err(|_| format!("invalid count: {}", count))?);
+            }
+            "-s" => {
+                options.silent = true;
+                continue;
+            }
+            "--" => {
+                variables.extend_from_slice(&args[index..]);
+                break;
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 => return Err(format!("invalid option: {}", arg)),
+            _ => {
+                variables.push(arg);
+                continue;
+            }
+        }
+        // The value of the option has been consumed.
+        index += 1;
+    }
+    Ok((options, variables))
+}
+
+/// Reads a single byte from the standard input without buffering, so that no more of the input
+/// is consumed than is needed, leaving the remainder for the commands that follow.
+fn read_byte(deadline: Option<Instant>) -> Result<u8, Interruption> {
+    if let Some(deadline) = deadline {
+        let now = Instant::now();
+        let remaining = if deadline > now { deadline - now } else { Duration::from_millis(0) };
+        let millis = remaining.as_secs() as i32 * 1000 + (remaining.subsec_nanos() / 1_000_000) as i32;
+        match sys::poll_input(sys::STDIN_FILENO, millis) {
+            Ok(true) => (),
+            Ok(false) => return Err(Interruption::TimedOut),
+            Err(why) => return Err(Interruption::Failed(why)),
+        }
+    }
+
+    let mut stdin = unsafe { File::from_raw_fd(sys::STDIN_FILENO) };
+    let mut byte = [0];
+    let result = stdin.read(&mut byte);
+    // The standard input must remain open after it has been read from.
+    stdin.into_raw_fd();
+    match result {
+        Ok(0) => Err(Interruption::EndOfFile),
+        Ok(_) => Ok(byte[0]),
+        Err(why) => Err(Interruption::Failed(why)),
+    }
+}
+
+/// Reads a line, or `count` characters if a count was given, with the newline removed.
+fn read_line(count: Option<usize>, deadline: Option<Instant>) -> (Vec<u8>, Option<Interruption>) {
+    let mut line = Vec::new();
+    let mut characters = 0;
+    while count.map_or(true, |count| characters < count) {
+        let byte = match read_byte(deadline) {
+            Ok(b'\n') => break,
+            Ok(byte) => byte,
+            Err(interruption) => return (line, Some(interruption)),
+        };
+        line.push(byte);
+        characters += 1;
+        // The remaining bytes of a multibyte character belong to the same character.
+        let width = match byte { 0xF0...0xFF => 4, 0xE0...0xEF => 3, 0xC0...0xDF => 2, _ => 1 };
+        for _ in 1..width {
+            match read_byte(deadline) {
+                Ok(byte) => line.push(byte),
+                Err(interruption) => return (line, Some(interruption)),
+            }
+        }
+    }
+    (line, None)
+}
+
+/// Assigns a word to each variable, where the last variable is assigned the remaining words.
+/// Returns `false` if any of the variables were readonly.
+fn assign(shell: &mut Shell, variables: &[&str], line: &str) -> bool {
+    let mut assigned = true;
+    let mut rest = line.trim();
+    for (id, variable) in variables.iter().enumerate() {
+        if id + 1 == variables.len() {
+            assigned &= shell.variables.set_var(variable, rest);
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            assigned &= shell.variables.set_var(variable, &rest[..end]);
+            rest = rest[end..].trim_left();
+        }
+    }
+    assigned
+}
+
+pub fn read(args: &[&str], shell: &mut Shell) -> i32 {
+    let (options, mut variables) = match parse_options(args) {
+        Ok(parsed) => parsed,
+        Err(why) => {
+            eprintln!("ion: read: {}", why);
+            print_usage(shell, "read");
+            return BAD_ARG;
+        }
+    };
+    if variables.is_empty() && options.array.is_none() {
+        variables.push("REPLY");
+    }
+
+    let terminal = sys::isatty(sys::STDIN_FILENO);
+    let plain = !options.silent && options.count.is_none() && options.timeout.is_none();
+
+    // Lines which are read from the terminal without any other options may be edited.
+    let (line, interruption) = if terminal && plain {
+        let mut context = Context::new();
+        match context.read_line(options.prompt.unwrap_or("").to_owned(), &mut |_| {}) {
+            Ok(line) => (line.into_bytes(), None),
+            Err(ref why) if why.kind() == io::ErrorKind::Interrupted => return get_signal_code(sys::SIGINT),
+            Err(ref why) if why.kind() == io::ErrorKind::UnexpectedEof => (Vec::new(), Some(Interruption::EndOfFile)),
+            Err(why) => (Vec::new(), Some(Interruption::Failed(why))),
+        }
+    } else {
+        let modes = if terminal {
+            if let Some(prompt) = options.prompt {
+                let stderr = io::stderr();
+                let mut stderr = stderr.lock();
+                let _ = stderr.write_all(prompt.as_bytes()).and_then(|_| stderr.flush());
+            }
+            match sys::set_input_modes(sys::STDIN_FILENO, !options.silent, options.count.is_none()) {
+                Ok(modes) => Some(modes),
+                Err(why) => {
+                    eprintln!("ion: read: {}", why);
+                    return FAILURE;
+                }
+            }
+        } else {
+            None
+        };
+
+        let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+        let result = read_line(options.count, deadline);
+
+        if let Some(modes) = modes {
+            let _ = sys::tcsetattr(sys::STDIN_FILENO, &modes);
+            // The newline which ended the input was not echoed.
+            if options.silent {
+                let _ = io::stderr().write_all(b"\n");
+            }
+        }
+        result
+    };
+
+    let line = String::from_utf8_lossy(&line);
+    let mut assigned = true;
+    if let Some(array) = options.array {
+        let words: Array = line.split_whitespace().map(Into::into).collect();
+        assigned &= shell.variables.set_array(array, words);
+    }
+    assigned &= assign(shell, &variables, &line);
+
+    match interruption {
+        None if !assigned => FAILURE,
+        None => SUCCESS,
+        Some(Interruption::EndOfFile) => FAILURE,
+        Some(Interruption::TimedOut) => TIMED_OUT,
+        Some(Interruption::Failed(why)) => {
+            eprintln!("ion: read: {}", why);
+            FAILURE
+        }
+    }
+}
@@ -2,24 +2,57 @@ use std::iter;
 use std::io::{self, Write};
 use shell::flags::*;
 use shell::Shell;
+use shell::job_control::NOTIFY_NOW;
+use shell::status::*;
+use std::sync::atomic::Ordering;
 use liner::KeyBindings;
 
 const HELP: &'static str = r#"NAME
     set - Set or unset values of shell options and positional parameters.
 
 SYNOPSIS
-    set [ --help ] [-e | +e] [-x | +x] [-o [vi | emacs]] [- | --] [STRING]...
+    set [ --help ] [-b | +b] [-e | +e] [-m | +m] [-u | +u] [-x | +x] [-C | +C]
+        [-o [vi | emacs | OPTION]] [+o [OPTION]] [- | --] [STRING]...
 
 DESCRIPTION
     Shell options may be set using the '-' character, and unset using the '+' character.
 
 OPTIONS
+    -b  Report background jobs as soon as they exit, rather than before the next prompt.
+        This is the same as `-o notify`.
+
+    -C  Prevent the > redirection from overwriting existing files, which >| may still overwrite.
+        This is the same as `-o noclobber`.
+
     -e  Exit immediately if a command exits with a non-zero status.
+        This is the same as `-o errexit`.
+
+    -m  Enable job control, which is always enabled for interactive sessions, so that a script
+        may use `fg` and `bg`, and is notified of jobs which have finished between statements.
+        This is the same as `-o monitor`.
 
-    -o  Specifies that an argument will follow that sets the key map.
-        The keymap argument may be either `vi` or `emacs`.
+    -o  Sets the option which is named by the following argument, which `+o` unsets.
+        Without an argument, `-o` lists whether each option is set, and `+o` prints the
+        commands which would restore the current options.
+        The `vi` and `emacs` arguments set the key map. The `extglob` argument
+        enables extended glob patterns.
+        By default, a glob which matches nothing is passed on as the literal pattern. The
+        `nullglob` argument expands such globs to nothing, whereas `failglob` treats them as
+        an error which fails the statement. Setting either one unsets the other.
+        The `pipefail` argument makes the status of a pipeline the status of the first
+        command within it that failed, rather than the status of the last command.
+        The `errexit` argument is the same as the -e option, `noclobber` is the same as the
+        -C option, `notify` is the same as the -b option, `monitor` is the same as the -m
+        option, `nounset` is the same as the -u option, and `xtrace` is the same as the
+        -x option.
+        The `expand_aliases` argument expands aliases, which is always enabled for interactive
+        sessions, so that a script may use them.
+
+    -u  Treat the expansion of an undefined variable as an error, which fails the statement.
+        This is the same as `-o nounset`.
 
     -x  Specifies that commands will be printed as they are executed.
+        This is the same as `-o xtrace`.
 
     --  Following arguments will be set as positional arguments in the shell.
         If no argument are supplied, arguments will be unset.
@@ -35,6 +68,21 @@ enum PositionalArgs {
 
 use self::PositionalArgs::*;
 
+/// Prints whether each option is set, or the commands which would restore the options if
+/// `as_commands` is given, as `set +o` does.
+fn print_options(flags: u16, as_commands: bool) {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for &(name, flag) in OPTIONS {
+        let set = flags & flag != 0;
+        let _ = if as_commands {
+            writeln!(stdout, "set {}o {}", if set { '-' } else { '+' }, name)
+        } else {
+            writeln!(stdout, "{:15} {}", name, if set { "on" } else { "off" })
+        };
+    }
+}
+
 pub fn set(args: &[&str], shell: &mut Shell) -> i32 {
     let stdout = io::stdout();
     let stderr = io::stderr();
@@ -67,35 +115,63 @@ pub fn set(args: &[&str], shell: &mut Shell) -> i32 {
                                     context.key_bindings = KeyBindings::Emacs;
                                 }
                             }
-                            Some(_) => {
-                                let _ = stderr.lock().write_all(b"set: invalid keymap\n");
-                                return 0
+                            Some(&mode) if mode == "nullglob" => {
+                                shell.flags = (shell.flags & (!FAIL_GLOB)) | NULL_GLOB;
                             },
-                            None => {
-                                let _ = stderr.lock().write_all(b"set: no keymap given\n");
-                                return 0
-                            }
+                            Some(&mode) if mode == "failglob" => {
+                                shell.flags = (shell.flags & (!NULL_GLOB)) | FAIL_GLOB;
+                            },
+                            Some(&mode) => match flag_of(mode) {
+                                Some(flag) => shell.flags |= flag,
+                                None => {
+                                    let _ = writeln!(stderr.lock(), "ion: set: {}: invalid option name", mode);
+                                    return BAD_ARG
+                                }
+                            },
+                            None => print_options(shell.flags, false),
                         }
                     },
+                    b'b' => shell.flags |= NOTIFY,
+                    b'm' => shell.flags |= MONITOR,
+                    b'C' => shell.flags |= NO_CLOBBER,
+                    b'u' => shell.flags |= NO_UNSET,
                     b'x' => shell.flags |= PRINT_COMMS,
                     _ => {
-                        return 0
+                        let _ = writeln!(stderr.lock(), "ion: set: -{}: invalid option", flag as char);
+                        return BAD_ARG
                     }
                 }
             }
         } else if arg.starts_with('+') {
             for flag in arg.bytes().skip(1) {
                 match flag {
-                    b'e' => shell.flags &= 255 ^ ERR_EXIT,
-                    b'x' => shell.flags &= 255 ^ PRINT_COMMS,
+                    b'e' => shell.flags &= !ERR_EXIT,
+                    b'o' => match args_iter.next() {
+                        Some(&mode) => match flag_of(mode) {
+                            Some(flag) => shell.flags &= !flag,
+                            None => {
+                                let _ = writeln!(stderr.lock(), "ion: set: {}: invalid option name", mode);
+                                return BAD_ARG
+                            }
+                        },
+                        None => print_options(shell.flags, true),
+                    },
+                    b'b' => shell.flags &= !NOTIFY,
+                    b'm' => shell.flags &= !MONITOR,
+                    b'C' => shell.flags &= !NO_CLOBBER,
+                    b'u' => shell.flags &= !NO_UNSET,
+                    b'x' => shell.flags &= !PRINT_COMMS,
                     _ => {
-                        return 0
+                        let _ = writeln!(stderr.lock(), "ion: set: +{}: invalid option", flag as char);
+                        return BAD_ARG
                     }
                 }
             }
         }
     }
 
+    NOTIFY_NOW.store(shell.flags & NOTIFY != 0, Ordering::Relaxed);
+
     match positionals {
         None => (),
         Some(kind) => {
@@ -107,7 +183,7 @@ pub fn set(args: &[&str], shell: &mut Shell) -> i32 {
                 .chain(args_iter.map(|i| i.to_string()))
                 .collect();
             match kind {
-                UnsetIfNone  => shell.variables.set_array("args", arguments),
+                UnsetIfNone  => { shell.variables.set_array("args", arguments); },
                 RetainIfNone => if arguments.len() != 1 {
                     shell.variables.set_array("args", arguments);
                 }
@@ -0,0 +1,55 @@
+//! The `sleep` builtin, which waits within the shell, rather than within a process of its own, so
+//! that loops which poll for a condition need not spawn a process each time that they wait.
+
+use super::print_usage;
+use shell::{parse_duration, Shell};
+use shell::job_control::JobControl;
+use shell::status::*;
+use std::cmp;
+use std::thread;
+use std::time::{Duration, Instant};
+use sys;
+
+/// Waits for the sum of the given durations, which may be fractional, as in `0.25`, or be given
+/// in other units, as in `2m`. As with `wait`, a signal which is trapped, or an interrupt, ends
+/// the wait early. Within a pipeline that was prefixed with `timeout`, the wait also ends once
+/// the pipeline has timed out.
+pub fn sleep(args: &[&str], shell: &mut Shell) -> i32 {
+    if args.is_empty() {
+        print_usage(shell, "sleep");
+        return BAD_ARG;
+    }
+
+    let mut duration = Duration::from_secs(0);
+    for &arg in args {
+        match parse_duration(arg) {
+            Some(interval) => duration += interval,
+            None => {
+                eprintln!("ion: sleep: {}: invalid time interval", arg);
+                return BAD_ARG;
+            }
+        }
+    }
+
+    let (duration, status) = match shell.time_remaining() {
+        Some(remaining) if remaining < duration => (remaining, TIMED_OUT),
+        _ => (duration, SUCCESS),
+    };
+
+    let end = Instant::now() + duration;
+    loop {
+        if let Some(signal) = shell.next_signal() {
+            if shell.run_trap(signal) || signal == sys::SIGINT {
+                return get_signal_code(signal);
+            } else if shell.handle_signal(signal) {
+                shell.exit(get_signal_code(signal));
+            }
+        }
+
+        let now = Instant::now();
+        if now >= end {
+            return status;
+        }
+        thread::sleep(cmp::min(end - now, Duration::from_millis(25)));
+    }
+}
@@ -1,6 +1,8 @@
 use std::fs::File;
 use std::io::Read;
+use std::iter;
 use shell::{Shell, FlowLogic};
+use types::Array;
 
 /// Evaluates the given file and returns 'SUCCESS' if it succeeds.
 pub fn source(shell: &mut Shell, arguments: &[&str]) -> Result<(), String> {
@@ -12,10 +14,29 @@ pub fn source(shell: &mut Shell, arguments: &[&str]) -> Result<(), String> {
                 file.read_to_string(&mut command_list)
                     .map_err(|message| format!("ion: {}: failed to read {}\n", message, argument))
                     .map(|_| {
+                        // Arguments given to the script replace the positional arguments of the
+                        // caller until the script has been evaluated.
+                        let previous_args = if arguments.len() > 2 {
+                            let args: Array = iter::once(argument)
+                                .chain(&arguments[2..])
+                                .map(|&arg| arg.into())
+                                .collect();
+                            let previous = shell.variables.get_array("args").cloned();
+                            shell.variables.set_array("args", args);
+                            Some(previous)
+                        } else {
+                            None
+                        };
+
                         for command in command_list.lines() {
                             shell.on_command(command);
                         }
-                        ()
+
+                        match previous_args {
+                            Some(Some(args)) => { shell.variables.set_array("args", args); }
+                            Some(None) => { shell.variables.unset_array("args"); }
+                            None => (),
+                        }
                     })
             } else {
                 Err(format!("ion: failed to open {}\n", argument))
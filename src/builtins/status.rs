@@ -0,0 +1,43 @@
+//! The `status` builtin, which describes the context that the shell is executing within, so that
+//! scripts and prompt functions may behave differently within an interactive session.
+
+use super::print_usage;
+use shell::Shell;
+use shell::status::*;
+use std::env;
+
+/// A login shell is started with a name that begins with a `-`.
+pub fn is_login_shell() -> bool {
+    env::args().next().map_or(false, |name| name.starts_with('-'))
+}
+
+/// Answers the query that was given, where the `is-` queries are answered by the exit status, and
+/// the others are printed.
+pub fn status(args: &[&str], shell: &mut Shell) -> i32 {
+    if args.len() != 1 {
+        print_usage(shell, "status");
+        return BAD_ARG;
+    }
+
+    let query = args[0];
+    match query {
+        "is-interactive" => if shell.context.is_some() { SUCCESS } else { FAILURE },
+        "is-login" => if is_login_shell() { SUCCESS } else { FAILURE },
+        "current-function" => match shell.variables.function_names.last() {
+            Some(name) => {
+                println!("{}", name);
+                SUCCESS
+            }
+            None => FAILURE,
+        },
+        "line-number" => {
+            println!("{}", shell.variables.line_number);
+            SUCCESS
+        }
+        _ => {
+            eprintln!("ion: status: {}: invalid query", query);
+            print_usage(shell, "status");
+            BAD_ARG
+        }
+    }
+}
@@ -4,18 +4,33 @@ use std::path::Path;
 use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
 use std::time::SystemTime;
 use std::error::Error;
-use smallstring::SmallString;
+use std::os::unix::io::RawFd;
+use sys;
 
 const MAN_PAGE: &'static str = /* @MANSTART{test} */ r#"NAME
     test - perform tests on files and text
 
 SYNOPSIS
     test [EXPRESSION]
+    [ EXPRESSION ]
 
 DESCRIPTION
-    Tests the expressions given and returns an exit status of 0 if true, else 1.
+    Tests the expressions given and returns an exit status of 0 if true, 1 if false, and 2 if
+    the expression is invalid.
 
 OPTIONS
+    ( EXPRESSION )
+        the EXPRESSION is true
+
+    ! EXPRESSION
+        the EXPRESSION is false
+
+    EXPRESSION -a EXPRESSION
+        both of the EXPRESSIONs are true
+
+    EXPRESSION -o EXPRESSION
+        either of the EXPRESSIONs is true
+
     -n STRING
         the length of STRING is nonzero
 
@@ -31,6 +46,12 @@ OPTIONS
     STRING != STRING
         the strings are not equal
 
+    STRING < STRING
+        the first STRING sorts before the second STRING
+
+    STRING > STRING
+        the first STRING sorts after the second STRING
+
     INTEGER -eq INTEGER
         the integers are equal
 
@@ -73,12 +94,24 @@ OPTIONS
     -f FILE
         FILE exists and is a regular file
 
+    -g FILE
+        FILE exists and is set-group-ID
+
     -h FILE
         FILE exists and is a symbolic link (same as -L)
 
+    -k FILE
+        FILE exists and has its sticky bit set
+
     -L FILE
         FILE exists and is a symbolic link (same as -h)
 
+    -O FILE
+        FILE exists and is owned by the effective user ID
+
+    -p FILE
+        FILE exists and is a named pipe
+
     -r FILE
         FILE exists and read permission is granted
 
@@ -88,6 +121,12 @@ OPTIONS
     -S FILE
         FILE exists and is a socket
 
+    -t FD
+        the file descriptor FD is opened on a terminal
+
+    -u FILE
+        FILE exists and its set-user-ID bit is set
+
     -w FILE
         FILE exists and write permission is granted
 
@@ -104,6 +143,9 @@ EXAMPLES
     Test if 10 is greater than 5:
         test 10 -gt 5 && echo "10 is greater than 5" || echo "10 is not greater than 5"
 
+    Test if a directory exists and is writable:
+        [ -d $dir -a -w $dir ] && echo "$dir is writable" || echo "$dir is not writable"
+
     Test if the user is running a 64-bit OS (POSIX environment only):
         test $(getconf LONG_BIT) = 64 && echo "64-bit OS" || echo "32-bit OS"
 
@@ -119,6 +161,14 @@ pub fn test(args: &[&str]) -> Result<bool, String> {
     evaluate_arguments(arguments, &mut buffer)
 }
 
+/// The `[` form of `test`, whose expression must be closed by a `]`.
+pub fn bracket(args: &[&str]) -> Result<bool, String> {
+    match args.last() {
+        Some(&"]") if args.len() > 1 => test(&args[..args.len() - 1]),
+        _ => Err("[: missing ']'".into()),
+    }
+}
+
 fn evaluate_arguments<W: io::Write>(arguments: &[&str], buffer: &mut W) -> Result<bool, String> {
     match arguments.first() {
         Some(&"--help") => {
@@ -126,34 +176,120 @@ fn evaluate_arguments<W: io::Write>(arguments: &[&str], buffer: &mut W) -> Resul
             buffer.flush().map_err(|x| x.description().to_owned())?;
             Ok(true)
         },
-        Some(&s) if s.starts_with("-") => {
-            // Access the second character in the flag string: this will be type of the flag.
-            // If no flag was given, return `SUCCESS`
-            s.chars().nth(1).map_or(Ok(true), |flag| {
-                // If no argument was given, return `SUCCESS`
-                arguments.get(1).map_or(Ok(true), { |arg|
-                    // Match the correct function to the associated flag
-                    Ok(match_flag_argument(flag, arg))
-                })
-            })
-        },
-        Some(arg) => {
-            // If there is no operator, check if the first argument is non-zero
-            arguments.get(1).map_or(Ok(string_is_nonzero(arg)), |operator| {
-                // If there is no right hand argument, a condition was expected
-                let right_arg = arguments.get(2).ok_or_else(||
-                    SmallString::from("parse error: condition expected"))?;
-                evaluate_expression(arg, operator, right_arg)
-            })
-        },
+        Some(_) => Expression::evaluate(arguments),
         None => Ok(false)
     }
 }
 
+/// A recursive descent parser of the expressions given to `test`, where `-o` binds more loosely
+/// than `-a`, which binds more loosely than `!`.
+struct Expression<'a> {
+    arguments: &'a [&'a str],
+    position: usize,
+}
+
+impl<'a> Expression<'a> {
+    fn evaluate(arguments: &'a [&'a str]) -> Result<bool, String> {
+        let mut expression = Expression { arguments, position: 0 };
+        let result = expression.or()?;
+        match expression.peek(0) {
+            Some(argument) => Err(format!("test: unexpected argument: {:?}", argument)),
+            None => Ok(result),
+        }
+    }
+
+    fn peek(&self, offset: usize) -> Option<&'a str> {
+        self.arguments.get(self.position + offset).cloned()
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let argument = self.peek(0);
+        if argument.is_some() {
+            self.position += 1;
+        }
+        argument
+    }
+
+    fn or(&mut self) -> Result<bool, String> {
+        let mut result = self.and()?;
+        while self.peek(0) == Some("-o") {
+            self.position += 1;
+            // The right operand is always parsed, so that errors are reported regardless.
+            result = self.and()? || result;
+        }
+        Ok(result)
+    }
+
+    fn and(&mut self) -> Result<bool, String> {
+        let mut result = self.not()?;
+        while self.peek(0) == Some("-a") {
+            self.position += 1;
+            result = self.not()? && result;
+        }
+        Ok(result)
+    }
+
+    fn not(&mut self) -> Result<bool, String> {
+        // A `!` which is followed by a binary operator is the left operand of that operator.
+        if self.peek(0) == Some("!") && !self.peek(1).map_or(false, is_binary_operator) {
+            self.position += 1;
+            return self.not().map(|result| !result);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<bool, String> {
+        let first = self.next().ok_or_else(|| String::from("test: expression expected"))?;
+
+        if let (Some(operator), Some(second)) = (self.peek(0), self.peek(1)) {
+            if is_binary_operator(operator) {
+                self.position += 2;
+                return evaluate_expression(first, operator, second);
+            }
+        }
+
+        if first == "(" {
+            let result = self.or()?;
+            return match self.next() {
+                Some(")") => Ok(result),
+                _ => Err("test: missing ')'".into()),
+            };
+        }
+
+        // A flag without an operand is instead a string, which is not empty.
+        match (unary_flag(first), self.peek(0)) {
+            (Some(flag), Some(argument)) => {
+                self.position += 1;
+                Ok(match_flag_argument(flag, argument))
+            }
+            _ => Ok(string_is_nonzero(first)),
+        }
+    }
+}
+
+fn is_binary_operator(operator: &str) -> bool {
+    match operator {
+        "=" | "==" | "!=" | "<" | ">" | "-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge" | "-ef"
+            | "-nt" | "-ot" => true,
+        _ => false,
+    }
+}
+
+/// Obtains the type of a unary flag, such as the `d` of `-d`.
+fn unary_flag(argument: &str) -> Option<char> {
+    let mut chars = argument.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('-'), Some(flag), None) if "bcdefghkLnOprsStuwxz".contains(flag) => Some(flag),
+        _ => None,
+    }
+}
+
 fn evaluate_expression(first: &str, operator: &str, second: &str) -> Result<bool, String> {
     match operator {
         "=" | "==" => Ok(first == second),
         "!="       => Ok(first != second),
+        "<"        => Ok(first < second),
+        ">"        => Ok(first > second),
         "-ef"      => Ok(files_have_same_device_and_inode_numbers(first, second)),
         "-nt"      => Ok(file_is_newer_than(first, second)),
         "-ot"      => Ok(file_is_newer_than(second, first)),
@@ -209,42 +345,34 @@ fn get_modified_file_time(filename: &str) -> Option<SystemTime> {
     fs::metadata(filename).ok().and_then(|file| file.modified().ok())
 }
 
-/// Attempt to parse a &str as a usize.
-fn parse_integers(left: &str, right: &str) -> Result<(Option<usize>, Option<usize>), String> {
-    let parse_integer = |input: &str| -> Result<Option<usize>, String> {
-        match input.parse::<usize>().map_err(|_| {
-            format!("test: integer expression expected: {:?}", input)
-        }) {
-            Err(why) => Err(String::from(why)),
-            Ok(res) => Ok(Some(res)),
-        }
+/// Attempt to parse both operands as integers, which may be negative.
+fn parse_integers(left: &str, right: &str) -> Result<(i64, i64), String> {
+    let parse_integer = |input: &str| -> Result<i64, String> {
+        input.trim().parse::<i64>()
+            .map_err(|_| format!("test: integer expression expected: {:?}", input))
     };
 
-    parse_integer(left).and_then(|left| match parse_integer(right){
-        Ok(right) => Ok((left, right)),
-        Err(why) => Err(why)
-    })
+    Ok((parse_integer(left)?, parse_integer(right)?))
 }
 
 /// Matches flag arguments to their respective functionaity when the `-` character is detected.
 fn match_flag_argument(flag: char, argument: &str) -> bool {
-    // TODO: Implement missing flags
     match flag {
         'b' => file_is_block_device(argument),
         'c' => file_is_character_device(argument),
         'd' => file_is_directory(argument),
         'e' => file_exists(argument),
         'f' => file_is_regular(argument),
-        //'g' => file_is_set_group_id(argument),
-        //'G' => file_is_owned_by_effective_group_id(argument),
+        'g' => file_has_mode_bits(argument, 0o2000),
         'h' | 'L' => file_is_symlink(argument),
-        //'k' => file_has_sticky_bit(argument),
-        //'O' => file_is_owned_by_effective_user_id(argument),
-        //'p' => file_is_named_pipe(argument),
+        'k' => file_has_mode_bits(argument, 0o1000),
+        'O' => file_is_owned_by_effective_user_id(argument),
+        'p' => file_is_named_pipe(argument),
         'r' => file_has_read_permission(argument),
         's' => file_size_is_greater_than_zero(argument),
         'S' => file_is_socket(argument),
-        //'t' => file_descriptor_is_opened_on_a_terminal(argument),
+        't' => file_descriptor_is_opened_on_a_terminal(argument),
+        'u' => file_has_mode_bits(argument, 0o4000),
         'w' => file_has_write_permission(argument),
         'x' => file_has_execute_permission(argument),
         'n' => string_is_nonzero(argument),
@@ -253,6 +381,27 @@ fn match_flag_argument(flag: char, argument: &str) -> bool {
     }
 }
 
+/// Exits SUCCESS if the set-group-ID, set-user-ID, or sticky bit of the file is set.
+fn file_has_mode_bits(filepath: &str, bits: u32) -> bool {
+    fs::metadata(filepath).ok().map_or(false, |metadata| metadata.mode() & bits != 0)
+}
+
+/// Exits SUCCESS if the file is owned by the effective user ID of the shell.
+fn file_is_owned_by_effective_user_id(filepath: &str) -> bool {
+    fs::metadata(filepath).ok().map_or(false, |metadata| metadata.uid() == sys::geteuid())
+}
+
+/// Exits SUCCESS if the file is a named pipe.
+fn file_is_named_pipe(filepath: &str) -> bool {
+    fs::metadata(filepath).ok()
+        .map_or(false, |metadata| metadata.file_type().is_fifo())
+}
+
+/// Exits SUCCESS if the file descriptor is open and refers to a terminal.
+fn file_descriptor_is_opened_on_a_terminal(descriptor: &str) -> bool {
+    descriptor.parse::<RawFd>().ok().map_or(false, sys::isatty)
+}
+
 /// Exits SUCCESS if the file size is greather than zero.
 fn file_size_is_greater_than_zero(filepath: &str) -> bool {
     fs::metadata(filepath).ok().map_or(false, |metadata| metadata.len() > 0)
@@ -417,6 +566,44 @@ fn test_integers_arguments() {
         &mut buffer), Ok(false));
 }
 
+#[test]
+fn test_negative_integers() {
+    let mut empty = BufWriter::new(io::sink());
+    let mut eval = |args: Vec<&str>| evaluate_arguments(&args, &mut empty);
+    assert_eq!(eval(vec!["-5", "-lt", "3"]), Ok(true));
+    assert_eq!(eval(vec!["-5", "-gt", "-10"]), Ok(true));
+    assert!(eval(vec!["five", "-eq", "5"]).is_err());
+}
+
+#[test]
+fn test_operators() {
+    let mut empty = BufWriter::new(io::sink());
+    let mut eval = |args: Vec<&str>| evaluate_arguments(&args, &mut empty);
+    assert_eq!(eval(vec!["!", "-d", "testing/empty_file"]), Ok(true));
+    assert_eq!(eval(vec!["!", "=", "!"]), Ok(true));
+    assert_eq!(eval(vec!["-d", "testing", "-a", "-f", "testing/empty_file"]), Ok(true));
+    assert_eq!(eval(vec!["-d", "testing", "-a", "-f", "testing"]), Ok(false));
+    assert_eq!(eval(vec!["-f", "testing", "-o", "a", "<", "b"]), Ok(true));
+    assert_eq!(eval(vec!["", "-o", "a", "-a", ""]), Ok(false));
+    assert_eq!(eval(vec!["!", "(", "a", "=", "b", "-o", "-n", "c", ")"]), Ok(false));
+    assert_eq!(eval(vec!["-n"]), Ok(true));
+    assert!(eval(vec!["(", "a"]).is_err());
+    assert!(eval(vec!["a", "b"]).is_err());
+    assert!(eval(vec!["a", "-a"]).is_err());
+}
+
+#[test]
+fn test_brackets() {
+    assert_eq!(bracket(&["[", "a", "=", "a", "]"]), Ok(true));
+    assert!(bracket(&["[", "a", "=", "a"]).is_err());
+}
+
+#[test]
+fn test_file_is_named_pipe() {
+    assert_eq!(file_is_named_pipe("testing/empty_file"), false);
+    assert_eq!(file_descriptor_is_opened_on_a_terminal("x"), false);
+}
+
 #[test]
 fn test_file_exists() {
     assert_eq!(file_exists("testing/empty_file"), true);
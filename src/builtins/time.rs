@@ -7,15 +7,20 @@ const MAN_PAGE: &'static str = r#"NAME
     time - timer for commands
 
 SYNOPSIS
-    time [ -h | --help ][COMMAND] [ARGUEMENT]...
+    time [ -h | --help ] [-p] [PIPELINE]
 
 DESCRIPTION
-    Runs the command taken as the first arguement and outputs the time the command took to execute.
+    Executes the pipeline which follows, and reports the real, user, and system time that the
+    pipeline as a whole took to the standard error once it has completed. The times are also
+    stored, in seconds, within the TIME_REAL, TIME_USER, and TIME_SYS variables.
 
 OPTIONS
     -h
     --help
         display this help and exit
+
+    -p
+        report the times in the format given by POSIX
 "#;
 
 pub fn time(args: &[&str]) -> Result<(), String> {
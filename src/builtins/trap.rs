@@ -0,0 +1,119 @@
+//! The `trap` builtin, which registers commands to be run when the shell receives a signal, or
+//! when the shell exits.
+
+use super::print_usage;
+use shell::Shell;
+use shell::signals;
+use shell::status::*;
+use std::io::{self, Write};
+use sys;
+
+/// The pseudo-signal of the shell's exit.
+const EXIT: i32 = 0;
+
+/// Parses a signal given by its name or number, or `EXIT` for the shell's exit.
+fn parse_signal(signal: &str) -> Option<i32> {
+    if signal.to_uppercase() == "EXIT" { Some(EXIT) } else { signals::parse(signal) }
+}
+
+fn signal_name(signal: i32) -> String {
+    if signal == EXIT {
+        "EXIT".into()
+    } else {
+        signals::name(signal).map_or_else(|| signal.to_string(), String::from)
+    }
+}
+
+/// The shell always catches these signals, as it must handle them even when they are not trapped.
+fn is_caught(signal: i32) -> bool {
+    signal == sys::SIGHUP || signal == sys::SIGINT || signal == sys::SIGTERM
+}
+
+fn print_trap<W: Write>(stdout: &mut W, signal: i32, command: &str) {
+    let _ = writeln!(stdout, "trap -- '{}' {}", command.replace('\'', "'\\''"), signal_name(signal));
+}
+
+/// Prints the traps of the given signals, or of every trapped signal if none were given.
+fn print_traps(shell: &Shell, names: &[&str]) -> i32 {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    if names.is_empty() {
+        let mut traps: Vec<_> = shell.traps.iter().collect();
+        traps.sort();
+        for (&signal, command) in traps {
+            print_trap(&mut stdout, signal, command);
+        }
+        return SUCCESS;
+    }
+
+    let mut status = SUCCESS;
+    for &name in names {
+        match parse_signal(name) {
+            Some(signal) => if let Some(command) = shell.traps.get(&signal) {
+                print_trap(&mut stdout, signal, command);
+            },
+            None => {
+                eprintln!("ion: trap: {}: invalid signal specification", name);
+                status = FAILURE;
+            }
+        }
+    }
+    status
+}
+
+/// Sets the command which is run when any of the given signals are received. The command `-`
+/// restores the default action of the signals, and an empty command ignores them.
+pub fn trap(args: &[&str], shell: &mut Shell) -> i32 {
+    let args = match args.first() {
+        Some(&"-l") => {
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+            for &(name, number) in sys::SIGNALS {
+                let _ = writeln!(stdout, "{:2}) SIG{}", number, name);
+            }
+            return SUCCESS;
+        }
+        Some(&"-p") => return print_traps(shell, &args[1..]),
+        Some(&"--") => &args[1..],
+        _ => args,
+    };
+
+    let (command, names) = match args.split_first() {
+        Some((&command, names)) if !names.is_empty() => (command, names),
+        Some(_) => {
+            print_usage(shell, "trap");
+            return BAD_ARG;
+        }
+        None => return print_traps(shell, &[]),
+    };
+
+    let mut status = SUCCESS;
+    for &name in names {
+        let signal = match parse_signal(name) {
+            Some(signal) => signal,
+            None => {
+                eprintln!("ion: trap: {}: invalid signal specification", name);
+                status = FAILURE;
+                continue
+            }
+        };
+
+        if command == "-" {
+            shell.traps.remove(&signal);
+            if signal != EXIT && !is_caught(signal) {
+                let _ = sys::reset_signal(signal);
+            }
+            continue
+        }
+
+        if signal != EXIT && !is_caught(signal) {
+            if let Err(why) = sys::signal(signal, signals::handler) {
+                eprintln!("ion: trap: {}: {}", name, why);
+                status = FAILURE;
+                continue
+            }
+        }
+        shell.traps.insert(signal, command.into());
+    }
+    status
+}
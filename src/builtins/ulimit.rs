@@ -0,0 +1,143 @@
+//! The `ulimit` builtin, which displays or sets the resource limits of the shell, which are
+//! inherited by each command that the shell executes. Unlike `limit`, which only restricts the
+//! pipeline that it prefixes, the limits remain until the shell exits.
+
+use super::print_usage;
+use shell::Shell;
+use shell::status::*;
+use sys;
+
+/// A resource which may be limited, with the option that selects it, its description, and the
+/// number of bytes in each of the units which its limit is given in.
+struct Resource {
+    option: char,
+    resource: i32,
+    description: &'static str,
+    unit: u64,
+}
+
+const RESOURCES: &[Resource] = &[
+    Resource { option: 'c', resource: sys::RLIMIT_CORE, description: "core file size (kbytes)", unit: 1024 },
+    Resource { option: 'd', resource: sys::RLIMIT_DATA, description: "data segment size (kbytes)", unit: 1024 },
+    Resource { option: 'f', resource: sys::RLIMIT_FSIZE, description: "file size (kbytes)", unit: 1024 },
+    Resource { option: 'l', resource: sys::RLIMIT_MEMLOCK, description: "locked memory (kbytes)", unit: 1024 },
+    Resource { option: 'm', resource: sys::RLIMIT_RSS, description: "resident set size (kbytes)", unit: 1024 },
+    Resource { option: 'n', resource: sys::RLIMIT_NOFILE, description: "open files", unit: 1 },
+    Resource { option: 's', resource: sys::RLIMIT_STACK, description: "stack size (kbytes)", unit: 1024 },
+    Resource { option: 't', resource: sys::RLIMIT_CPU, description: "cpu time (seconds)", unit: 1 },
+    Resource { option: 'u', resource: sys::RLIMIT_NPROC, description: "processes", unit: 1 },
+    Resource { option: 'v', resource: sys::RLIMIT_AS, description: "virtual memory (kbytes)", unit: 1024 },
+];
+
+/// Which of the limits of a resource are displayed or set.
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Soft,
+    Hard,
+    /// Both limits are set, while the soft limit is displayed.
+    Both,
+}
+
+fn format_limit(limit: u64, unit: u64) -> String {
+    if limit == sys::RLIM_INFINITY { "unlimited".into() } else { (limit / unit).to_string() }
+}
+
+fn parse_limit(input: &str, unit: u64) -> Option<u64> {
+    if input == "unlimited" {
+        Some(sys::RLIM_INFINITY)
+    } else {
+        input.parse::<u64>().ok().and_then(|limit| limit.checked_mul(unit))
+    }
+}
+
+fn print_limits(kind: Kind) -> i32 {
+    let mut status = SUCCESS;
+    for resource in RESOURCES {
+        match sys::getrlimit(resource.resource) {
+            Ok((soft, hard)) => {
+                let limit = if kind == Kind::Hard { hard } else { soft };
+                println!("{:<28}(-{}) {}", resource.description, resource.option, format_limit(limit, resource.unit));
+            }
+            Err(why) => {
+                eprintln!("ion: ulimit: {}: {}", resource.description, why);
+                status = FAILURE;
+            }
+        }
+    }
+    status
+}
+
+/// Displays the limit of a resource, which is the file size unless another resource is given,
+/// or sets it to the given limit. With `-H` or `-S`, only the hard or the soft limit is
+/// displayed or set, where the soft limit is displayed and both limits are set by default.
+pub fn ulimit(args: &[&str], shell: &mut Shell) -> i32 {
+    let mut kind = Kind::Both;
+    let mut all = false;
+    let mut selected = None;
+    let mut value = None;
+    for &arg in args {
+        if arg.starts_with('-') && arg.len() > 1 {
+            for option in arg[1..].chars() {
+                match option {
+                    'H' => kind = Kind::Hard,
+                    'S' => kind = Kind::Soft,
+                    'a' => all = true,
+                    _ => match RESOURCES.iter().find(|resource| resource.option == option) {
+                        Some(resource) if selected.is_none() => selected = Some(resource),
+                        _ => {
+                            print_usage(shell, "ulimit");
+                            return BAD_ARG;
+                        }
+                    },
+                }
+            }
+        } else if value.is_none() {
+            value = Some(arg);
+        } else {
+            print_usage(shell, "ulimit");
+            return BAD_ARG;
+        }
+    }
+
+    if all {
+        return print_limits(kind);
+    }
+
+    // The file size is the resource which is limited when no other resource is given.
+    let resource = selected.unwrap_or(&RESOURCES[2]);
+    let (soft, hard) = match sys::getrlimit(resource.resource) {
+        Ok(limits) => limits,
+        Err(why) => {
+            eprintln!("ion: ulimit: {}: {}", resource.description, why);
+            return FAILURE;
+        }
+    };
+
+    let value = match value {
+        Some(value) => value,
+        None => {
+            println!("{}", format_limit(if kind == Kind::Hard { hard } else { soft }, resource.unit));
+            return SUCCESS;
+        }
+    };
+
+    let limit = match parse_limit(value, resource.unit) {
+        Some(limit) => limit,
+        None => {
+            eprintln!("ion: ulimit: {}: invalid limit", value);
+            return FAILURE;
+        }
+    };
+    let (soft, hard) = match kind {
+        Kind::Soft => (limit, hard),
+        Kind::Hard => (soft, limit),
+        Kind::Both => (limit, limit),
+    };
+    match sys::setrlimit(resource.resource, soft, hard) {
+        Ok(()) => SUCCESS,
+        Err(why) => {
+            eprintln!("ion: ulimit: {}: cannot modify limit: {}", resource.description, why);
+            FAILURE
+        }
+    }
+}
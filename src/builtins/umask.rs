@@ -0,0 +1,159 @@
+//! The `umask` builtin, which displays or sets the file creation mask of the shell, which is
+//! inherited by each command that the shell executes.
+
+use super::print_usage;
+use shell::Shell;
+use shell::status::*;
+use sys;
+
+/// The permissions of the user, the group, and others, in the order that they are displayed.
+const CLASSES: [(char, u32); 3] = [('u', 0o700), ('g', 0o070), ('o', 0o007)];
+
+/// Displays the permissions which the mask allows, such as `u=rwx,g=rx,o=rx`.
+fn symbolic(mask: u32) -> String {
+    let allowed = !mask & 0o777;
+    let mut output = String::new();
+    for &(class, bits) in &CLASSES {
+        if !output.is_empty() {
+            output.push(',');
+        }
+        output.push(class);
+        output.push('=');
+        let permissions = allowed & bits;
+        for &(permission, bit) in &[('r', 0o444), ('w', 0o222), ('x', 0o111)] {
+            if permissions & bit != 0 {
+                output.push(permission);
+            }
+        }
+    }
+    output
+}
+
+fn parse_octal(input: &str) -> Option<u32> {
+    if input.is_empty() || input.len() > 4 || !input.bytes().all(|byte| byte >= b'0' && byte <= b'7') {
+        return None;
+    }
+    u32::from_str_radix(input, 8).ok().and_then(|mask| if mask <= 0o777 { Some(mask) } else { None })
+}
+
+/// Applies a symbolic mode such as `u=rwx,g+w,o-rwx` to the permissions which the mask allows,
+/// returning the new mask.
+fn parse_symbolic(input: &str, mask: u32) -> Option<u32> {
+    let mut allowed = !mask & 0o777;
+    for clause in input.split(',') {
+        let operator = match clause.find(|c: char| c == '+' || c == '-' || c == '=') {
+            Some(operator) => operator,
+            None => return None,
+        };
+        let mut classes = 0;
+        for class in clause[..operator].chars() {
+            classes |= match class {
+                'u' => 0o700,
+                'g' => 0o070,
+                'o' => 0o007,
+                'a' => 0o777,
+                _ => return None,
+            };
+        }
+        if classes == 0 {
+            classes = 0o777;
+        }
+
+        let mut permissions = 0;
+        for permission in clause[operator + 1..].chars() {
+            permissions |= match permission {
+                'r' => 0o444,
+                'w' => 0o222,
+                'x' => 0o111,
+                _ => return None,
+            };
+        }
+        permissions &= classes;
+
+        match &clause[operator..operator + 1] {
+            "+" => allowed |= permissions,
+            "-" => allowed &= !permissions,
+            _ => allowed = (allowed & !classes) | permissions,
+        }
+    }
+    Some(!allowed & 0o777)
+}
+
+/// The mask can only be read by setting it, and so it is set again straight afterwards.
+fn current_mask() -> Result<u32, String> {
+    let mask = sys::umask(0o022).map_err(|why| why.to_string())?;
+    sys::umask(mask).map_err(|why| why.to_string())?;
+    Ok(mask)
+}
+
+/// Displays the mask in octal, or symbolically with `-S`, or sets the mask to the given octal or
+/// symbolic mode. With `-p`, the mask is displayed as the command which sets it.
+pub fn umask(args: &[&str], shell: &mut Shell) -> i32 {
+    let mut symbolic_output = false;
+    let mut reusable = false;
+    let mut mode = None;
+    for &arg in args {
+        match arg {
+            "-S" => symbolic_output = true,
+            "-p" => reusable = true,
+            _ if mode.is_none() && !arg.starts_with('-') => mode = Some(arg),
+            _ => {
+                print_usage(shell, "umask");
+                return BAD_ARG;
+            }
+        }
+    }
+
+    let mask = match current_mask() {
+        Ok(mask) => mask,
+        Err(why) => {
+            eprintln!("ion: umask: {}", why);
+            return FAILURE;
+        }
+    };
+
+    match mode {
+        Some(mode) => {
+            let mask = match parse_octal(mode).or_else(|| parse_symbolic(mode, mask)) {
+                Some(mask) => mask,
+                None => {
+                    eprintln!("ion: umask: {}: invalid mode", mode);
+                    return FAILURE;
+                }
+            };
+            if let Err(why) = sys::umask(mask) {
+                eprintln!("ion: umask: {}", why);
+                return FAILURE;
+            }
+        }
+        None => {
+            let mask = if symbolic_output { symbolic(mask) } else { format!("{:04o}", mask) };
+            if reusable {
+                println!("umask {}{}", if symbolic_output { "-S " } else { "" }, mask);
+            } else {
+                println!("{}", mask);
+            }
+        }
+    }
+    SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn modes() {
+        assert_eq!(symbolic(0o022), "u=rwx,g=rx,o=rx");
+        assert_eq!(symbolic(0o077), "u=rwx,g=,o=");
+        assert_eq!(parse_octal("0027"), Some(0o027));
+        assert_eq!(parse_octal("8"), None);
+        assert_eq!(parse_octal("1777"), None);
+        assert_eq!(parse_symbolic("u=rwx,g=rx,o=", 0o022), Some(0o027));
+        assert_eq!(parse_symbolic("go-w", 0o002), Some(0o022));
+        assert_eq!(parse_symbolic("a+rwx", 0o077), Some(0o000));
+        assert_eq!(parse_symbolic("+x", 0o111), Some(0o000));
+        assert_eq!(parse_symbolic("u=rwz", 0o022), None);
+        assert_eq!(parse_symbolic("ur", 0o022), None);
+    }
+}
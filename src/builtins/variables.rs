@@ -3,19 +3,44 @@
 use std::io::{self, Write};
 
 use types::*;
+use shell::command_cache::CommandCache;
 use shell::status::*;
-use shell::variables::Variables;
+use shell::variables::{unexport, Variables};
+use std::path::PathBuf;
 
+/// Prints an alias in the form that defines it, with its value in single quotes.
+fn print_alias<W: Write>(stdout: &mut W, name: &str, value: &str) {
+    let _ = writeln!(stdout, "alias {}='{}'", name, value.replace('\'', "'\\''"));
+}
+
+/// Prints every alias, sorted by name.
 fn print_list(list: &VariableContext) {
     let stdout = io::stdout();
     let stdout = &mut stdout.lock();
 
-    for (key, value) in list {
-        let _ = stdout.write(key.as_bytes())
-            .and_then(|_| stdout.write_all(b" = "))
-            .and_then(|_| stdout.write_all(value.as_bytes()))
-            .and_then(|_| stdout.write_all(b"\n"));
+    let mut aliases: Vec<_> = list.iter().collect();
+    aliases.sort();
+    for (key, value) in aliases {
+        print_alias(stdout, key, value);
+    }
+}
+
+/// Prints each of the named aliases, failing if any of them are not defined.
+pub fn print_aliases(vars: &Variables, names: &[&str]) -> i32 {
+    let stdout = io::stdout();
+    let stdout = &mut stdout.lock();
+
+    let mut status = SUCCESS;
+    for name in names {
+        match vars.aliases.get(*name) {
+            Some(value) => print_alias(stdout, name, value),
+            None => {
+                eprintln!("ion: alias: {}: not found", name);
+                status = FAILURE;
+            }
+        }
     }
+    status
 }
 
 enum Binding {
@@ -137,11 +162,15 @@ pub fn alias(vars: &mut Variables, args: &str) -> i32 {
 }
 
 
-/// Dropping an alias will erase it from the shell.
+/// Dropping an alias will erase it from the shell, where `-a` erases every alias.
 pub fn drop_alias<I: IntoIterator>(vars: &mut Variables, args: I) -> i32
     where I::Item: AsRef<str>
 {
     let args = args.into_iter().collect::<Vec<I::Item>>();
+    if args.get(1).map(|arg| arg.as_ref()) == Some("-a") {
+        vars.aliases.clear();
+        return SUCCESS;
+    }
     if args.len() <= 1 {
         let stderr = io::stderr();
         let _ = writeln!(&mut stderr.lock(), "ion: you must specify an alias name");
@@ -157,7 +186,114 @@ pub fn drop_alias<I: IntoIterator>(vars: &mut Variables, args: I) -> i32
     SUCCESS
 }
 
-/// Dropping an array will erase it from the shell.
+/// Caches the executables of commands, or with `-p path name`, caches the given executable as the
+/// command. The cached commands are listed when no names are given, and `-r` clears them.
+fn hash_commands(cache: &mut CommandCache, args: &[&str]) -> i32 {
+    let stderr = io::stderr();
+    match args.get(1) {
+        None => {
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+            let commands = cache.commands();
+            if !commands.is_empty() {
+                let _ = writeln!(stdout, "hits    command");
+            }
+            for (_, hashed) in commands {
+                let _ = writeln!(stdout, "{:4}    {}", hashed.hits, hashed.path.display());
+            }
+            SUCCESS
+        }
+        Some(&"-r") if args.len() == 2 => {
+            cache.clear();
+            SUCCESS
+        }
+        Some(&"-p") if args.len() == 4 => {
+            cache.insert(args[3], PathBuf::from(args[2]));
+            SUCCESS
+        }
+        Some(&arg) if arg.starts_with('-') => {
+            let _ = writeln!(&mut stderr.lock(), "ion: hash: usage: hash [-r] [-p path name] [name...]");
+            BAD_ARG
+        }
+        Some(_) => {
+            let mut status = SUCCESS;
+            for name in &args[1..] {
+                if !cache.add(name) {
+                    let _ = writeln!(&mut stderr.lock(), "ion: hash: {}: not found", name);
+                    status = FAILURE;
+                }
+            }
+            status
+        }
+    }
+}
+
+/// Names directories, so that `~name` will expand to the directory, with `hash -d name=path`.
+/// The named directories are listed when no names are given. Without `-d`, the executables of
+/// commands are cached instead.
+pub fn hash(vars: &mut Variables, cache: &mut CommandCache, args: &[&str]) -> i32 {
+    let stderr = io::stderr();
+    if args.get(1) != Some(&"-d") {
+        return hash_commands(cache, args);
+    }
+
+    if args.len() == 2 {
+        let mut names: Vec<_> = vars.named_dirs.iter().collect();
+        names.sort();
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        for (name, path) in names {
+            let _ = writeln!(stdout, "{}={}", name, path);
+        }
+        return SUCCESS;
+    }
+
+    for arg in &args[2..] {
+        match arg.find('=').map(|pos| (&arg[..pos], &arg[pos + 1..])) {
+            Some((name, path)) if Variables::is_valid_variable_name(name) && !path.is_empty() => {
+                vars.named_dirs.insert(name.into(), path.into());
+            },
+            Some((name, _)) if !Variables::is_valid_variable_name(name) => {
+                let _ = writeln!(&mut stderr.lock(), "ion: hash: directory name, '{}', is invalid", name);
+                return FAILURE;
+            },
+            _ => {
+                let _ = writeln!(&mut stderr.lock(), "ion: hash: please provide a path for '{}'", arg);
+                return FAILURE;
+            },
+        }
+    }
+    SUCCESS
+}
+
+/// Erases named directories which were created with `hash -d`, or without `-d`, forgets the
+/// executables of commands which were cached.
+pub fn unhash(vars: &mut Variables, cache: &mut CommandCache, args: &[&str]) -> i32 {
+    let stderr = io::stderr();
+    if args.len() < 2 || (args.len() == 2 && args[1] == "-d") {
+        let _ = writeln!(&mut stderr.lock(), "ion: unhash: usage: unhash [-d] name...");
+        return BAD_ARG;
+    }
+    if args[1] != "-d" {
+        for name in &args[1..] {
+            if !cache.remove(name) {
+                let _ = writeln!(&mut stderr.lock(), "ion: unhash: {}: not hashed", name);
+                return FAILURE;
+            }
+        }
+        return SUCCESS;
+    }
+
+    for name in &args[2..] {
+        if vars.named_dirs.remove(*name).is_none() {
+            let _ = writeln!(&mut stderr.lock(), "ion: unhash: undefined named directory: {}", name);
+            return FAILURE;
+        }
+    }
+    SUCCESS
+}
+
+/// Dropping an array will erase it from the shell, and from the environment if it was exported.
 pub fn drop_array<I: IntoIterator>(vars: &mut Variables, args: I) -> i32
     where I::Item: AsRef<str>
 {
@@ -175,7 +311,13 @@ pub fn drop_array<I: IntoIterator>(vars: &mut Variables, args: I) -> i32
     }
 
     for array in args.iter().skip(2) {
-        if vars.unset_array(array.as_ref()).is_none() {
+        if vars.is_readonly(array.as_ref()) {
+            let stderr = io::stderr();
+            let _ = writeln!(&mut stderr.lock(), "ion: cannot drop readonly array: {}", array.as_ref());
+            return FAILURE;
+        }
+        let exported = unexport(array.as_ref());
+        if vars.unset_array(array.as_ref()).is_none() && !exported {
             let stderr = io::stderr();
             let _ = writeln!(&mut stderr.lock(), "ion: undefined array: {}", array.as_ref());
             return FAILURE;
@@ -184,7 +326,7 @@ pub fn drop_array<I: IntoIterator>(vars: &mut Variables, args: I) -> i32
     SUCCESS
 }
 
-/// Dropping a variable will erase it from the shell.
+/// Dropping a variable will erase it from the shell, and from the environment if it was exported.
 pub fn drop_variable<I: IntoIterator>(vars: &mut Variables, args: I) -> i32
     where I::Item: AsRef<str>
 {
@@ -196,7 +338,13 @@ pub fn drop_variable<I: IntoIterator>(vars: &mut Variables, args: I) -> i32
     }
 
     for variable in args.iter().skip(1) {
-        if vars.unset_var(variable.as_ref()).is_none() {
+        if vars.is_readonly(variable.as_ref()) {
+            let stderr = io::stderr();
+            let _ = writeln!(&mut stderr.lock(), "ion: cannot drop readonly variable: {}", variable.as_ref());
+            return FAILURE;
+        }
+        let exported = unexport(variable.as_ref());
+        if vars.unset_var(variable.as_ref()).is_none() && !exported {
             let stderr = io::stderr();
             let _ = writeln!(&mut stderr.lock(), "ion: undefined variable: {}", variable.as_ref());
             return FAILURE;
@@ -206,6 +354,26 @@ pub fn drop_variable<I: IntoIterator>(vars: &mut Variables, args: I) -> i32
     SUCCESS
 }
 
+/// Marks the given variables as readonly, or lists all readonly variables if none are given.
+pub fn readonly<I: IntoIterator>(vars: &mut Variables, args: I) -> i32
+    where I::Item: AsRef<str>
+{
+    let args = args.into_iter().collect::<Vec<I::Item>>();
+    if args.len() <= 1 {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        for name in vars.readonly_names() {
+            let _ = writeln!(stdout, "{}", name);
+        }
+        return SUCCESS;
+    }
+
+    for name in args.iter().skip(1) {
+        vars.set_readonly(name.as_ref());
+    }
+    SUCCESS
+}
+
 
 #[cfg(test)]
 mod test {
@@ -269,6 +437,28 @@ mod test {
         assert_eq!(FAILURE, return_status);
     }
 
+    #[test]
+    fn drop_fails_with_readonly_variable() {
+        let mut variables = Variables::default();
+        variables.set_var("FOO", "BAR");
+        assert_eq!(SUCCESS, readonly(&mut variables, vec!["readonly", "FOO"]));
+        let return_status = drop_variable(&mut variables, vec!["drop", "FOO"]);
+        assert_eq!(FAILURE, return_status);
+        assert_eq!(Some("BAR".into()), variables.get_var("FOO"));
+    }
+
+    #[test]
+    fn unalias_removes_aliases() {
+        let mut variables = Variables::default();
+        assert_eq!(SUCCESS, alias(&mut variables, "ll=ls -l"));
+        assert_eq!(Some(&"ls -l".to_owned()), variables.aliases.get("ll"));
+        assert_eq!(SUCCESS, drop_alias(&mut variables, vec!["unalias", "ll"]));
+        assert_eq!(FAILURE, drop_alias(&mut variables, vec!["unalias", "ll"]));
+        assert_eq!(SUCCESS, alias(&mut variables, "la=ls -a"));
+        assert_eq!(SUCCESS, drop_alias(&mut variables, vec!["unalias", "-a"]));
+        assert!(variables.aliases.is_empty());
+    }
+
     #[test]
     fn drop_deletes_array() {
         let mut variables = Variables::default();
@@ -0,0 +1,122 @@
+//! The `type` and `which` builtins, which report how a command would be resolved: as an alias,
+//! a function, a builtin, or an executable, which is either cached by `hash` or found within the
+//! `PATH`, in that order.
+
+use shell::Shell;
+use shell::command_cache::is_executable;
+use shell::flags::EXPAND_ALIASES;
+use shell::status::*;
+use std::env;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use sys;
+
+/// One of the ways in which a command may be resolved.
+enum Resolution {
+    Alias(String),
+    Function,
+    Builtin,
+    /// An executable which was cached by the shell, which may not be within the `PATH`.
+    Hashed(PathBuf),
+    File(PathBuf),
+}
+
+/// Collects the ways in which the command may be resolved, in the order in which they are tried.
+/// Unless `all` is set, only the first of them is collected.
+fn resolve(shell: &Shell, name: &str, all: bool) -> Vec<Resolution> {
+    let mut resolutions = Vec::new();
+    macro_rules! found {
+        ($resolution:expr) => {{
+            resolutions.push($resolution);
+            if !all { return resolutions }
+        }}
+    }
+
+    if shell.flags & EXPAND_ALIASES != 0 {
+        if let Some(value) = shell.variables.aliases.get(name) {
+            found!(Resolution::Alias(value.clone()));
+        }
+    }
+    if shell.resolve_function(name).is_some() {
+        found!(Resolution::Function);
+    }
+    if shell.builtins.contains_key(name) {
+        found!(Resolution::Builtin);
+    }
+
+    if name.contains('/') {
+        if is_executable(Path::new(name)) {
+            resolutions.push(Resolution::File(name.into()));
+        }
+    } else {
+        let hashed = shell.command_cache.borrow_mut().cached(name);
+        if let Some(ref path) = hashed {
+            found!(Resolution::Hashed(path.clone()));
+        }
+        if let Ok(paths) = env::var("PATH") {
+            for directory in paths.split(sys::PATH_SEPARATOR).filter(|directory| !directory.is_empty()) {
+                let path = Path::new(directory).join(name);
+                if is_executable(&path) && hashed.as_ref() != Some(&path) {
+                    found!(Resolution::File(path));
+                }
+            }
+        }
+    }
+    resolutions
+}
+
+/// Parses the `-a` flag, which reports every resolution rather than only the first.
+fn parse_args<'a>(args: &[&'a str]) -> (bool, Vec<&'a str>) {
+    let all = args.get(1) == Some(&"-a");
+    let names = args.iter().skip(if all { 2 } else { 1 }).cloned().collect();
+    (all, names)
+}
+
+/// Describes how each command would be resolved, such as `ls is /bin/ls`.
+pub fn type_(args: &[&str], shell: &Shell) -> i32 {
+    let (all, names) = parse_args(args);
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut status = SUCCESS;
+    for name in names {
+        let resolutions = resolve(shell, name, all);
+        if resolutions.is_empty() {
+            eprintln!("ion: type: {}: not found", name);
+            status = FAILURE;
+        }
+        for resolution in resolutions {
+            let _ = match resolution {
+                Resolution::Alias(value) => writeln!(stdout, "{} is aliased to `{}`", name, value),
+                Resolution::Function => writeln!(stdout, "{} is a function", name),
+                Resolution::Builtin => writeln!(stdout, "{} is a shell builtin", name),
+                Resolution::Hashed(path) => writeln!(stdout, "{} is hashed ({})", name, path.display()),
+                Resolution::File(path) => writeln!(stdout, "{} is {}", name, path.display()),
+            };
+        }
+    }
+    status
+}
+
+/// Prints the path of the executable of each command, or what the command resolves to instead.
+pub fn which(args: &[&str], shell: &Shell) -> i32 {
+    let (all, names) = parse_args(args);
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut status = SUCCESS;
+    for name in names {
+        let resolutions = resolve(shell, name, all);
+        if resolutions.is_empty() {
+            eprintln!("ion: which: {}: not found", name);
+            status = FAILURE;
+        }
+        for resolution in resolutions {
+            let _ = match resolution {
+                Resolution::Alias(value) => writeln!(stdout, "{}: aliased to {}", name, value),
+                Resolution::Function => writeln!(stdout, "{}: function", name),
+                Resolution::Builtin => writeln!(stdout, "{}: shell builtin", name),
+                Resolution::Hashed(path) | Resolution::File(path) => writeln!(stdout, "{}", path.display()),
+            };
+        }
+    }
+    status
+}
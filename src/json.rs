@@ -0,0 +1,265 @@
+//! Converts the shell's nested values to and from JSON. JSON strings, numbers, booleans, and
+//! nulls are all represented as strings within the shell, where null becomes an empty string.
+
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::str::Chars;
+use types::{Array, BTreeMap, Data, HashMap, Key};
+
+/// Serializes a value as compact JSON text.
+pub fn to_json(data: &Data) -> String {
+    let mut output = String::new();
+    write_data(&mut output, data);
+    output
+}
+
+/// Serializes an array of strings as a JSON array.
+pub fn array_to_json(array: &Array) -> String {
+    to_json(&Data::Array(array.iter().cloned().map(Data::Str).collect()))
+}
+
+fn write_string(output: &mut String, string: &str) {
+    output.push('"');
+    for character in string.chars() {
+        match character {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(output, "\\u{:04x}", c as u32); },
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+}
+
+fn write_data(output: &mut String, data: &Data) {
+    match *data {
+        Data::Str(ref value) => write_string(output, value),
+        Data::Array(ref array) => {
+            output.push('[');
+            for (id, value) in array.iter().enumerate() {
+                if id != 0 { output.push(','); }
+                write_data(output, value);
+            }
+            output.push(']');
+        },
+        Data::HashMap(ref map) => {
+            // Keys are sorted so that the output is deterministic.
+            let mut keys: Vec<_> = map.keys().collect();
+            keys.sort();
+            output.push('{');
+            for (id, key) in keys.into_iter().enumerate() {
+                if id != 0 { output.push(','); }
+                write_string(output, key);
+                output.push(':');
+                write_data(output, &map[key]);
+            }
+            output.push('}');
+        },
+        Data::BTreeMap(ref map) => {
+            output.push('{');
+            for (id, (key, value)) in map.iter().enumerate() {
+                if id != 0 { output.push(','); }
+                write_string(output, key);
+                output.push(':');
+                write_data(output, value);
+            }
+            output.push('}');
+        },
+    }
+}
+
+/// Parses JSON text into a value, where objects become maps which are sorted by key if `sorted`
+/// is set.
+pub fn from_json(input: &str, sorted: bool) -> Result<Data, String> {
+    let mut parser = Parser { chars: input.chars().peekable(), sorted };
+    let data = parser.value()?;
+    parser.skip_whitespace();
+    match parser.chars.next() {
+        None => Ok(data),
+        Some(character) => Err(format!("unexpected character after JSON value: '{}'", character)),
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    sorted: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while let Some(&character) = self.chars.peek() {
+            if !character.is_whitespace() { break }
+            self.chars.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(character) if character == expected => Ok(()),
+            Some(character) => Err(format!("expected '{}' but found '{}'", expected, character)),
+            None => Err(format!("expected '{}' but found the end of the input", expected)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Data, String> {
+        self.skip_whitespace();
+        match self.chars.peek().cloned() {
+            Some('"') => self.string().map(Data::Str),
+            Some('[') => self.array(),
+            Some('{') => self.object(),
+            Some(character) if character == '-' || character.is_digit(10) => self.number(),
+            Some(character) if character.is_alphabetic() => self.literal(),
+            Some(character) => Err(format!("unexpected character in JSON: '{}'", character)),
+            None => Err("unexpected end of JSON input".into()),
+        }
+    }
+
+    fn literal(&mut self) -> Result<Data, String> {
+        let mut word = String::new();
+        while let Some(&character) = self.chars.peek() {
+            if !character.is_alphabetic() { break }
+            word.push(character);
+            self.chars.next();
+        }
+        match word.as_str() {
+            "true" | "false" => Ok(Data::Str(word)),
+            "null" => Ok(Data::Str(String::new())),
+            _ => Err(format!("invalid JSON literal: '{}'", word)),
+        }
+    }
+
+    fn number(&mut self) -> Result<Data, String> {
+        let mut number = String::new();
+        while let Some(&character) = self.chars.peek() {
+            match character {
+                '0'...'9' | '-' | '+' | '.' | 'e' | 'E' => number.push(character),
+                _ => break,
+            }
+            self.chars.next();
+        }
+        match number.parse::<f64>() {
+            Ok(_) => Ok(Data::Str(number)),
+            Err(_) => Err(format!("invalid JSON number: '{}'", number)),
+        }
+    }
+
+    fn hex_escape(&mut self) -> Result<u32, String> {
+        let digits: String = self.chars.by_ref().take(4).collect();
+        u32::from_str_radix(&digits, 16).map_err(|_| format!("invalid unicode escape: '{}'", digits))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => match self.chars.next() {
+                    Some('n') => string.push('\n'),
+                    Some('r') => string.push('\r'),
+                    Some('t') => string.push('\t'),
+                    Some('b') => string.push('\u{8}'),
+                    Some('f') => string.push('\u{c}'),
+                    Some('u') => {
+                        let mut code = self.hex_escape()?;
+                        // Characters outside of the basic multilingual plane are encoded as a
+                        // surrogate pair of escapes.
+                        if code >= 0xD800 && code < 0xDC00 {
+                            self.expect('\\')?;
+                            self.expect('u')?;
+                            let low = self.hex_escape()?;
+                            code = 0x10000 + ((code - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+                        }
+                        match ::std::char::from_u32(code) {
+                            Some(character) => string.push(character),
+                            None => return Err(format!("invalid unicode escape: '{:x}'", code)),
+                        }
+                    },
+                    Some(character) => string.push(character),
+                    None => return Err("unterminated JSON string".into()),
+                },
+                Some(character) => string.push(character),
+                None => return Err("unterminated JSON string".into()),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Data, String> {
+        self.expect('[')?;
+        let mut array = Vec::new();
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&']') {
+            self.chars.next();
+            return Ok(Data::Array(array));
+        }
+        loop {
+            array.push(self.value()?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(Data::Array(array)),
+                _ => return Err("expected ',' or ']' within JSON array".into()),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Data, String> {
+        self.expect('{')?;
+        let mut entries: Vec<(Key, Data)> = Vec::new();
+        self.skip_whitespace();
+        if self.chars.peek() != Some(&'}') {
+            loop {
+                self.skip_whitespace();
+                let key = self.string()?;
+                self.expect(':')?;
+                entries.push((key.into(), self.value()?));
+                self.skip_whitespace();
+                match self.chars.next() {
+                    Some(',') => continue,
+                    Some('}') => break,
+                    _ => return Err("expected ',' or '}' within JSON object".into()),
+                }
+            }
+        } else {
+            self.chars.next();
+        }
+
+        Ok(if self.sorted {
+            Data::BTreeMap(entries.into_iter().collect::<BTreeMap>())
+        } else {
+            Data::HashMap(entries.into_iter().collect::<HashMap>())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let input = r#"{"name":"web","ports":["80","443"],"db":{"host":"local\"host\"","port":"5432"}}"#;
+        let data = from_json(input, true).unwrap();
+        assert_eq!(Some(array!["80", "443"]), data.get("ports").map(Data::flatten));
+        assert_eq!(r#"{"db":{"host":"local\"host\"","port":"5432"},"name":"web","ports":["80","443"]}"#,
+            to_json(&data));
+    }
+
+    #[test]
+    fn literals_and_numbers() {
+        let data = from_json(" [ 1.5e3, -2, true, null, \"\\u00e9\\ud83d\\ude00\" ] ", false).unwrap();
+        assert_eq!(array!["1.5e3", "-2", "true", "", "é😀"], data.flatten());
+    }
+
+    #[test]
+    fn invalid_json() {
+        assert!(from_json("[1, 2", false).is_err());
+        assert!(from_json("{\"a\" 1}", false).is_err());
+        assert!(from_json("[1] 2", false).is_err());
+        assert!(from_json("nope", false).is_err());
+    }
+}
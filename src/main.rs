@@ -34,16 +34,11 @@ mod sys;
 mod builtins;
 mod shell;
 mod ascii_helpers;
+mod json;
 
 use builtins::Builtin;
 use shell::{Shell, Binary, signals};
-use std::sync::atomic::Ordering;
-
-extern "C" fn handler(signal: i32) {
-    if signal < 32 {
-        signals::PENDING.fetch_or(1 << signal, Ordering::SeqCst);
-    }
-}
+use shell::signals::handler;
 
 fn main() {
     let _ = sys::signal(sys::SIGHUP, handler);
@@ -1,3 +1,5 @@
+use super::closing_paren;
+
 const DOUBLE: u8 = 1;
 const SINGLE: u8 = 2;
 const BACK:   u8 = 4;
@@ -34,7 +36,7 @@ impl<'a> Iterator for ArgumentSplitter<'a> {
         let start = self.read;
 
         let (mut level, mut array_level, mut array_process_level) = (0, 0, 0);
-        for character in self.data.bytes().skip(self.read) {
+        while let Some(&character) = self.data.as_bytes().get(self.read) {
             match character {
                 _ if self.flags & BACK != 0 => self.flags ^= BACK,
                 b'\\'                       => self.flags ^= BACK,
@@ -54,7 +56,13 @@ impl<'a> Iterator for ArgumentSplitter<'a> {
                 b'['  if self.flags & SINGLE == 0 => array_level += 1,
                 b']'  if self.flags & SINGLE == 0 && array_level != 0 => array_level -= 1,
                 b']'  if self.flags & SINGLE == 0 => array_process_level -= 1,
-                b'('  if self.flags & SINGLE == 0 && self.flags & COMM_1 != 0 => level += 1,
+                // Subshells are skipped over in their entirety, as they may contain quotes of their own.
+                b'('  if self.flags & SINGLE == 0 && self.flags & (COMM_1 + COMM_2) != 0 => {
+                    match closing_paren(self.data.as_bytes(), self.read + 1) {
+                        Some(end) => self.read = end,
+                        None => level += 1,
+                    }
+                },
                 b'('  if self.flags & SINGLE == 0 && self.flags & (VARIAB + ARRAY) != 0 => {
                     self.flags |= METHOD;
                     self.flags &= 255 ^ (VARIAB + ARRAY);
@@ -107,6 +115,20 @@ mod tests {
         compare(input, expected);
     }
 
+    #[test]
+    fn nested_quotes() {
+        let input = "echo $(echo \")\") \"$(echo \"a b\")\" two";
+        let expected = vec!["echo", "$(echo \")\")", "\"$(echo \"a b\")\"", "two"];
+        compare(input, expected);
+    }
+
+    #[test]
+    fn arithmetic() {
+        let input = "echo $((1 + 2)) @((@a * 2)) @(echo one @(echo two)) three";
+        let expected = vec!["echo", "$((1 + 2))", "@((@a * 2))", "@(echo one @(echo two))", "three"];
+        compare(input, expected);
+    }
+
 
     #[test]
     fn arrays() {
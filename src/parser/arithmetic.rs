@@ -0,0 +1,227 @@
+use calc;
+use std::f64::consts;
+
+/// The math functions which may be called within an arithmetic expression.
+const FUNCTIONS: &[&str] = &[
+    "abs", "acos", "asin", "atan", "atan2", "cbrt", "ceil", "cos", "cosh", "exp", "floor", "hypot",
+    "ln", "log", "log2", "max", "min", "pow", "round", "sin", "sinh", "sqrt", "tan", "tanh", "trunc",
+];
+
+/// Evaluates an arithmetic expression, such as `1.5 * sin(3)`, returning the result as a string.
+///
+/// Math functions and constants within the expression are evaluated first, and their results
+/// substituted into the expression, before the expression is handed to the `calc` crate.
+pub fn eval(expression: &str) -> Result<String, String> {
+    let expression = expand_functions(expression)?;
+    calc::eval(&expression).map(|value| value.to_string()).map_err(|why| why.into())
+}
+
+/// Reduces an array of numbers to a single number with the given method, which may be one of
+/// `sum`, `product`, `min`, or `max`.
+pub fn reduce(method: &str, values: &[String]) -> Result<String, String> {
+    let mut numbers = Vec::with_capacity(values.len());
+    for value in values {
+        match value.parse::<f64>() {
+            Ok(number) => numbers.push(number),
+            Err(_) => return Err(format!("math error: '{}' is not a number", value)),
+        }
+    }
+
+    let result = match method {
+        "sum" => numbers.iter().sum::<f64>(),
+        "product" => numbers.iter().product::<f64>(),
+        _ if numbers.is_empty() => return Err(format!("math error: ${}() requires a value", method)),
+        "min" => numbers.iter().cloned().fold(numbers[0], f64::min),
+        "max" => numbers.iter().cloned().fold(numbers[0], f64::max),
+        _ => return Err(format!("math error: unknown reduction '{}'", method)),
+    };
+    Ok(f64::to_string(&result))
+}
+
+fn is_alphabetic(byte: u8) -> bool {
+    match byte {
+        b'a'...b'z' | b'A'...b'Z' => true,
+        _ => false,
+    }
+}
+
+fn is_identifier(byte: u8) -> bool {
+    match byte {
+        b'0'...b'9' | b'_' => true,
+        _ => is_alphabetic(byte),
+    }
+}
+
+fn constant(name: &str) -> Option<f64> {
+    match name {
+        "e" => Some(consts::E),
+        "pi" => Some(consts::PI),
+        "tau" => Some(2.0 * consts::PI),
+        _ => None,
+    }
+}
+
+/// Formats a number so that it may be substituted back into an expression.
+fn number(value: f64) -> Result<String, String> {
+    if !value.is_finite() {
+        Err("math error: result is not a finite number".into())
+    } else if value < 0.0 {
+        Ok(format!("(0 - {})", -value))
+    } else {
+        Ok(value.to_string())
+    }
+}
+
+fn apply(name: &str, args: &[f64]) -> Result<f64, String> {
+    let result = match (name, args.len()) {
+        ("abs", 1) => args[0].abs(),
+        ("acos", 1) => args[0].acos(),
+        ("asin", 1) => args[0].asin(),
+        ("atan", 1) => args[0].atan(),
+        ("atan2", 2) => args[0].atan2(args[1]),
+        ("cbrt", 1) => args[0].cbrt(),
+        ("ceil", 1) => args[0].ceil(),
+        ("cos", 1) => args[0].cos(),
+        ("cosh", 1) => args[0].cosh(),
+        ("exp", 1) => args[0].exp(),
+        ("floor", 1) => args[0].floor(),
+        ("hypot", 2) => args[0].hypot(args[1]),
+        ("ln", 1) => args[0].ln(),
+        ("log", 1) => args[0].log10(),
+        ("log", 2) => args[0].log(args[1]),
+        ("log2", 1) => args[0].log2(),
+        ("max", n) if n != 0 => args.iter().cloned().fold(args[0], f64::max),
+        ("min", n) if n != 0 => args.iter().cloned().fold(args[0], f64::min),
+        ("pow", 2) => args[0].powf(args[1]),
+        ("round", 1) => args[0].round(),
+        ("sin", 1) => args[0].sin(),
+        ("sinh", 1) => args[0].sinh(),
+        ("sqrt", 1) => args[0].sqrt(),
+        ("tan", 1) => args[0].tan(),
+        ("tanh", 1) => args[0].tanh(),
+        ("trunc", 1) => args[0].trunc(),
+        _ if FUNCTIONS.contains(&name) => {
+            return Err(format!("math error: invalid number of arguments supplied to '{}'", name));
+        },
+        _ => return Err(format!("math error: unknown function '{}'", name)),
+    };
+    Ok(result)
+}
+
+/// Splits the arguments of a function call on each comma that is not nested within parenthesis.
+fn split_arguments(input: &str) -> Vec<&str> {
+    if input.trim().is_empty() { return Vec::new(); }
+    let mut arguments = Vec::new();
+    let mut level = 0;
+    let mut start = 0;
+    for (id, byte) in input.bytes().enumerate() {
+        match byte {
+            b'(' => level += 1,
+            b')' => level -= 1,
+            b',' if level == 0 => {
+                arguments.push(&input[start..id]);
+                start = id + 1;
+            },
+            _ => (),
+        }
+    }
+    arguments.push(&input[start..]);
+    arguments
+}
+
+/// Replaces each function call and constant within the expression with its value.
+fn expand_functions(input: &str) -> Result<String, String> {
+    let bytes = input.as_bytes();
+    let mut output = String::with_capacity(input.len());
+    let mut start = 0;
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let word_start = is_alphabetic(bytes[index])
+            && (index == 0 || !is_identifier(bytes[index - 1]));
+        if !word_start {
+            index += 1;
+            continue;
+        }
+
+        let begin = index;
+        while index < bytes.len() && is_identifier(bytes[index]) { index += 1; }
+        let name = &input[begin..index];
+
+        let mut paren = index;
+        while paren < bytes.len() && bytes[paren] == b' ' { paren += 1; }
+        if paren < bytes.len() && bytes[paren] == b'(' {
+            let mut level = 0;
+            let end = match bytes[paren..].iter().position(|&byte| {
+                match byte {
+                    b'(' => level += 1,
+                    b')' => level -= 1,
+                    _ => (),
+                }
+                level == 0
+            }) {
+                Some(offset) => paren + offset,
+                None => return Err(format!("math error: unterminated call to '{}'", name)),
+            };
+
+            let mut args = Vec::new();
+            for argument in split_arguments(&input[paren + 1..end]) {
+                let value = eval(argument)?;
+                match value.parse::<f64>() {
+                    Ok(value) => args.push(value),
+                    Err(_) => return Err(format!("math error: '{}' is not a number", value)),
+                }
+            }
+
+            output.push_str(&input[start..begin]);
+            output.push_str(&number(apply(name, &args)?)?);
+            index = end + 1;
+            start = index;
+        } else if let Some(value) = constant(name) {
+            output.push_str(&input[start..begin]);
+            output.push_str(&number(value)?);
+            start = index;
+        }
+    }
+
+    output.push_str(&input[start..]);
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval_f64(expression: &str) -> Result<f64, String> {
+        eval(expression).map(|value| value.parse::<f64>().unwrap())
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(Ok(5.0), eval_f64("sqrt(16) + 1"));
+        assert_eq!(Ok(3.0), eval_f64("max(1, 2, 3)"));
+        assert_eq!(Ok(8.0), eval_f64("pow(2, abs(0 - 3))"));
+        assert_eq!(Ok(0.0), eval_f64("sin(0)"));
+        assert_eq!(Ok(2.0), eval_f64("floor(pi) - 1"));
+    }
+
+    #[test]
+    fn reductions() {
+        let values = ["3".to_owned(), "-1.5".to_owned(), "4".to_owned()];
+        assert_eq!(Ok("5.5".to_owned()), reduce("sum", &values));
+        assert_eq!(Ok("-18".to_owned()), reduce("product", &values));
+        assert_eq!(Ok("-1.5".to_owned()), reduce("min", &values));
+        assert_eq!(Ok("4".to_owned()), reduce("max", &values));
+        assert_eq!(Ok("0".to_owned()), reduce("sum", &[]));
+        assert!(reduce("max", &[]).is_err());
+        assert!(reduce("sum", &["one".to_owned()]).is_err());
+    }
+
+    #[test]
+    fn function_errors() {
+        assert!(eval("sqrt(1, 2)").is_err());
+        assert!(eval("foo(1)").is_err());
+        assert!(eval("sqrt(4").is_err());
+        assert!(eval("sqrt(0 - 1)").is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use shell::variables::Variables;
+use std::fmt;
 use types::{Identifier, Value as VString, Array, Key};
 
 #[derive(Debug, PartialEq, Clone)]
@@ -16,16 +17,141 @@ pub enum Binding {
     KeyValue(Identifier, VString),
     MapKeyValue(Identifier, Key, VString),
     Math(Identifier, Operator, VString),
-    MultipleKeys(Vec<Identifier>, VString)
+    MultipleKeys(Vec<Identifier>, VString),
+    Typed(Identifier, Primitive, VString),
+    Readonly(Box<Binding>),
+    /// A binding declared with `const`, which may not be reassigned within the function call.
+    Constant(Box<Binding>)
 }
 
-#[derive(Debug, PartialEq, Clone)]
+impl Binding {
+    /// The names of the variables which are assigned to by the binding.
+    pub fn keys(&self) -> Vec<&str> {
+        match *self {
+            Binding::ListEntries => Vec::new(),
+            Binding::InvalidKey(ref key)
+                | Binding::KeyOnly(ref key)
+                | Binding::KeyValue(ref key, _)
+                | Binding::MapKeyValue(ref key, _, _)
+                | Binding::Math(ref key, _, _)
+                | Binding::Typed(ref key, _, _) => vec![&**key],
+            Binding::MultipleKeys(ref keys, _) => keys.iter().map(|key| &**key).collect(),
+            Binding::Readonly(ref binding) | Binding::Constant(ref binding) => binding.keys(),
+        }
+    }
+
+    /// The unexpanded value which is assigned by the binding, if it has one.
+    pub fn value(&self) -> Option<&str> {
+        match *self {
+            Binding::KeyValue(_, ref value)
+                | Binding::MapKeyValue(_, _, ref value)
+                | Binding::Math(_, _, ref value)
+                | Binding::MultipleKeys(_, ref value)
+                | Binding::Typed(_, _, ref value) => Some(&**value),
+            Binding::Readonly(ref binding) | Binding::Constant(ref binding) => binding.value(),
+            _ => None,
+        }
+    }
+}
+
+/// A type which may be assigned to a variable with an annotation, as in `let map:hmap = [ ... ]`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Primitive {
+    Boolean,
+    Float,
+    Integer,
+    HashMap,
+    BTreeMap,
+}
+
+impl Primitive {
+    /// Determines whether the given string is a valid value of a scalar type. Maps have no
+    /// string representation, and are therefore never valid.
+    pub fn is_valid(self, value: &str) -> bool {
+        match self {
+            Primitive::Boolean => value == "true" || value == "false",
+            Primitive::Float => value.parse::<f64>().is_ok(),
+            Primitive::Integer => value.parse::<i64>().is_ok(),
+            Primitive::HashMap | Primitive::BTreeMap => false,
+        }
+    }
+
+    fn parse(data: &str) -> Option<Primitive> {
+        match data {
+            "bool" => Some(Primitive::Boolean),
+            "float" => Some(Primitive::Float),
+            "int" => Some(Primitive::Integer),
+            "hmap" | "hmap[]" => Some(Primitive::HashMap),
+            "bmap" | "bmap[]" => Some(Primitive::BTreeMap),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Primitive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let primitive = match *self {
+            Primitive::Boolean => "bool",
+            Primitive::Float => "float",
+            Primitive::Integer => "int",
+            Primitive::HashMap => "hmap",
+            Primitive::BTreeMap => "bmap",
+        };
+        write!(f, "{}", primitive)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Operator {
     Add,
     Subtract,
     Divide,
+    IntegerDivide,
     Multiply,
     Exponent,
+    Concatenate,
+    ConcatenateArray,
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operator = match *self {
+            Operator::Add      => "+",
+            Operator::Subtract => "-",
+            Operator::Divide   => "/",
+            Operator::IntegerDivide => "//",
+            Operator::Multiply => "*",
+            Operator::Exponent => "^",
+            Operator::Concatenate => "::",
+            Operator::ConcatenateArray => "++",
+        };
+        write!(f, "{}", operator)
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Binding::InvalidKey(ref key) | Binding::KeyOnly(ref key) => write!(f, "{}", &**key),
+            Binding::ListEntries => Ok(()),
+            Binding::KeyValue(ref key, ref value) => write!(f, "{} = {}", &**key, value),
+            Binding::MapKeyValue(ref key, ref inner_key, ref value) => {
+                write!(f, "{}['{}'] = {}", &**key, &**inner_key, value)
+            },
+            Binding::Math(ref key, ref operator, ref value) => {
+                write!(f, "{} {}= {}", &**key, operator, value)
+            },
+            Binding::MultipleKeys(ref keys, ref value) => {
+                let keys: Vec<&str> = keys.iter().map(|key| &**key).collect();
+                write!(f, "{} = {}", keys.join(" "), value)
+            },
+            Binding::Typed(ref key, primitive, ref value) => {
+                write!(f, "{}:{} = {}", &**key, primitive, value)
+            },
+            Binding::Readonly(ref binding) => write!(f, "-r {}", binding),
+            Binding::Constant(ref binding) => write!(f, "{}", binding)
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -36,6 +162,11 @@ enum Expression {
 
 /// Parses let bindings, `let VAR = KEY`, returning the result as a `(key, value)` tuple.
 pub fn parse_assignment(arguments: &str) -> Binding {
+    // Variables assigned with `let -r` may not be assigned to again.
+    if arguments.starts_with("-r ") {
+        return Binding::Readonly(Box::new(parse_assignment(arguments[3..].trim_left())));
+    }
+
     // Create a character iterator from the arguments.
     let mut char_iter = arguments.chars();
 
@@ -64,7 +195,13 @@ pub fn parse_assignment(arguments: &str) -> Binding {
                 key.clear();
             },
             '+' => {
-                match_operator!(Operator::Add);
+                if char_iter.as_str().starts_with("+=") {
+                    char_iter.nth(1);
+                    operator = Some(Operator::ConcatenateArray);
+                    found_key = true;
+                } else {
+                    match_operator!(Operator::Add);
+                }
                 break
             },
             '-' => {
@@ -76,7 +213,19 @@ pub fn parse_assignment(arguments: &str) -> Binding {
                 break
             },
             '/' => {
-                match_operator!(Operator::Divide);
+                if char_iter.as_str().starts_with("/=") {
+                    char_iter.nth(1);
+                    operator = Some(Operator::IntegerDivide);
+                    found_key = true;
+                } else {
+                    match_operator!(Operator::Divide);
+                }
+                break
+            },
+            ':' if char_iter.as_str().starts_with(":=") => {
+                char_iter.nth(1);
+                operator = Some(Operator::Concatenate);
+                found_key = true;
                 break
             },
             '^' => {
@@ -109,8 +258,18 @@ pub fn parse_assignment(arguments: &str) -> Binding {
         let value = char_iter.skip_while(|&x| x == ' ').collect::<VString>();
         if value.is_empty() {
             Binding::KeyOnly(key.into())
+        } else if let (Some(pos), None) = (key.find(':'), operator.as_ref()) {
+            match Primitive::parse(&key[pos+1..]) {
+                Some(primitive) if Variables::is_valid_variable_name(&key[..pos]) => {
+                    Binding::Typed(key[..pos].into(), primitive, value)
+                },
+                _ => Binding::InvalidKey(key.into())
+            }
         } else if let Some((key, inner_key)) = Variables::is_hashmap_reference(&key) {
             Binding::MapKeyValue(key.into(), inner_key.into(), value)
+        } else if key.starts_with('@') && operator.is_none() && Variables::is_valid_variable_name(&key[1..]) {
+            // An array sigil on the key, as in `let @b = @a`, assigns the value as an array.
+            Binding::KeyValue(key[1..].into(), ["[", &value, "]"].concat())
         } else if !Variables::is_valid_variable_name(&key) {
             Binding::InvalidKey(key.into())
         } else {
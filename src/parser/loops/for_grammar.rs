@@ -1,57 +1,63 @@
-use types::Value;
+use types::{Array, Value};
 use parser::{expand_string, Expander};
 
 #[derive(Debug, PartialEq)]
 pub enum ForExpression {
     Multiple(Vec<Value>),
     Normal(Value),
-    Range(usize, usize)
+    Range(usize, usize),
+    Map(Vec<(Value, Array)>)
 }
 
 impl ForExpression {
     pub fn new<E: Expander>(expression: &[String], expanders: &E) -> ForExpression {
+        if expression.len() == 1 && expression[0].starts_with('@') {
+            if let Some(entries) = expanders.map(&expression[0][1..]) {
+                return ForExpression::Map(entries);
+            }
+        }
+
         let output: Vec<_> = expression.iter()
             .flat_map(|expression| expand_string(expression, expanders, true))
             .collect();
 
         if output.len() == 1 {
             let output = output.into_iter().next().unwrap();
-            {
-                let mut bytes_iterator = output.bytes().enumerate();
-                while let Some((id, byte)) = bytes_iterator.next() {
-                    match byte {
-                        b'0'...b'9' => continue,
-                        b'.' => match output[0..id].parse::<usize>().ok() {
-                            Some(first_number) => {
-                                let mut dots = 1;
-                                for (_, byte) in bytes_iterator {
-                                    if byte == b'.' { dots += 1 } else { break }
-                                }
-
-                                match output[id+dots..].parse::<usize>().ok() {
-                                    Some(second_number) => {
-                                        match dots {
-                                            2 => return ForExpression::Range(first_number, second_number),
-                                            3 => return ForExpression::Range(first_number, second_number+1),
-                                            _ => break
-                                        }
-                                    },
-                                    None => break
-                                }
-                            },
-                            None => break
-                        },
-                        _ => break
-                    }
-                }
+            match parse_range(&output) {
+                Some((start, end)) => ForExpression::Range(start, end),
+                None => ForExpression::Normal(output)
             }
-            ForExpression::Normal(output)
         } else {
             ForExpression::Multiple(output)
         }
     }
 }
 
+/// Parses an exclusive (`start..end`) or inclusive (`start...end`) range from an expression whose
+/// variables have already been expanded, so that the endpoints may originate from variables,
+/// command substitutions, or arithmetic. Returns the exclusive bounds of the range.
+fn parse_range(expression: &str) -> Option<(usize, usize)> {
+    let pos = match expression.find("..") {
+        Some(pos) => pos,
+        None => return None
+    };
+    let (dots, inclusive) = if expression[pos + 2..].starts_with('.') { (3, true) } else { (2, false) };
+    let start = expression[..pos].trim();
+    let end = expression[pos + dots..].trim();
+    if !is_number(start) || !is_number(end) {
+        return None;
+    }
+
+    match (start.parse::<usize>(), end.parse::<usize>()) {
+        (Ok(start), Ok(end)) => Some((start, if inclusive { end + 1 } else { end })),
+        _ => None
+    }
+}
+
+fn is_number(text: &str) -> bool {
+    !text.is_empty() && text.bytes().all(|byte| byte >= b'0' && byte <= b'9')
+}
+
 mod tests {
     use super::*;
     use shell::variables::Variables;
@@ -62,6 +68,10 @@ mod tests {
         fn variable(&self, var: &str, _: bool) -> Option<Value> {
             self.0.get_var(var)
         }
+
+        fn map(&self, name: &str) -> Option<Vec<(Value, Array)>> {
+            self.0.get_map_entries(name)
+        }
     }
 
     #[test]
@@ -78,6 +88,19 @@ mod tests {
         assert_eq!(ForExpression::new(input, &VariableExpander(variables)), ForExpression::Range(1, 10));
     }
 
+    #[test]
+    fn for_variable_range() {
+        let mut variables = Variables::default();
+        variables.set_var("start", "2");
+        variables.set_var("end", "5");
+        let expander = VariableExpander(variables);
+        assert_eq!(ForExpression::new(&["$start..$end".to_owned()], &expander), ForExpression::Range(2, 5));
+        assert_eq!(ForExpression::new(&["$start...$((end + 1))".to_owned()], &expander),
+                   ForExpression::Range(2, 7));
+        assert_eq!(ForExpression::new(&["$start..$missing".to_owned()], &expander),
+                   ForExpression::Normal("2..".to_owned()));
+    }
+
     #[test]
     fn for_normal() {
         let variables = Variables::default();
@@ -92,5 +115,15 @@ mod tests {
         assert_eq!(ForExpression::new(&["$A".to_owned()], &VariableExpander(variables)),
                    ForExpression::Normal("1 2 3 4 5".to_owned()));
     }
-}
 
+    #[test]
+    fn for_map() {
+        use types::{BTreeMap, Data};
+        let mut variables = Variables::default();
+        let mut map = BTreeMap::new();
+        map.insert("key".into(), Data::Str("value".into()));
+        variables.set_btreemap("map", map);
+        assert_eq!(ForExpression::new(&["@map".to_owned()], &VariableExpander(variables)),
+                   ForExpression::Map(vec![("key".to_owned(), array!["value"])]));
+    }
+}
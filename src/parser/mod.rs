@@ -1,3 +1,4 @@
+pub mod arithmetic;
 mod arguments;
 pub mod assignments;
 mod loops;
@@ -6,7 +7,9 @@ pub mod shell_expand;
 mod statement;
 mod quotes;
 
-pub use self::shell_expand::{Select, Range, Index, Expander, expand_string, expand_tokens, WordToken, WordIterator};
+pub use self::shell_expand::{Select, Range, Index, Expander, GlobMode, GlobOptions, GlobSort, DEFAULT_GLOB_DEPTH,
+    expand_string, expand_tokens, closing_paren, find_unset_variable, WordToken,
+    WordIterator};
 pub use self::arguments::ArgumentSplitter;
 pub use self::loops::for_grammar::ForExpression;
 pub use self::statement::{StatementSplitter, StatementError, parse_and_validate};
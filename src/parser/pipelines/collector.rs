@@ -3,8 +3,10 @@
 use std::collections::HashSet;
 use std::iter::Peekable;
 
-use super::{Input, Pipeline, RedirectFrom, Redirection};
-use shell::{Job, JobKind};
+use super::{FdRedirection, Input, Pipeline, RedirectFrom, Redirection};
+use parser::closing_paren;
+use parser::quotes::heredoc_delimiter;
+use shell::{Job, JobKind, Resolution};
 use types::*;
 
 pub struct Collector<'a> {
@@ -28,8 +30,13 @@ impl<'a> Collector<'a> {
     fn single_quoted<I>(&self, bytes: &mut Peekable<I>, start: usize) -> Result<&'a str, &'static str>
         where I: Iterator<Item = (usize, u8)>
     {
+        // Quotes may be escaped within ANSI-C quoted strings, such as `$'it\'s'`.
+        let ansi = start != 0 && self.data.as_bytes()[start - 1] == b'$';
         while let Some(&(i, b)) = bytes.peek() {
             match b {
+                b'\\' if ansi => {
+                    bytes.next();
+                }
                 // We return an inclusive range to keep the quote type intact
                 b'\'' => {
                     bytes.next();
@@ -50,6 +57,16 @@ impl<'a> Collector<'a> {
                 b'\\' => {
                     bytes.next();
                 }
+                // Subshells within the quotes may contain quotes of their own.
+                b'$' | b'@' if self.peek(i + 1) == Some(b'(') => {
+                    if let Some(end) = closing_paren(self.data.as_bytes(), i + 2) {
+                        while let Some(&(j, _)) = bytes.peek() {
+                            if j > end { break }
+                            bytes.next();
+                        }
+                        continue;
+                    }
+                }
                 // We return an inclusive range to keep the quote type intact
                 b'"' => {
                     bytes.next();
@@ -62,6 +79,69 @@ impl<'a> Collector<'a> {
         Err("ion: syntax error: unterminated quote")
     }
 
+    /// Determines whether the digits at `start` are the file descriptor of a redirection, such as
+    /// the `3` of `3> file`, returning the descriptor and the position of the redirection operator.
+    fn redirected_fd(&self, start: usize) -> Option<(i32, usize)> {
+        let bytes = self.data.as_bytes();
+        let mut end = start;
+        while end < bytes.len() && bytes[end] >= b'0' && bytes[end] <= b'9' {
+            end += 1;
+        }
+        match bytes.get(end) {
+            Some(&b'>') | Some(&b'<') if bytes.get(end + 1) != Some(&b'(') => {
+                self.data[start..end].parse::<i32>().ok().map(|fd| (fd, end))
+            }
+            _ => None,
+        }
+    }
+
+    /// Parses the descriptor which follows a `>&` or `<&`, which `fd` will become a duplicate of,
+    /// or a `-` if `fd` is to be closed. The descriptor may be given by a variable.
+    fn fd_duplicate<I>(&self, fd: i32, bytes: &mut Peekable<I>) -> Result<FdRedirection, &'static str>
+        where I: Iterator<Item = (usize, u8)>
+    {
+        match self.arg(bytes)? {
+            Some("-") => Ok(FdRedirection::Close(fd)),
+            Some(source) if source.parse::<i32>().is_ok() || source.starts_with('$') || source.starts_with('@') => {
+                Ok(FdRedirection::Duplicate { fd, source: source.into() })
+            }
+            Some(_) => Err("expected file descriptor after '&' in redirection"),
+            None => Err("expected file descriptor after '&' in redirection"),
+        }
+    }
+
+    /// Parses the heredoc which begins after the `<<` at `start`, where the first line holds the
+    /// delimiter, and the lines which follow hold the body of the heredoc, up until the line that
+    /// consists of the delimiter. With `<<-`, the leading tabs of each line are removed, and if
+    /// the delimiter is quoted, the body is supplied as is, rather than being expanded.
+    fn heredoc(&self, start: usize) -> Result<Input, &'static str> {
+        let strip_tabs = self.peek(start) == Some(b'-');
+        let mut lines = self.data[start..].lines();
+        let (delimiter, quoted) = heredoc_delimiter(lines.next().unwrap_or(""));
+
+        if delimiter.is_empty() {
+            return Err("ion: syntax error: expected delimiter after '<<'");
+        }
+
+        let mut body = Vec::new();
+        let mut terminated = false;
+        for line in lines {
+            let line = if strip_tabs { line.trim_left_matches('\t') } else { line };
+            if line.trim() == delimiter {
+                terminated = true;
+                break;
+            }
+            body.push(line);
+        }
+
+        if !terminated {
+            return Err("ion: syntax error: unterminated heredoc");
+        }
+
+        let body = body.join("\n");
+        Ok(if quoted { Input::Literal(body) } else { Input::HereString(body) })
+    }
+
     fn arg<I>(&self, bytes: &mut Peekable<I>) -> Result<Option<&'a str>, &'static str>
         where I: Iterator<Item = (usize, u8)>
     {
@@ -132,6 +212,11 @@ impl<'a> Collector<'a> {
                         bytes.next();
                     }
                 }
+                // A process substitution, such as `<(sort file)`, is an argument rather than a
+                // redirection.
+                b'<' | b'>' if self.peek(i + 1) == Some(b'(') => {
+                    bytes.next();
+                }
                 // Evaluate a quoted string but do not return it
                 // We pass in i, the index of a quote, but start a character later. This ensures
                 // the production rules will produce strings with the quotes intact
@@ -183,19 +268,47 @@ impl<'a> Collector<'a> {
         }
     }
 
+    /// Determines whether the bracket at `index` is the name of the `[` command, or the `]` which
+    /// closes it, rather than a part of an array.
+    fn test_bracket(&self, index: usize, args: &Array) -> bool {
+        let standalone = self.peek(index + 1).map_or(true, |b| FOLLOW_ARGS.contains(&b));
+        standalone && match self.data.as_bytes()[index] {
+            b'[' => args.is_empty(),
+            _ => args.first().map_or(false, |command| command.as_str() == "["),
+        }
+    }
+
     pub fn parse(&self) -> Result<Pipeline, &'static str> {
         let mut bytes = self.data.bytes().enumerate().peekable();
         let mut args = Array::new();
         let mut jobs: Vec<Job> = Vec::new();
         let mut input: Option<Input> = None;
         let mut outfile: Option<Redirection> = None;
+        let mut errfile: Option<Redirection> = None;
+        let mut redirections: Vec<FdRedirection> = Vec::new();
+        let mut subshell = false;
 
         /// Attempt to create a new job given a list of collected arguments
         macro_rules! try_add_job {
             ($kind:expr) => {{
                 if ! args.is_empty() {
-                    jobs.push(Job::new(args.clone(), $kind));
+                    let mut job = Job::new(args.clone(), $kind);
+                    if subshell {
+                        if args.len() > 1 {
+                            return Err("ion: syntax error: unexpected argument after subshell");
+                        }
+                        job.resolution = Resolution::Subshell;
+                        subshell = false;
+                    }
+                    job.redirections = redirections.drain(..).collect();
+                    jobs.push(job);
                     args.clear();
+                } else if !redirections.is_empty() {
+                    // Redirections which follow the redirection of an output belong to the job
+                    // that was completed by that redirection.
+                    if let Some(job) = jobs.last_mut() {
+                        job.redirections.extend(redirections.drain(..));
+                    }
                 }
             }}
         }
@@ -211,12 +324,31 @@ impl<'a> Collector<'a> {
                 } else {
                     false
                 };
+                // A `>|` truncates the file even when the `noclobber` option is set.
+                let force = if let (false, Some(&(_, b'|'))) = (append, bytes.peek()) {
+                    bytes.next();
+                    true
+                } else {
+                    false
+                };
                 if let Some(file) = self.arg(&mut bytes)? {
-                    outfile = Some(Redirection {
+                    let redirection = Redirection {
                         from: $from,
                         file: file.into(),
-                        append
-                    });
+                        append,
+                        targets: Vec::new(),
+                        force,
+                    };
+                    // The standard error may be redirected separately from the standard output,
+                    // whereas redirecting both streams overrides any previous redirections.
+                    match $from {
+                        RedirectFrom::Stdout => outfile = Some(redirection),
+                        RedirectFrom::Stderr => errfile = Some(redirection),
+                        RedirectFrom::Both => {
+                            outfile = Some(redirection);
+                            errfile = None;
+                        }
+                    }
                 } else {
                     return Err("expected file argument after redirection for output");
                 }
@@ -274,6 +406,39 @@ impl<'a> Collector<'a> {
                         Some(_) | None => push_arg!(),
                     }
                 }
+                // A redirection of a file descriptor, such as `3> file`, `4< file`, or `2>&1`, when
+                // the descriptor is not part of another argument. `1>` and `2>` are alternatives
+                // to `>` and `^>`.
+                b'0'...b'9' if self.redirected_fd(i).is_some() => {
+                    let (fd, operator) = self.redirected_fd(i).unwrap();
+                    while let Some(&(j, _)) = bytes.peek() {
+                        if j > operator { break }
+                        bytes.next();
+                    }
+                    let output = self.data.as_bytes()[operator] == b'>';
+                    if self.peek(operator + 1) == Some(b'&') {
+                        bytes.next();
+                        redirections.push(self.fd_duplicate(fd, &mut bytes)?);
+                    } else if output && fd == 1 {
+                        try_redir_out!(RedirectFrom::Stdout);
+                    } else if output && fd == 2 {
+                        try_redir_out!(RedirectFrom::Stderr);
+                    } else {
+                        let append = output && self.peek(operator + 1) == Some(b'>');
+                        if append {
+                            bytes.next();
+                        }
+                        match self.arg(&mut bytes)? {
+                            Some(path) => redirections.push(FdRedirection::File {
+                                fd,
+                                path: path.into(),
+                                write: output,
+                                append,
+                            }),
+                            None => return Err("expected file argument after redirection"),
+                        }
+                    }
+                }
                 b'|' => {
                     bytes.next();
                     match bytes.peek() {
@@ -281,18 +446,32 @@ impl<'a> Collector<'a> {
                             bytes.next();
                             try_add_job!(JobKind::Or);
                         }
+                        // `|&` is an alternative to `&|`, piping both streams to the next job.
+                        Some(&(_, b'&')) => {
+                            bytes.next();
+                            try_add_job!(JobKind::Pipe(RedirectFrom::Both));
+                        }
                         Some(_) | None => {
                             try_add_job!(JobKind::Pipe(RedirectFrom::Stdout));
                         }
                     }
                 }
+                b'<' | b'>' if self.peek(i + 1) == Some(b'(') => push_arg!(),
                 b'>' => {
                     bytes.next();
-                    try_redir_out!(RedirectFrom::Stdout);
+                    if self.peek(i + 1) == Some(b'&') {
+                        bytes.next();
+                        redirections.push(self.fd_duplicate(1, &mut bytes)?);
+                    } else {
+                        try_redir_out!(RedirectFrom::Stdout);
+                    }
                 }
                 b'<' => {
                     bytes.next();
-                    if Some(b'<') == self.peek(i + 1) {
+                    if self.peek(i + 1) == Some(b'&') {
+                        bytes.next();
+                        redirections.push(self.fd_duplicate(0, &mut bytes)?);
+                    } else if Some(b'<') == self.peek(i + 1) {
                         if Some(b'<') == self.peek(i + 2) {
                             // If the next two characters are arrows, then interpret
                             // the next argument as a herestring
@@ -304,21 +483,11 @@ impl<'a> Collector<'a> {
                                 return Err("expected string argument after '<<<'");
                             }
                         } else {
-                            // Otherwise, what we have is not a herestring, but a heredoc.
+                            // Otherwise, what we have is not a herestring, but a heredoc, which
+                            // consumes the remainder of the statement.
                             bytes.next();
-                            // Collect the rest of the byte iterator and then trim the result
-                            // in order to get the EOF phrase that will be used to terminate
-                            // the heredoc.
-                            let heredoc = {
-                                let mut buffer = Vec::new();
-                                while let Some((_, byte)) = bytes.next() {
-                                    buffer.push(byte);
-                                }
-                                unsafe { String::from_utf8_unchecked(buffer) }
-                            };
-                            let heredoc = heredoc.lines().collect::<Vec<&str>>();
-                            // Then collect the heredoc from standard input.
-                            input = Some(Input::HereString(heredoc[1..heredoc.len() - 1].join("\n")));
+                            while bytes.next().is_some() {}
+                            input = Some(self.heredoc(i + 2)?);
                         }
                     } else if let Some(file) = self.arg(&mut bytes)? {
                         // Otherwise interpret it as stdin redirection
@@ -327,6 +496,25 @@ impl<'a> Collector<'a> {
                         return Err("expected file argument after redirection for input");
                     }
                 }
+                // A group of commands within parenthesis, such as `(cd dir; make)`, is a job which
+                // is executed within a subshell.
+                b'(' if args.is_empty() => {
+                    let end = match closing_paren(self.data.as_bytes(), i + 1) {
+                        Some(end) => end,
+                        None => return Err("ion: syntax error: unmatched left paren"),
+                    };
+                    while let Some(&(j, _)) = bytes.peek() {
+                        if j > end { break }
+                        bytes.next();
+                    }
+                    args.push(self.data[i..end + 1].into());
+                    subshell = true;
+                }
+                // The `[` builtin is a word of its own, as is the `]` which ends its expression.
+                b'[' | b']' if self.test_bracket(i, &args) => {
+                    bytes.next();
+                    args.push(self.data[i..i + 1].into());
+                }
                 // Skip over whitespace between jobs
                 b' ' | b'\t' => {
                     bytes.next();
@@ -336,20 +524,24 @@ impl<'a> Collector<'a> {
             }
         }
 
-        if !args.is_empty() {
-            jobs.push(Job::new(args, JobKind::Last));
+        try_add_job!(JobKind::Last);
+
+        // The redirections of descriptors are applied to a command, and would otherwise be lost.
+        if jobs.is_empty() && !redirections.is_empty() {
+            return Err("file descriptors may only be redirected for a command");
         }
 
-        Ok(Pipeline::new(jobs, input, outfile))
+        Ok(Pipeline { jobs, stdin: input, stdout: outfile, stderr: errfile })
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use parser::pipelines::{Input, Pipeline, RedirectFrom, Redirection};
+    use parser::pipelines::{FdRedirection, Input, Pipeline, RedirectFrom, Redirection};
     use parser::statement::parse;
-    use shell::{Job, JobKind};
+    use shell::{Job, JobKind, Resolution};
     use shell::flow_control::Statement;
+    use super::Collector;
     use types::Array;
 
     #[test]
@@ -364,6 +556,8 @@ mod tests {
                 from: RedirectFrom::Stderr,
                 file: "/dev/null".to_owned(),
                 append: false,
+                targets: Vec::new(),
+                force: false,
             };
 
             assert_eq!(Some(expected), pipeline.stdout);
@@ -709,6 +903,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn pipeline_with_forced_redirection() {
+        if let Statement::Pipeline(pipeline) = parse("echo hello >| out ^>| err") {
+            assert_eq!(1, pipeline.jobs.len());
+            assert!(pipeline.clone().stdout.unwrap().force);
+            assert!(pipeline.clone().stderr.unwrap().force);
+            assert_eq!("echo hello >| out ^>| err", pipeline.to_string());
+        } else {
+            assert!(false);
+        }
+
+        if let Statement::Pipeline(pipeline) = parse("echo hello > out | cat") {
+            assert_eq!(2, pipeline.jobs.len());
+            assert!(!pipeline.clone().stdout.unwrap().force);
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn pipeline_with_redirection_to_list() {
+        if let Statement::Pipeline(pipeline) = parse("echo hello > [one two -]") {
+            let stdout = pipeline.clone().stdout.unwrap();
+            assert_eq!("[one two -]", &stdout.file);
+            assert!(stdout.is_list());
+            assert_eq!("echo hello > [one two -]", pipeline.to_string());
+        } else {
+            assert!(false);
+        }
+    }
+
     #[test]
     fn pipeline_with_redirection_append_stderr() {
         let input = "cat | echo hello | cat < stuff ^>> other";
@@ -719,15 +944,81 @@ mod tests {
                 Job::new(array!["cat"], JobKind::Last),
             ],
             stdin: Some(Input::File("stuff".into())),
-            stdout: Some(Redirection {
+            stdout: None,
+            stderr: Some(Redirection {
                 from: RedirectFrom::Stderr,
                 file: "other".into(),
                 append: true,
+                targets: Vec::new(),
+                force: false,
             }),
         };
         assert_eq!(parse(input), Statement::Pipeline(expected));
     }
 
+    #[test]
+    fn pipeline_with_separate_redirections() {
+        let input = "make > out.log 2> err.log";
+        let expected = Pipeline {
+            jobs: vec![Job::new(array!["make"], JobKind::Last)],
+            stdin: None,
+            stdout: Some(Redirection { from: RedirectFrom::Stdout, file: "out.log".into(), append: false, targets: Vec::new(), force: false }),
+            stderr: Some(Redirection { from: RedirectFrom::Stderr, file: "err.log".into(), append: false, targets: Vec::new(), force: false }),
+        };
+        assert_eq!(parse(input), Statement::Pipeline(expected));
+
+        let input = "echo a2 2>> err.log &> all.log";
+        let expected = Pipeline {
+            jobs: vec![Job::new(array!["echo", "a2"], JobKind::Last)],
+            stdin: None,
+            stdout: Some(Redirection { from: RedirectFrom::Both, file: "all.log".into(), append: false, targets: Vec::new(), force: false }),
+            stderr: None,
+        };
+        assert_eq!(parse(input), Statement::Pipeline(expected));
+        assert_eq!("make > out ^> err", Collector::run("make > out 2> err").unwrap().to_string());
+    }
+
+    #[test]
+    fn pipeline_with_fd_redirections() {
+        let mut job = Job::new(array!["cmd"], JobKind::Last);
+        job.redirections = vec![
+            FdRedirection::File { fd: 3, path: "file".into(), write: true, append: false },
+            FdRedirection::File { fd: 4, path: "log".into(), write: true, append: true },
+            FdRedirection::File { fd: 5, path: "input".into(), write: false, append: false },
+        ];
+        let input = "cmd 3> file 4>> log 5< input";
+        assert_eq!(parse(input), Statement::Pipeline(Pipeline::new(vec![job], None, None)));
+        assert_eq!(input, Collector::run(input).unwrap().to_string());
+
+        let mut first = Job::new(array!["cmd"], JobKind::Pipe(RedirectFrom::Stdout));
+        first.redirections = vec![FdRedirection::Duplicate { fd: 2, source: "1".into() }];
+        let expected = Pipeline::new(vec![first, Job::new(array!["less"], JobKind::Last)], None, None);
+        assert_eq!(parse("cmd 2>&1 | less"), Statement::Pipeline(expected));
+
+        let mut job = Job::new(array!["cmd"], JobKind::Last);
+        job.redirections = vec![FdRedirection::Duplicate { fd: 2, source: "1".into() }];
+        let expected = Pipeline {
+            jobs: vec![job],
+            stdin: None,
+            stdout: Some(Redirection { from: RedirectFrom::Stdout, file: "out".into(), append: false, targets: Vec::new(), force: false }),
+            stderr: None,
+        };
+        assert_eq!(parse("cmd > out 2>&1"), Statement::Pipeline(expected));
+
+        let mut job = Job::new(array!["cmd", "a3"], JobKind::Last);
+        job.redirections = vec![
+            FdRedirection::Duplicate { fd: 0, source: "4".into() },
+            FdRedirection::Close(3),
+            FdRedirection::Duplicate { fd: 1, source: "2".into() },
+        ];
+        let input = "cmd a3 <&4 3>&- >&2";
+        assert_eq!(parse(input), Statement::Pipeline(Pipeline::new(vec![job], None, None)));
+        assert_eq!("cmd a3 0>&4 3>&- 1>&2", Collector::run(input).unwrap().to_string());
+
+        assert_eq!("cmd 1>&@db[1]", Collector::run("cmd >&@db[1]").unwrap().to_string());
+        assert!(Collector::run("cmd 2>&x").is_err());
+    }
+
     #[test]
     fn pipeline_with_redirection_append_both() {
         let input = "cat | echo hello | cat < stuff &>> other";
@@ -742,11 +1033,31 @@ mod tests {
                 from: RedirectFrom::Both,
                 file: "other".into(),
                 append: true,
+                targets: Vec::new(),
+                force: false,
             }),
+            stderr: None,
         };
         assert_eq!(parse(input), Statement::Pipeline(expected));
     }
 
+    #[test]
+    fn pipeline_with_stream_pipes() {
+        let input = "make ^| grep error |& tee log";
+        let expected = Pipeline {
+            jobs: vec![
+                Job::new(array!["make"], JobKind::Pipe(RedirectFrom::Stderr)),
+                Job::new(array!["grep", "error"], JobKind::Pipe(RedirectFrom::Both)),
+                Job::new(array!["tee", "log"], JobKind::Last),
+            ],
+            stdin: None,
+            stdout: None,
+            stderr: None,
+        };
+        assert_eq!(parse(input), Statement::Pipeline(expected));
+        assert_eq!("make ^| grep error &| tee log", Collector::run(input).unwrap().to_string());
+    }
+
     #[test]
     fn pipeline_with_redirection_reverse_order() {
         if let Statement::Pipeline(pipeline) = parse("cat | echo hello | cat > stuff < other") {
@@ -787,6 +1098,77 @@ mod tests {
             jobs: vec![Job::new(array!["calc"], JobKind::Last)],
             stdin: Some(Input::HereString("$(cat math.txt)".into())),
             stdout: None,
+            stderr: None,
+        };
+        assert_eq!(Statement::Pipeline(expected), parse(input));
+    }
+
+    #[test]
+    fn nested_quotes_in_processes() {
+        let input = "echo \"$(echo \"a b\")\" $(echo \")\") | cat";
+        if let Statement::Pipeline(pipeline) = parse(input) {
+            assert_eq!(array!["echo", "\"$(echo \"a b\")\"", "$(echo \")\")"], pipeline.jobs[0].args);
+            assert_eq!(2, pipeline.jobs.len());
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn ansi_c_quotes() {
+        if let Statement::Pipeline(pipeline) = parse("echo $'it\\'s | here' 'a\\' | cat") {
+            assert_eq!(array!["echo", "$'it\\'s | here'", "'a\\'"], pipeline.jobs[0].args);
+            assert_eq!(2, pipeline.jobs.len());
+        } else {
+            assert!(false);
+        }
+    }
+
+    #[test]
+    fn subshells() {
+        if let Statement::Pipeline(pipeline) = parse("(cd src; echo \")\" | cat) > file") {
+            assert_eq!(1, pipeline.jobs.len());
+            assert_eq!(array!["(cd src; echo \")\" | cat)"], pipeline.jobs[0].args);
+            assert_eq!(Resolution::Subshell, pipeline.jobs[0].resolution);
+            assert_eq!("file", pipeline.stdout.unwrap().file);
+        } else {
+            assert!(false);
+        }
+
+        if let Statement::Pipeline(pipeline) = parse("true && (exit 1)") {
+            assert_eq!(Resolution::Any, pipeline.jobs[0].resolution);
+            assert_eq!(JobKind::And, pipeline.jobs[0].kind);
+            assert_eq!(array!["(exit 1)"], pipeline.jobs[1].args);
+            assert_eq!(Resolution::Subshell, pipeline.jobs[1].resolution);
+        } else {
+            assert!(false);
+        }
+
+        assert!(Collector::run("(echo) arg").is_err());
+        assert!(Collector::run("(echo").is_err());
+    }
+
+    #[test]
+    fn test_brackets() {
+        if let Statement::Pipeline(pipeline) = parse("[ -d src ] && echo [ 1 2 ][0]") {
+            assert_eq!(array!["[", "-d", "src", "]"], pipeline.jobs[0].args);
+            assert_eq!(array!["echo", "[ 1 2 ][0]"], pipeline.jobs[1].args);
+        } else {
+            assert!(false);
+        }
+
+        assert!(Collector::run("echo ]").is_err());
+        assert!(Collector::run("echo [ a").is_err());
+    }
+
+    #[test]
+    fn process_substitution() {
+        let input = "diff <(sort a) --new=<(sort b) > >(cat -n) < <(echo)";
+        let expected = Pipeline {
+            jobs: vec![Job::new(array!["diff", "<(sort a)", "--new=<(sort b)"], JobKind::Last)],
+            stdin: Some(Input::File("<(echo)".into())),
+            stdout: Some(Redirection { from: RedirectFrom::Stdout, file: ">(cat -n)".into(), append: false, targets: Vec::new(), force: false }),
+            stderr: None,
         };
         assert_eq!(Statement::Pipeline(expected), parse(input));
     }
@@ -798,8 +1180,34 @@ mod tests {
             jobs: vec![Job::new(array!["calc"], JobKind::Last)],
             stdin: Some(Input::HereString("1 + 2\n3 + 4".into())),
             stdout: None,
+            stderr: None,
+        };
+        assert_eq!(Statement::Pipeline(expected), parse(input));
+    }
+
+    #[test]
+    fn heredoc_modes() {
+        let input = "cat <<- 'EOF'\n\t$HOME\n\t\tindented\n\tEOF";
+        let expected = Pipeline {
+            jobs: vec![Job::new(array!["cat"], JobKind::Last)],
+            stdin: Some(Input::Literal("$HOME\nindented".into())),
+            stdout: None,
+            stderr: None,
+        };
+        assert_eq!(Statement::Pipeline(expected), parse(input));
+
+        let input = "cat <<\"END\"\nEOF\nEND";
+        let expected = Pipeline {
+            jobs: vec![Job::new(array!["cat"], JobKind::Last)],
+            stdin: Some(Input::Literal("EOF".into())),
+            stdout: None,
+            stderr: None,
         };
         assert_eq!(Statement::Pipeline(expected), parse(input));
+
+        assert_eq!(Err("ion: syntax error: unterminated heredoc"), Collector::run("cat << EOF\none"));
+        assert_eq!(Err("ion: syntax error: expected delimiter after '<<'"), Collector::run("cat <<"));
+        assert_eq!(Err("file descriptors may only be redirected for a command"), Collector::run("3> file"));
     }
 
     #[test]
@@ -815,7 +1223,10 @@ mod tests {
                 from: RedirectFrom::Stdout,
                 file: "out.log".into(),
                 append: false,
+                targets: Vec::new(),
+                force: false,
             }),
+            stderr: None,
         };
         assert_eq!(Statement::Pipeline(expected), parse(input));
     }
@@ -844,7 +1255,10 @@ mod tests {
                 from: RedirectFrom::Stdout,
                 file: "foo\\'bar".into(),
                 append: true,
+                targets: Vec::new(),
+                force: false,
             }),
+            stderr: None,
         };
         assert_eq!(parse(input), Statement::Pipeline(expected));
 
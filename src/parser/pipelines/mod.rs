@@ -18,6 +18,44 @@ pub struct Redirection {
     pub from: RedirectFrom,
     pub file: String,
     pub append: bool,
+    /// The files that the output is written to when the target is a list, such as `[a b -]`,
+    /// which are collected when the pipeline is expanded.
+    pub targets: Vec<String>,
+    /// Whether an existing file may be truncated even when the `noclobber` option is set, as
+    /// with `>|`.
+    pub force: bool,
+}
+
+impl Redirection {
+    /// Whether the output is written to each file of a list, rather than to a single file.
+    pub fn is_list(&self) -> bool { self.file.starts_with('[') && self.file.ends_with(']') }
+}
+
+/// A redirection of an arbitrary file descriptor of a job, such as `3> file`, `2>&1`, or `<&4`,
+/// which is applied after the standard input and outputs of the job have been set up.
+#[derive(Debug, PartialEq, Clone)]
+pub enum FdRedirection {
+    /// Opens a file onto the descriptor, for reading with `N<`, writing with `N>`, or appending
+    /// with `N>>`.
+    File { fd: i32, path: String, write: bool, append: bool },
+    /// Makes the descriptor a duplicate of another descriptor, as in `N>&M` or `N<&M`, where
+    /// the source may also be a variable which is expanded to a descriptor, as in `>&$fd`.
+    Duplicate { fd: i32, source: String },
+    /// Closes the descriptor, as in `N>&-`.
+    Close(i32),
+}
+
+impl fmt::Display for FdRedirection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FdRedirection::File { fd, ref path, write, append } => {
+                let operator = if append { ">>" } else if write { ">" } else { "<" };
+                write!(f, "{}{} {}", fd, operator, path)
+            }
+            FdRedirection::Duplicate { fd, ref source } => write!(f, "{}>&{}", fd, source),
+            FdRedirection::Close(fd) => write!(f, "{}>&-", fd),
+        }
+    }
 }
 
 /// Represents input that a process could initially receive from `stdin`
@@ -25,15 +63,20 @@ pub struct Redirection {
 pub enum Input {
     /// A file; the contents of said file will be written to the `stdin` of a process
     File(String),
-    /// A string literal that is written to the `stdin` of a process.
-    /// If there is a second string, that second string is the EOF phrase for the heredoc.
+    /// A string literal that is written to the `stdin` of a process, after being expanded.
     HereString(String),
+    /// Text that is written to the `stdin` of a process as is, from a heredoc whose delimiter
+    /// was quoted.
+    Literal(String),
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Pipeline {
     pub jobs: Vec<Job>,
     pub stdout: Option<Redirection>,
+    /// A redirection of the standard error alone, which is applied after `stdout`, so that the
+    /// two streams may be sent to separate files.
+    pub stderr: Option<Redirection>,
     pub stdin: Option<Input>,
 }
 
@@ -43,6 +86,7 @@ impl Pipeline {
             jobs,
             stdin,
             stdout,
+            stderr: None,
         }
     }
 
@@ -56,20 +100,50 @@ impl Pipeline {
                 Some(Input::File(expand_string(s, expanders, false).join(" "))),
             Some(Input::HereString(ref s)) =>
                 Some(Input::HereString(expand_string(s, expanders, true).join(" "))),
+            Some(Input::Literal(ref s)) => Some(Input::Literal(s.clone())),
             None => None,
         };
 
         self.stdin = stdin;
 
-        if let Some(stdout) = self.stdout.iter_mut().next() {
-            stdout.file = expand_string(stdout.file.as_str(), expanders, false).join(" ");
+        for redirection in self.stdout.iter_mut().chain(self.stderr.iter_mut()) {
+            if redirection.is_list() {
+                redirection.targets = expand_string(redirection.file.as_str(), expanders, false).into_iter().collect();
+            } else {
+                redirection.file = expand_string(redirection.file.as_str(), expanders, false).join(" ");
+            }
         }
     }
 
+    /// The words of the pipeline's redirections which are expanded along with its arguments,
+    /// such as the file of `> $file`.
+    pub fn redirection_words(&self) -> Vec<&str> {
+        let mut words = Vec::new();
+        match self.stdin {
+            Some(Input::File(ref word)) | Some(Input::HereString(ref word)) => words.push(word.as_str()),
+            _ => (),
+        }
+        for redirection in self.stdout.iter().chain(self.stderr.iter()) {
+            words.push(redirection.file.as_str());
+        }
+        for job in &self.jobs {
+            for redirection in &job.redirections {
+                match *redirection {
+                    FdRedirection::File { path: ref word, .. }
+                        | FdRedirection::Duplicate { source: ref word, .. } => words.push(word.as_str()),
+                    FdRedirection::Close(_) => (),
+                }
+            }
+        }
+        words
+    }
+
     pub fn requires_piping(&self) -> bool {
         self.jobs.len() > 1 ||
             self.stdin != None ||
             self.stdout != None ||
+            self.stderr != None ||
+            self.jobs.iter().any(|job| !job.redirections.is_empty()) ||
             self.jobs.last().unwrap().kind == JobKind::Background
     }
 }
@@ -79,6 +153,7 @@ impl fmt::Display for Pipeline {
         let mut tokens: Vec<String> = Vec::with_capacity(self.jobs.len());
         for job in &self.jobs {
             tokens.extend(job.args.clone().into_iter());
+            tokens.extend(job.redirections.iter().map(|redirection| redirection.to_string()));
             match job.kind {
                 JobKind::Last => (),
                 JobKind::And => tokens.push("&&".into()),
@@ -99,19 +174,19 @@ impl fmt::Display for Pipeline {
                 tokens.push("<<<".into());
                 tokens.push(string.clone());
             }
-        }
-        if let Some(ref outfile) = self.stdout {
-            match outfile.from {
-                RedirectFrom::Stdout => {
-                    tokens.push((if outfile.append { ">>" } else { ">" }).into());
-                }
-                RedirectFrom::Stderr => {
-                    tokens.push((if outfile.append { "^>>" } else { "^>" }).into());
-                }
-                RedirectFrom::Both => {
-                    tokens.push((if outfile.append { "&>>" } else { "&>" }).into());
-                }
+            Some(Input::Literal(ref string)) => {
+                tokens.push("<<<".into());
+                tokens.push(["'", string, "'"].concat());
             }
+        }
+        for outfile in self.stdout.iter().chain(self.stderr.iter()) {
+            let operator = match outfile.from {
+                RedirectFrom::Stdout => ">",
+                RedirectFrom::Stderr => "^>",
+                RedirectFrom::Both => "&>",
+            };
+            let suffix = if outfile.append { ">" } else if outfile.force { "|" } else { "" };
+            tokens.push([operator, suffix].concat());
             tokens.push(outfile.file.clone());
         }
 
@@ -7,6 +7,21 @@ bitflags! {
     }
 }
 
+/// Obtains the delimiter of a heredoc from the remainder of the line which follows the `<<`,
+/// along with whether the delimiter was quoted, which disables expansions within the heredoc.
+/// The `-` of a `<<-` heredoc, which strips leading tabs, may be included.
+pub fn heredoc_delimiter(header: &str) -> (&str, bool) {
+    let header = header.trim();
+    let header = if header.starts_with('-') { header[1..].trim_left() } else { header };
+    let bytes = header.as_bytes();
+    match bytes.first() {
+        Some(&quote) if (quote == b'\'' || quote == b'"') && bytes.len() > 1 && bytes[bytes.len() - 1] == quote => {
+            (&header[1..header.len() - 1], true)
+        }
+        _ => (header, false),
+    }
+}
+
 pub struct QuoteTerminator {
     buffer: String,
     eof:    Option<String>,
@@ -54,7 +69,7 @@ impl QuoteTerminator {
                                     if Some(&b'<') != as_bytes.get(self.read) {
                                         use std::str;
                                         let eof_phrase = unsafe { str::from_utf8_unchecked(&as_bytes[self.read..]) };
-                                        self.eof = Some(eof_phrase.trim().to_owned());
+                                        self.eof = Some(heredoc_delimiter(eof_phrase).0.to_owned());
                                         eof_found = true;
                                         break
                                     }
@@ -0,0 +1,531 @@
+use glob::glob;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use types::Array;
+
+/// The number of directories which `**` may descend into when `GLOB_DEPTH` is not set.
+pub const DEFAULT_GLOB_DEPTH: usize = 64;
+
+/// Determines what a glob pattern which does not match any paths expands to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GlobMode {
+    /// The pattern is passed on as is.
+    Literal,
+    /// The pattern expands to nothing.
+    Null,
+    /// The pattern is an error, which fails the statement.
+    Fail,
+}
+
+/// Determines the order in which the paths matched by a glob pattern are expanded, which is
+/// selected with the `GLOB_SORT` variable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GlobSort {
+    /// Sorted by name, which is the default.
+    Name,
+    /// The most recently modified paths first.
+    Modified,
+    /// The largest paths first.
+    Size,
+    /// Sorted by name, where runs of digits are compared by their numeric value.
+    Natural,
+}
+
+impl GlobSort {
+    pub fn from_name(name: &str) -> Option<GlobSort> {
+        match name {
+            "name" => Some(GlobSort::Name),
+            "mtime" => Some(GlobSort::Modified),
+            "size" => Some(GlobSort::Size),
+            "natural" => Some(GlobSort::Natural),
+            _ => None,
+        }
+    }
+}
+
+/// Options which control how glob patterns are matched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlobOptions {
+    /// The number of directories which a `**` component may descend into.
+    pub depth: usize,
+    /// Enables the extended matchers, such as `!(pattern)`, and `[^...]` negated classes.
+    pub extended: bool,
+    /// What a pattern which does not match any paths expands to.
+    pub mode: GlobMode,
+    /// The order of the matched paths.
+    pub sort: GlobSort,
+}
+
+impl Default for GlobOptions {
+    fn default() -> GlobOptions {
+        GlobOptions { depth: DEFAULT_GLOB_DEPTH, extended: false, mode: GlobMode::Literal, sort: GlobSort::Name }
+    }
+}
+
+/// Pushes each path which matches the glob pattern. A `**` component within the pattern matches
+/// any number of directories, up to `depth` levels below the directory that it appears in.
+///
+/// If no paths match, the pattern itself is pushed, or nothing is pushed if the mode is
+/// `GlobMode::Null`, and the pattern is returned as an error if the mode is `GlobMode::Fail`.
+pub fn expand_glob(pattern: String, options: GlobOptions, words: &mut Array) -> Result<(), String> {
+    let mut matches = glob_paths(&pattern, options);
+    if !matches.is_empty() {
+        sort_paths(&mut matches, options.sort);
+        words.extend(matches);
+        return Ok(());
+    }
+
+    match options.mode {
+        GlobMode::Literal => words.push(pattern),
+        GlobMode::Null => (),
+        GlobMode::Fail => return Err(pattern),
+    }
+    Ok(())
+}
+
+/// Obtains the sorted list of paths which match the pattern.
+fn glob_paths(pattern: &str, options: GlobOptions) -> Vec<String> {
+    let components: Vec<&str> = pattern.split('/').collect();
+    let recursive = components.iter().any(|&component| component == "**");
+    let extended = options.extended && components.iter().any(|component| is_extended(component));
+
+    // Patterns which only use the standard matchers are handled by the `glob` crate.
+    if !recursive && !extended {
+        return match glob(pattern) {
+            Ok(paths) => paths.filter_map(Result::ok)
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+    }
+
+    let mut matches = Vec::new();
+    if pattern.starts_with('/') {
+        match_components(PathBuf::from("/"), &components[1..], options, &mut matches);
+    } else {
+        match_components(PathBuf::new(), &components, options, &mut matches);
+    }
+    matches.sort();
+    matches.dedup();
+    matches
+}
+
+/// Reorders paths which are sorted by name according to the given method. Paths which are equal
+/// according to the method remain sorted by name.
+fn sort_paths(paths: &mut Vec<String>, sort: GlobSort) {
+    match sort {
+        GlobSort::Name => (),
+        GlobSort::Modified => {
+            let mut keyed: Vec<_> = paths.drain(..).map(|path| {
+                let modified = fs::metadata(&path).and_then(|metadata| metadata.modified())
+                    .ok()
+                    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                    .unwrap_or_default();
+                (modified, path)
+            }).collect();
+            keyed.sort_by(|a, b| b.0.cmp(&a.0));
+            paths.extend(keyed.into_iter().map(|(_, path)| path));
+        },
+        GlobSort::Size => {
+            let mut keyed: Vec<_> = paths.drain(..).map(|path| {
+                (fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or(0), path)
+            }).collect();
+            keyed.sort_by(|a, b| b.0.cmp(&a.0));
+            paths.extend(keyed.into_iter().map(|(_, path)| path));
+        },
+        GlobSort::Natural => paths.sort_by(|a, b| natural_cmp(a, b)),
+    }
+}
+
+/// Compares two strings, where runs of digits are compared by their numeric value, so that
+/// `file2` is ordered before `file10`.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if is_digit(a[i]) && is_digit(b[j]) {
+            let (start_a, start_b) = (i, j);
+            while i < a.len() && is_digit(a[i]) { i += 1; }
+            while j < b.len() && is_digit(b[j]) { j += 1; }
+            // Leading zeros are ignored, so that the longer run of digits is the larger number.
+            let digits_a = trim_zeros(&a[start_a..i]);
+            let digits_b = trim_zeros(&b[start_b..j]);
+            let ordering = digits_a.len().cmp(&digits_b.len()).then_with(|| digits_a.cmp(digits_b));
+            if ordering != Ordering::Equal { return ordering }
+        } else {
+            if a[i] != b[j] { return a[i].cmp(&b[j]) }
+            i += 1;
+            j += 1;
+        }
+    }
+    (a.len() - i).cmp(&(b.len() - j)).then_with(|| a.cmp(b))
+}
+
+fn is_digit(byte: u8) -> bool { byte >= b'0' && byte <= b'9' }
+
+fn trim_zeros(digits: &[u8]) -> &[u8] {
+    let zeros = digits.iter().take_while(|&&digit| digit == b'0').count();
+    &digits[zeros..]
+}
+
+/// Determines whether a component of a pattern makes use of an extended matcher.
+fn is_extended(component: &str) -> bool {
+    ["!(", "+(", "?(", "*(", "[^"].iter().any(|matcher| component.contains(matcher))
+}
+
+/// Matches each component of a pattern against the entries of the directory, in turn.
+fn match_components(directory: PathBuf, components: &[&str], options: GlobOptions, matches: &mut Vec<String>) {
+    let (component, rest) = match components.split_first() {
+        Some((&component, rest)) => (component, rest),
+        None => {
+            matches.push(directory.to_string_lossy().into_owned());
+            return
+        }
+    };
+
+    if component == "**" {
+        // A trailing `**` matches everything beneath each directory.
+        let rest: &[&str] = if rest.is_empty() { &["*"] } else { rest };
+        for directory in walk_directories(directory, options.depth) {
+            match_components(directory, rest, options, matches);
+        }
+        return
+    }
+
+    let wildcards = component.contains(|c: char| c == '*' || c == '?' || c == '[')
+        || (options.extended && is_extended(component));
+    if !wildcards {
+        let path = directory.join(component);
+        if (rest.is_empty() && path.exists()) || path.is_dir() {
+            match_components(path, rest, options, matches);
+        }
+        return
+    }
+
+    let matcher = match compile(component, options.extended) {
+        Some(matcher) => matcher,
+        None => return,
+    };
+
+    let path = if directory.as_os_str().is_empty() { Path::new(".") } else { directory.as_path() };
+    if let Ok(entries) = fs::read_dir(path) {
+        let mut entries: Vec<PathBuf> = entries.filter_map(Result::ok)
+            .filter(|entry| {
+                let name: Vec<char> = entry.file_name().to_string_lossy().chars().collect();
+                matches_tokens(&matcher, &name)
+            })
+            .map(|entry| directory.join(entry.file_name()))
+            .filter(|path| rest.is_empty() || path.is_dir())
+            .collect();
+        entries.sort();
+        for entry in entries {
+            match_components(entry, rest, options, matches);
+        }
+    }
+}
+
+/// Collects the base directory and every directory beneath it, up to `depth` levels deep.
+/// Hidden directories are skipped, and each directory is only visited once, so that symbolic
+/// links which point to a parent directory do not cause an infinite loop.
+fn walk_directories(base: PathBuf, depth: usize) -> Vec<PathBuf> {
+    let mut visited = HashSet::new();
+    let mut directories = Vec::new();
+    let mut pending = vec![(base, 0)];
+
+    while let Some((directory, level)) = pending.pop() {
+        let path = if directory.as_os_str().is_empty() { Path::new(".") } else { directory.as_path() };
+        match fs::canonicalize(path) {
+            Ok(canonical) => if !visited.insert(canonical) { continue },
+            Err(_) => continue,
+        }
+
+        if level < depth {
+            if let Ok(entries) = fs::read_dir(path) {
+                let mut children: Vec<PathBuf> = entries.filter_map(Result::ok)
+                    .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+                    .map(|entry| directory.join(entry.file_name()))
+                    .filter(|child| child.is_dir())
+                    .collect();
+                // Children are reversed so that they are popped in order.
+                children.sort_by(|a, b| b.cmp(a));
+                pending.extend(children.into_iter().map(|child| (child, level + 1)));
+            }
+        }
+
+        directories.push(directory);
+    }
+
+    directories
+}
+
+/// The kinds of extended matchers, which match a list of alternative patterns.
+#[derive(Debug, PartialEq)]
+enum Repeat {
+    /// `?(pattern)` matches zero or one occurrences of the patterns.
+    ZeroOrOne,
+    /// `*(pattern)` matches zero or more occurrences of the patterns.
+    ZeroOrMore,
+    /// `+(pattern)` matches one or more occurrences of the patterns.
+    OneOrMore,
+    /// `!(pattern)` matches anything except the patterns.
+    Not,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Char(char),
+    AnyChar,
+    AnySequence,
+    Class { negated: bool, ranges: Vec<(char, char)> },
+    Extended(Repeat, Vec<Vec<Token>>),
+}
+
+/// Compiles a single component of a glob pattern, returning `None` if it is invalid.
+fn compile(pattern: &str, extended: bool) -> Option<Vec<Token>> {
+    let chars: Vec<char> = pattern.chars().collect();
+    compile_sequence(&chars, &mut 0, extended, false)
+}
+
+/// Compiles tokens until the end of the pattern, or until the end of an alternative when
+/// `nested` is set, leaving the position at the `|` or `)` which terminated it.
+fn compile_sequence(chars: &[char], position: &mut usize, extended: bool, nested: bool) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    while *position < chars.len() {
+        let character = chars[*position];
+        let next = chars.get(*position + 1).cloned();
+        if nested && (character == '|' || character == ')') { break }
+
+        if extended && next == Some('(') && "?*+!".contains(character) {
+            let repeat = match character {
+                '?' => Repeat::ZeroOrOne,
+                '*' => Repeat::ZeroOrMore,
+                '+' => Repeat::OneOrMore,
+                _ => Repeat::Not,
+            };
+            *position += 2;
+            let mut alternatives = Vec::new();
+            loop {
+                match compile_sequence(chars, position, extended, true) {
+                    Some(alternative) => alternatives.push(alternative),
+                    None => return None,
+                }
+                match chars.get(*position) {
+                    Some(&'|') => *position += 1,
+                    Some(&')') => { *position += 1; break },
+                    _ => return None,
+                }
+            }
+            tokens.push(Token::Extended(repeat, alternatives));
+            continue
+        }
+
+        *position += 1;
+        tokens.push(match character {
+            '?' => Token::AnyChar,
+            '*' => Token::AnySequence,
+            '[' => match compile_class(chars, position, extended) {
+                Some(class) => class,
+                None => Token::Char('['),
+            },
+            _ => Token::Char(character),
+        });
+    }
+    Some(tokens)
+}
+
+/// Compiles a character class, such as `[a-z]` or `[!0-9]`, where the position follows the
+/// opening bracket. The position is left untouched if the class is not terminated.
+fn compile_class(chars: &[char], position: &mut usize, extended: bool) -> Option<Token> {
+    let mut index = *position;
+    let negated = match chars.get(index) {
+        Some(&'!') => true,
+        Some(&'^') if extended => true,
+        _ => false,
+    };
+    if negated { index += 1; }
+
+    let mut ranges = Vec::new();
+    let mut first = true;
+    while index < chars.len() {
+        let character = chars[index];
+        // A closing bracket at the beginning of the class is matched literally.
+        if character == ']' && !first {
+            *position = index + 1;
+            return Some(Token::Class { negated, ranges });
+        }
+        first = false;
+        if chars.get(index + 1) == Some(&'-') && index + 2 < chars.len() && chars[index + 2] != ']' {
+            ranges.push((character, chars[index + 2]));
+            index += 3;
+        } else {
+            ranges.push((character, character));
+            index += 1;
+        }
+    }
+    None
+}
+
+/// Determines whether the compiled pattern matches the entirety of the text.
+fn matches_tokens(tokens: &[Token], text: &[char]) -> bool {
+    let (token, rest) = match tokens.split_first() {
+        Some(split) => split,
+        None => return text.is_empty(),
+    };
+
+    match *token {
+        Token::Char(character) => !text.is_empty() && text[0] == character && matches_tokens(rest, &text[1..]),
+        Token::AnyChar => !text.is_empty() && matches_tokens(rest, &text[1..]),
+        Token::AnySequence => (0..text.len() + 1).any(|index| matches_tokens(rest, &text[index..])),
+        Token::Class { negated, ref ranges } => {
+            !text.is_empty()
+                && ranges.iter().any(|&(start, end)| text[0] >= start && text[0] <= end) != negated
+                && matches_tokens(rest, &text[1..])
+        },
+        Token::Extended(ref repeat, ref alternatives) => (0..text.len() + 1).any(|index| {
+            let (head, tail) = text.split_at(index);
+            let matched = match *repeat {
+                Repeat::ZeroOrOne => head.is_empty() || matches_any(alternatives, head),
+                Repeat::ZeroOrMore => matches_repeated(alternatives, head),
+                Repeat::OneOrMore => (0..head.len() + 1).any(|split| {
+                    matches_any(alternatives, &head[..split]) && matches_repeated(alternatives, &head[split..])
+                }),
+                Repeat::Not => !matches_any(alternatives, head),
+            };
+            matched && matches_tokens(rest, tail)
+        }),
+    }
+}
+
+fn matches_any(alternatives: &[Vec<Token>], text: &[char]) -> bool {
+    alternatives.iter().any(|alternative| matches_tokens(alternative, text))
+}
+
+/// Determines whether the text consists of zero or more matches of the alternatives.
+fn matches_repeated(alternatives: &[Vec<Token>], text: &[char]) -> bool {
+    text.is_empty() || (1..text.len() + 1).any(|split| {
+        matches_any(alternatives, &text[..split]) && matches_repeated(alternatives, &text[split..])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn matches(pattern: &str, depth: usize) -> Array {
+        let mut words = Array::new();
+        let options = GlobOptions { depth, ..GlobOptions::default() };
+        let _ = expand_glob(pattern.to_owned(), options, &mut words);
+        words
+    }
+
+    fn extended_matches(pattern: &str, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        matches_tokens(&compile(pattern, true).unwrap(), &text)
+    }
+
+    #[test]
+    fn recursive_globs() {
+        assert_eq!(array!["src/parser/shell_expand/ranges.rs"], matches("src/**/ranges.rs", DEFAULT_GLOB_DEPTH));
+        assert_eq!(array!["src/main.rs"], matches("src/**/main.rs", 0));
+        assert_eq!(array!["src/**/words.rs"], matches("src/**/words.rs", 1));
+        assert_eq!(array!["src/parser/shell_expand/words.rs"], matches("src/**/words.rs", 2));
+        assert_eq!(
+            array!["src/parser/shell_expand/globs.rs", "src/parser/shell_expand/words.rs"],
+            matches("src/**/shell_expand/**/[gw]*s.rs", DEFAULT_GLOB_DEPTH)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_loops() {
+        use std::os::unix::fs::symlink;
+        let root = env::temp_dir().join("ion_glob_loop_test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("inner")).unwrap();
+        fs::File::create(root.join("inner").join("file.txt")).unwrap();
+        symlink(&root, root.join("inner").join("loop")).unwrap();
+
+        let pattern = format!("{}/**/file.txt", root.display());
+        let found = matches(&pattern, DEFAULT_GLOB_DEPTH);
+        let _ = fs::remove_dir_all(&root);
+        assert_eq!(array![format!("{}/inner/file.txt", root.display())], found);
+    }
+
+    #[test]
+    fn extended_patterns() {
+        assert!(extended_matches("!(*.rs)", "Cargo.toml"));
+        assert!(!extended_matches("!(*.rs)", "main.rs"));
+        assert!(extended_matches("+(ab)c", "ababc"));
+        assert!(!extended_matches("+(ab)c", "c"));
+        assert!(extended_matches("*(ab|cd)", ""));
+        assert!(extended_matches("*(ab|cd)", "abcdab"));
+        assert!(extended_matches("file?(s).txt", "file.txt"));
+        assert!(extended_matches("file?(s).txt", "files.txt"));
+        assert!(!extended_matches("file?(s).txt", "filess.txt"));
+        assert!(extended_matches("[^a-c]*", "dog"));
+        assert!(!extended_matches("[!a-c]*", "cat"));
+        assert!(extended_matches("[]x]", "]"));
+        assert!(compile("+(ab", true).is_none());
+    }
+
+    #[test]
+    fn extended_globs() {
+        let options = GlobOptions { extended: true, ..GlobOptions::default() };
+        let mut words = Array::new();
+        let _ = expand_glob("src/parser/shell_expand/!(mod|words).rs".to_owned(), options, &mut words);
+        assert_eq!(
+            array!["src/parser/shell_expand/braces.rs", "src/parser/shell_expand/globs.rs",
+                "src/parser/shell_expand/ranges.rs"],
+            words
+        );
+    }
+
+    #[test]
+    fn natural_order() {
+        let mut paths = vec!["file10".to_owned(), "file2".to_owned(), "file1".to_owned(), "file02".to_owned()];
+        sort_paths(&mut paths, GlobSort::Natural);
+        assert_eq!(vec!["file1", "file02", "file2", "file10"], paths);
+        assert_eq!(Ordering::Less, natural_cmp("a9b", "a10"));
+        assert_eq!(Ordering::Less, natural_cmp("a", "a1"));
+    }
+
+    #[test]
+    fn size_order() {
+        use std::io::Write;
+        let root = env::temp_dir().join("ion_glob_size_test");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        for &(name, size) in &[("a", 1), ("b", 3), ("c", 2)] {
+            fs::File::create(root.join(name)).unwrap().write_all(&vec![0; size]).unwrap();
+        }
+
+        let options = GlobOptions { sort: GlobSort::Size, ..GlobOptions::default() };
+        let mut words = Array::new();
+        let _ = expand_glob(format!("{}/*", root.display()), options, &mut words);
+        let _ = fs::remove_dir_all(&root);
+        let prefix = root.display().to_string().len() + 1;
+        let names: Vec<&str> = words.iter().map(|word| &word[prefix..]).collect();
+        assert_eq!(vec!["b", "c", "a"], names);
+    }
+
+    #[test]
+    fn unmatched_globs() {
+        let mut words = Array::new();
+        let null = GlobOptions { mode: GlobMode::Null, ..GlobOptions::default() };
+        assert_eq!(Ok(()), expand_glob("src/*.nothing".to_owned(), null, &mut words));
+        assert!(words.is_empty());
+
+        let fail = GlobOptions { mode: GlobMode::Fail, ..GlobOptions::default() };
+        assert_eq!(Err("src/*.nothing".to_owned()), expand_glob("src/*.nothing".to_owned(), fail, &mut words));
+        assert_eq!(Ok(()), expand_glob("src/main.r?".to_owned(), fail, &mut words));
+        assert_eq!(array!["src/main.rs"], words);
+
+        let literal = GlobOptions::default();
+        assert_eq!(Ok(()), expand_glob("src/*.nothing".to_owned(), literal, &mut words));
+        assert_eq!(array!["src/main.rs", "src/*.nothing"], words);
+    }
+}
@@ -1,16 +1,20 @@
 // TODO: Handle Runtime Errors
 extern crate permutate;
 extern crate unicode_segmentation;
-extern crate calc;
 use self::unicode_segmentation::UnicodeSegmentation;
 
+use std::char;
+use std::mem;
 use types::Array;
+use super::arithmetic;
 
 mod braces;
+mod globs;
 mod ranges;
 mod words;
-use glob::glob;
 use self::braces::BraceToken;
+use self::globs::expand_glob;
+pub use self::globs::{GlobMode, GlobOptions, GlobSort, DEFAULT_GLOB_DEPTH};
 use self::ranges::parse_range;
 pub use self::words::{WordIterator, WordToken, Select, Index, Range};
 use types::*;
@@ -34,15 +38,22 @@ pub trait Expander {
     fn array(&self, &str, Select) -> Option<Array> { None }
     /// Expand a string variable given if its quoted / unquoted
     fn variable(&self, &str, bool) -> Option<Value> { None }
+    /// Obtain the keys of a map variable, along with their values as arrays
+    fn map(&self, &str) -> Option<Vec<(Value, Array)>> { None }
     /// Expand a subshell expression
     fn command(&self, &str) -> Option<Value> { None }
+    /// Spawn the command of a process substitution, returning the path of a pipe which is
+    /// connected to its standard output, or to its standard input if the second argument is set.
+    fn process_substitution(&self, &str, bool) -> Option<String> { None }
+    /// Obtain the options which control how glob patterns are matched
+    fn glob_options(&self) -> GlobOptions { GlobOptions::default() }
+    /// Report a glob pattern which failed to match any paths while in the `GlobMode::Fail` mode
+    fn unmatched_glob(&self, &str) {}
 }
 
-fn expand_process<E: Expander>(current: &mut String,
-                               command: &str,
-                               selection: Select,
-                               expand_func: &E)
-{
+/// Expands the inner command of a process expansion into the form that will be supplied to
+/// the `Expander::command` method.
+fn expand_command<E: Expander>(command: &str, expand_func: &E) -> String {
     let mut tokens = Vec::new();
     let mut contains_brace = false;
 
@@ -51,13 +62,189 @@ fn expand_process<E: Expander>(current: &mut String,
         tokens.push(token);
     }
 
-    let expanded = expand_tokens(&tokens, expand_func, false, contains_brace).join(" ");
+    expand_tokens(&tokens, expand_func, false, contains_brace).join(" ")
+}
+
+/// Finds the parenthesis which closes a subshell, such as `$(...)`, whose contents begin at
+/// `start`. Quotes within the subshell are independent of any which surround it, and nested
+/// subshells are skipped over in their entirety, so that `$(echo ")" "$(date)")` is closed by
+/// its final parenthesis.
+pub fn closing_paren(data: &[u8], start: usize) -> Option<usize> {
+    let mut index = start;
+    let mut level = 0;
+    let mut quoted = false;
+    while index < data.len() {
+        match data[index] {
+            b'\\' => index += 1,
+            b'\'' if !quoted => {
+                index += 1;
+                while index < data.len() && data[index] != b'\'' { index += 1; }
+            },
+            b'"' => quoted = !quoted,
+            b'(' if quoted => if index != 0 && (data[index - 1] == b'$' || data[index - 1] == b'@') {
+                index = match closing_paren(data, index + 1) {
+                    Some(end) => end,
+                    None => return None,
+                };
+            },
+            b'(' => level += 1,
+            b')' if !quoted => if level == 0 { return Some(index) } else { level -= 1 },
+            _ => (),
+        }
+        index += 1;
+    }
+    None
+}
+
+/// Finds the first string or array variable referenced within the input which the expander is
+/// unable to expand, which is used to report undefined variables when the `NO_UNSET` flag is set.
+pub fn find_unset_variable<'a, E: Expander>(input: &'a str, expand_func: &'a E) -> Option<&'a str> {
+    for token in WordIterator::new(input, true, expand_func) {
+        match token {
+            WordToken::Variable(name, quoted, _) => if expand_func.variable(name, quoted).is_none() {
+                return Some(name);
+            },
+            WordToken::ArrayVariable(name, _, _) | WordToken::ArraySplat(name, _) => {
+                if expand_func.array(name, Select::All).is_none() {
+                    return Some(name);
+                }
+            },
+            _ => ()
+        }
+    }
+    None
+}
+
+fn expand_process<E: Expander>(current: &mut String,
+                               command: &str,
+                               selection: Select,
+                               expand_func: &E)
+{
+    let expanded = expand_command(command, expand_func);
 
     if let Some(result) = expand_func.command(&expanded) {
         slice(current, result, selection);
     }
 }
 
+fn expand_process_substitution<E: Expander>(current: &mut String,
+                                            command: &str,
+                                            writes: bool,
+                                            expand_func: &E)
+{
+    let expanded = expand_command(command, expand_func);
+
+    if let Some(path) = expand_func.process_substitution(&expanded, writes) {
+        current.push_str(&path);
+    }
+}
+
+/// Interprets the escape sequences of an ANSI-C quoted string, such as `$'one\ttwo\n'`, where
+/// unknown escape sequences are left as is.
+pub fn unescape(input: &str) -> String { unescape_with(input, true) }
+
+/// Interprets the escape sequences of the separators that are supplied to `@split()` or stored
+/// within `$IFS`, which are those of ANSI-C quoted strings, except that the backslash of an
+/// unknown escape sequence is dropped, so that `\|` stands for a `|`.
+pub fn unescape_separator(input: &str) -> String { unescape_with(input, false) }
+
+fn unescape_with(input: &str, keep_unknown: bool) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+    while let Some(character) = chars.next() {
+        if character != '\\' {
+            output.push(character);
+            continue
+        }
+        let escaped = match chars.next() {
+            Some('a') => '\u{7}',
+            Some('b') => '\u{8}',
+            Some('e') | Some('E') => '\u{1b}',
+            Some('f') => '\u{c}',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('v') => '\u{b}',
+            Some(character @ '\\') | Some(character @ '\'') | Some(character @ '"') | Some(character @ '?') => {
+                character
+            },
+            Some('c') => match chars.next() {
+                Some(control) => char::from_u32(control as u32 & 0x1f).unwrap_or(control),
+                None => {
+                    output.push_str("\\c");
+                    continue
+                }
+            },
+            Some(digit @ '0'...'7') => {
+                let mut value = digit as u32 - '0' as u32;
+                for _ in 0..2 {
+                    match chars.peek().and_then(|character| character.to_digit(8)) {
+                        Some(digit) => value = value * 8 + digit,
+                        None => break,
+                    }
+                    chars.next();
+                }
+                char::from_u32(value).unwrap_or('\u{fffd}')
+            },
+            Some(prefix @ 'x') | Some(prefix @ 'u') | Some(prefix @ 'U') => {
+                let length = match prefix { 'x' => 2, 'u' => 4, _ => 8 };
+                let mut value = 0;
+                let mut read = 0;
+                while read < length {
+                    match chars.peek().and_then(|character| character.to_digit(16)) {
+                        Some(digit) => value = value * 16 + digit,
+                        None => break,
+                    }
+                    chars.next();
+                    read += 1;
+                }
+                if read == 0 {
+                    output.push('\\');
+                    prefix
+                } else {
+                    char::from_u32(value).unwrap_or('\u{fffd}')
+                }
+            },
+            Some(character) => {
+                if keep_unknown { output.push('\\'); }
+                character
+            },
+            None => '\\',
+        };
+        output.push(escaped);
+    }
+    output
+}
+
+/// Expands an array process, such as `@(cmd)`, by splitting the output of the command into fields
+/// on each of the characters within `$IFS`, or on whitespace if it is not set, and then selecting
+/// from those fields. Empty fields are discarded.
+fn expand_array_process<E: Expander>(command: &str, selection: Select, expand_func: &E) -> Array {
+    let mut output = String::new();
+    expand_process(&mut output, command, Select::All, expand_func);
+
+    let separators = expand_func.variable("IFS", true).map(|ifs| unescape_separator(&ifs));
+    let fields: Vec<&str> = match separators {
+        Some(ref separators) if !separators.is_empty() => output
+            .split(|character: char| separators.contains(character))
+            .filter(|field| !field.is_empty())
+            .collect(),
+        _ => output.split_whitespace().collect(),
+    };
+
+    let selected = match selection {
+        Select::None | Select::Key(_) => Vec::new(),
+        Select::All => fields,
+        Select::Index(Index::Forward(id)) => fields.get(id).cloned().into_iter().collect(),
+        Select::Index(Index::Backward(id)) => fields.iter().rev().nth(id).cloned().into_iter().collect(),
+        Select::Range(range) => {
+            let len = fields.len();
+            range.select(fields.into_iter(), len).unwrap_or_default()
+        }
+    };
+    selected.into_iter().map(From::from).collect()
+}
+
 fn expand_brace<E: Expander>(current: &mut String,
                              expanders: &mut Vec<Vec<String>>,
                              tokens: &mut Vec<BraceToken>,
@@ -66,12 +253,17 @@ fn expand_brace<E: Expander>(current: &mut String,
                              reverse_quoting: bool)
 {
     let mut temp = Vec::new();
-    for word in nodes.into_iter()
-        .flat_map(|node| expand_string(node, expand_func, reverse_quoting))
-    {
-        match parse_range(&word) {
-            Some(elements) => for word in elements { temp.push(word.into()) },
-            None           => temp.push(word.into()),
+    for node in nodes {
+        // Empty elements are kept, so that `file{,.bak}` expands to `file file.bak`.
+        if node.is_empty() && nodes.len() > 1 {
+            temp.push(String::new());
+            continue
+        }
+        for word in expand_string(node, expand_func, reverse_quoting) {
+            match parse_range(&word) {
+                Some(elements) => for word in elements { temp.push(word.into()) },
+                None           => temp.push(word.into()),
+            }
         }
     }
 
@@ -116,13 +308,9 @@ fn array_range<E: Expander>(elements: &[&str], expand_func: &E, range : Range) -
                            .flat_map(|e| expand_string(e, expand_func, false))
                            .collect::<Array>();
     let len = expanded.len();
-    if let Some((start, length)) = range.bounds(len) {
-        expanded.into_iter()
-                .skip(start)
-                .take(length)
-                .collect()
-    } else {
-        Array::new()
+    match range.select(expanded.into_iter(), len) {
+        Some(range) => range.into_iter().collect(),
+        None => Array::new()
     }
 }
 
@@ -142,12 +330,8 @@ fn slice<S: AsRef<str>>(output: &mut String, expanded: S, selection: Select) {
         }
         Select::Range(range) => {
             let graphemes = UnicodeSegmentation::graphemes(expanded.as_ref(), true);
-            if let Some((start, length)) = range.bounds(graphemes.clone().count()) {
-                let substring = graphemes.skip(start)
-                                         .take(length)
-                                         .collect::<Vec<&str>>()
-                                         .join("");
-               output.push_str(&substring);
+            if let Some(substring) = range.select(graphemes.clone(), graphemes.count()) {
+               output.push_str(&substring.join(""));
             }
         },
         Select::Key(_) => ()
@@ -198,16 +382,8 @@ pub fn expand_tokens<E: Expander>(token_buffer: &[WordToken],
                 $text.into()
             };
             if $do_glob {
-                match glob(&expanded) {
-                    Ok(var) => {
-                        let mut globs_found = false;
-                        for path in var.filter_map(Result::ok) {
-                            globs_found = true;
-                            expanded_words.push(path.to_string_lossy().into_owned());
-                        }
-                        if !globs_found { expanded_words.push(expanded); }
-                    }
-                    Err(_) => expanded_words.push(expanded)
+                if let Err(pattern) = expand_glob(expanded, expand_func.glob_options(), &mut expanded_words) {
+                    expand_func.unmatched_glob(&pattern);
                 }
             } else {
                 output.push_str(&expanded);
@@ -219,53 +395,22 @@ pub fn expand_tokens<E: Expander>(token_buffer: &[WordToken],
         if contains_brace {
             let mut tokens: Vec<BraceToken> = Vec::new();
             let mut expanders: Vec<Vec<String>> = Vec::new();
+            // Braces are expanded before globbing, so that each permutation may be globbed.
+            let mut do_glob = false;
 
             for word in token_buffer {
                 match *word {
                     WordToken::Array(ref elements, ref index) => {
                         output.push_str(&array_expand(elements, expand_func, index.clone()).join(" "));
                     },
-                    WordToken::ArrayVariable(array, _, ref index) => {
+                    WordToken::ArrayVariable(array, _, ref index) |
+                        WordToken::ArraySplat(array, ref index) => {
                         if let Some(array) = expand_func.array(array, index.clone()) {
                             output.push_str(&array.join(" "));
                         }
                     },
                     WordToken::ArrayProcess(command, _, ref index) => {
-                        match *index {
-                            Select::None => (),
-                            Select::All => {
-                                let mut temp = String::new();
-                                expand_process(&mut temp, command, Select::All, expand_func);
-                                let temp = temp.split_whitespace().collect::<Vec<&str>>();
-                                output.push_str(&temp.join(" "));
-                            },
-                            Select::Index(Index::Forward(id)) => {
-                                let mut temp = String::new();
-                                expand_process(&mut temp, command, Select::All, expand_func);
-                                output.push_str(temp.split_whitespace().nth(id).unwrap_or_default());
-                            },
-                            Select::Index(Index::Backward(id)) => {
-                                let mut temp = String::new();
-                                expand_process(&mut temp, command, Select::All, expand_func);
-                                output.push_str(temp.split_whitespace()
-                                                    .rev()
-                                                    .nth(id)
-                                                    .unwrap_or_default());
-                            }
-                            Select::Range(range) => {
-                                let mut temp = String::new();
-                                expand_process(&mut temp, command, Select::All, expand_func);
-                                let len = temp.split_whitespace().count();
-                                if let Some((start, length)) = range.bounds(len) {
-                                    let res = temp.split_whitespace()
-                                                  .skip(start)
-                                                  .take(length)
-                                                  .collect::<Vec<&str>>();
-                                    output.push_str(&res.join(" "));
-                                }
-                            },
-                            Select::Key(_) => (),
-                        }
+                        output.push_str(&expand_array_process(command, index.clone(), expand_func).join(" "));
                     },
                     WordToken::ArrayMethod(ref method) => {
                         method.handle(&mut output, expand_func);
@@ -279,6 +424,10 @@ pub fn expand_tokens<E: Expander>(token_buffer: &[WordToken],
                     WordToken::Process(command, _, ref index) => {
                         expand_process(&mut output, command, index.clone(), expand_func);
                     },
+                    WordToken::ProcessSubstitution(command, writes) => {
+                        expand_process_substitution(&mut output, command, writes, expand_func);
+                    },
+                    WordToken::AnsiString(text) => output.push_str(&unescape(text)),
                     WordToken::Variable(text, quoted, ref index) => {
                         let quoted = if reverse_quoting { !quoted } else { quoted };
                         let expanded = match expand_func.variable(text, quoted) {
@@ -288,21 +437,33 @@ pub fn expand_tokens<E: Expander>(token_buffer: &[WordToken],
 
                         slice(&mut output, expanded, index.clone());
                     },
-                    WordToken::Normal(text, do_glob, tilde) => {
-                        expand!(text, do_glob, tilde);
+                    WordToken::Normal(text, glob, tilde) => {
+                        do_glob = do_glob || glob;
+                        expand!(text, false, tilde);
                     },
                     WordToken::Arithmetic(s) => expand_arithmetic(&mut output, s, expand_func),
+                    WordToken::ArrayArithmetic(s, _) => {
+                        output.push_str(&expand_array_arithmetic(s, expand_func).join(" "));
+                    },
                 }
             }
 
-            if expanders.is_empty() {
-                expanded_words.push(output.into());
+            let words = if expanders.is_empty() {
+                vec![output]
             } else {
                 if !output.is_empty() {
                     tokens.push(BraceToken::Normal(output));
                 }
-                for word in braces::expand_braces(&tokens, expanders) {
-                    expanded_words.push(word.into());
+                braces::expand_braces(&tokens, expanders)
+            };
+
+            for word in words {
+                if do_glob {
+                    if let Err(pattern) = expand_glob(word, expand_func.glob_options(), &mut expanded_words) {
+                        expand_func.unmatched_glob(&pattern);
+                    }
+                } else {
+                    expanded_words.push(word);
                 }
             }
 
@@ -320,47 +481,15 @@ pub fn expand_tokens<E: Expander>(token_buffer: &[WordToken],
                         None                      => Array::new(),
                     };
                 },
+                WordToken::ArraySplat(array, ref index) => {
+                    return expand_func.array(array, index.clone()).unwrap_or_else(Array::new);
+                },
+                WordToken::ArrayArithmetic(expression, quoted) => {
+                    let array = expand_array_arithmetic(expression, expand_func);
+                    return if quoted { array![array.join(" ")] } else { array };
+                },
                 WordToken::ArrayProcess(command, _, ref index) => {
-                    match *index {
-                        Select::None => return Array::new(),
-                        Select::All => {
-                            expand_process(&mut output, command, Select::All, expand_func);
-                            return output.split_whitespace()
-                                .map(From::from)
-                                .collect::<Array>();
-                        },
-                        Select::Index(Index::Forward(id)) => {
-                            expand_process(&mut output, command, Select::All, expand_func);
-                            return output.split_whitespace()
-                                         .nth(id)
-                                         .map(Into::into)
-                                         .into_iter()
-                                         .collect();
-                        },
-                        Select::Index(Index::Backward(id)) => {
-                            expand_process(&mut output, command, Select::All, expand_func);
-                            return output.split_whitespace()
-                                         .rev()
-                                         .nth(id)
-                                         .map(Into::into)
-                                         .into_iter()
-                                         .collect();
-                        }
-                        Select::Range(range) => {
-                            expand_process(&mut output, command, Select::All, expand_func);
-                            if let Some((start, length)) = range.bounds(output.split_whitespace().count()) {
-                                return output.split_whitespace()
-                                             .skip(start)
-                                             .take(length)
-                                             .map(From::from)
-                                             .collect();
-
-                            } else {
-                                return Array::new();
-                            }
-                        },
-                        Select::Key(_) => ()
-                    }
+                    return expand_array_process(command, index.clone(), expand_func);
                 },
                 WordToken::ArrayMethod(ref array_method) => {
 
@@ -386,42 +515,23 @@ pub fn expand_tokens<E: Expander>(token_buffer: &[WordToken],
                         output.push_str(&array.join(" "));
                     }
                 },
-                WordToken::ArrayProcess(command, _, ref index) => {
-                    match index.clone() {
-                        Select::None => (),
-                        Select::All => {
-                            let mut temp = String::new();
-                            expand_process(&mut temp, command, Select::All, expand_func);
-                            let temp = temp.split_whitespace().collect::<Vec<&str>>();
-                            output.push_str(&temp.join(" "));
-                        },
-                        Select::Index(Index::Forward(id)) => {
-                            let mut temp = String::new();
-                            expand_process(&mut temp, command, Select::All, expand_func);
-                            output.push_str(temp.split_whitespace().nth(id).unwrap_or_default());
-                        },
-                        Select::Index(Index::Backward(id)) => {
-                            let mut temp = String::new();
-                            expand_process(&mut temp, command, Select::All, expand_func);
-                            output.push_str(temp.split_whitespace()
-                                                .rev()
-                                                .nth(id)
-                                                .unwrap_or_default());
-                        },
-                        Select::Range(range) => {
-                            let mut temp = String::new();
-                            expand_process(&mut temp, command, Select::All, expand_func);
-                            if let Some((start, length)) = range.bounds(temp.split_whitespace().count()) {
-                                let temp = temp.split_whitespace()
-                                               .skip(start)
-                                               .take(length)
-                                               .collect::<Vec<_>>();
-                                output.push_str(&temp.join(" "))
-                            }
-                        },
-                        Select::Key(_) => ()
+                WordToken::ArraySplat(array, ref index) => {
+                    // The first element is joined with the preceding text, and each following
+                    // element begins a new word, so that `a@array...b` expands like `a$@b`.
+                    if let Some(array) = expand_func.array(array, index.clone()) {
+                        let mut elements = array.into_iter();
+                        if let Some(first) = elements.next() {
+                            output.push_str(&first);
+                        }
+                        for element in elements {
+                            let word = mem::replace(&mut output, element.into());
+                            expanded_words.push(word.into());
+                        }
                     }
                 },
+                WordToken::ArrayProcess(command, _, ref index) => {
+                    output.push_str(&expand_array_process(command, index.clone(), expand_func).join(" "));
+                },
                 WordToken::ArrayMethod(ref method) => {
                     method.handle(&mut output, expand_func);
                 },
@@ -438,6 +548,10 @@ pub fn expand_tokens<E: Expander>(token_buffer: &[WordToken],
                 WordToken::Process(command, _, ref index) => {
                     expand_process(&mut output, command, index.clone(), expand_func);
                 }
+                WordToken::ProcessSubstitution(command, writes) => {
+                    expand_process_substitution(&mut output, command, writes, expand_func);
+                }
+                WordToken::AnsiString(text) => output.push_str(&unescape(text)),
                 WordToken::Variable(text, quoted, ref index) => {
                     let quoted = if reverse_quoting { !quoted } else { quoted };
                     let expanded = match expand_func.variable(text, quoted) {
@@ -448,6 +562,9 @@ pub fn expand_tokens<E: Expander>(token_buffer: &[WordToken],
                     slice(&mut output, expanded, index.clone());
                 },
                 WordToken::Arithmetic(s) => expand_arithmetic(&mut output, s, expand_func),
+                WordToken::ArrayArithmetic(s, _) => {
+                    output.push_str(&expand_array_arithmetic(s, expand_func).join(" "));
+                },
             }
         }
         // I'm not entirely sure if empty strings are valid in any case- maarten
@@ -493,13 +610,76 @@ fn expand_arithmetic<E: Expander>(output: &mut String,
         }
     }
     flush(&mut varbuf, &mut intermediate);
-    match calc::eval(&intermediate) {
-        Ok(s) => output.push_str(&(s.to_string())),
-        Err(e) => {
-            let err_string : String = e.into();
-            output.push_str(&err_string);
+    match arithmetic::eval(&intermediate) {
+        Ok(result) => output.push_str(&result),
+        Err(why) => output.push_str(&why),
+    }
+}
+
+/// Evaluates an arithmetic expression once for each element of the arrays referenced within it,
+/// for example:
+/// ```ignore
+/// @a * 2 + x => [3 5 7]
+/// ```
+/// if `a = [1 2 3]` and `x = 1`. Every array within the expression must have the same length.
+fn expand_array_arithmetic<E: Expander>(input: &str, expander: &E) -> Array {
+    let bytes = input.as_bytes();
+    let mut parts: Vec<&str> = Vec::new();
+    let mut arrays: Vec<Array> = Vec::new();
+    let mut start = 0;
+    let mut index = 0;
+
+    while index < bytes.len() {
+        if bytes[index] != b'@' {
+            index += 1;
+            continue
         }
+
+        let name_start = index + 1;
+        let mut name_end = name_start;
+        while name_end < bytes.len() {
+            match bytes[name_end] {
+                b'0'...b'9' | b'A'...b'Z' | b'_' | b'a'...b'z' => name_end += 1,
+                _ => break
+            }
+        }
+
+        let name = &input[name_start..name_end];
+        match expander.array(name, Select::All) {
+            Some(array) => arrays.push(array),
+            None => {
+                eprintln!("ion: array arithmetic: '@{}' is not an array", name);
+                return Array::new();
+            }
+        }
+        parts.push(&input[start..index]);
+        start = name_end;
+        index = name_end;
+    }
+    parts.push(&input[start..]);
+
+    let length = arrays.first().map_or(1, |array| array.len());
+    if arrays.iter().any(|array| array.len() != length) {
+        eprintln!("ion: array arithmetic: arrays must all be of the same length");
+        return Array::new();
     }
+
+    (0..length).map(|id| {
+        let mut expression = String::from(parts[0]);
+        for (array, part) in arrays.iter().zip(parts[1..].iter()) {
+            // Negative elements are parenthesized, as the evaluator does not support unary minus.
+            let element = &array[id];
+            if element.starts_with('-') {
+                expression.push_str(&["(0 - ", &element[1..], ")"].concat());
+            } else {
+                expression.push_str(element);
+            }
+            expression.push_str(part);
+        }
+        let mut output = String::new();
+        expand_arithmetic(&mut output, &expression, expander);
+        output
+    }).collect()
 }
 
 // TODO: Write Nested Brace Tests
@@ -522,6 +702,23 @@ mod test {
                 _   => None
             }
         }
+
+        fn array(&self, array: &str, selection: Select) -> Option<Array> {
+            match (array, selection) {
+                ("ARGS", Select::All) => Some(array!["one", "two words", "three"]),
+                ("NUMS", Select::All) => Some(array!["1", "2", "3"]),
+                ("PAIR", Select::All) => Some(array!["1", "2"]),
+                _ => None
+            }
+        }
+    }
+
+    #[test]
+    fn closing_parens() {
+        assert_eq!(Some(10), closing_paren(b"$(echo one)", 2));
+        assert_eq!(Some(14), closing_paren(b"$(echo \")\" ')')", 2));
+        assert_eq!(Some(20), closing_paren(b"$(echo \"$(echo \")\")\")", 2));
+        assert_eq!(None, closing_paren(b"$(echo \")", 2));
     }
 
     #[test]
@@ -545,6 +742,28 @@ mod test {
         );
     }
 
+    #[test]
+    fn expand_brace_permutations() {
+        let expanded = expand_string("{a,b}{1,2}", &VariableExpander, false);
+        assert_eq!(array!["a1", "a2", "b1", "b2"], expanded);
+        let expanded = expand_string("x{a,b{1,2}}{y,z}", &VariableExpander, false);
+        assert_eq!(array!["xay", "xaz", "xb1y", "xb1z", "xb2y", "xb2z"], expanded);
+        let expanded = expand_string("file{,.bak}", &VariableExpander, false);
+        assert_eq!(array!["file", "file.bak"], expanded);
+        let expanded = expand_string("{a..c}{1...2}", &VariableExpander, false);
+        assert_eq!(array!["a1", "a2", "b1", "b2"], expanded);
+    }
+
+    #[test]
+    fn expand_ranges_before_globbing() {
+        let expanded = expand_string("file{1..3}.txt", &VariableExpander, false);
+        assert_eq!(array!["file1.txt", "file2.txt"], expanded);
+        let expanded = expand_string("{0...15..5}", &VariableExpander, false);
+        assert_eq!(array!["0", "5", "10", "15"], expanded);
+        let expanded = expand_string("src/parser/shell_expand/{mod,ranges}.r?", &VariableExpander, false);
+        assert_eq!(array!["src/parser/shell_expand/mod.rs", "src/parser/shell_expand/ranges.rs"], expanded);
+    }
+
     #[test]
     fn expand_variables_with_colons() {
         let expanded = expand_string("$FOO:$BAR", &VariableExpander, false);
@@ -635,4 +854,81 @@ mod test {
             assert_eq!(expected, expand_string(input, &VariableExpander, false));
         }
     }
+
+    #[test]
+    fn array_arith_expression() {
+        let cases = vec![
+            (array!["2", "4", "6"], "@((@NUMS * 2))"),
+            (array!["0", "0", "0"], "@((@NUMS - @NUMS))"),
+            (array!["2 3 4"], "\"@((@NUMS + A))\""),
+            (array!["4"], "@((A + 3))"),
+            (Array::new(), "@((@NUMS + @PAIR))"),
+            (Array::new(), "@((@NUMS + @MISSING))"),
+        ];
+        for (expected, input) in cases {
+            assert_eq!(expected, expand_string(input, &VariableExpander, false));
+        }
+    }
+
+    #[test]
+    fn unset_variables() {
+        assert_eq!(None, find_unset_variable("$A @ARGS... $(echo $Z)", &VariableExpander));
+        assert_eq!(Some("Z"), find_unset_variable("echo $B${Z}", &VariableExpander));
+        assert_eq!(Some("ARRAY"), find_unset_variable("echo \"@ARRAY\"", &VariableExpander));
+        assert_eq!(None, find_unset_variable("echo '$Z'", &VariableExpander));
+    }
+
+    struct CommandExpander(&'static str);
+
+    impl Expander for CommandExpander {
+        fn variable(&self, variable: &str, _: bool) -> Option<Value> {
+            if variable == "IFS" && !self.0.is_empty() { Some(self.0.to_owned()) } else { None }
+        }
+
+        fn command(&self, _: &str) -> Option<Value> {
+            Some("one two\nthree  four\0five".to_owned())
+        }
+    }
+
+    #[test]
+    fn array_process_fields() {
+        let expanded = expand_string("@(cmd)", &CommandExpander(""), false);
+        assert_eq!(array!["one", "two", "three", "four\0five"], expanded);
+        let expanded = expand_string("@(cmd)", &CommandExpander("\\n"), false);
+        assert_eq!(array!["one two", "three  four\0five"], expanded);
+        let expanded = expand_string("@(cmd)[-1]", &CommandExpander(" \\0"), false);
+        assert_eq!(array!["five"], expanded);
+        let expanded = expand_string("@split($(cmd), '\\0')", &CommandExpander(""), false);
+        assert_eq!(array!["one two\nthree  four", "five"], expanded);
+        assert_eq!("a\tb\\", unescape_separator("a\\tb\\\\"));
+        assert_eq!("a|b\0", unescape_separator("a\\|b\\0"));
+        assert_eq!("a\\|b", unescape("a\\|b"));
+    }
+
+    #[test]
+    fn ansi_c_quoting() {
+        assert_eq!("\u{1b}[0m\x07\0A\u{e9}é\u{3}\\q\\x", &unescape("\\e[0m\\a\\0\\101\\xe9\\u00e9\\cc\\q\\x"));
+        let cases = vec![
+            (array!["line1\nline2\t!"], "$'line1\\nline2\\t!'"),
+            (array!["it's quoted"], "$'it\\'s'\" quoted\""),
+            (array!["$'\\n'"], "\"$'\\n'\""),
+            (array!["a\nb", "c"], "a$'\\n'b c"),
+        ];
+        for (expected, input) in cases {
+            assert_eq!(expected, expand_string(input, &VariableExpander, false));
+        }
+    }
+
+    #[test]
+    fn array_splat() {
+        let cases = vec![
+            (array!["one", "two words", "three"], "@ARGS..."),
+            (array!["one", "two words", "three"], "\"@ARGS...\""),
+            (array!["one two words three"], "\"@ARGS\""),
+            (array!["-one", "two words", "three!"], "-@ARGS...!"),
+        ];
+        for (expected, input) in cases {
+            assert_eq!(expected, expand_string(input, &VariableExpander, false));
+        }
+    }
 }
@@ -110,110 +110,38 @@ fn strings_to_isizes(a: &str, b: &str) -> Option<(isize, isize)> {
 // In a range we allow the following syntax:
 //      Exclusive nonstepped: {start..end}
 //      Inclusive nonstepped: {start...end}
-//      Exclusive stepped: {start..step..end}
-//      Inclusive stepped: {start..step...end}
+//      Exclusive stepped: {start..end..step}
+//      Inclusive stepped: {start...end..step}
 pub fn parse_range(input: &str) -> Option<Vec<String>> {
-    let mut read = 0;
-    let mut bytes_iterator = input.bytes();
-    while let Some(byte) = bytes_iterator.next() {
-        match byte {
-            // can only find these as the first byte, otherwise the syntax is bad
-            b'a'...b'z' | b'-' | b'A'...b'Z' if read == 0 => read += 1,
-            b'0'...b'9' => read += 1,
-            b'.' => {
-                let first = &input[..read];
-                read += 1;
-                // The next byte has to be a dot to be valid range
-                // syntax
-                match bytes_iterator.next() {
-                    Some(b'.') => read += 1,
-                    _ => return None,
-                }
-
-                macro_rules! finish_char {
-                    ($inclusive:expr, $end_str:expr, $step:expr) => {
-                        if first.len() == 1 && $end_str.len() == 1 {
-                            let start = first.as_bytes()[0];
-                            let end = $end_str.as_bytes()[0];
-                            return char_range(start, end, $step, $inclusive);
-                        } else {
-                            return None;
-                        }
-                    }
-                }
+    // The start may be negative, but never contains a dot, so the first dots end it.
+    let pos = match input.find("..") {
+        Some(pos) if pos != 0 => pos,
+        _ => return None,
+    };
+    let first = &input[..pos];
+    let (inclusive, rest) = if input[pos + 2..].starts_with('.') {
+        (true, &input[pos + 3..])
+    } else {
+        (false, &input[pos + 2..])
+    };
 
-                macro_rules! finish {
-                    ($inclusive:expr, $read:expr) => {
-                        let end_str = &input[$read..];
-                        if let Some((start, end)) = strings_to_isizes(first, end_str) {
-                            return numeric_range(start, end, if start < end { 1 } else { -1 }, $inclusive);
-                        } else {
-                            finish_char!($inclusive, end_str, 1);
-                        }
-                    };
-                    ($inclusive:expr, $read:expr, $step:expr) => {
-                        let end_str = &input[$read..];
-                        if let Some((start, end)) = strings_to_isizes(first, end_str) {
-                            return numeric_range(start, end, $step, $inclusive);
-                        } else {
-                            finish_char!($inclusive, end_str, $step);
-                        }
-                    };
-                }
+    // As with slices, a step may follow the end of the range.
+    let (end_str, step) = match rest.find("..") {
+        Some(pos) => match rest[pos + 2..].parse::<isize>() {
+            Ok(step) if step != 0 => (&rest[..pos], step.abs()),
+            _ => return None,
+        },
+        None => (rest, 1),
+    };
 
-                // if the next byte is a dot we're certain it is an inclusive
-                // unstepped range otherwise it has to be [-0-9a-zA-Z]
-                if let Some(b) = bytes_iterator.next() {
-                    read += 1;
-                    match b {
-                        b'.' => {
-                            // this can only be an inclusive range
-                            finish!(true, read);
-                        }
-                        b'0'...b'9' | b'-' | b'a'...b'z' | b'A'...b'Z' => {
-                            // further processing needed to find out if we're reading a step or
-                            // the end of an exclusive range. Step until we find another dot or
-                            // the iterator ends
-                            let start = read - 1;
-                            while let Some(b) = bytes_iterator.next() {
-                                read += 1;
-                                match b {
-                                    b'.' => {
-                                        // stepped range input[start..read - 1] contains the step size
-                                        let step = match (&input[start..read - 1]).parse::<isize>() {
-                                            Ok(v) => v,
-                                            Err(_) => return None,
-                                        };
-                                        // count the dots to determine inclusive/exclusive
-                                        let mut dots = 1;
-                                        while let Some(b) = bytes_iterator.next() {
-                                            read += 1;
-                                            match b {
-                                                b'.' => dots += 1,
-                                                _ => break,
-                                            }
-                                        }
-                                        finish!(dots == 3, read - 1, step);
-                                    }
-                                    // numeric values are OK but no letters anymore
-                                    b'0'...b'9' => {}
-                                    // unexpected
-                                    _ => return None,
-                                }
-                            }
-                            // exhausted the iterator without finding anything new means
-                            // exclusive unstepped range
-                            finish!(false, start);
-                        }
-                        // not a valid byte for ranges
-                        _ => return None,
-                    }
-                }
-            }
-            _ => break,
-        }
+    if let Some((start, end)) = strings_to_isizes(first, end_str) {
+        // The direction of the range is given by its ends, rather than by the sign of the step.
+        numeric_range(start, end, if start > end { -step } else { step }, inclusive)
+    } else if first.len() == 1 && end_str.len() == 1 {
+        char_range(first.as_bytes()[0], end_str.as_bytes()[0], step, inclusive)
+    } else {
+        None
     }
-    None
 }
 
 pub fn parse_index_range(input: &str) -> Option<Range> {
@@ -241,12 +169,21 @@ pub fn parse_index_range(input: &str) -> Option<Range> {
 
                 let end = &input[id + dots..];
 
+                // A step may follow the end of the range, as in `0..10..2`.
+                let (end, step) = match end.find("..") {
+                    Some(pos) => match end[pos + 2..].parse::<usize>() {
+                        Ok(step) if step != 0 && pos != 0 => (&end[..pos], step),
+                        _ => break,
+                    },
+                    None => (end, 1),
+                };
+
                 if first.is_empty() {
                     return if end.is_empty() {
                         None
                     } else {
                         match end.parse::<isize>() {
-                            Ok(end) => Some(Range::to(Index::new(end))),
+                            Ok(end) => Some(Range::to(Index::new(end)).stepped(step)),
                             Err(_) => None,
                         }
                     };
@@ -263,7 +200,7 @@ pub fn parse_index_range(input: &str) -> Option<Range> {
                             Range::inclusive(Index::new(start), Index::new(end))
                         } else {
                             Range::exclusive(Index::new(start), Index::new(end))
-                        });
+                        }.stepped(step));
                     }
                 } else {
                     break;
@@ -287,13 +224,16 @@ fn index_ranges() {
         (Range::exclusive(Index::Backward(2), Index::Backward(0)), "-3..-1"),
         (Range::from(Index::Backward(2)), "-3.."),
         (Range::to(Index::Forward(5)), "..5"),
+        (Range::exclusive(Index::Forward(0), Index::Forward(10)).stepped(2), "0..10..2"),
+        (Range::inclusive(Index::Forward(1), Index::Backward(0)).stepped(3), "1...-1..3"),
+        (Range::to(Index::Forward(6)).stepped(2), "..6..2"),
     ];
 
     for (range, string) in valid_cases {
         assert_eq!(Some(range), parse_index_range(string));
     }
 
-    let invalid_cases = vec!["0..A", "3-3..42"];
+    let invalid_cases = vec!["0..A", "3-3..42", "0..10..0", "0..10..-1"];
 
     for range in invalid_cases {
         assert_eq!(None, parse_index_range(range))
@@ -392,4 +332,18 @@ fn range_expand() {
     let actual = parse_range("-3..0");
     let expected = Some(vec!["-3".into(), "-2".into(), "-1".into()]);
     assert_eq!(actual, expected);
+
+    let actual = parse_range("0..100..30");
+    let expected = Some(vec!["0".into(), "30".into(), "60".into(), "90".into()]);
+    assert_eq!(actual, expected);
+
+    let actual = parse_range("4...0..2");
+    let expected = Some(vec!["4".into(), "2".into(), "0".into()]);
+    assert_eq!(actual, expected);
+
+    let actual = parse_range("a...e..-2");
+    let expected = Some(vec!["a".into(), "c".into(), "e".into()]);
+    assert_eq!(actual, expected);
+
+    assert_eq!(None, parse_range("0..10..0"));
 }
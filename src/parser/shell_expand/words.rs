@@ -1,15 +1,18 @@
+use fnv::FnvHashSet;
 use std::io::{self, Write};
 use std::char;
+use std::cmp::Ordering;
 use std::str::FromStr;
 use std::iter::{empty, FromIterator};
 
 use super::super::ArgumentSplitter;
+use super::super::arithmetic;
 use super::unicode_segmentation::UnicodeSegmentation;
 use super::{Expander, expand_string};
 use super::ranges::parse_index_range;
-use super::{slice, is_expression};
+use super::{slice, is_expression, unescape_separator, closing_paren};
 
-use types::Array;
+use types::{Array, Value};
 use std::path::Path;
 
 // Bit Twiddling Guide:
@@ -81,24 +84,50 @@ pub struct Range {
     /// Is this range inclusive? If false, this object represents a half-open
     /// range of [start, end), otherwise [start, end]
     inclusive: bool,
+    /// The distance between each selected element, where a step of `1` selects every element
+    step: usize,
 }
 
 impl Range {
 
     pub fn to(end : Index) -> Range {
-        Range {start : Index::new(0), end, inclusive : false}
+        Range {start : Index::new(0), end, inclusive : false, step : 1}
     }
 
     pub fn from(start : Index) -> Range {
-        Range {start , end : Index::new(-1), inclusive : true}
+        Range {start , end : Index::new(-1), inclusive : true, step : 1}
     }
 
     pub fn inclusive(start : Index, end : Index) -> Range {
-        Range {start, end, inclusive : true}
+        Range {start, end, inclusive : true, step : 1}
     }
 
     pub fn exclusive(start : Index, end : Index) -> Range {
-        Range {start, end, inclusive : false}
+        Range {start, end, inclusive : false, step : 1}
+    }
+
+    /// Selects only every `step`th element of the range, starting with the first.
+    pub fn stepped(self, step : usize) -> Range {
+        Range {step, ..self}
+    }
+
+    /// Collects the elements of this range from an iterator over a vector-like object which
+    /// contains `vector_length` elements, or returns `None` if the range is out of bounds.
+    /// ```
+    /// let vec = vec![0, 1, 2, 3, 4, 5, 6, 7, 8];
+    /// let range = Range::exclusive(Index::new(1), Index::new(7)).stepped(2);
+    /// assert_eq!(Some(vec![&1, &3, &5]), range.select(vec.iter(), vec.len()));
+    /// ```
+    pub fn select<I : Iterator>(&self, iter : I, vector_length : usize) -> Option<Vec<I::Item>> {
+        let step = self.step;
+        self.bounds(vector_length).map(|(start, length)| {
+            iter.skip(start)
+                .take(length)
+                .enumerate()
+                .filter(|&(id, _)| id % step == 0)
+                .map(|(_, item)| item)
+                .collect()
+        })
     }
 
     /// Returns the bounds of this range as a tuple containing:
@@ -174,13 +203,9 @@ impl<I, T> SelectWithSize for I where I : Iterator<Item=T> {
                    .into_iter()
                    .collect(),
             Select::Range(range) => {
-                if let Some((start, length)) = range.bounds(size) {
-                    self.skip(start)
-                        .take(length)
-                        .collect()
-                } else {
-                    empty().collect()
-                }
+                range.select(self, size)
+                     .map(|items| items.into_iter().collect())
+                     .unwrap_or_else(|| empty().collect())
             },
             Select::Key(_) => {
                 empty().collect()
@@ -228,13 +253,41 @@ impl<'a> ArrayMethod<'a> {
 
     pub fn returns_array(&self) -> bool {
         match self.method {
-            "split" | "chars" | "bytes" | "graphemes" => true,
+            "split" | "chars" | "bytes" | "graphemes" | "reverse" | "sort" | "unique" | "keys" | "values" => true,
             _ => false
         }
     }
 
+    /// Resolves the array that this method operates upon, which is either the name of an array
+    /// variable or an array expression.
+    fn resolve_array<E: Expander>(&self, expand_func: &E) -> Array {
+        if let Some(array) = expand_func.array(self.variable, Select::All) {
+            array
+        } else if is_expression(self.variable) {
+            expand_string(self.variable, expand_func, false)
+        } else {
+            Array::new()
+        }
+    }
+
+    /// Obtains the entries of the map that this method operates upon, which may be referenced
+    /// with or without the array sigil.
+    fn resolve_map<E: Expander>(&self, expand_func: &E) -> Vec<(Value, Array)> {
+        let name = if self.variable.starts_with('@') { &self.variable[1..] } else { self.variable };
+        match expand_func.map(name) {
+            Some(entries) => entries,
+            None => {
+                eprintln!("ion: {}: '{}' is not a map", self.method, name);
+                Vec::new()
+            }
+        }
+    }
+
     pub fn handle<E: Expander>(&self, current: &mut String, expand_func: &E) {
         match self.method {
+            "reverse" | "sort" | "unique" | "keys" | "values" => {
+                current.push_str(&self.handle_as_array(expand_func).join(" "));
+            },
             "split" => {
                 let variable = if let Some(variable) = expand_func.variable(self.variable, false) {
                     variable
@@ -245,7 +298,7 @@ impl<'a> ArrayMethod<'a> {
                 };
                 match (&self.pattern, self.selection.clone()) {
                     (&Pattern::StringPattern(pattern), Select::All) => current.push_str (
-                        &variable.split(&expand_string(pattern, expand_func, false).join(" "))
+                        &variable.split(&unescape_separator(&expand_string(pattern, expand_func, false).join(" ")))
                             .collect::<Vec<&str>>()
                             .join(" ")
                     ),
@@ -258,7 +311,7 @@ impl<'a> ArrayMethod<'a> {
                     (_, Select::None) => (),
                     (&Pattern::StringPattern(pattern), Select::Index(Index::Forward(id))) => {
                         current.push_str(
-                            variable.split(&expand_string(pattern, expand_func, false).join(" "))
+                            variable.split(&unescape_separator(&expand_string(pattern, expand_func, false).join(" ")))
                                     .nth(id)
                                     .unwrap_or_default())
                     } ,
@@ -270,7 +323,7 @@ impl<'a> ArrayMethod<'a> {
                     ),
                     (&Pattern::StringPattern(pattern), Select::Index(Index::Backward(id))) => {
                         current.push_str(
-                            variable.rsplit(&expand_string(pattern, expand_func, false).join(" "))
+                            variable.rsplit(&unescape_separator(&expand_string(pattern, expand_func, false).join(" ")))
                                     .nth(id)
                                     .unwrap_or_default())
                     },
@@ -281,26 +334,17 @@ impl<'a> ArrayMethod<'a> {
                                 .unwrap_or_default()
                     ),
                     (&Pattern::StringPattern(pattern), Select::Range(range)) => {
-                        let expansion = expand_string(pattern, expand_func, false).join(" ");
+                        let expansion = unescape_separator(&expand_string(pattern, expand_func, false).join(" "));
                         let iter = variable.split(&expansion);
-                        if let Some((start, length)) = range.bounds(iter.clone().count()) {
-                            let range = iter.skip(start)
-                                            .take(length)
-                                            .collect::<Vec<_>>()
-                                            .join(" ");
-                            current.push_str(&range)
+                        if let Some(range) = range.select(iter.clone(), iter.count()) {
+                            current.push_str(&range.join(" "))
                         }
                     },
                     (&Pattern::Whitespace, Select::Range(range)) => {
                         let len = variable.split(char::is_whitespace).filter(|x| !x.is_empty()).count();
-                        if let Some((start, length)) = range.bounds(len) {
-                            let range = variable.split(char::is_whitespace)
-                                                .filter(|x| !x.is_empty())
-                                                .skip(start)
-                                                .take(length)
-                                                .collect::<Vec<&str>>()
-                                                .join(" ");
-                            current.push_str(&range);
+                        let iter = variable.split(char::is_whitespace).filter(|x| !x.is_empty());
+                        if let Some(range) = range.select(iter, len) {
+                            current.push_str(&range.join(" "));
                         }
 
                     },
@@ -335,7 +379,7 @@ impl<'a> ArrayMethod<'a> {
                 return match (&self.pattern, self.selection.clone()) {
                     (_, Select::None) => Some("".into()).into_iter().collect(),
                     (&Pattern::StringPattern(pattern), Select::All) => variable
-                        .split(&expand_string(pattern, expand_func, false).join(" "))
+                        .split(&unescape_separator(&expand_string(pattern, expand_func, false).join(" ")))
                         .map(From::from)
                         .collect(),
                     (&Pattern::Whitespace, Select::All) => variable
@@ -344,7 +388,7 @@ impl<'a> ArrayMethod<'a> {
                         .map(From::from)
                         .collect(),
                     (&Pattern::StringPattern(pattern), Select::Index(Index::Forward(id))) =>
-                            variable.split(&expand_string(pattern, expand_func, false).join(" "))
+                            variable.split(&unescape_separator(&expand_string(pattern, expand_func, false).join(" ")))
                                     .nth(id)
                                     .map(From::from)
                                     .into_iter()
@@ -357,7 +401,7 @@ impl<'a> ArrayMethod<'a> {
                                     .into_iter()
                                     .collect(),
                     (&Pattern::StringPattern(pattern), Select::Index(Index::Backward(id))) =>
-                            variable.rsplit(&expand_string(pattern, expand_func, false).join(" "))
+                            variable.rsplit(&unescape_separator(&expand_string(pattern, expand_func, false).join(" ")))
                                     .nth(id)
                                     .map(From::from)
                                     .into_iter()
@@ -370,28 +414,19 @@ impl<'a> ArrayMethod<'a> {
                                     .into_iter()
                                     .collect(),
                     (&Pattern::StringPattern(pattern), Select::Range(range)) => {
-                        let expansion = expand_string(pattern, expand_func, false).join(" ");
+                        let expansion = unescape_separator(&expand_string(pattern, expand_func, false).join(" "));
                         let iter = variable.split(&expansion);
-                        if let Some((start, length)) = range.bounds(iter.clone().count()) {
-                            iter.skip(start)
-                                .take(length)
-                                .map(From::from)
-                                .collect()
-                        } else {
-                            Array::new()
+                        match range.select(iter.clone(), iter.count()) {
+                            Some(range) => range.into_iter().map(From::from).collect(),
+                            None => Array::new()
                         }
                     },
                     (&Pattern::Whitespace, Select::Range(range)) => {
                         let len = variable.split(char::is_whitespace).filter(|x| !x.is_empty()).count();
-                        if let Some((start, length)) = range.bounds(len) {
-                            variable.split(char::is_whitespace)
-                                    .filter(|x| !x.is_empty())
-                                    .skip(start)
-                                    .take(length)
-                                    .map(From::from)
-                                    .collect()
-                        } else {
-                            Array::new()
+                        let iter = variable.split(char::is_whitespace).filter(|x| !x.is_empty());
+                        match range.select(iter, len) {
+                            Some(range) => range.into_iter().map(From::from).collect(),
+                            None => Array::new()
                         }
                     },
                     (_, Select::Key(_)) => {
@@ -399,6 +434,51 @@ impl<'a> ArrayMethod<'a> {
                     }
                 }
             },
+            "reverse" => {
+                let mut array = self.resolve_array(expand_func);
+                array.reverse();
+                let len = array.len();
+                return array.into_iter().select(self.selection.clone(), len);
+            },
+            "sort" => {
+                // Arrays that consist entirely of numbers are sorted numerically.
+                let mut array = self.resolve_array(expand_func);
+                if array.iter().all(|value| value.parse::<f64>().is_ok()) {
+                    array.sort_by(|a, b| {
+                        let (a, b) = (a.parse::<f64>().unwrap(), b.parse::<f64>().unwrap());
+                        a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+                    });
+                } else {
+                    array.sort();
+                }
+                let len = array.len();
+                return array.into_iter().select(self.selection.clone(), len);
+            },
+            "keys" => {
+                let keys = self.resolve_map(expand_func)
+                    .into_iter()
+                    .map(|(key, _)| key)
+                    .collect::<Array>();
+                let len = keys.len();
+                return keys.into_iter().select(self.selection.clone(), len);
+            },
+            "values" => {
+                let values = self.resolve_map(expand_func)
+                    .into_iter()
+                    .flat_map(|(_, value)| value)
+                    .collect::<Array>();
+                let len = values.len();
+                return values.into_iter().select(self.selection.clone(), len);
+            },
+            "unique" => {
+                let mut seen = FnvHashSet::default();
+                let array = self.resolve_array(expand_func)
+                    .into_iter()
+                    .filter(|value| seen.insert(value.clone()))
+                    .collect::<Array>();
+                let len = array.len();
+                return array.into_iter().select(self.selection.clone(), len);
+            },
             "graphemes" => {
                 let variable = resolve_var!();
                 let graphemes = UnicodeSegmentation::graphemes(variable.as_str(), true);
@@ -434,8 +514,7 @@ impl<'a> ArrayMethod<'a> {
 /// Represents a method that operates on and returns a string
 #[derive(Debug, PartialEq, Clone)]
 pub struct StringMethod<'a> {
-    /// Name of this method: currently `join`, `len`, and `len_bytes` are the
-    /// supported methods
+    /// Name of this method, such as `join`, `len`, `replace`, or `trim`
     method: &'a str,
     /// Variable that this method will operator on. This is a bit of a misnomer
     /// as this can be an expression as well
@@ -483,10 +562,10 @@ impl<'a> StringMethod<'a> {
         macro_rules! string_case {
             ($method:tt) => {{
                 if let Some(value) = expand.variable(variable, false) {
-                    output.push_str(value.$method().as_str());
+                    output.push_str(&value.$method());
                 } else if is_expression(variable) {
                     let word = expand_string(variable, expand, false).join(pattern);
-                    output.push_str(word.$method().as_str());
+                    output.push_str(&word.$method());
                 }
             }}
         }
@@ -501,6 +580,27 @@ impl<'a> StringMethod<'a> {
             "parent"       => path_eval!(parent),
             "to_lowercase" => string_case!(to_lowercase),
             "to_uppercase" => string_case!(to_uppercase),
+            "trim"         => string_case!(trim),
+            "trim_left"    => string_case!(trim_left),
+            "trim_right"   => string_case!(trim_right),
+            "find" => {
+                // The position is counted in graphemes, to be consistent with `$len()` and slicing.
+                let pattern = expand_string(pattern, expand, false).join(" ");
+                let word = if let Some(value) = expand.variable(variable, false) {
+                    value
+                } else if is_expression(variable) {
+                    expand_string(variable, expand, false).join(" ")
+                } else {
+                    return;
+                };
+                match word.find(pattern.as_str()) {
+                    Some(position) => {
+                        let graphemes = UnicodeSegmentation::graphemes(&word[..position], true).count();
+                        output.push_str(&graphemes.to_string());
+                    },
+                    None => output.push_str("-1"),
+                }
+            },
             "repeat" => {
                 let pattern = expand_string(pattern, expand, false).join(" ");
                 match pattern.parse::<usize>() {
@@ -578,6 +678,29 @@ impl<'a> StringMethod<'a> {
                     output.push_str(&count.to_string());
                 }
             },
+            "sum" | "product" | "min" | "max" => {
+                let array = match expand.array(variable, Select::All) {
+                    Some(array) => array,
+                    None => expand_string(variable, expand, false),
+                };
+                match arithmetic::reduce(self.method, &array) {
+                    Ok(result) => output.push_str(&result),
+                    Err(why) => eprintln!("ion: {}", why),
+                }
+            },
+            "random" => {
+                let array = match expand.array(variable, Select::All) {
+                    Some(array) => array,
+                    None => expand_string(variable, expand, false),
+                };
+                if !array.is_empty() {
+                    // The element is chosen by the same generator as `$RANDOM`.
+                    let random = expand.variable("RANDOM", false)
+                        .and_then(|random| random.parse::<usize>().ok())
+                        .unwrap_or(0);
+                    output.push_str(&array[random % array.len()]);
+                }
+            },
             "len_bytes" => {
                 if let Some(value) = expand.variable(variable, false) {
                     output.push_str(&value.as_bytes().len().to_string());
@@ -615,11 +738,21 @@ pub enum WordToken<'a> {
     Array(Vec<&'a str>, Select),
     Variable(&'a str, bool, Select),
     ArrayVariable(&'a str, bool, Select),
+    /// An array variable followed by the splat operator, `@array...`, which is always split
+    /// into separate words, even when quoted.
+    ArraySplat(&'a str, Select),
     ArrayProcess(&'a str, bool, Select),
     Process(&'a str, bool, Select),
+    /// An ANSI-C quoted string, `$'...'`, whose escape sequences are interpreted when expanded.
+    AnsiString(&'a str),
+    /// A process substitution, which is `<(command)`, or `>(command)` when the second element
+    /// is set, that is replaced by a path to a pipe that is connected to the command.
+    ProcessSubstitution(&'a str, bool),
     StringMethod(StringMethod<'a>),
     ArrayMethod(ArrayMethod<'a>),
-    Arithmetic(&'a str)
+    Arithmetic(&'a str),
+    /// An arithmetic expression which is evaluated for each element of the arrays within it.
+    ArrayArithmetic(&'a str, bool)
     // Glob(&'a str),
 }
 
@@ -790,15 +923,23 @@ impl<'a, E: Expander + 'a> WordIterator<'a, E> {
     {
         self.read += 1;
         let start = self.read;
+        // The selection is expanded before it is parsed, so its indices may be given by
+        // variables, including subscripted variables, and arithmetic.
+        let mut depth = 0;
         while let Some(character) = iterator.next() {
-            if let b']' = character {
-                let value = expand_string(&self.data[start..self.read], self.expanders, false).join(" ");
-                let selection = match value.parse::<Select>() {
-                    Ok(selection) => selection,
-                    Err(_)       => Select::None
-                };
-                self.read += 1;
-                return selection
+            match character {
+                b'[' => depth += 1,
+                b']' if depth != 0 => depth -= 1,
+                b']' => {
+                    let value = expand_string(&self.data[start..self.read], self.expanders, false).join(" ");
+                    let selection = match value.parse::<Select>() {
+                        Ok(selection) => selection,
+                        Err(_)       => Select::None
+                    };
+                    self.read += 1;
+                    return selection
+                },
+                _ => (),
             }
             self.read += 1;
         }
@@ -881,10 +1022,19 @@ impl<'a, E: Expander + 'a> WordIterator<'a, E> {
                     panic!("ion: fatal error with syntax validation parsing: unterminated method");
                 },
                 b'[' => {
+                    // Chained subscripts select values nested within maps, and all but the
+                    // last subscript are kept within the name of the variable.
+                    let mut end = self.read;
+                    let mut selection = self.read_selection(iterator);
+                    while let Some(&b'[') = self.data.as_bytes().get(self.read) {
+                        let _ = iterator.next();
+                        end = self.read;
+                        selection = self.read_selection(iterator);
+                    }
                     return WordToken::ArrayVariable (
-                        &self.data[start..self.read],
+                        &self.data[start..end],
                         self.flags.contains(DQUOTE),
-                        self.read_selection(iterator)
+                        selection
                     );
                 },
                 // Only alphanumerical and underscores are allowed in variable names
@@ -901,6 +1051,17 @@ impl<'a, E: Expander + 'a> WordIterator<'a, E> {
         WordToken::ArrayVariable(&self.data[start..], self.flags.contains(DQUOTE), Select::All)
     }
 
+    /// Converts an array variable into a splat if it is immediately followed by `...`.
+    fn splat(&mut self, token: WordToken<'a>) -> WordToken<'a> {
+        match token {
+            WordToken::ArrayVariable(name, _, selection) if self.data[self.read..].starts_with("...") => {
+                self.read += 3;
+                WordToken::ArraySplat(name, selection)
+            },
+            token => token
+        }
+    }
+
     fn braced_array_variable<I>(&mut self, iterator : &mut I) -> WordToken<'a>
         where I : Iterator<Item=u8>
     {
@@ -943,47 +1104,55 @@ impl<'a, E: Expander + 'a> WordIterator<'a, E> {
         where I: Iterator<Item = u8>
     {
         let start = self.read;
-        let mut level = 0;
+        let end = match closing_paren(self.data.as_bytes(), start) {
+            Some(end) => end,
+            // The validator at the frontend should catch unterminated processes.
+            None => panic!("ion: fatal error with syntax validation: unterminated process"),
+        };
+        let _ = iterator.nth(end - start);
+        self.read = end + 1;
+
+        let output = &self.data[start..end];
+        if let Some(&b'[') = self.data.as_bytes().get(self.read) {
+            let _ = iterator.next();
+            WordToken::Process(output, self.flags.contains(DQUOTE), self.read_selection(iterator))
+        } else {
+            WordToken::Process(output, self.flags.contains(DQUOTE), Select::All)
+        }
+    }
+
+    fn ansi_string<I>(&mut self, iterator: &mut I) -> WordToken<'a>
+        where I: Iterator<Item = u8>
+    {
+        let start = self.read;
+        let mut escaped = false;
         while let Some(character) = iterator.next() {
             match character {
-                _ if self.flags.contains(BACKSL)      => self.flags ^= BACKSL,
-                b'\\'                                 => self.flags ^= BACKSL,
-                b'\'' if !self.flags.contains(DQUOTE) => self.flags ^= SQUOTE,
-                b'"'  if !self.flags.contains(SQUOTE) => self.flags ^= DQUOTE,
-                b'$'  if !self.flags.contains(SQUOTE) => {
-                    if self.data.as_bytes()[self.read+1] == b'(' {
-                        level += 1;
-                    }
-                },
-                b')' if !self.flags.contains(SQUOTE) => {
-                    if level == 0 {
-                        let output = &self.data[start..self.read];
-                        self.read += 1;
-                        return if let Some(&b'[') = self.data.as_bytes().get(self.read) {
-                            let _ = iterator.next();
-                            WordToken::Process(
-                                output,
-                                self.flags.contains(DQUOTE),
-                                self.read_selection(iterator)
-                            )
-                        } else {
-                            WordToken::Process(
-                                output,
-                                self.flags.contains(DQUOTE),
-                                Select::All
-                            )
-                        }
-                    } else {
-                        level -= 1;
-                    }
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'\'' => {
+                    let output = &self.data[start..self.read];
+                    self.read += 1;
+                    return WordToken::AnsiString(output);
                 }
                 _ => (),
             }
             self.read += 1;
         }
+        WordToken::AnsiString(&self.data[start..])
+    }
 
-        // The validator at the frontend should catch unterminated processes.
-        panic!("ion: fatal error with syntax validation: unterminated process");
+    fn process_substitution<I>(&mut self, iterator: &mut I, writes: bool) -> WordToken<'a>
+        where I: Iterator<Item = u8>
+    {
+        let start = self.read;
+        let end = match closing_paren(self.data.as_bytes(), start) {
+            Some(end) => end,
+            None => panic!("ion: fatal error with syntax validation: unterminated process substitution"),
+        };
+        let _ = iterator.nth(end - start);
+        self.read = end + 1;
+        WordToken::ProcessSubstitution(&self.data[start..end], writes)
     }
 
     /// Contains the logic for parsing array subshell syntax.
@@ -991,47 +1160,21 @@ impl<'a, E: Expander + 'a> WordIterator<'a, E> {
         where I: Iterator<Item = u8>
     {
         let start = self.read;
-        let mut level = 0;
-        while let Some(character) = iterator.next() {
-            match character {
-                _ if self.flags.contains(BACKSL)  => self.flags ^= BACKSL,
-                b'\\'                             => self.flags ^= BACKSL,
-                b'\'' if !self.flags.contains(DQUOTE) => self.flags ^= SQUOTE,
-                b'"'  if !self.flags.contains(SQUOTE) => self.flags ^= DQUOTE,
-                b'@'  if !self.flags.contains(SQUOTE) => {
-                    if self.data.as_bytes()[self.read+1] == b'(' {
-                        level += 1;
-                    }
-                },
-                b')' if !self.flags.contains(SQUOTE) => {
-                    if level == 0 {
-                        let array_process_contents = &self.data[start..self.read];
-                        self.read += 1;
-                        return if let Some(&b'[') = self.data.as_bytes().get(self.read) {
-                            let _ = iterator.next();
-                            WordToken::ArrayProcess (
-                                array_process_contents,
-                                self.flags.contains(DQUOTE),
-                                self.read_selection(iterator)
-                            )
-                        } else {
-                            WordToken::ArrayProcess (
-                                array_process_contents,
-                                self.flags.contains(DQUOTE),
-                                Select::All
-                            )
-                        }
-                    } else {
-                        level -= 1;
-                    }
-                }
-                _ => (),
-            }
-            self.read += 1;
-        }
+        let end = match closing_paren(self.data.as_bytes(), start) {
+            Some(end) => end,
+            // The validator at the frontend should catch unterminated processes.
+            None => panic!("ion: fatal error with syntax validation: unterminated array process"),
+        };
+        let _ = iterator.nth(end - start);
+        self.read = end + 1;
 
-        // The validator at the frontend should catch unterminated processes.
-        panic!("ion: fatal error with syntax validation: unterminated array process");
+        let output = &self.data[start..end];
+        if let Some(&b'[') = self.data.as_bytes().get(self.read) {
+            let _ = iterator.next();
+            WordToken::ArrayProcess(output, self.flags.contains(DQUOTE), self.read_selection(iterator))
+        } else {
+            WordToken::ArrayProcess(output, self.flags.contains(DQUOTE), Select::All)
+        }
     }
 
     /// Contains the grammar for parsing brace expansion syntax
@@ -1148,7 +1291,7 @@ impl<'a, E: Expander + 'a> WordIterator<'a, E> {
         }
     }
 
-    fn arithmetic_expression<I : Iterator<Item=u8>>(&mut self, iter : &mut I) -> WordToken<'a> {
+    fn arithmetic_expression<I : Iterator<Item=u8>>(&mut self, iter : &mut I) -> &'a str {
         let mut paren : i8 = 0;
         let start = self.read;
         while let Some(character) = iter.next() {
@@ -1160,7 +1303,7 @@ impl<'a, E: Expander + 'a> WordIterator<'a, E> {
                         let _ = iter.next();
                         let output = &self.data[start..self.read];
                         self.read += 2;
-                        return WordToken::Arithmetic(output)
+                        return output
                     } else {
                         paren -= 1;
                     }
@@ -1179,6 +1322,12 @@ impl<'a, E: Expander + 'a> Iterator for WordIterator<'a, E> {
     fn next(&mut self) -> Option<WordToken<'a>> {
         if self.read == self.data.len() { return None }
 
+        // A lone bracket is the name of the `[` builtin, rather than the start of an array.
+        if self.data == "[" {
+            self.read = 1;
+            return Some(WordToken::Normal("[", false, false));
+        }
+
         let mut iterator = self.data.bytes().skip(self.read);
         let mut start = self.read;
         let mut glob = false;
@@ -1235,6 +1384,12 @@ impl<'a, E: Expander + 'a> Iterator for WordIterator<'a, E> {
                         self.read += 1;
                         return Some(self.braces(&mut iterator));
                     },
+                    b'<' | b'>' if !self.flags.intersects(DQUOTE | SQUOTE) && self.flags.contains(EXPAND_PROCESSES)
+                        && self.data.as_bytes().get(self.read + 1) == Some(&b'(') => {
+                        let _ = iterator.next();
+                        self.read += 2;
+                        return Some(self.process_substitution(&mut iterator, character == b'>'));
+                    },
                     b'[' if !self.flags.contains(SQUOTE) => {
                         if self.glob_check(&mut iterator) {
                             glob = true;
@@ -1246,7 +1401,13 @@ impl<'a, E: Expander + 'a> Iterator for WordIterator<'a, E> {
                         match iterator.next() {
                             Some(b'(') => {
                                 self.read += 2;
-                                return if self.flags.contains(EXPAND_PROCESSES) {
+                                return if self.data.as_bytes()[self.read] == b'(' {
+                                    // Pop the incoming left paren
+                                    let _ = iterator.next();
+                                    self.read += 1;
+                                    let expression = self.arithmetic_expression(&mut iterator);
+                                    Some(WordToken::ArrayArithmetic(expression, self.flags.contains(DQUOTE)))
+                                } else if self.flags.contains(EXPAND_PROCESSES) {
                                     Some(self.array_process(&mut iterator))
                                 } else {
                                     Some(WordToken::Normal(
@@ -1258,11 +1419,13 @@ impl<'a, E: Expander + 'a> Iterator for WordIterator<'a, E> {
                             },
                             Some(b'{') => {
                                 self.read += 2;
-                                return Some(self.braced_array_variable(&mut iterator));
+                                let token = self.braced_array_variable(&mut iterator);
+                                return Some(self.splat(token));
                             },
                             _ => {
                                 self.read += 1;
-                                return Some(self.array_variable(&mut iterator));
+                                let token = self.array_variable(&mut iterator);
+                                return Some(self.splat(token));
                             }
                         }
                     }
@@ -1274,7 +1437,7 @@ impl<'a, E: Expander + 'a> Iterator for WordIterator<'a, E> {
                                     // Pop the incoming left paren
                                     let _ = iterator.next();
                                     self.read += 1;
-                                    Some(self.arithmetic_expression(&mut iterator))
+                                    Some(WordToken::Arithmetic(self.arithmetic_expression(&mut iterator)))
                                 } else if self.flags.contains(EXPAND_PROCESSES) {
                                     Some(self.process(&mut iterator))
                                 } else {
@@ -1289,6 +1452,15 @@ impl<'a, E: Expander + 'a> Iterator for WordIterator<'a, E> {
                                 self.read += 2;
                                 return Some(self.braced_variable(&mut iterator));
                             }
+                            Some(b'\'') => {
+                                return if self.flags.contains(EXPAND_PROCESSES) && !self.flags.contains(DQUOTE) {
+                                    self.read += 2;
+                                    Some(self.ansi_string(&mut iterator))
+                                } else {
+                                    self.read += 1;
+                                    Some(WordToken::Normal(&self.data[start..self.read], glob, tilde))
+                                };
+                            }
                             _ => {
                                 self.read += 1;
                                 return Some(self.variable(&mut iterator));
@@ -1362,6 +1534,11 @@ impl<'a, E: Expander + 'a> Iterator for WordIterator<'a, E> {
                 b'*'|b'?' if !self.flags.contains(SQUOTE) => {
                     glob = true;
                 },
+                // Extended glob patterns, such as `!(*.rs)` and `+(ab)`.
+                b'(' if !self.flags.intersects(SQUOTE | DQUOTE) && self.read > start
+                    && b"!+".contains(&self.data.as_bytes()[self.read - 1]) => {
+                    glob = true;
+                },
                 b'~' if !self.flags.intersects(SQUOTE | DQUOTE) => {
                     let output = &self.data[start..self.read];
                     if output != "" {
@@ -1370,6 +1547,10 @@ impl<'a, E: Expander + 'a> Iterator for WordIterator<'a, E> {
                         return self.next();
                     }
                 }
+                b'<' | b'>' if !self.flags.intersects(SQUOTE | DQUOTE) && self.flags.contains(EXPAND_PROCESSES)
+                    && self.data.as_bytes().get(self.read + 1) == Some(&b'(') => {
+                    return Some(WordToken::Normal(&self.data[start..self.read], glob, tilde));
+                }
                 _ => (),
             }
             self.read += 1;
@@ -1410,6 +1591,9 @@ mod tests {
         let range2 = Range::inclusive(Index::new(2), Index::new(-4));
         assert_eq!(Some((2, 5)), range2.bounds(10));
         assert_eq!(None, range2.bounds(3));
+        let range3 = Range::inclusive(Index::new(1), Index::new(-1)).stepped(3);
+        let vec = vec![0, 1, 2, 3, 4, 5, 6, 7];
+        assert_eq!(Some(vec![&1, &4, &7]), range3.select(vec.iter(), vec.len()));
     }
 
 
@@ -1458,6 +1642,7 @@ mod tests {
             WordToken::Array(second, Select::Index(Index::new(0))),
         ];
         compare(input, expected);
+        compare("[", vec![WordToken::Normal("[", false, false)]);
     }
 
     #[test]
@@ -1473,6 +1658,17 @@ mod tests {
         compare(input, expected);
     }
 
+    #[test]
+    fn array_splats() {
+        let input = "@array... \"@{array[1..]}...\"";
+        let expected = vec![
+            WordToken::ArraySplat("array", Select::All),
+            WordToken::Whitespace(" "),
+            WordToken::ArraySplat("array", Select::Range(Range::from(Index::new(1))))
+        ];
+        compare(input, expected);
+    }
+
     #[test]
     fn array_processes() {
         let input = "@(echo one two three) @(echo one two three)[0]";
@@ -1516,6 +1712,17 @@ mod tests {
         compare(input, expected);
     }
 
+    #[test]
+    fn chained_subscripts() {
+        let input = "@config[servers][0] @config[db][host]";
+        let expected = vec![
+            WordToken::ArrayVariable("config[servers]", false, Select::Index(Index::new(0))),
+            WordToken::Whitespace(" "),
+            WordToken::ArrayVariable("config[db]", false, Select::Key(Key{key: "host".into()})),
+        ];
+        compare(input, expected);
+    }
+
     #[test]
     fn nested_processes() {
         let input = "echo $(echo $(echo one)) $(echo one $(echo two) three)";
@@ -1529,6 +1736,19 @@ mod tests {
         compare(input, expected);
     }
 
+    #[test]
+    fn nested_processes_with_quotes() {
+        let input = "echo $(echo \")\" @(echo '(') $(date)) \"$(echo \"a b\")\"";
+        let expected = vec![
+            WordToken::Normal("echo",false, false),
+            WordToken::Whitespace(" "),
+            WordToken::Process("echo \")\" @(echo '(') $(date)", false, Select::All),
+            WordToken::Whitespace(" "),
+            WordToken::Process("echo \"a b\"", true, Select::All),
+        ];
+        compare(input, expected);
+    }
+
     #[test]
     fn words_process_with_quotes() {
         let input = "echo $(git branch | rg '[*]' | awk '{print $2}')";
@@ -1596,6 +1816,19 @@ mod tests {
         compare(input, expected);
     }
 
+    #[test]
+    fn test_array_arithmetic() {
+        let input = "echo @((@a * (2 + x))) @(echo 1)";
+        let expected = vec![
+            WordToken::Normal("echo", false, false),
+            WordToken::Whitespace(" "),
+            WordToken::ArrayArithmetic("@a * (2 + x)", false),
+            WordToken::Whitespace(" "),
+            WordToken::ArrayProcess("echo 1", false, Select::All),
+        ];
+        compare(input, expected);
+    }
+
     #[test]
     fn test_globbing() {
         let input = "barbaz* bingcrosb*";
@@ -1607,6 +1840,50 @@ mod tests {
         compare(input, expected);
     }
 
+    #[test]
+    fn test_extended_globbing() {
+        let input = "!(*.rs) +(ab)c '!(x)'";
+        let expected = vec![
+            WordToken::Normal("!(*.rs)", true, false),
+            WordToken::Whitespace(" "),
+            WordToken::Normal("+(ab)c", true, false),
+            WordToken::Whitespace(" "),
+            WordToken::Normal("!(x)", false, false)
+        ];
+        compare(input, expected);
+    }
+
+    #[test]
+    fn test_process_substitution() {
+        let input = "diff <(sort \"a)\" (b)) --out=>(cat) '<(x)'";
+        let expected = vec![
+            WordToken::Normal("diff", false, false),
+            WordToken::Whitespace(" "),
+            WordToken::ProcessSubstitution("sort \"a)\" (b)", false),
+            WordToken::Whitespace(" "),
+            WordToken::Normal("--out=", false, false),
+            WordToken::ProcessSubstitution("cat", true),
+            WordToken::Whitespace(" "),
+            WordToken::Normal("<(x)", false, false)
+        ];
+        compare(input, expected);
+    }
+
+    #[test]
+    fn test_ansi_strings() {
+        let input = "echo $'a\\'b\\n'c \"$'x'\"";
+        let expected = vec![
+            WordToken::Normal("echo", false, false),
+            WordToken::Whitespace(" "),
+            WordToken::AnsiString("a\\'b\\n"),
+            WordToken::Normal("c", false, false),
+            WordToken::Whitespace(" "),
+            WordToken::Normal("$", false, false),
+            WordToken::Normal("'x'", false, false),
+        ];
+        compare(input, expected);
+    }
+
     #[test]
     fn test_empty_strings() {
         let input = "rename '' 0 a \"\"";
@@ -1631,11 +1908,62 @@ mod tests {
             match var {
                 "pkmn1" => "Pokémon".to_owned().into(),
                 "pkmn2" => "Poke\u{0301}mon".to_owned().into(),
+                "start" => "1".to_owned().into(),
+                "end" => "3".to_owned().into(),
+                _ => None
+            }
+        }
+
+        fn array(&self, name: &str, selection: Select) -> Option<Array> {
+            match (name, selection) {
+                ("bounds", Select::Index(Index::Forward(1))) => Some(array!["4"]),
+                _ => None
+            }
+        }
+
+        fn map(&self, name: &str) -> Option<Vec<(Value, Array)>> {
+            match name {
+                "ports" => Some(vec![("http".into(), array!["80"]), ("https".into(), array!["443", "8443"])]),
                 _ => None
             }
         }
     }
 
+    #[test]
+    fn expanded_selections() {
+        let input = "@array[$start..$end] @array[$((start + 1))..] @array[$start...@bounds[1]]";
+        let expected = vec![
+            WordToken::ArrayVariable("array", false, Select::Range(Range::exclusive(Index::new(1), Index::new(3)))),
+            WordToken::Whitespace(" "),
+            WordToken::ArrayVariable("array", false, Select::Range(Range::from(Index::new(2)))),
+            WordToken::Whitespace(" "),
+            WordToken::ArrayVariable("array", false, Select::Range(Range::inclusive(Index::new(1), Index::new(4)))),
+        ];
+        let actual = WordIterator::new(input, true, &WithVars).collect::<Vec<WordToken>>();
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn string_methods() {
+        let expanders = WithVars;
+        let cases = vec![
+            ("trim", "\"  one two  \"", "", "one two"),
+            ("trim_left", "\"  one \"", "", "one "),
+            ("trim_right", "\" one  \"", "", " one"),
+            ("to_uppercase", "pkmn1", "", "POKÉMON"),
+            ("find", "pkmn2", "mon", "4"),
+            ("find", "pkmn1", "xyz", "-1"),
+            ("replace", "pkmn1", "Poké Poke", "Pokemon"),
+            ("repeat", "\"ab\"", "3", "ababab"),
+        ];
+        for (method, variable, pattern, expected) in cases {
+            let method = StringMethod { method, variable, pattern, selection: Select::All };
+            let mut output = String::new();
+            method.handle(&mut output, &expanders);
+            assert_eq!(expected, output);
+        }
+    }
+
     #[test]
     fn array_methods() {
         let expanders = WithVars;
@@ -1663,6 +1991,46 @@ mod tests {
         };
         let expected = array!["111"];
         assert_eq!(method.handle_as_array(&expanders), expected);
+        let method = ArrayMethod {
+            method: "sort",
+            variable: "[10 9 100 -1]",
+            pattern: Pattern::Whitespace,
+            selection: Select::All
+        };
+        let expected = array!["-1", "9", "10", "100"];
+        assert_eq!(method.handle_as_array(&expanders), expected);
+        let method = ArrayMethod {
+            method: "unique",
+            variable: "[b a b c a]",
+            pattern: Pattern::Whitespace,
+            selection: Select::All
+        };
+        let expected = array!["b", "a", "c"];
+        assert_eq!(method.handle_as_array(&expanders), expected);
+        let method = ArrayMethod {
+            method: "reverse",
+            variable: "[a b c]",
+            pattern: Pattern::Whitespace,
+            selection: Select::Index(Index::Forward(0))
+        };
+        let expected = array!["c"];
+        assert_eq!(method.handle_as_array(&expanders), expected);
+        let method = ArrayMethod {
+            method: "keys",
+            variable: "@ports",
+            pattern: Pattern::Whitespace,
+            selection: Select::All
+        };
+        let expected = array!["http", "https"];
+        assert_eq!(method.handle_as_array(&expanders), expected);
+        let method = ArrayMethod {
+            method: "values",
+            variable: "ports",
+            pattern: Pattern::Whitespace,
+            selection: Select::All
+        };
+        let expected = array!["80", "443", "8443"];
+        assert_eq!(method.handle_as_array(&expanders), expected);
     }
 
 }
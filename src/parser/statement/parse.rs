@@ -1,7 +1,7 @@
 use std::char;
 
 use super::super::{ArgumentSplitter, pipelines};
-use super::super::assignments::parse_assignment;
+use super::super::assignments::{parse_assignment, Binding};
 use super::super::pipelines::Pipeline;
 use shell::flow_control::{Case, ElseIf, FunctionArgument, Statement, Type};
 
@@ -17,6 +17,8 @@ fn collect<F>(arguments: &str, statement: F) -> Statement
     }
 }
 
+fn is_function_export(args: &str) -> bool { args == "-f" || args.starts_with("-f ") }
+
 fn is_valid_name(name: &str) -> bool { !name.chars().any(|c| !(c.is_alphanumeric() || c == '_')) }
 
 pub fn parse(code: &str) -> Statement {
@@ -25,16 +27,37 @@ pub fn parse(code: &str) -> Statement {
         "end" => return Statement::End,
         "break" => return Statement::Break,
         "continue" => return Statement::Continue,
-        "for" | "match" | "case" => {
+        "for" | "match" | "case" | "guard" | "switch" | "namespace" => {
             eprintln!("ion: syntax error: incomplete control flow statement");
             return Statement::Default;
         }
+        // The output of a block may be redirected, or piped into other commands, from its `end`.
+        _ if cmd.starts_with("end ") => {
+            let output = cmd[4..].trim_left();
+            let piped = output.starts_with('|');
+            let output = if piped { output[1..].trim_left() } else { output };
+            return collect(output, |pipeline| {
+                if pipeline.jobs.is_empty() == piped {
+                    eprintln!("ion: syntax error: expected a redirection or a pipe after end");
+                    Statement::Default
+                } else {
+                    Statement::RedirectedEnd(pipeline)
+                }
+            });
+        }
         _ if cmd.starts_with("let ") => return Statement::Let { expression: parse_assignment(cmd[4..].trim_left()) },
-        _ if cmd.starts_with("export ") => return Statement::Export(parse_assignment(cmd[7..].trim_left())),
+        _ if cmd.starts_with("const ") => {
+            let binding = parse_assignment(cmd[6..].trim_left());
+            return Statement::Let { expression: Binding::Constant(Box::new(binding)) }
+        }
+        // Exporting functions is handled by the `export` builtin.
+        _ if cmd.starts_with("export ") && !is_function_export(cmd[7..].trim_left()) => {
+            return Statement::Export(parse_assignment(cmd[7..].trim_left()))
+        }
         _ if cmd.starts_with("if ") => {
             return collect(cmd[3..].trim_left(), |pipeline| {
                 Statement::If {
-                    expression: pipeline,
+                    expression: vec![pipeline],
                     success: Vec::new(),
                     else_if: Vec::new(),
                     failure: Vec::new(),
@@ -49,44 +72,82 @@ pub fn parse(code: &str) -> Statement {
             } else if cmd.starts_with("if ") {
                 return collect(cmd[3..].trim_left(), |pipeline| {
                     Statement::ElseIf(ElseIf {
-                        expression: pipeline,
+                        expression: vec![pipeline],
                         success: Vec::new(),
                     })
                 });
             }
         }
+        _ if cmd.starts_with("guard ") => {
+            let cmd = cmd[6..].trim_left();
+            if !cmd.ends_with(" else") {
+                eprintln!("ion: syntax error: guard statements must end with the else keyword");
+                return Statement::Default;
+            }
+            return collect(cmd[..cmd.len() - 5].trim_right(), |pipeline| {
+                Statement::Guard {
+                    expression: pipeline,
+                    statements: Vec::new(),
+                }
+            });
+        }
+        _ if cmd.starts_with("coproc ") => {
+            // The name of the coprocess may be omitted, in which case `COPROC` is used.
+            let cmd = cmd[7..].trim_left();
+            let (name, body) = match cmd.find('{') {
+                Some(pos) => (cmd[..pos].trim_right(), &cmd[pos..]),
+                None => (cmd, ""),
+            };
+            let name = if name.is_empty() { "COPROC" } else { name };
+            if !is_valid_name(name) {
+                eprintln!("ion: syntax error: invalid coprocess name '{}'", name);
+                return Statement::Default;
+            } else if body.len() < 2 || !body.ends_with('}') {
+                eprintln!("ion: syntax error: coprocesses must be given a command within braces");
+                return Statement::Default;
+            }
+            return collect(body[1..body.len() - 1].trim(), |pipeline| {
+                Statement::Coproc { name: name.into(), pipeline }
+            });
+        }
         _ if cmd.starts_with("while ") => {
             return collect(cmd[6..].trim_left(), |pipeline| {
                 Statement::While {
-                    expression: pipeline,
+                    expression: vec![pipeline],
                     statements: Vec::new(),
+                    else_branch: Vec::new(),
                 }
             });
         }
         _ if cmd.starts_with("for ") => {
             let mut cmd = cmd[4..].trim_left();
-            let pos = match cmd.find(char::is_whitespace) {
-                Some(pos) => pos,
-                None => {
-                    eprintln!("ion: syntax error: incorrect for loop syntax");
-                    return Statement::Default;
-                }
-            };
+            // Multiple variables may be bound per iteration, as in `for key value in @map`.
+            let mut variables = Vec::new();
+            while !cmd.starts_with("in ") {
+                let pos = match cmd.find(char::is_whitespace) {
+                    Some(pos) => pos,
+                    None => {
+                        eprintln!("ion: syntax error: incorrect for loop syntax");
+                        return Statement::Default;
+                    }
+                };
 
-            let variable = &cmd[..pos];
-            cmd = &cmd[pos..].trim_left();
+                variables.push(cmd[..pos].into());
+                cmd = &cmd[pos..].trim_left();
+            }
 
-            if !cmd.starts_with("in ") {
+            if variables.is_empty() {
                 eprintln!("ion: syntax error: incorrect for loop syntax");
                 return Statement::Default;
             }
 
             return Statement::For {
-                variable: variable.into(),
+                variables: variables,
                 values: ArgumentSplitter::new(cmd[3..].trim_left())
                     .map(String::from)
                     .collect(),
                 statements: Vec::new(),
+                else_branch: Vec::new(),
             };
         }
         _ if cmd.starts_with("case ") => {
@@ -105,8 +166,33 @@ pub fn parse(code: &str) -> Statement {
                 cases: Vec::new(),
             };
         }
-        _ if cmd.starts_with("fn ") => {
+        _ if cmd.starts_with("namespace ") => {
+            let name = cmd[10..].trim_left();
+            if !is_valid_name(name) {
+                eprintln!("ion: syntax error: {} is not a valid namespace name", name);
+                return Statement::Default;
+            }
+            return Statement::Namespace {
+                name: name.into(),
+                statements: Vec::new(),
+            };
+        }
+        _ if cmd.starts_with("switch ") => {
+            return collect(cmd[7..].trim_left(), |pipeline| {
+                Statement::MatchStatus {
+                    expression: pipeline,
+                    cases: Vec::new(),
+                }
+            });
+        }
+        // Options supplied to the `fn` builtin are not function declarations.
+        _ if cmd.starts_with("fn ") && !cmd[3..].trim_left().starts_with("--") => {
             let cmd = cmd[3..].trim_left();
+            let (private, cmd) = if cmd.starts_with("-p ") {
+                (true, cmd[3..].trim_left())
+            } else {
+                (false, cmd)
+            };
             let pos = cmd.find(char::is_whitespace).unwrap_or(cmd.len());
             let name = &cmd[..pos];
             if !is_valid_name(name) {
@@ -166,6 +252,7 @@ pub fn parse(code: &str) -> Statement {
                         name: name.into(),
                         args: args,
                         statements: Vec::new(),
+                        private: private,
                     };
                 }
                 None => {
@@ -186,39 +273,68 @@ pub fn parse(code: &str) -> Statement {
 
 }
 
+fn get_function_arg(argument: &str) -> Option<FunctionArgument> {
+    let length = argument.len();
+    let argument = if argument.ends_with(":int") {
+        if length <= 4 {
+            return None;
+        }
+        let arg = &argument[..length - 4];
+        if arg.contains(':') {
+            return None;
+        }
+        FunctionArgument::Typed(arg.to_owned(), Type::Int)
+    } else if argument.ends_with(":float") {
+        if length <= 6 {
+            return None;
+        }
+        let arg = &argument[..length - 6];
+        if arg.contains(':') {
+            return None;
+        }
+        FunctionArgument::Typed(arg.to_owned(), Type::Float)
+    } else if argument.ends_with(":bool") {
+        if length <= 5 {
+            return None;
+        }
+        let arg = &argument[..length - 5];
+        if arg.contains(':') {
+            return None;
+        }
+        FunctionArgument::Typed(arg.to_owned(), Type::Bool)
+    } else {
+        FunctionArgument::Untyped(argument.to_owned())
+    };
+    Some(argument)
+}
+
 pub fn get_function_args(args: Vec<String>) -> Option<Vec<FunctionArgument>> {
     let mut fn_args = Vec::with_capacity(args.len());
-    for argument in args.into_iter() {
-        let length = argument.len();
-        let argument = if argument.ends_with(":int") {
-            if length <= 4 {
+    let mut defaults_found = false;
+    let mut args = args.into_iter().peekable();
+    while let Some(argument) = args.next() {
+        if argument.ends_with("...[]") {
+            let name = &argument[..argument.len() - 5];
+            // A variadic argument must be the last argument, and may not be combined with defaults.
+            if name.is_empty() || !is_valid_name(name) || defaults_found || args.peek().is_some() {
                 return None;
             }
-            let arg = &argument[..length - 4];
-            if arg.contains(':') {
-                return None;
-            }
-            FunctionArgument::Typed(arg.to_owned(), Type::Int)
-        } else if argument.ends_with(":float") {
-            if length <= 6 {
-                return None;
-            }
-            let arg = &argument[..length - 6];
-            if arg.contains(':') {
-                return None;
-            }
-            FunctionArgument::Typed(arg.to_owned(), Type::Float)
-        } else if argument.ends_with(":bool") {
-            if length <= 5 {
-                return None;
-            }
-            let arg = &argument[..length - 5];
-            if arg.contains(':') {
-                return None;
+            fn_args.push(FunctionArgument::Variadic(name.to_owned()));
+            break
+        }
+
+        let argument = match argument.find('=') {
+            Some(pos) => {
+                if pos == 0 {
+                    return None;
+                }
+                defaults_found = true;
+                let arg = get_function_arg(&argument[..pos])?;
+                FunctionArgument::Default(Box::new(arg), argument[pos + 1..].to_owned())
             }
-            FunctionArgument::Typed(arg.to_owned(), Type::Bool)
-        } else {
-            FunctionArgument::Untyped(argument)
+            // Arguments without a default may not follow arguments that have one.
+            None if defaults_found => return None,
+            None => get_function_arg(&argument)?,
         };
         fn_args.push(argument);
     }
@@ -229,6 +345,7 @@ pub fn get_function_args(args: Vec<String>) -> Option<Vec<FunctionArgument>> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use parser::pipelines::Input;
     use shell::{Job, JobKind};
     use shell::flow_control::Statement;
 
@@ -237,7 +354,7 @@ mod tests {
         // Default case where spaced normally
         let parsed_if = parse("if test 1 -eq 2");
         let correct_parse = Statement::If {
-            expression: Pipeline::new(
+            expression: vec![Pipeline::new(
                 vec![
                     Job::new(
                         vec![
@@ -252,7 +369,7 @@ mod tests {
                 ],
                 None,
                 None,
-            ),
+            )],
             success: vec![],
             else_if: vec![],
             failure: vec![],
@@ -264,6 +381,99 @@ mod tests {
         assert_eq!(correct_parse, parsed_if);
     }
 
+    #[test]
+    fn parsing_status_matches() {
+        let parsed_match = parse("switch grep -q foo");
+        let correct_parse = Statement::MatchStatus {
+            expression: Pipeline::new(
+                vec![
+                    Job::new(
+                        vec!["grep".to_owned(), "-q".to_owned(), "foo".to_owned()]
+                            .into_iter()
+                            .collect(),
+                        JobKind::Last
+                    ),
+                ],
+                None,
+                None,
+            ),
+            cases: vec![],
+        };
+        assert_eq!(correct_parse, parsed_match);
+    }
+
+    #[test]
+    fn parsing_coprocs() {
+        let correct_parse = Statement::Coproc {
+            name: "DB".into(),
+            pipeline: Pipeline::new(
+                vec![
+                    Job::new(
+                        vec!["sqlite3".to_owned(), "db".to_owned()].into_iter().collect(),
+                        JobKind::Last
+                    ),
+                ],
+                None,
+                None,
+            ),
+        };
+        assert_eq!(correct_parse, parse("coproc DB { sqlite3 db }"));
+
+        match parse("coproc {bc -l}") {
+            Statement::Coproc { name, .. } => assert_eq!(&*name, "COPROC"),
+            statement => panic!("expected a coprocess, found {:?}", statement),
+        }
+        assert_eq!(Statement::Default, parse("coproc DB sqlite3 db"));
+        assert_eq!(Statement::Default, parse("coproc D-B { sqlite3 db }"));
+    }
+
+    #[test]
+    fn parsing_guards() {
+        let parsed_guard = parse("guard test 1 -eq 1 else");
+        let correct_parse = Statement::Guard {
+            expression: Pipeline::new(
+                vec![
+                    Job::new(
+                        vec!["test".to_owned(), "1".to_owned(), "-eq".to_owned(), "1".to_owned()]
+                            .into_iter()
+                            .collect(),
+                        JobKind::Last
+                    ),
+                ],
+                None,
+                None,
+            ),
+            statements: vec![],
+        };
+        assert_eq!(correct_parse, parsed_guard);
+
+        // A guard without an else keyword is a syntax error
+        assert_eq!(Statement::Default, parse("guard test 1 -eq 1"));
+    }
+
+    #[test]
+    fn parsing_namespaces() {
+        let parsed_namespace = parse("namespace mylib");
+        let correct_parse = Statement::Namespace {
+            name: "mylib".into(),
+            statements: vec![],
+        };
+        assert_eq!(correct_parse, parsed_namespace);
+
+        // Private functions are declared with the -p flag
+        let parsed_fn = parse("fn -p helper a");
+        let correct_parse = Statement::Function {
+            description: "".into(),
+            name: "helper".into(),
+            args: vec![FunctionArgument::Untyped("a".to_owned())],
+            statements: vec![],
+            private: true,
+        };
+        assert_eq!(correct_parse, parsed_fn);
+
+        assert_eq!(Statement::Default, parse("namespace my-lib"));
+    }
+
     #[test]
     fn parsing_elses() {
         // Default case where spaced normally
@@ -280,6 +490,12 @@ mod tests {
         assert_eq!(correct_parse, parsed_if);
     }
 
+    #[test]
+    fn parsing_constants() {
+        let expression = Binding::Constant(Box::new(Binding::KeyValue("max".into(), "10".into())));
+        assert_eq!(Statement::Let { expression }, parse("const max = 10"));
+    }
+
     #[test]
     fn parsing_ends() {
         // Default case where spaced normally
@@ -298,6 +514,37 @@ mod tests {
         assert_eq!(correct_parse, parsed_if);
     }
 
+    #[test]
+    fn parsing_redirected_ends() {
+        match parse("end > log.txt") {
+            Statement::RedirectedEnd(pipeline) => {
+                assert!(pipeline.jobs.is_empty());
+                assert_eq!("log.txt", &pipeline.stdout.unwrap().file);
+            }
+            statement => panic!("expected a redirected end, found {:?}", statement),
+        }
+
+        match parse("end | sort -r") {
+            Statement::RedirectedEnd(pipeline) => {
+                assert_eq!(1, pipeline.jobs.len());
+                assert_eq!("sort -r", pipeline.to_string());
+            }
+            statement => panic!("expected a redirected end, found {:?}", statement),
+        }
+
+        assert_eq!(Statement::Default, parse("end sort"));
+        assert_eq!(Statement::Default, parse("end |"));
+        assert_eq!(Statement::Default, parse("end 3> log.txt"));
+
+        match parse("end < input.txt") {
+            Statement::RedirectedEnd(pipeline) => {
+                assert!(pipeline.jobs.is_empty());
+                assert_eq!(Some(Input::File("input.txt".into())), pipeline.stdin);
+            }
+            statement => panic!("expected a redirected end, found {:?}", statement),
+        }
+    }
+
     #[test]
     fn parsing_functions() {
         // Default case where spaced normally
@@ -307,6 +554,7 @@ mod tests {
             name: "bob".into(),
             args: Default::default(),
             statements: Default::default(),
+            private: false,
         };
         assert_eq!(correct_parse, parsed_if);
 
@@ -327,6 +575,7 @@ mod tests {
                 FunctionArgument::Untyped("b".to_owned()),
             ],
             statements: Default::default(),
+            private: false,
         };
         assert_eq!(correct_parse, parsed_if);
 
@@ -343,11 +592,53 @@ mod tests {
                 FunctionArgument::Untyped("b".to_owned()),
             ],
             statements: vec![],
+            private: false,
         };
         assert_eq!(correct_parse, parsed_if);
         let parsed_if = parse("fn bob a b --          bob is a nice function");
         assert_eq!(correct_parse, parsed_if);
         let parsed_if = parse("fn bob a b      --bob is a nice function");
         assert_eq!(correct_parse, parsed_if);
+
+        // Arguments with default values
+        let parsed_if = parse("fn bob a b=\"world\" c:int=5");
+        let correct_parse = Statement::Function {
+            description: "".into(),
+            name: "bob".into(),
+            args: vec![
+                FunctionArgument::Untyped("a".to_owned()),
+                FunctionArgument::Default(
+                    Box::new(FunctionArgument::Untyped("b".to_owned())),
+                    "\"world\"".to_owned()
+                ),
+                FunctionArgument::Default(
+                    Box::new(FunctionArgument::Typed("c".to_owned(), Type::Int)),
+                    "5".to_owned()
+                ),
+            ],
+            statements: vec![],
+            private: false,
+        };
+        assert_eq!(correct_parse, parsed_if);
+
+        // Arguments without defaults may not follow arguments with defaults
+        assert_eq!(Statement::Default, parse("fn bob a=1 b"));
+
+        // Variadic arguments collect the remaining arguments into an array
+        let parsed_if = parse("fn log level args...[]");
+        let correct_parse = Statement::Function {
+            description: "".into(),
+            name: "log".into(),
+            args: vec![
+                FunctionArgument::Untyped("level".to_owned()),
+                FunctionArgument::Variadic("args".to_owned()),
+            ],
+            statements: vec![],
+            private: false,
+        };
+        assert_eq!(correct_parse, parsed_if);
+
+        // Variadic arguments must be the last argument
+        assert_eq!(Statement::Default, parse("fn log args...[] level"));
     }
 }
@@ -59,8 +59,9 @@ pub struct StatementSplitter<'a> {
     read: usize,
     flags: Flags,
     array_level: u8,
-    array_process_level: u8,
-    process_level: u8,
+    /// The quotes which surround each of the subshells that are currently open, which are
+    /// restored when the subshell is closed, as quotes within a subshell are independent of them.
+    subshells: Vec<Flags>,
     brace_level: u8,
     math_paren_level: i8,
 }
@@ -72,12 +73,27 @@ impl<'a> StatementSplitter<'a> {
             read: 0,
             flags: Flags::empty(),
             array_level: 0,
-            array_process_level: 0,
-            process_level: 0,
+            subshells: Vec::new(),
             brace_level: 0,
             math_paren_level: 0,
         }
     }
+
+    /// Determines whether the character which was just read begins a command, being either the
+    /// first of the statement, or following a pipe, `&&`, `||`, `&`, or a `;` within a subshell.
+    fn is_command_start(&self, start: usize) -> bool {
+        match self.data[start..self.read - 1].trim_right().bytes().last() {
+            None | Some(b'|') | Some(b'&') | Some(b';') => true,
+            _ => false,
+        }
+    }
+
+    /// Determines whether the `<` which was just read begins a heredoc, rather than a herestring.
+    fn is_heredoc(&self) -> bool {
+        let bytes = self.data.as_bytes();
+        bytes.get(self.read) == Some(&b'<') && bytes.get(self.read + 1) != Some(&b'<')
+            && (self.read < 2 || bytes[self.read - 2] != b'<')
+    }
 }
 
 impl<'a> Iterator for StatementSplitter<'a> {
@@ -136,6 +152,17 @@ impl<'a> Iterator for StatementSplitter<'a> {
                 b'(' if self.flags.contains(MATHEXPR) => {
                     self.math_paren_level += 1;
                 }
+                // The parenthesis of an extended glob pattern, such as `!(*.rs)`, or of a process
+                // substitution, such as `<(sort file)`.
+                b'(' if !self.flags.intersects(SQUOTE | DQUOTE | COMM_1 | VARIAB | ARRAY) && self.read >= 2
+                    && b"!+?*<>".contains(&self.data.as_bytes()[self.read - 2]) => {
+                    self.subshells.push(Flags::empty());
+                }
+                // A group of commands which is executed within a subshell, such as `(cd dir; make)`.
+                b'(' if !self.flags.intersects(SQUOTE | DQUOTE | COMM_1 | VARIAB | ARRAY)
+                    && self.is_command_start(start) => {
+                    self.subshells.push(Flags::empty());
+                }
                 b'(' if !self.flags.intersects(COMM_1 | VARIAB | ARRAY) => {
                     if error.is_none() && !self.flags.intersects(SQUOTE | DQUOTE) {
                         error = Some(StatementError::InvalidCharacter(character as char, self.read))
@@ -149,11 +176,13 @@ impl<'a> Iterator for StatementSplitter<'a> {
                         // The next character will always be a left paren in this branch;
                         self.math_paren_level = -1;
                     } else {
-                        self.process_level += 1;
+                        self.subshells.push(self.flags & DQUOTE);
+                        self.flags -= DQUOTE;
                     }
                 }
                 b'(' if self.flags.contains(COMM_2) => {
-                    self.array_process_level += 1;
+                    self.subshells.push(self.flags & DQUOTE);
+                    self.flags -= DQUOTE;
                 }
                 b'(' if self.flags.intersects(VARIAB | ARRAY) => {
                     self.flags -= VARIAB | ARRAY;
@@ -185,28 +214,38 @@ impl<'a> Iterator for StatementSplitter<'a> {
                         self.math_paren_level -= 1;
                     }
                 }
-                b')' if !self.flags.contains(SQUOTE) && self.flags.contains(METHOD) && self.process_level == 0 => {
+                b')' if !self.flags.contains(SQUOTE) && self.flags.contains(METHOD) && self.subshells.is_empty() => {
                     self.flags ^= METHOD;
                 }
-                b')' if self.process_level == 0 && self.array_process_level == 0 && !self.flags.contains(SQUOTE) => {
+                b')' if self.subshells.is_empty() && !self.flags.contains(SQUOTE) => {
                     if error.is_none() && !self.flags.intersects(SQUOTE | DQUOTE) {
                         error = Some(StatementError::InvalidCharacter(character as char, self.read))
                     }
                 }
-                b')' if !self.flags.contains(SQUOTE) && self.process_level != 0 => self.process_level -= 1,
-                b')' if !self.flags.contains(SQUOTE) => self.array_process_level -= 1,
-                b';'
-                    if !self.flags.intersects(SQUOTE | DQUOTE) && self.process_level == 0 &&
-                           self.array_process_level == 0 => {
+                b')' if !self.flags.intersects(SQUOTE | DQUOTE) => {
+                    if let Some(quotes) = self.subshells.pop() {
+                        self.flags |= quotes;
+                    }
+                }
+                b';' if !self.flags.intersects(SQUOTE | DQUOTE) && self.subshells.is_empty() => {
                     return match error {
                         Some(error) => Some(Err(error)),
                         None => Some(Ok(self.data[start..self.read - 1].trim())),
                     };
                 }
+                // The body of a heredoc, which follows the line of its delimiter, may contain any
+                // text, and so the heredoc extends to the end of the statement.
+                b'<' if !self.flags.intersects(SQUOTE | DQUOTE | MATHEXPR) && self.subshells.is_empty()
+                    && self.is_heredoc() => {
+                    self.read = self.data.len();
+                    return match error {
+                        Some(error) => Some(Err(error)),
+                        None => Some(Ok(self.data[start..].trim())),
+                    };
+                }
                 b'#'
                     if self.read == 1 ||
-                           (!self.flags.intersects(SQUOTE | DQUOTE) && self.process_level == 0 &&
-                                self.array_process_level == 0 &&
+                           (!self.flags.intersects(SQUOTE | DQUOTE) && self.subshells.is_empty() &&
                                 match self.data.as_bytes()[self.read - 2] {
                                     b' ' | b'\t' => true,
                                     _ => false,
@@ -253,7 +292,7 @@ impl<'a> Iterator for StatementSplitter<'a> {
             self.read = self.data.len();
             match error {
                 Some(error) => Some(Err(error)),
-                None if self.process_level != 0 || self.array_process_level != 0 || self.array_level != 0 => {
+                None if !self.subshells.is_empty() || self.array_level != 0 => {
                     Some(Err(StatementError::UnterminatedSubshell))
                 }
                 None if self.flags.contains(METHOD) => Some(Err(StatementError::UnterminatedMethod)),
@@ -275,6 +314,31 @@ impl<'a> Iterator for StatementSplitter<'a> {
     }
 }
 
+#[test]
+fn subshell_groups() {
+    let command = "(cd src; ls) | wc -l; true && (echo \")\"; (exit 1)); echo (a)";
+    let results = StatementSplitter::new(command).collect::<Vec<Result<&str, StatementError>>>();
+    assert_eq!(results[0], Ok("(cd src; ls) | wc -l"));
+    assert_eq!(results[1], Ok("true && (echo \")\"; (exit 1))"));
+    assert_eq!(results[2], Err(StatementError::InvalidCharacter('(', 58)));
+    assert_eq!(results.len(), 3);
+}
+
+#[test]
+fn heredocs() {
+    let command = "echo one; cat << EOF\nit's #1; (two)\nEOF";
+    let results = StatementSplitter::new(command).collect::<Vec<Result<&str, StatementError>>>();
+    assert_eq!(results[0], Ok("echo one"));
+    assert_eq!(results[1], Ok("cat << EOF\nit's #1; (two)\nEOF"));
+    assert_eq!(results.len(), 2);
+
+    let command = "cat <<< one; echo $((1 << 2))";
+    let results = StatementSplitter::new(command).collect::<Vec<Result<&str, StatementError>>>();
+    assert_eq!(results[0], Ok("cat <<< one"));
+    assert_eq!(results[1], Ok("echo $((1 << 2))"));
+    assert_eq!(results.len(), 2);
+}
+
 #[test]
 fn syntax_errors() {
     let command = "echo (echo one); echo $( (echo one); echo ) two; echo $(echo one";
@@ -296,6 +360,24 @@ fn syntax_errors() {
     assert_eq!(results.len(), 1);
 }
 
+#[test]
+fn extended_globs() {
+    let command = "ls !(*.rs|*.toml); echo +(ab|cd)e ?(x)";
+    let statements = StatementSplitter::new(command).collect::<Vec<_>>();
+    assert_eq!(statements[0], Ok("ls !(*.rs|*.toml)"));
+    assert_eq!(statements[1], Ok("echo +(ab|cd)e ?(x)"));
+    assert_eq!(statements.len(), 2);
+}
+
+#[test]
+fn process_substitutions() {
+    let command = "diff <(sort a; echo) <(sort b); tee >(wc -l)";
+    let statements = StatementSplitter::new(command).collect::<Vec<_>>();
+    assert_eq!(statements[0], Ok("diff <(sort a; echo) <(sort b)"));
+    assert_eq!(statements[1], Ok("tee >(wc -l)"));
+    assert_eq!(statements.len(), 2);
+}
+
 #[test]
 fn methods() {
     let command = "echo $join(array, ', '); echo @join(var, ', ')";
@@ -360,6 +442,16 @@ fn nested_process() {
     assert_eq!(results[0], Ok(command));
 }
 
+#[test]
+fn nested_process_quotes() {
+    let command = "echo \"$(echo \"a; b\")\"; echo $(echo \")\" '(' $(date)); echo @(echo \")\")";
+    let results = StatementSplitter::new(command).collect::<Vec<Result<&str, StatementError>>>();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0], Ok("echo \"$(echo \"a; b\")\""));
+    assert_eq!(results[1], Ok("echo $(echo \")\" '(' $(date))"));
+    assert_eq!(results[2], Ok("echo @(echo \")\")"));
+}
+
 #[test]
 fn nested_array_process() {
     let command = "echo @(echo one @(echo two) three)";
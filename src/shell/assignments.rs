@@ -1,11 +1,14 @@
 use std::io::{self, Write};
 use std::env;
+use fnv::FnvHashMap;
 
 use parser::assignments::{
-    Binding, Operator, Value
+    Binding, Operator, Primitive, Value
 };
 use parser::{
+    arithmetic,
     Expander,
+    Select,
     ArgumentSplitter,
     expand_string,
 };
@@ -13,18 +16,29 @@ use types::{
     Identifier,
     Value as VString,
     Array as VArray,
+    BTreeMap,
+    Data,
+    HashMap,
     Key,
     ArrayVariableContext,
+    BTreeMapVariableContext,
+    HashMapVariableContext,
     VariableContext,
 };
 use super::status::*;
-use super::Shell;
+use super::variables::{remove_exported_data, EXPORTED_ARRAY_PREFIX, EXPORTED_BTREEMAP_PREFIX,
+                       EXPORTED_HASHMAP_PREFIX};
+use super::{FunctionExpander, Shell};
+use json;
 
 enum Action {
     UpdateString(Identifier, VString),
     UpdateStrings(Vec<Identifier>, VArray),
-    UpdateHashMap(Identifier, Key, VString),
+    UpdateHashMap(Identifier, Key, Data),
     UpdateArray(Identifier, VArray),
+    UpdateMap(Identifier, HashMap),
+    UpdateBTreeMap(Identifier, BTreeMap),
+    UpdateTyped(Identifier, Primitive, VString),
     List
 }
 
@@ -63,9 +77,184 @@ fn print_arrays(list: &ArrayVariableContext) {
     }
 }
 
+fn print_maps(hashmaps: &HashMapVariableContext, btreemaps: &BTreeMapVariableContext) {
+    let stdout = io::stdout();
+    let stdout = &mut stdout.lock();
+
+    let _ = stdout.write(b"\n# Maps\n");
+    for (key, map) in hashmaps {
+        let _ = write!(stdout, "{} = [", key);
+        for (inner_key, value) in map {
+            let _ = write!(stdout, " {}={}", inner_key, value);
+        }
+        let _ = stdout.write(b" ]\n");
+    }
+    for (key, map) in btreemaps {
+        let _ = write!(stdout, "{} = [", key);
+        for (inner_key, value) in map {
+            let _ = write!(stdout, " {}={}", inner_key, value);
+        }
+        let _ = stdout.write(b" ]\n");
+    }
+}
+
+/// Splits the elements of a bracketed literal, such as `[ a b=[ c d ] ]`, returning `None` if
+/// the expression is not bracketed.
+fn literal_elements(expression: &str) -> Option<Vec<&str>> {
+    let expression = expression.trim();
+    if expression.len() >= 2 && expression.starts_with('[') && expression.ends_with(']') {
+        Some(ArgumentSplitter::new(&expression[1..expression.len()-1]).collect())
+    } else {
+        None
+    }
+}
+
+/// Obtains the key and value of a `key=value` element within a map literal.
+fn key_value(element: &str) -> Option<(&str, &str)> {
+    element.find('=').and_then(|pos| {
+        let key = &element[..pos];
+        if key.is_empty() || key.contains('[') { None } else { Some((key, &element[pos+1..])) }
+    })
+}
+
+/// Parses a value within a map literal. Bracketed values are parsed as nested maps if each of
+/// their elements is a `key=value` pair, and as nested arrays otherwise. Nested maps are sorted
+/// by their keys if the map which contains them is sorted.
+fn parse_data<E: Expander>(expression: &str, sorted: bool, expanders: &E) -> Data {
+    match literal_elements(expression) {
+        Some(ref elements) if !elements.is_empty() && elements.iter().all(|e| key_value(e).is_some()) => {
+            let entries = elements.iter()
+                .filter_map(|element| key_value(element))
+                .map(|(key, value)| {
+                    let key = expand_string(key, expanders, false).join(" ");
+                    (key.into(), parse_data(value, sorted, expanders))
+                });
+            if sorted {
+                Data::BTreeMap(entries.collect())
+            } else {
+                Data::HashMap(entries.collect())
+            }
+        },
+        Some(elements) => Data::Array(elements.iter().flat_map(|element| {
+            if literal_elements(element).is_some() {
+                vec![parse_data(element, sorted, expanders)]
+            } else {
+                expand_string(element, expanders, false).into_iter().map(Data::Str).collect()
+            }
+        }).collect()),
+        None => Data::Str(expand_string(expression, expanders, false).join(" ")),
+    }
+}
+
+/// Parses a map literal of the form `[ key=value ... ]`, whose values may be nested arrays
+/// and maps.
+fn parse_map<E: Expander>(key: &str, expression: &str, sorted: bool, expanders: &E)
+    -> Result<Vec<(Key, Data)>, i32>
+{
+    let stderr = io::stderr();
+    let elements = match literal_elements(expression) {
+        Some(elements) => elements,
+        None => {
+            let _ = writeln!(&mut stderr.lock(), "ion: map '{}' must be assigned a map literal: [ key=value ... ]", key);
+            return Err(FAILURE);
+        }
+    };
+
+    let mut entries = Vec::with_capacity(elements.len());
+    for element in elements {
+        match key_value(element) {
+            Some((key, value)) => {
+                let key = expand_string(key, expanders, false).join(" ");
+                entries.push((key.into(), parse_data(value, sorted, expanders)));
+            },
+            None => {
+                let _ = writeln!(&mut stderr.lock(), "ion: map element '{}' is not a key=value pair", element);
+                return Err(FAILURE);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Prints an error if the value is not valid for the given type.
+fn check_type(key: &str, primitive: Primitive, value: &str) -> Result<(), i32> {
+    if primitive.is_valid(value) {
+        Ok(())
+    } else {
+        let stderr = io::stderr();
+        let _ = writeln!(&mut stderr.lock(), "ion: invalid value for '{}': expected {}, found '{}'",
+            key, primitive, value);
+        Err(FAILURE)
+    }
+}
+
+/// Raises an integer to a non-negative power, returning `None` if the result overflows.
+fn integer_power(base: i64, exponent: i64) -> Option<i64> {
+    match base {
+        _ if exponent < 0 => None,
+        0 | 1 => Some(if exponent == 0 { 1 } else { base }),
+        -1 => Some(if exponent % 2 == 0 { 1 } else { -1 }),
+        _ if exponent > 63 => None,
+        _ => (0..exponent).fold(Some(1), |acc, _| acc.and_then(|acc: i64| acc.checked_mul(base)))
+    }
+}
+
+/// Divides two integers, rounding the quotient towards negative infinity.
+fn floor_division(left: i64, right: i64) -> Option<i64> {
+    left.checked_div(right).map(|quotient| {
+        if left % right != 0 && (left < 0) != (right < 0) { quotient - 1 } else { quotient }
+    })
+}
+
+/// Applies the operator to the value of a variable that was declared as an integer.
+fn integer_math(key: &str, left: &str, operator: Operator, right: &str) -> Result<VString, i32> {
+    check_type(key, Primitive::Integer, left)?;
+    check_type(key, Primitive::Integer, right)?;
+    let (left, right) = (left.parse::<i64>().unwrap(), right.parse::<i64>().unwrap());
+    let result = match operator {
+        Operator::Add      => left.checked_add(right),
+        Operator::Subtract => left.checked_sub(right),
+        Operator::Multiply => left.checked_mul(right),
+        Operator::Divide   => left.checked_div(right),
+        Operator::IntegerDivide => floor_division(left, right),
+        Operator::Exponent => integer_power(left, right),
+        Operator::Concatenate | Operator::ConcatenateArray => unreachable!(),
+    };
+
+    result.map(|result| result.to_string()).ok_or_else(|| {
+        let stderr = io::stderr();
+        let _ = match operator {
+            Operator::Divide | Operator::IntegerDivide if right == 0 => writeln!(&mut stderr.lock(), "ion: division by zero"),
+            Operator::Exponent if right < 0 => {
+                writeln!(&mut stderr.lock(), "ion: integers can not be raised to a negative power")
+            },
+            _ => writeln!(&mut stderr.lock(), "ion: integer overflow in '{} {}= {}'", key, operator, right),
+        };
+        FAILURE
+    })
+}
+
+/// Applies the operator to the value of a variable that was declared as a float.
+fn float_math(key: &str, left: &str, operator: Operator, right: &str) -> Result<VString, i32> {
+    check_type(key, Primitive::Float, left)?;
+    check_type(key, Primitive::Float, right)?;
+    let (left, right) = (left.parse::<f64>().unwrap(), right.parse::<f64>().unwrap());
+    let result = match operator {
+        Operator::Add      => left + right,
+        Operator::Subtract => left - right,
+        Operator::Divide   => left / right,
+        Operator::IntegerDivide => (left / right).floor(),
+        Operator::Multiply => left * right,
+        Operator::Exponent => f64::powf(left, right),
+        Operator::Concatenate | Operator::ConcatenateArray => unreachable!(),
+    };
+    Ok(result.to_string())
+}
+
 fn parse_assignment<E: Expander>(
     binding: Binding,
     expanders: &E,
+    types: &FnvHashMap<Identifier, Primitive>,
 ) -> Result<Action, i32> {
     match binding {
         Binding::InvalidKey(key) => {
@@ -74,11 +263,14 @@ fn parse_assignment<E: Expander>(
             Err(FAILURE)
         },
         Binding::KeyValue(key, value) => match parse_expression(&value, expanders) {
-            Value::String(value) => Ok(Action::UpdateString(key, value)),
+            Value::String(value) => match types.get(&key).cloned() {
+                Some(primitive) => check_type(&key, primitive, &value).map(|_| Action::UpdateString(key, value)),
+                None => Ok(Action::UpdateString(key, value)),
+            },
             Value::Array(array) => Ok(Action::UpdateArray(key, array)),
         },
         Binding::MapKeyValue(key, inner_key, value) => {
-            Ok(Action::UpdateHashMap(key, inner_key, value))
+            Ok(Action::UpdateHashMap(key, inner_key, parse_data(&value, false, expanders)))
         },
         Binding::MultipleKeys(keys, value) => match parse_expression(&value, expanders) {
             Value::String(value) => {
@@ -87,15 +279,76 @@ fn parse_assignment<E: Expander>(
                 Ok(Action::UpdateStrings(keys, array))
             },
             Value::Array(array)  => Ok(Action::UpdateStrings(keys, array)),
-        },
+        }.and_then(|action| {
+            if let Action::UpdateStrings(ref keys, ref array) = action {
+                for (key, value) in keys.iter().zip(array.iter()) {
+                    if let Some(&primitive) = types.get(key) {
+                        check_type(key, primitive, value)?;
+                    }
+                }
+            }
+            Ok(action)
+        }),
         Binding::KeyOnly(key) => {
             let stderr = io::stderr();
             let _ = writeln!(&mut stderr.lock(), "ion: please provide value for variable '{}'", key);
             Err(FAILURE)
         },
         Binding::ListEntries => Ok(Action::List),
-        Binding::Math(key, operator, value) => {
+        Binding::Readonly(binding) | Binding::Constant(binding) => parse_assignment(*binding, expanders, types),
+        Binding::Typed(key, Primitive::HashMap, value) => {
+            parse_map(&key, &value, false, expanders)
+                .map(|entries| Action::UpdateMap(key, entries.into_iter().collect()))
+        },
+        Binding::Typed(key, Primitive::BTreeMap, value) => {
+            parse_map(&key, &value, true, expanders)
+                .map(|entries| Action::UpdateBTreeMap(key, entries.into_iter().collect()))
+        },
+        Binding::Typed(key, primitive, value) => match parse_expression(&value, expanders) {
+            Value::String(value) => {
+                check_type(&key, primitive, &value).map(|_| Action::UpdateTyped(key, primitive, value))
+            },
+            Value::Array(_) => {
+                let stderr = io::stderr();
+                let _ = writeln!(&mut stderr.lock(), "ion: variable '{}' of type {} can not be assigned an array",
+                    key, primitive);
+                Err(FAILURE)
+            }
+        },
+        Binding::Math(key, Operator::Concatenate, value) => {
+            let left = expanders.variable(&key, false).unwrap_or_default();
+            let result = match parse_expression(&value, expanders) {
+                Value::String(value) => left + &value,
+                Value::Array(array) => left + &array.join(" "),
+            };
+            match types.get(&key).cloned() {
+                Some(primitive) => check_type(&key, primitive, &result).map(|_| Action::UpdateString(key, result)),
+                None => Ok(Action::UpdateString(key, result)),
+            }
+        },
+        Binding::Math(key, Operator::ConcatenateArray, value) => {
+            let mut array = expanders.array(&key, Select::All).unwrap_or_default();
             match parse_expression(&value, expanders) {
+                Value::String(value) => array.push(value),
+                Value::Array(values) => array.extend(values),
+            }
+            Ok(Action::UpdateArray(key, array))
+        },
+        Binding::Math(key, operator, value) => {
+            match evaluate(parse_expression(&value, expanders))? {
+                Value::String(ref value) if types.contains_key(&key) => {
+                    let left = expanders.variable(&key, false).unwrap_or_default();
+                    let primitive = types[&key];
+                    match primitive {
+                        Primitive::Integer => integer_math(&key, &left, operator, value),
+                        Primitive::Float => float_math(&key, &left, operator, value),
+                        primitive => {
+                            let stderr = io::stderr();
+                            let _ = writeln!(&mut stderr.lock(), "ion: arithmetic is not supported on type {}", primitive);
+                            Err(FAILURE)
+                        }
+                    }.map(|result| Action::UpdateString(key, result))
+                },
                 Value::String(ref value) => {
                     let left = match expanders.variable(&key, false).and_then(|x| {
                         x.parse::<f32>().ok()
@@ -113,8 +366,10 @@ fn parse_assignment<E: Expander>(
                         Operator::Add      => left + right,
                         Operator::Subtract => left - right,
                         Operator::Divide   => left / right,
+                        Operator::IntegerDivide => (left / right).floor(),
                         Operator::Multiply => left * right,
-                        Operator::Exponent => f32::powf(left, right)
+                        Operator::Exponent => f32::powf(left, right),
+                        Operator::Concatenate | Operator::ConcatenateArray => unreachable!(),
                     };
 
                     Ok(Action::UpdateString(key, result.to_string()))
@@ -129,6 +384,19 @@ fn parse_assignment<E: Expander>(
     }
 }
 
+/// Prints an error and returns `true` if the binding assigns to a readonly variable.
+fn is_readonly(shell: &Shell, binding: &Binding) -> bool {
+    match binding.keys().into_iter().find(|key| shell.variables.is_readonly(key)) {
+        Some(key) => {
+            let stderr = io::stderr();
+            let kind = if shell.variables.is_constant(key) { "constant" } else { "readonly variable" };
+            let _ = writeln!(&mut stderr.lock(), "ion: cannot assign to {} '{}'", kind, key);
+            true
+        },
+        None => false
+    }
+}
+
 /// Represents: A variable store capable of setting local variables or
 /// exporting variables to some global environment
 pub trait VariableStore {
@@ -141,20 +409,73 @@ pub trait VariableStore {
 impl<'a> VariableStore for Shell<'a> {
 
     fn local(&mut self, binding: Binding) -> i32 {
-        match parse_assignment(binding, self) {
-            Ok(Action::UpdateArray(key, array)) => self.variables.set_array(&key, array),
-            Ok(Action::UpdateString(key, string)) => self.variables.set_var(&key, &string),
+        if is_readonly(self, &binding) { return FAILURE }
+        if binding.value().map_or(false, |value| self.references_unset_variable(value)) {
+            return FAILURE
+        }
+        if let Binding::Readonly(binding) = binding {
+            let keys: Vec<Identifier> = binding.keys().into_iter().map(Identifier::from).collect();
+            let status = self.local(*binding);
+            if status == SUCCESS {
+                for key in keys {
+                    self.variables.set_readonly(&key);
+                }
+            }
+            return status;
+        }
+        if let Binding::Constant(binding) = binding {
+            let keys: Vec<Identifier> = binding.keys().into_iter().map(Identifier::from).collect();
+            let status = self.local(*binding);
+            if status == SUCCESS {
+                for key in keys {
+                    self.variables.set_constant(&key);
+                }
+            }
+            return status;
+        }
+
+        let types = self.variables.types.clone();
+        self.forget_failed_globs();
+        let action = parse_assignment(binding, &FunctionExpander::new(self), &types);
+        if self.glob_failed() { return FAILURE }
+        match action {
+            Ok(Action::UpdateArray(key, array)) => {
+                self.variables.shadow(&key);
+                self.variables.set_array(&key, array);
+            },
+            Ok(Action::UpdateString(key, string)) => {
+                self.variables.shadow(&key);
+                if !self.variables.set_var(&key, &string) { return FAILURE }
+            },
             Ok(Action::UpdateStrings(keys, array)) => {
+                let mut status = SUCCESS;
                 for (key, value) in keys.iter().zip(array.iter()) {
-                    self.variables.set_var(key, value);
+                    self.variables.shadow(key);
+                    if !self.variables.set_var(key, value) { status = FAILURE }
                 }
+                return status;
             },
             Ok(Action::UpdateHashMap(key, inner_key, value)) => {
-                self.variables.set_hashmap_value(&key, &inner_key, &value)
+                self.variables.shadow(&key);
+                self.variables.set_hashmap_value(&key, &inner_key, value)
+            },
+            Ok(Action::UpdateMap(key, map)) => {
+                self.variables.shadow(&key);
+                self.variables.set_hashmap(&key, map)
+            },
+            Ok(Action::UpdateBTreeMap(key, map)) => {
+                self.variables.shadow(&key);
+                self.variables.set_btreemap(&key, map)
+            },
+            Ok(Action::UpdateTyped(key, primitive, string)) => {
+                self.variables.shadow(&key);
+                self.variables.set_var(&key, &string);
+                self.variables.set_type(&key, primitive);
             },
             Ok(Action::List) => {
                 print_vars(&self.variables.variables);
                 print_arrays(&self.variables.arrays);
+                print_maps(&self.variables.hashmaps, &self.variables.btreemaps);
             }
             Err(code) => return code,
         };
@@ -163,16 +484,42 @@ impl<'a> VariableStore for Shell<'a> {
     }
 
     fn export(&mut self, binding: Binding) -> i32 {
-        match parse_assignment(binding, self) {
-            Ok(Action::UpdateArray(key, array)) => env::set_var(&key, array.join(" ")),
-            Ok(Action::UpdateString(key, string)) => env::set_var(&key, string),
+        if is_readonly(self, &binding) { return FAILURE }
+        if binding.value().map_or(false, |value| self.references_unset_variable(value)) {
+            return FAILURE
+        }
+        let types = self.variables.types.clone();
+        self.forget_failed_globs();
+        let action = parse_assignment(binding, &FunctionExpander::new(self), &types);
+        if self.glob_failed() { return FAILURE }
+        match action {
+            Ok(Action::UpdateArray(key, array)) => {
+                // Child ion processes will import the array from its JSON representation, while
+                // other processes will see the elements of the array separated by spaces.
+                remove_exported_data(&key, Some(EXPORTED_ARRAY_PREFIX));
+                env::set_var([EXPORTED_ARRAY_PREFIX, &*key].concat(), json::array_to_json(&array));
+                env::set_var(&key, array.join(" "))
+            },
+            Ok(Action::UpdateString(key, string)) | Ok(Action::UpdateTyped(key, _, string)) => {
+                remove_exported_data(&key, None);
+                env::set_var(&key, string)
+            },
             Ok(Action::UpdateStrings(keys, array)) => {
                 for (key, value) in keys.iter().zip(array.iter()) {
+                    remove_exported_data(key, None);
                     env::set_var(key, value);
                 }
             }
             Ok(Action::UpdateHashMap(key, inner_key, value)) => {
-                self.variables.set_hashmap_value(&key, &inner_key, &value)
+                self.variables.set_hashmap_value(&key, &inner_key, value)
+            },
+            Ok(Action::UpdateMap(key, map)) => {
+                remove_exported_data(&key, Some(EXPORTED_HASHMAP_PREFIX));
+                env::set_var([EXPORTED_HASHMAP_PREFIX, &*key].concat(), json::to_json(&Data::HashMap(map)))
+            },
+            Ok(Action::UpdateBTreeMap(key, map)) => {
+                remove_exported_data(&key, Some(EXPORTED_BTREEMAP_PREFIX));
+                env::set_var([EXPORTED_BTREEMAP_PREFIX, &*key].concat(), json::to_json(&Data::BTreeMap(map)))
             },
             Ok(Action::List) => {
                 let stdout = io::stdout();
@@ -192,6 +539,22 @@ impl<'a> VariableStore for Shell<'a> {
 
 }
 
+/// Evaluates a string which is not already a number as an arithmetic expression, so that
+/// expressions such as `let x += "sqrt(y) * 2"` may be used. An error is printed if the string
+/// is not a valid expression, and the assignment fails.
+fn evaluate(value: Value) -> Result<Value, i32> {
+    match value {
+        Value::String(ref value) if value.parse::<f64>().is_err() => {
+            arithmetic::eval(value).map(Value::String).map_err(|why| {
+                let stderr = io::stderr();
+                let _ = writeln!(&mut stderr.lock(), "ion: {}", why);
+                FAILURE
+            })
+        },
+        value => Ok(value),
+    }
+}
+
 fn parse_expression<E: Expander>(
     expression: &str,
     shell_funcs: &E
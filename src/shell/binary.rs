@@ -13,6 +13,8 @@ use std::mem;
 use std::path::{Path, PathBuf};
 use sys;
 use super::completer::*;
+use super::flags::{EXPAND_ALIASES, MONITOR};
+use super::prompt::expand_prompt;
 use super::flow_control::Statement;
 use super::status::*;
 use super::{Shell, FlowLogic, JobControl, ShellHistory, Variables, DirectoryStack};
@@ -42,7 +44,7 @@ impl<'a> Binary for Shell<'a> {
     fn prompt(&self) -> String {
         if self.flow_control.level == 0 {
             let prompt_var = self.variables.get_var_or_empty("PROMPT");
-            expand_string(&prompt_var, self, false).join(" ")
+            expand_prompt(self, &prompt_var)
         } else {
             "    ".repeat(self.flow_control.level as usize)
         }
@@ -50,12 +52,6 @@ impl<'a> Binary for Shell<'a> {
 
     fn readln(&mut self) -> Option<String> {
         {
-            let vars_ptr = &self.variables as *const Variables;
-            let dirs_ptr = &self.directory_stack as *const DirectoryStack;
-            let funcs = &self.functions;
-            let vars = &self.variables;
-            let builtins = self.builtins;
-
             // Collects the current list of values from history for completion.
             let history = &self.context.as_ref().unwrap().history.buffers.iter()
                 // Map each underlying `liner::Buffer` into a `String`.
@@ -65,10 +61,45 @@ impl<'a> Binary for Shell<'a> {
 
             loop {
                 let prompt = self.prompt();
-                let line = self.context.as_mut().unwrap().read_line(prompt, &mut move |Event { editor, kind }| {
+                // Completion functions are executed by the shell while the prompt is being read,
+                // so the context is taken out of the shell until then, and the shell is only
+                // accessed through this pointer.
+                let mut context = self.context.take().unwrap();
+                let shell_ptr = self as *mut Shell<'a>;
+                let line = context.read_line(prompt, &mut move |Event { editor, kind }| {
                     if let EventKind::BeforeComplete = kind {
+                        let shell = unsafe { &mut *shell_ptr };
                         let (words, pos) = editor.get_words_and_cursor_position();
 
+                        // The arguments of commands which have completions registered with
+                        // `complete` are completed by them, rather than as files.
+                        let index = match pos {
+                            CursorPosition::InWord(index) |
+                            CursorPosition::OnWordLeftEdge(index) |
+                            CursorPosition::OnWordRightEdge(index) => index,
+                            CursorPosition::InSpace(Some(index), _) => index + 1,
+                            CursorPosition::InSpace(None, _) => 0,
+                        };
+                        let completions = {
+                            let buffer = editor.current_buffer();
+                            let line: String = buffer.chars().cloned().collect();
+                            let arguments: Vec<String> = words.iter()
+                                .map(|&(start, end)| buffer.range(start, end))
+                                .collect();
+                            custom_completions(shell, &line, editor.cursor(), &arguments, index)
+                        };
+                        if let Some(completions) = completions {
+                            let completer = BasicCompleter::new(completions);
+                            mem::replace(&mut editor.context().completer, Some(Box::new(completer)));
+                            return;
+                        }
+
+                        let vars_ptr = &shell.variables as *const Variables;
+                        let dirs_ptr = &shell.directory_stack as *const DirectoryStack;
+                        let funcs = &shell.functions;
+                        let vars = &shell.variables;
+                        let builtins = shell.builtins;
+
                         let filename = match pos {
                             CursorPosition::InWord(index) => index > 0,
                             CursorPosition::InSpace(Some(_), _) => true,
@@ -138,6 +169,7 @@ impl<'a> Binary for Shell<'a> {
                         }
                     }
                 });
+                self.context = Some(context);
 
                 match line {
                     Ok(line) => return Some(line),
@@ -160,11 +192,15 @@ impl<'a> Binary for Shell<'a> {
     }
 
     fn terminate_script_quotes<I: Iterator<Item = String>>(&mut self, mut lines: I) {
+        // Scripts which are sourced by another script keep their own line count.
+        let previous_line = mem::replace(&mut self.variables.line_number, 0);
         while let Some(command) = lines.next() {
+            self.variables.line_number += 1;
             let mut buffer = QuoteTerminator::new(command);
             while !buffer.check_termination() {
                 loop {
                     if let Some(command) = lines.next() {
+                        self.variables.line_number += 1;
                         buffer.append(command);
                         break
                     } else {
@@ -174,6 +210,10 @@ impl<'a> Binary for Shell<'a> {
                     }
                 }
             }
+            // With job control enabled, finished jobs are reported as they would be at a prompt.
+            if self.flags & MONITOR != 0 {
+                self.report_finished_jobs();
+            }
             self.on_command(&buffer.consume());
         }
         // The flow control level being non zero means that we have a statement that has
@@ -182,6 +222,7 @@ impl<'a> Binary for Shell<'a> {
             eprintln!("ion: unexpected end of script: expected end block for `{}`",
                 self.flow_control.current_statement.short());
         }
+        self.variables.line_number = previous_line;
     }
 
     fn terminate_quotes(&mut self, command: String) -> Result<String, ()> {
@@ -190,6 +231,7 @@ impl<'a> Binary for Shell<'a> {
         while !buffer.check_termination() {
             loop {
                 if let Some(command) = self.readln() {
+                    self.variables.line_number += 1;
                     buffer.append(command);
                     break
                 } else {
@@ -221,6 +263,8 @@ impl<'a> Binary for Shell<'a> {
     }
 
     fn execute_interactive(mut self) {
+        // Job control and aliases are always enabled for an interactive session.
+        self.flags |= MONITOR | EXPAND_ALIASES;
         self.context = Some({
             let mut context = Context::new();
             context.word_divider_fn = Box::new(word_divide);
@@ -257,9 +301,25 @@ impl<'a> Binary for Shell<'a> {
         );
 
         loop {
+            self.report_finished_jobs();
             if let Some(command) = self.readln() {
+                self.variables.line_number += 1;
                 if ! command.is_empty() {
                     if let Ok(command) = self.terminate_quotes(command) {
+                        // References to earlier commands, such as `!!`, are expanded first, and
+                        // the expanded command is printed before it is executed.
+                        let command = match self.expand_history(&command) {
+                            Ok(Some(expanded)) => {
+                                println!("{}", expanded);
+                                expanded
+                            }
+                            Ok(None) => command,
+                            Err(why) => {
+                                eprintln!("ion: {}", why);
+                                continue
+                            }
+                        };
+
                         // Parse and potentially execute the command.
                         self.on_command(command.trim());
 
@@ -288,6 +348,8 @@ impl<'a> Binary for Shell<'a> {
     }
 
     fn main(mut self) {
+        self.import_functions();
+        self.import_variables();
         let mut args = env::args().skip(1);
         if let Some(path) = args.next() {
             if path == "-c" {
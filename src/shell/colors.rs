@@ -0,0 +1,88 @@
+//! Provides the `color` variable namespace, which expands to ANSI escape sequences, as in
+//! `echo ${color::red}error${color::reset}`.
+
+use types::Value;
+
+/// Obtains the SGR parameter of a named color or attribute.
+fn attribute(name: &str) -> Option<String> {
+    let code = match name {
+        "reset" | "default" => 0,
+        "bold" => 1,
+        "dim" => 2,
+        "italic" => 3,
+        "underline" => 4,
+        "blink" => 5,
+        "reverse" => 7,
+        "hidden" => 8,
+        _ => {
+            let (background, name) = if name.starts_with("bg_") {
+                (true, &name[3..])
+            } else {
+                (false, name)
+            };
+
+            // 256-color palette values are supplied as numbers.
+            if let Ok(value) = name.parse::<u8>() {
+                return Some(format!("{};5;{}", if background { 48 } else { 38 }, value));
+            }
+
+            let (light, name) = if name.starts_with("light") {
+                (true, &name[5..])
+            } else {
+                (false, name)
+            };
+
+            let color = match name {
+                "black" => 0,
+                "red" => 1,
+                "green" => 2,
+                "yellow" => 3,
+                "blue" => 4,
+                "magenta" => 5,
+                "cyan" => 6,
+                "white" => 7,
+                _ => return None,
+            };
+
+            match (background, light) {
+                (false, false) => 30 + color,
+                (false, true) => 90 + color,
+                (true, false) => 40 + color,
+                (true, true) => 100 + color,
+            }
+        }
+    };
+
+    Some(code.to_string())
+}
+
+/// Expands a comma-separated list of colors and attributes, such as `lightred,bold` or
+/// `bg_blue`, into a single escape sequence.
+pub fn color(names: &str) -> Option<Value> {
+    let mut codes = Vec::new();
+    for name in names.split(',') {
+        match attribute(name.trim()) {
+            Some(code) => codes.push(code),
+            None => {
+                eprintln!("ion: invalid color: '{}'", name);
+                return None;
+            }
+        }
+    }
+    Some(format!("\x1B[{}m", codes.join(";")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors() {
+        assert_eq!(Some("\x1B[31m".into()), color("red"));
+        assert_eq!(Some("\x1B[91;1m".into()), color("lightred,bold"));
+        assert_eq!(Some("\x1B[44m".into()), color("bg_blue"));
+        assert_eq!(Some("\x1B[38;5;196;48;5;16m".into()), color("196,bg_16"));
+        assert_eq!(Some("\x1B[0m".into()), color("reset"));
+        assert_eq!(None, color("purple"));
+    }
+}
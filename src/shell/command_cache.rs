@@ -0,0 +1,189 @@
+//! Remembers where the executables of commands were found within the `PATH`, so that the
+//! directories of the `PATH` need not be searched each time that a command is executed. The files
+//! which autoload functions from the `FNPATH` are remembered in the same way.
+
+use fnv::{FnvHashMap, FnvHashSet};
+use std::env;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use sys;
+
+pub fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).ok()
+        .map_or(false, |metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+/// Searches each directory of the `PATH` for the executable of the command.
+pub fn search_path(name: &str) -> Option<PathBuf> {
+    env::var("PATH").ok().and_then(|paths| {
+        paths.split(sys::PATH_SEPARATOR)
+            .filter(|directory| !directory.is_empty())
+            .map(|directory| Path::new(directory).join(name))
+            .find(|path| is_executable(path))
+    })
+}
+
+/// Searches each directory of the `FNPATH` for a file named after the function, either with or
+/// without an `.ion` extension.
+fn search_fnpath(fnpath: &str, name: &str) -> Option<PathBuf> {
+    for directory in fnpath.split(sys::PATH_SEPARATOR).filter(|directory| !directory.is_empty()) {
+        for file_name in &[name.to_owned(), [name, ".ion"].concat()] {
+            let path = Path::new(directory).join(file_name);
+            if path.is_file() {
+                return Some(path);
+            }
+        }
+    }
+    None
+}
+
+/// A cached executable, along with the number of times that the cache has resolved it.
+pub struct Hashed {
+    pub path: PathBuf,
+    pub hits: u32,
+}
+
+#[derive(Default)]
+pub struct CommandCache {
+    /// The value of the `PATH` when the commands were cached, which invalidates each of them
+    /// when it changes.
+    path: String,
+    commands: FnvHashMap<String, Hashed>,
+    /// Commands which were not found when checking whether they exist.
+    missing: FnvHashSet<String>,
+    /// The value of the `FNPATH` when the files of functions were cached.
+    fnpath: String,
+    functions: FnvHashMap<String, Option<PathBuf>>,
+}
+
+impl CommandCache {
+    /// Clears the cache if the `PATH` has changed since the commands were cached.
+    fn validate(&mut self) {
+        let path = env::var("PATH").unwrap_or_default();
+        if path != self.path {
+            self.commands.clear();
+            self.missing.clear();
+            self.path = path;
+        }
+    }
+
+    /// Finds the executable of the command, searching the `PATH` only if it has not already
+    /// been cached, or if the cached executable no longer exists.
+    pub fn resolve(&mut self, name: &str) -> Option<PathBuf> {
+        self.validate();
+        if let Some(hashed) = self.commands.get_mut(name) {
+            if is_executable(&hashed.path) {
+                hashed.hits += 1;
+                return Some(hashed.path.clone());
+            }
+        }
+
+        match search_path(name) {
+            Some(path) => {
+                self.missing.remove(name);
+                self.commands.insert(name.into(), Hashed { path: path.clone(), hits: 1 });
+                Some(path)
+            }
+            None => {
+                self.commands.remove(name);
+                None
+            }
+        }
+    }
+
+    /// Whether the command has an executable. Unlike `resolve`, this does not count as a use of the
+    /// command, and a command which was not found before is not searched for again until the
+    /// `PATH` changes, or the cache is cleared.
+    pub fn contains(&mut self, name: &str) -> bool {
+        self.validate();
+        if self.missing.contains(name) {
+            return false;
+        }
+        if self.commands.get(name).map_or(false, |hashed| is_executable(&hashed.path)) {
+            return true;
+        }
+
+        match search_path(name) {
+            Some(path) => {
+                self.commands.insert(name.into(), Hashed { path: path, hits: 0 });
+                true
+            }
+            None => {
+                self.commands.remove(name);
+                self.missing.insert(name.into());
+                false
+            }
+        }
+    }
+
+    /// The executable which the command has been cached as, if it still exists. This does not
+    /// count as a use of the command.
+    pub fn cached(&mut self, name: &str) -> Option<PathBuf> {
+        self.validate();
+        match self.commands.get(name) {
+            Some(hashed) if is_executable(&hashed.path) => Some(hashed.path.clone()),
+            _ => None,
+        }
+    }
+
+    /// Finds the file within the `FNPATH` which autoloads the function, searching its directories
+    /// only the first time that the function is looked for, unless the `FNPATH` has changed since.
+    pub fn function_file(&mut self, fnpath: &str, name: &str) -> Option<PathBuf> {
+        if fnpath != self.fnpath {
+            self.functions.clear();
+            self.fnpath = fnpath.into();
+        }
+        let cached = match self.functions.get(name) {
+            Some(&Some(ref path)) if path.is_file() => Some(Some(path.clone())),
+            Some(&None) => Some(None),
+            _ => None,
+        };
+        if let Some(file) = cached {
+            return file;
+        }
+
+        let file = search_fnpath(fnpath, name);
+        self.functions.insert(name.into(), file.clone());
+        file
+    }
+
+    /// Caches the executable of the command, without it having been executed, which fails if
+    /// it could not be found.
+    pub fn add(&mut self, name: &str) -> bool {
+        self.validate();
+        match search_path(name) {
+            Some(path) => {
+                self.insert(name, path);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Caches the given executable as the command, which need not be within the `PATH`.
+    pub fn insert(&mut self, name: &str, path: PathBuf) {
+        self.validate();
+        self.missing.remove(name);
+        self.commands.insert(name.into(), Hashed { path: path, hits: 0 });
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.validate();
+        self.commands.remove(name).is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.commands.clear();
+        self.missing.clear();
+        self.functions.clear();
+    }
+
+    /// The cached commands, sorted by their names.
+    pub fn commands(&mut self) -> Vec<(&String, &Hashed)> {
+        self.validate();
+        let mut commands: Vec<_> = self.commands.iter().collect();
+        commands.sort_by(|a, b| a.0.cmp(b.0));
+        commands
+    }
+}
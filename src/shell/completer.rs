@@ -1,6 +1,55 @@
 use liner::{Completer, FilenameCompleter};
+use super::Shell;
 use super::directory_stack::DirectoryStack;
 use super::variables::Variables;
+use types::{Array, Identifier};
+
+/// The completions of the arguments of a command, which were registered with `complete`.
+pub enum Completion {
+    /// A function which prints the completions, one per line.
+    Function(Identifier),
+    /// A list of words which are always completed.
+    Words(Vec<String>),
+}
+
+/// The variables which describe the line to a completion function.
+const COMPLETION_VARIABLES: [&str; 3] = ["COMP_LINE", "COMP_POINT", "COMP_CWORD"];
+
+/// Collects the completions which were registered for the command of the line, if the word at
+/// `index` is one of its arguments. A completion function is given the line within `$COMP_LINE`,
+/// the position of the cursor within `$COMP_POINT`, the words of the line within `@COMP_WORDS`,
+/// and the index of the word which is being completed within `$COMP_CWORD`.
+pub fn custom_completions(
+    shell: &mut Shell,
+    line: &str,
+    cursor: usize,
+    words: &[String],
+    index: usize,
+) -> Option<Vec<String>> {
+    if index == 0 || words.is_empty() {
+        return None;
+    }
+    let function = match shell.completions.get(&words[0]) {
+        Some(&Completion::Function(ref function)) => function.clone(),
+        Some(&Completion::Words(ref words)) => return Some(words.clone()),
+        None => return None,
+    };
+
+    let values = [line.to_owned(), cursor.to_string(), index.to_string()];
+    for (name, value) in COMPLETION_VARIABLES.iter().zip(values.iter()) {
+        shell.variables.set_var(name, value);
+    }
+    shell.variables.set_array("COMP_WORDS", words.iter().map(|word| word.as_str().into()).collect::<Array>());
+    let output = shell.capture_output(&function);
+    for name in &COMPLETION_VARIABLES {
+        shell.variables.unset_var(name);
+    }
+    shell.variables.unset_array("COMP_WORDS");
+
+    output.map(|output| {
+        output.lines().filter(|line| !line.is_empty()).map(String::from).collect()
+    })
+}
 
 /// Performs escaping to an inner `FilenameCompleter` to enable a handful of special cases
 /// needed by the shell, such as expanding '~' to a home directory, or adding a backslash
@@ -38,6 +87,19 @@ impl Completer for IonFileCompleter {
     fn completions(&self, start: &str) -> Vec<String> {
         // Only if the first character is a tilde character will we perform expansions
         if start.starts_with('~') {
+            // Until a `/` has been typed, the names of named directories are completed.
+            if start.len() > 1 && !start.contains('/') {
+                let vars = unsafe { &*self.vars };
+                let mut names: Vec<String> = vars.named_dirs.keys()
+                    .filter(|name| name.starts_with(&start[1..]))
+                    .map(|name| format!("~{}/", name))
+                    .collect();
+                if !names.is_empty() {
+                    names.sort();
+                    return names
+                }
+            }
+
             // Dereferencing the raw pointers here should be entirely safe, theoretically,
             // because no changes will occur to either of the underlying references in the
             // duration between creation of the completers and execution of their completions.
@@ -1,7 +1,8 @@
 use std::borrow::Cow;
 use std::collections::VecDeque;
-use std::env::{set_current_dir, current_dir, home_dir};
-use std::path::PathBuf;
+use std::env::{set_current_dir, set_var, current_dir, home_dir};
+use std::io;
+use std::path::{Path, PathBuf};
 use super::variables::Variables;
 use super::status::{SUCCESS, FAILURE};
 
@@ -126,14 +127,23 @@ impl DirectoryStack {
 			}
 		},
 		Action::RotRight(num) => {
+			// Counting from the bottom of the stack, which is the last directory.
+			let index = len.checked_sub(num + 1)
+				.ok_or_else(|| Cow::Owned(format!("ion: pushd: -{}: directory stack index out of range\n", num)))?;
 			if !keep_front {
-				self.set_current_dir_by_index(len - (num % len), "pushd")?;
-				self.rotate_right(num);
+				self.set_current_dir_by_index(index, "pushd")?;
+				self.rotate_left(index);
 			}
 		},
 		Action::Push(dir) => {
-			let index = if keep_front { 1 } else { 0 };
-			self.insert_dir(index, dir, variables);
+			if keep_front {
+				self.insert_dir(1, dir, variables);
+			} else {
+				change_dir(&dir)
+					.map_err(|why| Cow::Owned(format!("ion: pushd: {}: {}\n", dir.display(), why)))?;
+				let dir = current_dir().unwrap_or(dir);
+				self.insert_dir(0, dir, variables);
+			}
 		}
 	};
 
@@ -186,7 +196,7 @@ impl DirectoryStack {
     }
 
     pub fn change_and_push_dir(&mut self, dir: &str, variables: &Variables) -> Result<(), Cow<'static, str>> {
-        match (set_current_dir(dir), current_dir()) {
+        match (change_dir(dir), current_dir()) {
             (Ok(()), Ok(cur_dir)) => {
                 self.push_dir(cur_dir, variables);
                 Ok(())
@@ -231,7 +241,14 @@ impl DirectoryStack {
 				num_arg = match parse_numeric_arg(arg) {
 					Some((true, num)) => Some(num),
 					Some((false, num)) if self.dirs.len() > num => Some(self.dirs.len() - num - 1),
-					_ => return FAILURE // Err(Cow::Owned(format!("ion: dirs: {}: invalid argument\n", arg)))
+					Some(_) => {
+						eprintln!("ion: dirs: {}: directory stack index out of range", arg);
+						return FAILURE
+					}
+					None => {
+						eprintln!("ion: dirs: {}: invalid argument", arg);
+						return FAILURE
+					}
 				};
 			}
 		}
@@ -257,7 +274,10 @@ impl DirectoryStack {
 	if let Some(num) = num_arg {
 		match iter.nth(num) {
 			Some(x) => println!("{}", x),
-			None => return FAILURE
+			None => {
+				eprintln!("ion: dirs: {}: directory stack index out of range", num);
+				return FAILURE
+			}
 		};
 	} else {
 		let folder: fn(String, Cow<str>) -> String =
@@ -296,8 +316,8 @@ impl DirectoryStack {
 	let dir = self.dirs.iter().nth(index)
 		.ok_or_else(|| Cow::Owned(format!("ion: {}: {}: directory stack out of range\n", caller, index)))?;
 
-	set_current_dir(dir)
-		.map_err(|_| Cow::Owned(format!("ion: {}: Failed setting current dir\n", caller)))
+	change_dir(dir)
+		.map_err(|why| Cow::Owned(format!("ion: {}: {}: {}\n", caller, dir.display(), why)))
     }
 
     // pushd +<num>
@@ -309,12 +329,20 @@ impl DirectoryStack {
 		*dest = src.clone();
 	}
     }
+}
 
-    // pushd -<num>
-    fn rotate_right(&mut self, num: usize) {
-	let len = self.dirs.len();
-	self.rotate_left(len - (num % len));
+/// Changes the current directory, along with `PWD` and `OLDPWD`, which would otherwise not be
+/// updated until the next prompt, and so would never be updated within a script.
+fn change_dir<P: AsRef<Path>>(dir: P) -> io::Result<()> {
+    let previous = current_dir().ok();
+    set_current_dir(dir)?;
+    if let Some(previous) = previous {
+        set_var("OLDPWD", previous);
     }
+    if let Ok(current) = current_dir() {
+        set_var("PWD", current);
+    }
+    Ok(())
 }
 
 // parses -N or +N patterns
@@ -340,3 +368,30 @@ fn try_abs_path(pbuf: &PathBuf) -> Cow<str> {
 			.to_string()
 	)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stack(dirs: &[&str]) -> DirectoryStack {
+        DirectoryStack { dirs: dirs.iter().map(PathBuf::from).collect() }
+    }
+
+    #[test]
+    fn numeric_args() {
+        assert_eq!(parse_numeric_arg("+2"), Some((true, 2)));
+        assert_eq!(parse_numeric_arg("-0"), Some((false, 0)));
+        assert_eq!(parse_numeric_arg("2"), None);
+        assert_eq!(parse_numeric_arg("+a"), None);
+    }
+
+    #[test]
+    fn rotation() {
+        let mut dirs = stack(&["/a", "/b", "/c", "/d"]);
+        dirs.rotate_left(2);
+        assert_eq!(dirs.dir_from_top(0), Some(&PathBuf::from("/c")));
+        assert_eq!(dirs.dir_from_top(1), Some(&PathBuf::from("/d")));
+        assert_eq!(dirs.dir_from_bottom(0), Some(&PathBuf::from("/b")));
+        assert_eq!(dirs.dir_from_bottom(4), None);
+    }
+}
@@ -1,2 +1,47 @@
-pub const ERR_EXIT:    u8 = 1;
-pub const PRINT_COMMS: u8 = 2;
+pub const ERR_EXIT:    u16 = 1;
+pub const PRINT_COMMS: u16 = 2;
+pub const NO_UNSET:    u16 = 4;
+pub const EXT_GLOB:    u16 = 8;
+pub const NULL_GLOB:   u16 = 16;
+pub const FAIL_GLOB:   u16 = 32;
+pub const PIPE_FAIL:   u16 = 64;
+pub const NO_CLOBBER:  u16 = 128;
+pub const NOTIFY:      u16 = 256;
+pub const MONITOR:     u16 = 512;
+pub const EXPAND_ALIASES: u16 = 1024;
+
+/// The names of the options which `set -o` and `set +o` accept, along with the flags that they
+/// control, in the order in which they are listed.
+pub const OPTIONS: &[(&str, u16)] = &[
+    ("errexit", ERR_EXIT),
+    ("expand_aliases", EXPAND_ALIASES),
+    ("extglob", EXT_GLOB),
+    ("failglob", FAIL_GLOB),
+    ("monitor", MONITOR),
+    ("noclobber", NO_CLOBBER),
+    ("notify", NOTIFY),
+    ("nounset", NO_UNSET),
+    ("nullglob", NULL_GLOB),
+    ("pipefail", PIPE_FAIL),
+    ("xtrace", PRINT_COMMS),
+];
+
+/// The flag of the option which is known by the given name.
+pub fn flag_of(name: &str) -> Option<u16> {
+    OPTIONS.iter().find(|&&(option, _)| option == name).map(|&(_, flag)| flag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn option_names() {
+        for &(name, flag) in OPTIONS {
+            assert_eq!(flag_of(name), Some(flag));
+        }
+        assert_eq!(flag_of("pipefail"), Some(PIPE_FAIL));
+        assert_eq!(flag_of("xtrace"), Some(PRINT_COMMS));
+        assert_eq!(flag_of("vi"), None);
+    }
+}
@@ -1,14 +1,17 @@
 use std::io::{self, Write};
 use std::mem;
 use super::status::*;
-use super::Shell;
+use super::{FunctionExpander, Shell};
 use super::flags::*;
+use super::JobKind;
 use super::job_control::JobControl;
-use super::flow_control::{ElseIf, Function, Statement, collect_loops, collect_cases, collect_if, Case};
-use parser::{ForExpression, StatementSplitter, parse_and_validate, expand_string};
+use super::pipe_exec::{BlockOutput, Coproc};
+use super::flow_control::{ElseIf, FlowControl, Function, Statement, collect_loops, collect_loop_else, collect_cases, collect_if,
+                          mark_line, Case};
+use parser::{ForExpression, QuoteTerminator, StatementSplitter, parse_and_validate, expand_string};
 use parser::pipelines::Pipeline;
 use shell::assignments::VariableStore;
-use types::Array;
+use types::{Array, Identifier, Value};
 
 pub enum Condition {
     Continue,
@@ -21,32 +24,101 @@ pub trait FlowLogic {
     /// Receives a command and attempts to execute the contents.
     fn on_command(&mut self, command_string: &str);
 
+    /// Executes the text given to `eval`, which may span several lines. Any block that the text
+    /// begins must also be ended within it, and is kept apart from any partial block that the
+    /// shell is already collecting.
+    fn evaluate(&mut self, text: &str) -> Result<(), String>;
+
     /// The highest layer of the flow control handling which branches into lower blocks when found.
     fn execute_toplevel<I>(&mut self, iterator: &mut I, statement: Statement) -> Result<(), &'static str>
         where I: Iterator<Item = Statement>;
 
     /// Executes all of the statements within a while block until a certain condition is met.
-    fn execute_while(&mut self, expression: Pipeline, statements: Vec<Statement>) -> Condition;
+    /// If the loop finishes without a `break`, the `else_branch` will be executed afterwards.
+    fn execute_while(&mut self, expression: Vec<Pipeline>, statements: Vec<Statement>,
+        else_branch: Vec<Statement>) -> Condition;
 
     /// Executes all of the statements within a for block for each value specified in the range.
-    fn execute_for(&mut self, variable: &str, values: &[String], statements: Vec<Statement>) -> Condition;
+    /// If the loop finishes without a `break`, the `else_branch` will be executed afterwards.
+    fn execute_for(&mut self, variables: &[Identifier], values: &[String], statements: Vec<Statement>,
+        else_branch: Vec<Statement>) -> Condition;
 
     /// Conditionally executes branches of statements according to evaluated expressions
-    fn execute_if(&mut self, expression: Pipeline, success: Vec<Statement>,
+    fn execute_if(&mut self, expression: Vec<Pipeline>, success: Vec<Statement>,
         else_if: Vec<ElseIf>, failure: Vec<Statement>) -> Condition;
 
+    /// Executes the pipelines which make up the condition of an `if` or `while` statement as a
+    /// unit, returning whether the last pipeline to be executed succeeded.
+    fn execute_condition(&mut self, expression: &mut [Pipeline]) -> bool;
+
     /// Simply executes all supplied statemnts.
     fn execute_statements(&mut self, statements: Vec<Statement>) -> Condition;
 
     /// Expand an expression and run a branch based on the value of the expanded expression
     fn execute_match(&mut self, expression: String, cases: Vec<Case>) -> Condition;
 
+    /// Execute a pipeline and run a branch based on the exit status of that pipeline
+    fn execute_match_status(&mut self, expression: Pipeline, cases: Vec<Case>) -> Condition;
+
+    /// Executes the first case whose pattern matches the supplied value
+    fn execute_cases(&mut self, value: Array, cases: Vec<Case>) -> Condition;
+
+    /// Executes the else block of a guard statement if the expression fails. The else block
+    /// must end with a statement that diverges from the current block.
+    fn execute_guard(&mut self, expression: Pipeline, statements: Vec<Statement>) -> Condition;
+
+    /// Executes the statements of a namespace block, so that the functions declared within
+    /// are prefixed with the name of the namespace.
+    fn execute_namespace(&mut self, name: &str, statements: Vec<Statement>) -> Condition;
+
+    /// Spawns the pipeline in the background as a coprocess, whose standard input and output
+    /// are connected to descriptors that are stored within the `name` array.
+    fn execute_coproc(&mut self, name: &str, pipeline: Pipeline);
+
+    /// Executes an `if`, `for`, or `while` block, whose output as a whole is redirected or
+    /// piped into other commands when the `end` of the block was given an `output`.
+    fn execute_redirected<F>(&mut self, output: Option<Pipeline>, block: F) -> Condition
+        where F: FnOnce(&mut Self) -> Condition;
+
 }
 
 impl<'a> FlowLogic for Shell<'a> {
+    fn evaluate(&mut self, text: &str) -> Result<(), String> {
+        let outer = mem::replace(&mut self.flow_control, FlowControl::default());
+        let mut lines = text.lines();
+        let mut result = Ok(());
+        while let Some(line) = lines.next() {
+            let mut buffer = QuoteTerminator::new(line.into());
+            let mut terminated = buffer.check_termination();
+            while !terminated {
+                match lines.next() {
+                    Some(line) => {
+                        buffer.append(line.into());
+                        terminated = buffer.check_termination();
+                    }
+                    None => break,
+                }
+            }
+            if !terminated {
+                result = Err("expression was not terminated".into());
+                break;
+            }
+            self.on_command(&buffer.consume());
+        }
+
+        if result.is_ok() && self.flow_control.level != 0 {
+            result = Err(format!("expected end block for `{}`", self.flow_control.current_statement.short()));
+        }
+        self.flow_control = outer;
+        result
+    }
+
     fn on_command(&mut self, command_string: &str) {
         self.break_flow = false;
-        let mut iterator = StatementSplitter::new(command_string).map(parse_and_validate);
+        let line = self.variables.line_number;
+        let mut iterator = StatementSplitter::new(command_string)
+            .map(parse_and_validate)
+            .flat_map(move |statement| mark_line(line, statement));
 
         // If the value is set to `0`, this means that we don't need to append to an existing
         // partial statement block in memory, but can read and execute new statements.
@@ -66,16 +138,43 @@ impl<'a> FlowLogic for Shell<'a> {
             }
         } else {
             // Appends the newly parsed statements onto the existing statement stored in memory.
+            let mut output = None;
             match self.flow_control.current_statement {
-                Statement::While{ ref mut statements, .. }
-                    | Statement::For { ref mut statements, .. }
-                    | Statement::Function { ref mut statements, .. } =>
+                Statement::While { ref mut expression, ref mut statements, ref mut else_branch } => {
+                    self.flow_control.current_if_mode = match collect_loop_else(&mut iterator, Some(expression),
+                        statements, else_branch, &mut output, &mut self.flow_control.level,
+                        self.flow_control.current_if_mode) {
+                            Ok(mode) => mode,
+                            Err(why) => {
+                                let stderr = io::stderr();
+                                let mut stderr = stderr.lock();
+                                let _ = writeln!(stderr, "{}", why);
+                                4
+                            }
+                        };
+                },
+                Statement::For { ref mut statements, ref mut else_branch, .. } => {
+                    self.flow_control.current_if_mode = match collect_loop_else(&mut iterator, None,
+                        statements, else_branch, &mut output, &mut self.flow_control.level,
+                        self.flow_control.current_if_mode) {
+                            Ok(mode) => mode,
+                            Err(why) => {
+                                let stderr = io::stderr();
+                                let mut stderr = stderr.lock();
+                                let _ = writeln!(stderr, "{}", why);
+                                4
+                            }
+                        };
+                },
+                Statement::Function { ref mut statements, .. }
+                    | Statement::Guard { ref mut statements, .. }
+                    | Statement::Namespace { ref mut statements, .. } =>
                 {
                     collect_loops(&mut iterator, statements, &mut self.flow_control.level);
                 },
-                Statement::If { ref mut success, ref mut else_if, ref mut failure, .. } => {
-                    self.flow_control.current_if_mode = match collect_if(&mut iterator, success,
-                        else_if, failure, &mut self.flow_control.level,
+                Statement::If { ref mut expression, ref mut success, ref mut else_if, ref mut failure } => {
+                    self.flow_control.current_if_mode = match collect_if(&mut iterator, expression, success,
+                        else_if, failure, &mut output, &mut self.flow_control.level,
                         self.flow_control.current_if_mode) {
                             Ok(mode) => mode,
                             Err(why) => {
@@ -86,7 +185,7 @@ impl<'a> FlowLogic for Shell<'a> {
                             }
                         };
                 },
-                Statement::Match { ref mut cases, .. } => {
+                Statement::Match { ref mut cases, .. } | Statement::MatchStatus { ref mut cases, .. } => {
                     if let Err(why) = collect_cases(&mut iterator, cases, &mut self.flow_control.level) {
                         let stderr = io::stderr();
                         let mut stderr = stderr.lock();
@@ -115,35 +214,59 @@ impl<'a> FlowLogic for Shell<'a> {
                 match replacement {
                     Statement::Error(number) => self.previous_status = number,
                     Statement::Let { expression } => {
-                        self.previous_status = self.local(expression);
+                        let status = self.local(expression);
+                        self.set_previous_status(status);
                     },
                     Statement::Export(expression) => {
-                        self.previous_status = self.export(expression);
+                        let status = self.export(expression);
+                        self.set_previous_status(status);
                     }
-                    Statement::While { expression, statements } => {
-                        if let Condition::SigInt = self.execute_while(expression, statements) {
+                    Statement::While { expression, statements, else_branch } => {
+                        self.flow_control.current_if_mode = 0;
+                        let condition = self.execute_redirected(output, move |shell| {
+                            shell.execute_while(expression, statements, else_branch)
+                        });
+                        if let Condition::SigInt = condition {
                             return
                         }
                     },
-                    Statement::For { variable, values, statements } => {
-                        if let Condition::SigInt = self.execute_for(&variable, &values, statements) {
+                    Statement::For { variables, values, statements, else_branch } => {
+                        self.flow_control.current_if_mode = 0;
+                        let condition = self.execute_redirected(output, move |shell| {
+                            shell.execute_for(&variables, &values, statements, else_branch)
+                        });
+                        if let Condition::SigInt = condition {
                             return
                         }
                     },
-                    Statement::Function { name, args, statements, description } => {
+                    Statement::Function { name, args, statements, description, private } => {
+                        let name = self.qualify_function_name(&name);
                         self.functions.insert(name.clone(), Function {
                             name:       name,
                             args:       args,
                             statements: statements,
                             description: description,
+                            private:    private,
+                            traced:     false,
                         });
                     },
                     Statement::If { expression, success, else_if, failure } => {
-                        self.execute_if(expression, success, else_if, failure);
+                        self.execute_redirected(output, move |shell| {
+                            shell.execute_if(expression, success, else_if, failure)
+                        });
                     },
                     Statement::Match { expression, cases } => {
                         self.execute_match(expression, cases);
                     }
+                    Statement::MatchStatus { expression, cases } => {
+                        self.execute_match_status(expression, cases);
+                    }
+                    Statement::Guard { expression, statements } => {
+                        self.execute_guard(expression, statements);
+                    }
+                    Statement::Namespace { name, statements } => {
+                        self.execute_namespace(&name, statements);
+                    }
                     _ => ()
                 }
 
@@ -163,6 +286,26 @@ impl<'a> FlowLogic for Shell<'a> {
     }
 
     fn execute_match(&mut self, expression: String, cases: Vec<Case>) -> Condition {
+        if self.references_unset_variable(&expression) {
+            self.previous_status = FAILURE;
+            return Condition::NoOp;
+        }
+        self.forget_failed_globs();
+        let value = expand_string(&expression, &FunctionExpander::new(self), false);
+        if self.glob_failed() {
+            self.previous_status = FAILURE;
+            return Condition::NoOp;
+        }
+        self.execute_cases(value, cases)
+    }
+
+    fn execute_match_status(&mut self, mut expression: Pipeline, cases: Vec<Case>) -> Condition {
+        self.run_pipeline(&mut expression);
+        let value = array![self.previous_status.to_string()];
+        self.execute_cases(value, cases)
+    }
+
+    fn execute_cases(&mut self, value: Array, cases: Vec<Case>) -> Condition {
         // Logic for determining if the LHS of a match-case construct (the value we are matching
         // against) matches the RHS of a match-case construct (a value in a case statement). For
         // example, checking to see if the value "foo" matches the pattern "bar" would be invoked
@@ -176,10 +319,14 @@ impl<'a> FlowLogic for Shell<'a> {
             }
             return false;
         }
-        let value = expand_string(&expression, self, false);
         let mut condition = Condition::NoOp;
         for case in cases {
-            let pattern = case.value.map(|v| { expand_string(&v, self, false) });
+            self.forget_failed_globs();
+            let pattern = case.value.map(|v| { expand_string(&v, &FunctionExpander::new(self), false) });
+            if self.glob_failed() {
+                self.previous_status = FAILURE;
+                return Condition::NoOp;
+            }
             match pattern {
                 None => {
                     condition = self.execute_statements(case.statements);
@@ -195,35 +342,95 @@ impl<'a> FlowLogic for Shell<'a> {
         condition
     }
 
+    fn execute_guard(&mut self, mut expression: Pipeline, statements: Vec<Statement>) -> Condition {
+        if !statements.last().map_or(false, Statement::diverges) {
+            eprintln!("ion: syntax error: guard else block must end with break, continue, or exit");
+            self.previous_status = FAILURE;
+            return Condition::NoOp;
+        }
+
+        self.membership_test(&mut expression);
+        match self.run_pipeline(&mut expression) {
+            Some(SUCCESS) => Condition::NoOp,
+            _             => self.execute_statements(statements)
+        }
+    }
+
+    fn execute_namespace(&mut self, name: &str, statements: Vec<Statement>) -> Condition {
+        let namespace = self.qualify_function_name(name);
+        let previous = mem::replace(&mut self.namespace, Some(namespace));
+        let condition = self.execute_statements(statements);
+        self.namespace = previous;
+        condition
+    }
+
     fn execute_statements(&mut self, mut statements: Vec<Statement>) -> Condition {
         let mut iterator = statements.drain(..);
         while let Some(statement) = iterator.next() {
             match statement {
                 Statement::Error(number) => self.previous_status = number,
+                Statement::Line(line) => self.variables.line_number = line,
                 Statement::Let { expression } => {
-                    self.previous_status = self.local(expression);
+                    let status = self.local(expression);
+                    self.set_previous_status(status);
                 },
                 Statement::Export(expression) => {
-                    self.previous_status = self.export(expression);
+                    let status = self.export(expression);
+                    self.set_previous_status(status);
                 }
-                Statement::While { expression, mut statements } => {
+                Statement::While { mut expression, mut statements, mut else_branch } => {
                     self.flow_control.level += 1;
-                    collect_loops(&mut iterator, &mut statements, &mut self.flow_control.level);
-                    if let Condition::SigInt = self.execute_while(expression, statements) {
-                        return Condition::SigInt;
+                    let mut output = None;
+                    if let Err(why) = collect_loop_else(&mut iterator, Some(&mut expression), &mut statements,
+                        &mut else_branch, &mut output, &mut self.flow_control.level, 0)
+                    {
+                        let stderr = io::stderr();
+                        let mut stderr = stderr.lock();
+                        let _ = writeln!(stderr, "{}", why);
+                        self.flow_control.level = 0;
+                        self.flow_control.current_if_mode = 0;
+                        return Condition::Break
+                    }
+                    let condition = self.execute_redirected(output, move |shell| {
+                        shell.execute_while(expression, statements, else_branch)
+                    });
+                    // Only the else branch may break from, or continue, the enclosing loop.
+                    match condition {
+                        Condition::Break    => return Condition::Break,
+                        Condition::Continue => return Condition::Continue,
+                        Condition::NoOp     => (),
+                        Condition::SigInt   => return Condition::SigInt,
                     }
                 },
-                Statement::For { variable, values, mut statements } => {
+                Statement::For { variables, values, mut statements, mut else_branch } => {
                     self.flow_control.level += 1;
-                    collect_loops(&mut iterator, &mut statements, &mut self.flow_control.level);
-                    if let Condition::SigInt = self.execute_for(&variable, &values, statements) {
-                        return Condition::SigInt;
+                    let mut output = None;
+                    if let Err(why) = collect_loop_else(&mut iterator, None, &mut statements,
+                        &mut else_branch, &mut output, &mut self.flow_control.level, 0)
+                    {
+                        let stderr = io::stderr();
+                        let mut stderr = stderr.lock();
+                        let _ = writeln!(stderr, "{}", why);
+                        self.flow_control.level = 0;
+                        self.flow_control.current_if_mode = 0;
+                        return Condition::Break
+                    }
+                    let condition = self.execute_redirected(output, move |shell| {
+                        shell.execute_for(&variables, &values, statements, else_branch)
+                    });
+                    // Only the else branch may break from, or continue, the enclosing loop.
+                    match condition {
+                        Condition::Break    => return Condition::Break,
+                        Condition::Continue => return Condition::Continue,
+                        Condition::NoOp     => (),
+                        Condition::SigInt   => return Condition::SigInt,
                     }
                 },
-                Statement::If { expression, mut success, mut else_if, mut failure } => {
+                Statement::If { mut expression, mut success, mut else_if, mut failure } => {
                     self.flow_control.level += 1;
-                    if let Err(why) = collect_if(&mut iterator, &mut success, &mut else_if,
-                        &mut failure, &mut self.flow_control.level, 0)
+                    let mut output = None;
+                    if let Err(why) = collect_if(&mut iterator, &mut expression, &mut success, &mut else_if,
+                        &mut failure, &mut output, &mut self.flow_control.level, 0)
                     {
                         let stderr = io::stderr();
                         let mut stderr = stderr.lock();
@@ -233,23 +440,49 @@ impl<'a> FlowLogic for Shell<'a> {
                         return Condition::Break
                     }
 
-                    match self.execute_if(expression, success, else_if, failure) {
+                    let condition = self.execute_redirected(output, move |shell| {
+                        shell.execute_if(expression, success, else_if, failure)
+                    });
+                    match condition {
                         Condition::Break    => return Condition::Break,
                         Condition::Continue => return Condition::Continue,
                         Condition::NoOp     => (),
                         Condition::SigInt   => return Condition::SigInt,
                     }
                 },
-                Statement::Function { name, args, mut statements, description } => {
+                Statement::Function { name, args, mut statements, description, private } => {
                     self.flow_control.level += 1;
                     collect_loops(&mut iterator, &mut statements, &mut self.flow_control.level);
+                    let name = self.qualify_function_name(&name);
                     self.functions.insert(name.clone(), Function {
                         description: description,
                         name:        name,
                         args:        args,
-                        statements:  statements
+                        statements:  statements,
+                        private:     private,
+                        traced:      false
                     });
                 },
+                Statement::Namespace { name, mut statements } => {
+                    self.flow_control.level += 1;
+                    collect_loops(&mut iterator, &mut statements, &mut self.flow_control.level);
+                    match self.execute_namespace(&name, statements) {
+                        Condition::Break    => return Condition::Break,
+                        Condition::Continue => return Condition::Continue,
+                        Condition::NoOp     => (),
+                        Condition::SigInt   => return Condition::SigInt,
+                    }
+                },
+                Statement::Guard { expression, mut statements } => {
+                    self.flow_control.level += 1;
+                    collect_loops(&mut iterator, &mut statements, &mut self.flow_control.level);
+                    match self.execute_guard(expression, statements) {
+                        Condition::Break    => return Condition::Break,
+                        Condition::Continue => return Condition::Continue,
+                        Condition::NoOp     => (),
+                        Condition::SigInt   => return Condition::SigInt,
+                    }
+                },
                 Statement::Pipeline(mut pipeline)  => {
                     self.run_pipeline(&mut pipeline);
                     if self.flags & ERR_EXIT != 0 && self.previous_status != SUCCESS {
@@ -257,6 +490,7 @@ impl<'a> FlowLogic for Shell<'a> {
                         self.exit(status);
                     }
                 },
+                Statement::Coproc { name, pipeline } => self.execute_coproc(&name, pipeline),
                 Statement::Break => { return Condition::Break }
                 Statement::Continue => { return Condition::Continue }
                 Statement::Match {expression, mut cases} => {
@@ -275,9 +509,27 @@ impl<'a> FlowLogic for Shell<'a> {
                         Condition::NoOp     => (),
                         Condition::SigInt   => return Condition::SigInt,
                     }
+                },
+                Statement::MatchStatus {expression, mut cases} => {
+                    self.flow_control.level += 1;
+                    if let Err(why) = collect_cases(&mut iterator, &mut cases, &mut self.flow_control.level) {
+                        let stderr = io::stderr();
+                        let mut stderr = stderr.lock();
+                        let _ = writeln!(stderr, "{}", why);
+                        self.flow_control.level = 0;
+                        self.flow_control.current_if_mode = 0;
+                        return Condition::Break
+                    }
+                    match self.execute_match_status(expression, cases) {
+                        Condition::Break    => return Condition::Break,
+                        Condition::Continue => return Condition::Continue,
+                        Condition::NoOp     => (),
+                        Condition::SigInt   => return Condition::SigInt,
+                    }
                 }
                 _ => {}
             }
+            self.run_pending_traps();
             if let Some(signal) = self.next_signal() {
                 if self.handle_signal(signal) {
                     self.exit(get_signal_code(signal));
@@ -293,101 +545,140 @@ impl<'a> FlowLogic for Shell<'a> {
 
     fn execute_while (
         &mut self,
-        expression: Pipeline,
-        statements: Vec<Statement>
+        expression: Vec<Pipeline>,
+        statements: Vec<Statement>,
+        else_branch: Vec<Statement>
     ) -> Condition {
-        while self.run_pipeline(&mut expression.clone()) == Some(SUCCESS) {
+        while self.execute_condition(&mut expression.clone()) {
             // Cloning is needed so the statement can be re-iterated again if needed.
             match self.execute_statements(statements.clone()) {
-                Condition::Break  => break,
+                Condition::Break  => return Condition::NoOp,
                 Condition::SigInt => return Condition::SigInt,
                 _                 => ()
             }
         }
-        Condition::NoOp
+        // The loop was exhausted without breaking, so the else branch may now be executed. A
+        // `break` or `continue` within it applies to the enclosing loop.
+        self.execute_statements(else_branch)
     }
 
     fn execute_for (
         &mut self,
-        variable: &str,
+        variables: &[Identifier],
         values: &[String],
-        statements: Vec<Statement>
+        statements: Vec<Statement>,
+        else_branch: Vec<Statement>
     ) -> Condition {
-        let ignore_variable = variable == "_";
-        match ForExpression::new(values, self) {
-            ForExpression::Multiple(ref values) if ignore_variable => {
-                for _ in values.iter() {
-                    match self.execute_statements(statements.clone()) {
-                        Condition::Break  => break,
-                        Condition::SigInt => return Condition::SigInt,
-                        _                 => ()
-                    }
-                }
-            },
-            ForExpression::Multiple(values) => {
-                for value in values.iter() {
-                    self.variables.set_var(variable, &value);
-                    match self.execute_statements(statements.clone()) {
-                        Condition::Break  => break,
-                        Condition::SigInt => return Condition::SigInt,
-                        _                 => ()
-                    }
-                }
-            },
-            ForExpression::Normal(ref values) if ignore_variable => {
-                for _ in values.lines() {
-                    match self.execute_statements(statements.clone()) {
-                        Condition::Break  => break,
-                        Condition::SigInt => return Condition::SigInt,
-                        _                 => ()
-                    }
-                }
-            },
+        if values.iter().any(|value| self.references_unset_variable(value)) {
+            self.previous_status = FAILURE;
+            return Condition::NoOp;
+        }
+        self.forget_failed_globs();
+        let expression = ForExpression::new(values, &FunctionExpander::new(self));
+        if self.glob_failed() {
+            self.previous_status = FAILURE;
+            return Condition::NoOp;
+        }
+        let mut values: Box<Iterator<Item = Value>> = match expression {
+            ForExpression::Multiple(values) => Box::new(values.into_iter()),
             ForExpression::Normal(values) => {
-                for value in values.lines() {
-                    self.variables.set_var(variable, &value);
-                    match self.execute_statements(statements.clone()) {
-                        Condition::Break  => break,
-                        Condition::SigInt => return Condition::SigInt,
-                        _                 => ()
-                    }
-                }
+                Box::new(values.lines().map(String::from).collect::<Vec<Value>>().into_iter())
             },
-            ForExpression::Range(start, end) if ignore_variable => {
-                for _ in start..end {
-                    match self.execute_statements(statements.clone()) {
-                        Condition::Break  => break,
-                        Condition::SigInt => return Condition::SigInt,
-                        _                 => ()
-                    }
+            ForExpression::Range(start, end) => Box::new((start..end).map(|x| x.to_string())),
+            // Loops with multiple variables iterate over the keys and values of a map, while
+            // loops with a single variable iterate over the values alone.
+            ForExpression::Map(entries) => if variables.len() > 1 {
+                Box::new(entries.into_iter().flat_map(|(key, value)| vec![key, value.join(" ")]))
+            } else {
+                Box::new(entries.into_iter().flat_map(|(_, value)| value))
+            },
+        };
+
+        // Each iteration assigns the next value to each of the variables in turn, where any
+        // variables which are left over in the last iteration are assigned empty strings.
+        'outer: loop {
+            for (id, variable) in variables.iter().enumerate() {
+                let value = match values.next() {
+                    Some(value) => value,
+                    None if id == 0 => break 'outer,
+                    None => String::new(),
+                };
+                if &**variable != "_" && !self.variables.set_var(variable, &value) {
+                    self.previous_status = FAILURE;
+                    return Condition::NoOp;
                 }
             }
-            ForExpression::Range(start, end) => {
-                for value in (start..end).map(|x| x.to_string()) {
-                    self.variables.set_var(variable, &value);
-                    match self.execute_statements(statements.clone()) {
-                        Condition::Break  => break,
-                        Condition::SigInt => return Condition::SigInt,
-                        _                 => ()
-                    }
-                }
+            match self.execute_statements(statements.clone()) {
+                Condition::Break  => return Condition::NoOp,
+                Condition::SigInt => return Condition::SigInt,
+                _                 => ()
             }
         }
-        Condition::NoOp
+        // The loop was exhausted without breaking, so the else branch may now be executed. A
+        // `break` or `continue` within it applies to the enclosing loop.
+        self.execute_statements(else_branch)
     }
 
-    fn execute_if(&mut self, mut expression: Pipeline, success: Vec<Statement>,
+    fn execute_if(&mut self, mut expression: Vec<Pipeline>, success: Vec<Statement>,
         else_if: Vec<ElseIf>, failure: Vec<Statement>) -> Condition
     {
-        match self.run_pipeline(&mut expression) {
-            Some(SUCCESS) => self.execute_statements(success),
-            _             => {
-                for mut elseif in else_if {
-                    if self.run_pipeline(&mut elseif.expression) == Some(SUCCESS) {
-                        return self.execute_statements(elseif.success);
-                    }
+        if self.execute_condition(&mut expression) {
+            self.execute_statements(success)
+        } else {
+            for mut elseif in else_if {
+                if self.execute_condition(&mut elseif.expression) {
+                    return self.execute_statements(elseif.success);
                 }
-                self.execute_statements(failure)
+            }
+            self.execute_statements(failure)
+        }
+    }
+
+    fn execute_condition(&mut self, expression: &mut [Pipeline]) -> bool {
+        let mut status = None;
+        for pipeline in expression {
+            self.membership_test(pipeline);
+            status = self.run_pipeline(pipeline);
+        }
+        status == Some(SUCCESS)
+    }
+
+    fn execute_coproc(&mut self, name: &str, mut pipeline: Pipeline) {
+        self.coproc = match Coproc::new(name) {
+            Ok(coproc) => Some(coproc),
+            Err(why) => {
+                eprintln!("ion: failed to create the pipes of coprocess {}: {}", name, why);
+                self.previous_status = FAILURE;
+                return;
+            }
+        };
+        // The pipes are connected to the pipeline when it is forked into the background.
+        if let Some(job) = pipeline.jobs.last_mut() {
+            job.kind = JobKind::Background;
+        }
+        self.run_pipeline(&mut pipeline);
+        // The pipes are discarded if the pipeline could not be executed.
+        self.coproc = None;
+    }
+
+    fn execute_redirected<F>(&mut self, output: Option<Pipeline>, block: F) -> Condition
+        where F: FnOnce(&mut Self) -> Condition
+    {
+        let output = match output {
+            Some(output) => output,
+            None => return block(self),
+        };
+        match BlockOutput::redirect(self, output) {
+            Some(redirected) => {
+                let condition = block(self);
+                if let Some(status) = redirected.finish(self) {
+                    self.previous_status = status;
+                }
+                condition
+            }
+            None => {
+                self.previous_status = COULD_NOT_EXEC;
+                Condition::NoOp
             }
         }
     }
@@ -397,66 +688,86 @@ impl<'a> FlowLogic for Shell<'a> {
     {
         match statement {
             Statement::Error(number) => self.previous_status = number,
+            Statement::Line(line) => self.variables.line_number = line,
             // Execute a Let Statement
             Statement::Let { expression } => {
-                self.previous_status = self.local(expression);
+                let status = self.local(expression);
+                self.set_previous_status(status);
             },
             Statement::Export(expression) => {
-               self.previous_status = self.export(expression);
+               let status = self.export(expression);
+               self.set_previous_status(status);
             }
             // Collect the statements for the while loop, and if the loop is complete,
             // execute the while loop with the provided expression.
-            Statement::While { expression, mut statements } => {
+            Statement::While { mut expression, mut statements, mut else_branch } => {
                 self.flow_control.level += 1;
 
-                // Collect all of the statements contained within the while block.
-                collect_loops(iterator, &mut statements, &mut self.flow_control.level);
+                // Collect all of the statements contained within the while block, and its
+                // optional else block.
+                let mut output = None;
+                let mode = collect_loop_else(iterator, Some(&mut expression), &mut statements,
+                    &mut else_branch, &mut output, &mut self.flow_control.level, 0)?;
 
                 if self.flow_control.level == 0 {
                     // All blocks were read, thus we can immediately execute now
-                    self.execute_while(expression, statements);
+                    self.execute_redirected(output, move |shell| {
+                        shell.execute_while(expression, statements, else_branch)
+                    });
                 } else {
                     // Store the partial `Statement::While` to memory
+                    self.flow_control.current_if_mode = mode;
                     self.flow_control.current_statement = Statement::While {
-                        expression: expression,
-                        statements: statements,
+                        expression:  expression,
+                        statements:  statements,
+                        else_branch: else_branch,
                     }
                 }
             },
             // Collect the statements for the for loop, and if the loop is complete,
             // execute the for loop with the provided expression.
-            Statement::For { variable, values, mut statements } => {
+            Statement::For { variables, values, mut statements, mut else_branch } => {
                 self.flow_control.level += 1;
 
-                // Collect all of the statements contained within the for block.
-                collect_loops(iterator, &mut statements, &mut self.flow_control.level);
+                // Collect all of the statements contained within the for block, and its
+                // optional else block.
+                let mut output = None;
+                let mode = collect_loop_else(iterator, None, &mut statements, &mut else_branch,
+                    &mut output, &mut self.flow_control.level, 0)?;
 
                 if self.flow_control.level == 0 {
                     // All blocks were read, thus we can immediately execute now
-                    self.execute_for(&variable, &values, statements);
+                    self.execute_redirected(output, move |shell| {
+                        shell.execute_for(&variables, &values, statements, else_branch)
+                    });
                 } else {
                     // Store the partial `Statement::For` to memory
+                    self.flow_control.current_if_mode = mode;
                     self.flow_control.current_statement = Statement::For {
-                        variable:   variable,
-                        values:     values,
-                        statements: statements,
+                        variables:   variables,
+                        values:      values,
+                        statements:  statements,
+                        else_branch: else_branch,
                     }
                 }
             },
             // Collect the statements needed for the `success`, `else_if`, and `failure`
             // conditions; then execute the if statement if it is complete.
-            Statement::If { expression, mut success, mut else_if, mut failure } => {
+            Statement::If { mut expression, mut success, mut else_if, mut failure } => {
                 self.flow_control.level += 1;
 
                 // Collect all of the success and failure statements within the if condition.
                 // The `mode` value will let us know whether the collector ended while
                 // collecting the success block or the failure block.
-                let mode = collect_if(iterator, &mut success, &mut else_if,
-                    &mut failure, &mut self.flow_control.level, 0)?;
+                let mut output = None;
+                let mode = collect_if(iterator, &mut expression, &mut success, &mut else_if,
+                    &mut failure, &mut output, &mut self.flow_control.level, 0)?;
 
                 if self.flow_control.level == 0 {
                     // All blocks were read, thus we can immediately execute now
-                    self.execute_if(expression, success, else_if, failure);
+                    self.execute_redirected(output, move |shell| {
+                        shell.execute_if(expression, success, else_if, failure)
+                    });
                 } else {
                     // Set the mode and partial if statement in memory.
                     self.flow_control.current_if_mode = mode;
@@ -470,7 +781,7 @@ impl<'a> FlowLogic for Shell<'a> {
             },
             // Collect the statements needed by the function and add the function to the
             // list of functions if it is complete.
-            Statement::Function { name, args, mut statements, description } => {
+            Statement::Function { name, args, mut statements, description, private } => {
                 self.flow_control.level += 1;
 
                 // The same logic that applies to loops, also applies here.
@@ -478,11 +789,14 @@ impl<'a> FlowLogic for Shell<'a> {
 
                 if self.flow_control.level == 0 {
                     // All blocks were read, thus we can add it to the list
+                    let name = self.qualify_function_name(&name);
                     self.functions.insert(name.clone(), Function {
                         description: description,
                         name:        name,
                         args:        args,
-                        statements:  statements
+                        statements:  statements,
+                        private:     private,
+                        traced:      false
                     });
                 } else {
                     // Store the partial function declaration in memory.
@@ -490,18 +804,56 @@ impl<'a> FlowLogic for Shell<'a> {
                         description: description,
                         name:        name,
                         args:        args,
-                        statements:  statements
+                        statements:  statements,
+                        private:     private
+                    }
+                }
+            },
+            // Collect the statements within the namespace, and execute them if it is complete.
+            Statement::Namespace { name, mut statements } => {
+                self.flow_control.level += 1;
+
+                // The same logic that applies to loops, also applies here.
+                collect_loops(iterator, &mut statements, &mut self.flow_control.level);
+
+                if self.flow_control.level == 0 {
+                    self.execute_namespace(&name, statements);
+                } else {
+                    // Store the partial namespace block in memory.
+                    self.flow_control.current_statement = Statement::Namespace {
+                        name:       name,
+                        statements: statements
+                    }
+                }
+            },
+            // Collect the else block of the guard, and execute it if the guard is complete.
+            Statement::Guard { expression, mut statements } => {
+                self.flow_control.level += 1;
+
+                // The same logic that applies to loops, also applies here.
+                collect_loops(iterator, &mut statements, &mut self.flow_control.level);
+
+                if self.flow_control.level == 0 {
+                    // All blocks were read, thus we can immediately execute now
+                    self.execute_guard(expression, statements);
+                } else {
+                    // Store the partial guard statement in memory.
+                    self.flow_control.current_statement = Statement::Guard {
+                        expression: expression,
+                        statements: statements
                     }
                 }
             },
             // Simply executes a provided pipeline, immediately.
             Statement::Pipeline(mut pipeline)  => {
                 self.run_pipeline(&mut pipeline);
+                self.run_pending_traps();
                 if self.flags & ERR_EXIT != 0 && self.previous_status != SUCCESS {
                     let status = self.previous_status;
                     self.exit(status);
                 }
             },
+            Statement::Coproc { name, pipeline } => self.execute_coproc(&name, pipeline),
             // At this level, else and else if keywords are forbidden.
             Statement::ElseIf{..} | Statement::Else => {
                 let stderr = io::stderr();
@@ -509,7 +861,7 @@ impl<'a> FlowLogic for Shell<'a> {
                 let _ = writeln!(stderr, "ion: syntax error: not an if statement");
             },
             // Likewise to else and else if, the end keyword does nothing here.
-            Statement::End => {
+            Statement::End | Statement::RedirectedEnd(_) => {
                 let stderr = io::stderr();
                 let mut stderr = stderr.lock();
                 let _ = writeln!(stderr, "ion: syntax error: no block to end");
@@ -530,6 +882,22 @@ impl<'a> FlowLogic for Shell<'a> {
                     self.flow_control.current_statement = Statement::Match {expression, cases};
                 }
             }
+            // Collect all cases that are being used by a status match construct
+            Statement::MatchStatus {expression, mut cases} => {
+                self.flow_control.level += 1;
+                if let Err(why) = collect_cases(iterator, &mut cases, &mut self.flow_control.level) {
+                    let stderr = io::stderr();
+                    let mut stderr = stderr.lock();
+                    let _ = writeln!(stderr, "{}", why);
+                }
+                if self.flow_control.level == 0 {
+                    // If all blocks were read we execute the statement
+                    self.execute_match_status(expression, cases);
+                } else {
+                    // Store the partial status match in memory.
+                    self.flow_control.current_statement = Statement::MatchStatus {expression, cases};
+                }
+            }
             _ => {}
         }
         Ok(())
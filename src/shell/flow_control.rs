@@ -3,12 +3,16 @@ use parser::pipelines::Pipeline;
 use parser::assignments::Binding;
 use super::Shell;
 use super::flow::FlowLogic;
+use super::flags::PRINT_COMMS;
 use types::*;
 use fnv::*;
+use parser::expand_string;
+use std::{fmt, iter, option};
+use std::mem;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct ElseIf {
-    pub expression: Pipeline,
+    pub expression: Vec<Pipeline>,
     pub success:    Vec<Statement>
 }
 
@@ -16,7 +20,33 @@ pub struct ElseIf {
 pub enum Type { Float, Int, Bool }
 
 #[derive(Debug, PartialEq, Clone)]
-pub enum FunctionArgument { Typed(String, Type), Untyped(String) }
+pub enum FunctionArgument {
+    Typed(String, Type),
+    Untyped(String),
+    /// An argument with a default expression, which is expanded when the caller omits it.
+    Default(Box<FunctionArgument>, String),
+    /// Collects all remaining arguments into an array.
+    Variadic(String)
+}
+
+impl FunctionArgument {
+    pub fn name(&self) -> &str {
+        match *self {
+            FunctionArgument::Typed(ref name, _)
+                | FunctionArgument::Untyped(ref name)
+                | FunctionArgument::Variadic(ref name) => name,
+            FunctionArgument::Default(ref argument, _) => argument.name(),
+        }
+    }
+
+    fn type_(&self) -> Option<Type> {
+        match *self {
+            FunctionArgument::Typed(_, type_) => Some(type_),
+            FunctionArgument::Untyped(_) | FunctionArgument::Variadic(_) => None,
+            FunctionArgument::Default(ref argument, _) => argument.type_(),
+        }
+    }
+}
 
 
 /// Represents a single branch in a match statement. For example, in the expression
@@ -53,7 +83,7 @@ pub enum Statement {
     Case(Case),
     Export(Binding),
     If {
-        expression: Pipeline,
+        expression: Vec<Pipeline>,
         success: Vec<Statement>,
         else_if: Vec<ElseIf>,
         failure: Vec<Statement>
@@ -63,27 +93,53 @@ pub enum Statement {
         name: Identifier,
         description: String,
         args: Vec<FunctionArgument>,
+        statements: Vec<Statement>,
+        private: bool
+    },
+    Namespace {
+        name: Identifier,
+        statements: Vec<Statement>
+    },
+    Guard {
+        expression: Pipeline,
         statements: Vec<Statement>
     },
     For {
-        variable: Identifier,
+        variables: Vec<Identifier>,
         values: Vec<String>,
-        statements: Vec<Statement>
+        statements: Vec<Statement>,
+        else_branch: Vec<Statement>
     },
     While {
-        expression: Pipeline,
-        statements: Vec<Statement>
+        expression: Vec<Pipeline>,
+        statements: Vec<Statement>,
+        else_branch: Vec<Statement>
     },
     Match {
         expression: String,
         cases : Vec<Case>
     },
+    MatchStatus {
+        expression: Pipeline,
+        cases : Vec<Case>
+    },
+    Coproc {
+        name: Identifier,
+        pipeline: Pipeline
+    },
     Else,
     End,
+    /// The `end` of an `if`, `for`, or `while` block whose output is redirected or piped as a
+    /// whole, as in `end > log.txt` or `end | sort`. The pipeline holds the commands that the
+    /// output is piped into, if any, along with the redirections of the output.
+    RedirectedEnd(Pipeline),
     Error(i32),
     Break,
     Continue,
     Pipeline(Pipeline),
+    /// Marks the line of input that the following statement was read from, which updates
+    /// `$LINENO` when it is executed, including within the body of a loop or function.
+    Line(usize),
     Default
 }
 
@@ -97,20 +153,38 @@ impl Statement {
             Statement::If { .. } => "If { .. }",
             Statement::ElseIf(_) => "ElseIf { .. }",
             Statement::Function { .. } => "Function { .. }",
+            Statement::Namespace { .. } => "Namespace { .. }",
+            Statement::Guard { .. } => "Guard { .. }",
             Statement::For { .. } => "For { .. }",
             Statement::While { .. } => "While { .. }",
             Statement::Match { .. } => "Match { .. }",
+            Statement::MatchStatus { .. } => "MatchStatus { .. }",
+            Statement::Coproc { .. } => "Coproc { .. }",
             Statement::Else => "Else",
             Statement::End => "End",
+            Statement::RedirectedEnd(_) => "RedirectedEnd { .. }",
             Statement::Error(_) => "Error { .. }",
             Statement::Break => "Break",
             Statement::Continue => "Continue",
             Statement::Pipeline(_) => "Pipeline { .. }",
+            Statement::Line(_) => "Line",
             Statement::Default => "Default"
 
         }
     }
 
+    /// Returns `true` if the statement unconditionally diverts control flow away from the
+    /// current block, which is the case for `break`, `continue`, and the `exit` builtin.
+    pub fn diverges(&self) -> bool {
+        match *self {
+            Statement::Break | Statement::Continue => true,
+            Statement::Pipeline(ref pipeline) => {
+                pipeline.jobs.len() == 1 && "exit" == pipeline.jobs[0].command
+            },
+            _ => false
+        }
+    }
+
 }
 
 pub struct FlowControl {
@@ -129,23 +203,265 @@ impl Default for FlowControl {
     }
 }
 
+/// The prefix of environment variables which contain the source of an exported function.
+pub const EXPORTED_FUNCTION_PREFIX: &str = "ION_FUNC_";
+
+/// How deeply function calls may be nested when `RECURSION_LIMIT` is not set. Each call nests
+/// many frames of the shell, and so the limit is kept low enough that even an unoptimized build
+/// reaches it well before the main thread's stack of 8 MiB is exhausted.
+pub const DEFAULT_RECURSION_LIMIT: usize = 100;
+
 #[derive(Clone)]
 pub struct Function {
     pub description: String,
     pub name: Identifier,
     pub args: Vec<FunctionArgument>,
-    pub statements: Vec<Statement>
+    pub statements: Vec<Statement>,
+    /// Private functions may only be called from within their own namespace.
+    pub private: bool,
+    /// Commands are printed while a traced function, and the functions it calls, are executing.
+    pub traced: bool
+}
+
+impl fmt::Display for FunctionArgument {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FunctionArgument::Typed(ref name, Type::Float) => write!(f, "{}:float", name),
+            FunctionArgument::Typed(ref name, Type::Int) => write!(f, "{}:int", name),
+            FunctionArgument::Typed(ref name, Type::Bool) => write!(f, "{}:bool", name),
+            FunctionArgument::Untyped(ref name) => write!(f, "{}", name),
+            FunctionArgument::Default(ref argument, ref value) => write!(f, "{}={}", argument, value),
+            FunctionArgument::Variadic(ref name) => write!(f, "{}...[]", name),
+        }
+    }
+}
+
+/// Reconstructs the source code of the function.
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_function(f, &self.name, &self.description, &self.args, self.private, 0)?;
+        write_statements(f, &self.statements, 1)?;
+        writeln!(f, "end")
+    }
+}
+
+fn write_function(
+    f: &mut fmt::Formatter,
+    name: &str,
+    description: &str,
+    args: &[FunctionArgument],
+    private: bool,
+    level: usize
+) -> fmt::Result {
+    write!(f, "{}fn {}{}", "    ".repeat(level), if private { "-p " } else { "" }, name)?;
+    for argument in args {
+        write!(f, " {}", argument)?;
+    }
+    if !description.is_empty() {
+        write!(f, " -- {}", description)?;
+    }
+    writeln!(f)
+}
+
+/// Writes the source of a function's statements. Function bodies are stored flat, with each
+/// block being terminated by an `End` statement, so indentation is tracked with a stack of
+/// the blocks that are currently open, where `true` denotes a match block.
+fn write_statements(f: &mut fmt::Formatter, statements: &[Statement], mut level: usize) -> fmt::Result {
+    let mut blocks: Vec<bool> = Vec::new();
+    for statement in statements {
+        let indent = "    ".repeat(level);
+        let outer = "    ".repeat(level.saturating_sub(1));
+        match *statement {
+            Statement::Let { expression: Binding::Constant(ref binding) } => {
+                writeln!(f, "{}const {}", indent, binding)?
+            },
+            Statement::Let { ref expression } => writeln!(f, "{}let {}", indent, expression)?,
+            Statement::Export(ref expression) => writeln!(f, "{}export {}", indent, expression)?,
+            Statement::Break => writeln!(f, "{}break", indent)?,
+            Statement::Continue => writeln!(f, "{}continue", indent)?,
+            Statement::Pipeline(ref pipeline) => writeln!(f, "{}{}", indent, pipeline)?,
+            Statement::Coproc { ref name, ref pipeline } => {
+                writeln!(f, "{}coproc {} {{ {} }}", indent, &**name, pipeline)?
+            },
+            Statement::Else => writeln!(f, "{}else", outer)?,
+            Statement::ElseIf(ref branch) => writeln!(f, "{}else if {}", outer, conditions(&branch.expression))?,
+            Statement::Case(ref case) => {
+                writeln!(f, "{}case {}", outer, case.value.as_ref().map_or("_", |value| value.as_str()))?
+            },
+            Statement::End | Statement::RedirectedEnd(_) => {
+                if blocks.pop() == Some(true) {
+                    level = level.saturating_sub(1);
+                }
+                level = level.saturating_sub(1);
+                match *statement {
+                    Statement::RedirectedEnd(ref output) if output.jobs.is_empty() => {
+                        writeln!(f, "{}end {}", "    ".repeat(level), output)?
+                    }
+                    Statement::RedirectedEnd(ref output) => {
+                        writeln!(f, "{}end | {}", "    ".repeat(level), output)?
+                    }
+                    _ => writeln!(f, "{}end", "    ".repeat(level))?,
+                }
+            },
+            Statement::Error(_) | Statement::Line(_) | Statement::Default => (),
+            _ => {
+                let is_match = match *statement {
+                    Statement::If { ref expression, .. } => {
+                        writeln!(f, "{}if {}", indent, conditions(expression))?;
+                        false
+                    },
+                    Statement::Function { ref name, ref description, ref args, private, .. } => {
+                        write_function(f, name, description, args, private, level)?;
+                        false
+                    },
+                    Statement::Namespace { ref name, .. } => {
+                        writeln!(f, "{}namespace {}", indent, &**name)?;
+                        false
+                    },
+                    Statement::Guard { ref expression, .. } => {
+                        writeln!(f, "{}guard {} else", indent, expression)?;
+                        false
+                    },
+                    Statement::For { ref variables, ref values, .. } => {
+                        let variables: Vec<&str> = variables.iter().map(|variable| &**variable).collect();
+                        writeln!(f, "{}for {} in {}", indent, variables.join(" "), values.join(" "))?;
+                        false
+                    },
+                    Statement::While { ref expression, .. } => {
+                        writeln!(f, "{}while {}", indent, conditions(expression))?;
+                        false
+                    },
+                    Statement::Match { ref expression, .. } => {
+                        writeln!(f, "{}match {}", indent, expression)?;
+                        true
+                    },
+                    Statement::MatchStatus { ref expression, .. } => {
+                        writeln!(f, "{}switch {}", indent, expression)?;
+                        true
+                    },
+                    _ => unreachable!()
+                };
+                // The cases of a match block are indented one level deeper than the match.
+                level += if is_match { 2 } else { 1 };
+                blocks.push(is_match);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Formats the pipelines which make up the condition of an `if` or `while` statement, such as
+/// `test -f $f; and test -r $f`.
+fn conditions(expression: &[Pipeline]) -> String {
+    expression.iter().map(Pipeline::to_string).collect::<Vec<String>>().join("; ")
 }
 
 pub enum FunctionError {
-    InvalidArgumentCount,
+    /// Contains the usage text of the function, which is synthesized from its parameters.
+    InvalidArgumentCount(String),
     InvalidArgumentType(Type, String),
+    RecursionLimit(usize),
+    UnknownArgument(String),
+    DuplicateArgument(String),
+    ReadonlyArgument(String),
+}
+
+/// Splits a keyword argument of the form `--name=value` into its name and value.
+fn keyword_argument(argument: &str) -> Option<(&str, &str)> {
+    if !argument.starts_with("--") {
+        return None;
+    }
+    match argument.find('=') {
+        Some(pos) if pos > 2 => Some((&argument[2..pos], &argument[pos + 1..])),
+        _ => None
+    }
 }
 
 impl Function {
+    /// The namespace that the function was declared within, if any.
+    pub fn namespace(&self) -> Option<&str> {
+        self.name.rfind("::").map(|pos| &self.name[..pos])
+    }
+
+    /// Synthesizes a usage line from the declared parameters and description of the function.
+    /// Optional parameters are enclosed within brackets.
+    pub fn usage(&self) -> String {
+        let mut usage = self.name.to_string();
+        for argument in &self.args {
+            usage.push(' ');
+            match *argument {
+                FunctionArgument::Default(..) => usage.push_str(&format!("[{}]", argument)),
+                FunctionArgument::Variadic(ref name) => usage.push_str(&format!("[{}...]", name)),
+                _ => usage.push_str(&argument.to_string()),
+            }
+        }
+        if !self.description.is_empty() {
+            usage.push_str(" -- ");
+            usage.push_str(&self.description);
+        }
+        usage
+    }
+
     pub fn execute(self, shell: &mut Shell, args: &[&str]) -> Result<(), FunctionError> {
-        if args.len() - 1 != self.args.len() {
-            return Err(FunctionError::InvalidArgumentCount);
+        // Prevents infinitely-recursive functions from overflowing the stack.
+        let limit = shell.variables.get_var("RECURSION_LIMIT")
+            .and_then(|limit| limit.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_RECURSION_LIMIT);
+        if shell.call_depth >= limit {
+            return Err(FunctionError::RecursionLimit(limit));
+        }
+
+        if let Some(param) = self.args.iter().find(|param| shell.variables.is_readonly(param.name())) {
+            return Err(FunctionError::ReadonlyArgument(param.name().to_owned()));
+        }
+
+        let variadic = match self.args.last() {
+            Some(&FunctionArgument::Variadic(ref name)) => Some(name.as_str()),
+            _ => None
+        };
+        let positional = if variadic.is_some() { &self.args[..self.args.len() - 1] } else { &self.args[..] };
+
+        // Arguments of the form `--name=value` are bound to the parameter of the same name, and
+        // the remaining arguments are bound to the remaining parameters in order. Functions which
+        // collect their remaining arguments receive those which name no parameter, such as options
+        // which are forwarded to a command, as any other argument.
+        let mut named: FnvHashMap<&str, &str> = FnvHashMap::default();
+        let mut remaining = Vec::with_capacity(args.len());
+        for &arg in &args[1..] {
+            if let Some((name, value)) = keyword_argument(arg) {
+                if positional.iter().any(|param| param.name() == name) {
+                    if named.insert(name, value).is_some() {
+                        return Err(FunctionError::DuplicateArgument(name.to_owned()));
+                    }
+                    continue
+                } else if variadic.is_none() {
+                    return Err(FunctionError::UnknownArgument(name.to_owned()));
+                }
+            }
+            remaining.push(arg);
+        }
+
+        // Default expressions are only expanded when needed, and are expanded within the
+        // context of the caller, before any of the function's arguments have been assigned.
+        let mut remaining = remaining.into_iter();
+        let mut values: Vec<String> = Vec::with_capacity(positional.len());
+        for param in positional {
+            let value = match named.get(param.name()) {
+                Some(value) => (*value).to_owned(),
+                None => match (remaining.next(), param) {
+                    (Some(value), _) => value.to_owned(),
+                    (None, &FunctionArgument::Default(_, ref default)) => {
+                        expand_string(default, shell, false).join(" ")
+                    },
+                    (None, _) => return Err(FunctionError::InvalidArgumentCount(self.usage())),
+                }
+            };
+            values.push(value);
+        }
+
+        let rest: Array = remaining.map(|arg| arg.to_owned()).collect();
+        if variadic.is_none() && !rest.is_empty() {
+            return Err(FunctionError::InvalidArgumentCount(self.usage()));
         }
 
         let mut variables_backup: FnvHashMap<&str, Option<Value>> =
@@ -154,21 +470,19 @@ impl Function {
             );
 
         let mut bad_argument: Option<(&str, Type)> = None;
-        for (name_arg, value) in self.args.iter().zip(args.iter().skip(1)) {
-            let name: &str = match name_arg {
-                &FunctionArgument::Typed(ref name, ref type_) => {
-                    match *type_ {
-                        Type::Float if value.parse::<f64>().is_ok() => name.as_str(),
-                        Type::Int if value.parse::<i64>().is_ok() => name.as_str(),
-                        Type::Bool if *value == "true" || *value == "false" => name.as_str(),
-                        _ => {
-                            bad_argument = Some((value, *type_));
-                            break
-                        }
-                    }
-                },
-                &FunctionArgument::Untyped(ref name) => name.as_str()
-            };
+        for (name_arg, value) in positional.iter().zip(values.iter()) {
+            if let Some(type_) = name_arg.type_() {
+                let valid = match type_ {
+                    Type::Float => value.parse::<f64>().is_ok(),
+                    Type::Int => value.parse::<i64>().is_ok(),
+                    Type::Bool => value == "true" || value == "false",
+                };
+                if !valid {
+                    bad_argument = Some((value, type_));
+                    break
+                }
+            }
+            let name = name_arg.name();
             variables_backup.insert(name, shell.variables.get_var(name));
             shell.variables.set_var(name, value);
         }
@@ -177,7 +491,7 @@ impl Function {
             Some((actual_value, expected_type)) => {
                 for (name, value_option) in &variables_backup {
                     match *value_option {
-                        Some(ref value) => shell.variables.set_var(name, value),
+                        Some(ref value) => { shell.variables.set_var(name, value); },
                         None => {shell.variables.unset_var(name);},
                     }
                 }
@@ -185,14 +499,41 @@ impl Function {
                 return Err(FunctionError::InvalidArgumentType(expected_type, actual_value.to_owned()));
             }
             None => {
+                let array_backup = variadic.map(|name| {
+                    let backup = shell.variables.unset_array(name);
+                    shell.variables.set_array(name, rest);
+                    (name, backup)
+                });
+
+                // Variables assigned with `let` inside of the function are local to this call.
+                shell.variables.new_scope();
+                // Unqualified calls within the function are resolved within its namespace.
+                let namespace = mem::replace(&mut shell.namespace, self.namespace().map(Into::into));
+                let flags = shell.flags;
+                if self.traced { shell.flags |= PRINT_COMMS; }
+                shell.call_depth += 1;
+                shell.variables.function_names.push(self.name.clone());
+                let line = shell.variables.line_number;
                 shell.execute_statements(self.statements);
+                shell.variables.line_number = line;
+                shell.variables.function_names.pop();
+                shell.call_depth -= 1;
+                if self.traced { shell.flags = (shell.flags & !PRINT_COMMS) | (flags & PRINT_COMMS); }
+                shell.namespace = namespace;
+                shell.variables.pop_scope();
 
                 for (name, value_option) in &variables_backup {
                     match *value_option {
-                        Some(ref value) => shell.variables.set_var(name, value),
+                        Some(ref value) => { shell.variables.set_var(name, value); },
                         None => {shell.variables.unset_var(name);},
                     }
                 }
+                if let Some((name, backup)) = array_backup {
+                    match backup {
+                        Some(array) => { shell.variables.set_array(name, array); },
+                        None => {shell.variables.unset_array(name);},
+                    }
+                }
                 Ok(())
             }
         }
@@ -217,6 +558,7 @@ pub fn collect_cases<I>(iterator: &mut I, cases: &mut Vec<Case>, level: &mut usi
 
     while let Some(statement) = iterator.next() {
         match statement {
+            Statement::Line(_) if cases.is_empty() => (),
             Statement::Case(case) => {
                 if *level == 1 {
                     // If the level is 1, then we are at a top-level case
@@ -227,9 +569,12 @@ pub fn collect_cases<I>(iterator: &mut I, cases: &mut Vec<Case>, level: &mut usi
                     add_to_case!(Statement::Case(case));
                 }
             },
-            Statement::End => {
+            Statement::End | Statement::RedirectedEnd(_) => {
                 *level -= 1;
                 if *level == 0 {
+                    if let Statement::RedirectedEnd(_) = statement {
+                        return Err(UNREDIRECTABLE.into());
+                    }
                     return Ok(());
                 }
             }
@@ -237,6 +582,9 @@ pub fn collect_cases<I>(iterator: &mut I, cases: &mut Vec<Case>, level: &mut usi
             Statement::For { .. } |
             Statement::If { .. } |
             Statement::Match { .. } |
+            Statement::MatchStatus { .. } |
+            Statement::Guard { .. } |
+            Statement::Namespace { .. } |
             Statement::Function { .. } => {
                 *level += 1;
                 add_to_case!(statement);
@@ -249,6 +597,8 @@ pub fn collect_cases<I>(iterator: &mut I, cases: &mut Vec<Case>, level: &mut usi
             Statement::Continue |
             Statement::Let { .. } |
             Statement::Pipeline(_) |
+            Statement::Coproc { .. } |
+            Statement::Line(_) |
             Statement::Break => {
                 // This is the default case with all of the other statements explicitly listed
                 add_to_case!(statement);
@@ -258,6 +608,35 @@ pub fn collect_cases<I>(iterator: &mut I, cases: &mut Vec<Case>, level: &mut usi
     return Ok(());
 }
 
+/// Whether the statement continues the condition of an `if` or `while` statement, as with the
+/// `and cmd2` of `if cmd1; and cmd2`, when it comes before the first statement of the block.
+fn continues_condition(statement: &Statement) -> bool {
+    match *statement {
+        Statement::Pipeline(ref pipeline) => {
+            let command: &str = pipeline.jobs[0].command.as_ref();
+            command == "and" || command == "or"
+        }
+        _ => false,
+    }
+}
+
+/// Precedes the statement with a `Line` marker for the given `line`, unless the statement is
+/// only a part of a block, like `end` or `case`, or continues the condition of an `if` or `while`.
+pub fn mark_line(line: usize, statement: Statement) -> iter::Chain<option::IntoIter<Statement>, iter::Once<Statement>> {
+    let marker = match statement {
+        Statement::Pipeline(_) if continues_condition(&statement) => None,
+        Statement::Let { .. } | Statement::Export(_) | Statement::If { .. } | Statement::Guard { .. } |
+            Statement::For { .. } | Statement::While { .. } | Statement::Match { .. } |
+            Statement::MatchStatus { .. } | Statement::Namespace { .. } | Statement::Coproc { .. } |
+            Statement::Pipeline(_) => Some(Statement::Line(line)),
+        _ => None,
+    };
+    marker.into_iter().chain(iter::once(statement))
+}
+
+/// Only the output of `if`, `for`, and `while` blocks may be redirected.
+const UNREDIRECTABLE: &str = "ion: syntax error: only if, for, and while blocks may be redirected";
+
 pub fn collect_loops <I: Iterator<Item = Statement>> (
     iterator: &mut I,
     statements: &mut Vec<Statement>,
@@ -267,25 +646,104 @@ pub fn collect_loops <I: Iterator<Item = Statement>> (
     while let Some(statement) = iterator.next() {
         match statement {
             Statement::While{..} | Statement::For{..} | Statement::If{..} |
-                Statement::Function{..} | Statement::Match{..} => *level += 1,
+                Statement::Function{..} | Statement::Match{..} | Statement::MatchStatus{..} |
+                Statement::Guard{..} | Statement::Namespace{..} => *level += 1,
             Statement::End if *level == 1 => { *level = 0; break },
-            Statement::End => *level -= 1,
+            Statement::RedirectedEnd(_) if *level == 1 => {
+                eprintln!("{}", UNREDIRECTABLE);
+                *level = 0;
+                break
+            },
+            Statement::End | Statement::RedirectedEnd(_) => *level -= 1,
             _ => (),
         }
         statements.push(statement);
     }
 }
 
-pub fn collect_if<I>(iterator: &mut I, success: &mut Vec<Statement>, else_if: &mut Vec<ElseIf>,
-    failure: &mut Vec<Statement>, level: &mut usize, mut current_block: u8)
-        -> Result<u8, &'static str>
+/// Collects the statements of a `for` or `while` loop. Statements that follow a top-level
+/// `else` keyword are collected into the `else_branch`, which is executed only when the loop
+/// completes without encountering a `break`. The returned mode is `1` if the collector ended
+/// while collecting the else branch, and `0` otherwise. The condition of a `while` loop is
+/// given as the `expression`, which is extended by any `and` or `or` statements that lead the loop.
+/// If the `end` of the loop redirects its output, the redirection is stored in `output`.
+pub fn collect_loop_else<I>(iterator: &mut I, mut expression: Option<&mut Vec<Pipeline>>,
+    statements: &mut Vec<Statement>, else_branch: &mut Vec<Statement>, output: &mut Option<Pipeline>,
+    level: &mut usize, mut current_block: u8) -> Result<u8, &'static str>
     where I: Iterator<Item = Statement>
 {
     #[allow(while_let_on_iterator)]
     while let Some(statement) = iterator.next() {
+        if let Some(ref mut expression) = expression {
+            if *level == 1 && current_block == 0 && statements.is_empty() && continues_condition(&statement) {
+                if let Statement::Pipeline(pipeline) = statement {
+                    expression.push(pipeline);
+                }
+                continue
+            }
+        }
         match statement {
             Statement::While{..} | Statement::For{..} | Statement::If{..} |
-                Statement::Function{..} | Statement::Match{..} => *level += 1,
+                Statement::Function{..} | Statement::Match{..} | Statement::MatchStatus{..} |
+                Statement::Guard{..} | Statement::Namespace{..} => *level += 1,
+            Statement::ElseIf(_) if *level == 1 => {
+                return Err("ion: syntax error: loops do not support else if blocks");
+            }
+            Statement::Else if *level == 1 && current_block == 1 => {
+                return Err("ion: syntax error: else block already given");
+            }
+            Statement::Else if *level == 1 => {
+                current_block = 1;
+                continue
+            },
+            Statement::End | Statement::RedirectedEnd(_) if *level == 1 => {
+                *level = 0;
+                if let Statement::RedirectedEnd(pipeline) = statement {
+                    *output = Some(pipeline);
+                }
+                break
+            },
+            Statement::End | Statement::RedirectedEnd(_) => *level -= 1,
+            _ => (),
+        }
+
+        match current_block {
+            0 => statements.push(statement),
+            _ => else_branch.push(statement),
+        }
+    }
+
+    Ok(current_block)
+}
+
+/// Collects the blocks of an `if` statement, where any `and` or `or` statements that lead the
+/// success block, or the block of an `else if`, extend the condition of that block. If the `end`
+/// of the block redirects its output, the redirection is stored in `output`.
+pub fn collect_if<I>(iterator: &mut I, expression: &mut Vec<Pipeline>, success: &mut Vec<Statement>,
+    else_if: &mut Vec<ElseIf>, failure: &mut Vec<Statement>, output: &mut Option<Pipeline>,
+    level: &mut usize, mut current_block: u8) -> Result<u8, &'static str>
+    where I: Iterator<Item = Statement>
+{
+    #[allow(while_let_on_iterator)]
+    while let Some(statement) = iterator.next() {
+        if *level == 1 && continues_condition(&statement) {
+            let condition = match current_block {
+                0 if success.is_empty() => Some(&mut *expression),
+                2 => else_if.last_mut()
+                    .and_then(|elseif| if elseif.success.is_empty() { Some(&mut elseif.expression) } else { None }),
+                _ => None,
+            };
+            if let Some(condition) = condition {
+                if let Statement::Pipeline(pipeline) = statement {
+                    condition.push(pipeline);
+                }
+                continue
+            }
+        }
+        match statement {
+            Statement::While{..} | Statement::For{..} | Statement::If{..} |
+                Statement::Function{..} | Statement::Match{..} | Statement::MatchStatus{..} |
+                Statement::Guard{..} | Statement::Namespace{..} => *level += 1,
             Statement::ElseIf(ref elseif) if *level == 1 => {
                 if current_block == 1 {
                     return Err("ion: syntax error: else block already given");
@@ -302,8 +760,14 @@ pub fn collect_if<I>(iterator: &mut I, success: &mut Vec<Statement>, else_if: &m
             Statement::Else if *level == 1 && current_block == 1 => {
                 return Err("ion: syntax error: else block already given");
             }
-            Statement::End if *level == 1 => { *level = 0; break },
-            Statement::End => *level -= 1,
+            Statement::End | Statement::RedirectedEnd(_) if *level == 1 => {
+                *level = 0;
+                if let Statement::RedirectedEnd(pipeline) = statement {
+                    *output = Some(pipeline);
+                }
+                break
+            },
+            Statement::End | Statement::RedirectedEnd(_) => *level -= 1,
             _ => (),
         }
 
@@ -320,3 +784,64 @@ pub fn collect_if<I>(iterator: &mut I, success: &mut Vec<Statement>, else_if: &m
 
     Ok(current_block)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parser::parse_and_validate;
+
+    fn parse(statement: &str) -> Statement { parse_and_validate(Ok(statement)) }
+
+    #[test]
+    fn conditions_are_extended() {
+        let mut expression = match parse("if test -f file") {
+            Statement::If { expression, .. } => expression,
+            _ => unreachable!(),
+        };
+        let mut statements = ["and test -r file", "echo yes", "and true", "else if false", "or true", "end"]
+            .iter()
+            .map(|statement| parse(statement));
+        let (mut success, mut else_if, mut failure) = (Vec::new(), Vec::new(), Vec::new());
+        let mut level = 1;
+        assert_eq!(collect_if(&mut statements, &mut expression, &mut success, &mut else_if,
+            &mut failure, &mut None, &mut level, 0), Ok(2));
+        assert_eq!(conditions(&expression), "test -f file; and test -r file");
+        assert_eq!(success, vec![parse("echo yes"), parse("and true")]);
+        assert_eq!(conditions(&else_if[0].expression), "false; or true");
+        assert!(else_if[0].success.is_empty());
+
+        let mut expression = Vec::new();
+        let mut statements = ["echo", "and true", "end"].iter().map(|statement| parse(statement));
+        let (mut body, mut else_branch) = (Vec::new(), Vec::new());
+        let mut level = 1;
+        assert_eq!(collect_loop_else(&mut statements, Some(&mut expression), &mut body,
+            &mut else_branch, &mut None, &mut level, 0), Ok(0));
+        assert!(expression.is_empty());
+        assert_eq!(body.len(), 2);
+    }
+
+    #[test]
+    fn nested_blocks_are_printed() {
+        let statements = [
+            "for x in 1 2", "if test $x -eq 1", "echo one", "else", "echo other", "end", "end"
+        ];
+        let function = Function {
+            description: String::new(),
+            name: "nested".into(),
+            args: Vec::new(),
+            statements: statements.iter().map(|statement| parse(statement)).collect(),
+            private: false,
+            traced: false,
+        };
+        assert_eq!(function.to_string(), "fn nested
+    for x in 1 2
+        if test $x -eq 1
+            echo one
+        else
+            echo other
+        end
+    end
+end
+");
+    }
+}
@@ -2,10 +2,68 @@ use std::io::{self, Write};
 use super::status::*;
 use super::Shell;
 
+/// Expands each reference to an earlier command within the command, where `!!` refers to the
+/// previous command, `!N` to the Nth command of the history, and `!-N` to the Nth command before
+/// this one. The `event` function retrieves a command by its number, where negative numbers count
+/// back from the end of the history. Returns `None` if nothing was expanded.
+fn expand_events<F>(command: &str, event: F) -> Result<Option<String>, String>
+    where F: Fn(isize) -> Option<String>
+{
+    let bytes = command.as_bytes();
+    let mut output = String::with_capacity(command.len());
+    let (mut start, mut index, mut quoted, mut expanded) = (0, 0, false, false);
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\'' => quoted = !quoted,
+            b'\\' => index += 1,
+            // A `!` which follows a `$` is the variable of the last background job.
+            b'!' if !quoted && (index == 0 || bytes[index - 1] != b'$') => {
+                let rest = &command[index + 1..];
+                let (number, length) = if rest.starts_with('!') {
+                    (Some(-1), 1)
+                } else {
+                    let negative = rest.starts_with('-');
+                    let digits = rest[negative as usize..].bytes().take_while(|&byte| byte >= b'0' && byte <= b'9').count();
+                    let length = negative as usize + digits;
+                    (if digits == 0 { None } else { rest[..length].parse::<isize>().ok() }, length)
+                };
+
+                if let Some(number) = number {
+                    let reference = &command[index..index + 1 + length];
+                    match event(number) {
+                        Some(ref event) if number != 0 => {
+                            output.push_str(&command[start..index]);
+                            output.push_str(event);
+                        }
+                        _ => return Err(format!("{}: event not found", reference)),
+                    }
+                    index += 1 + length;
+                    start = index;
+                    expanded = true;
+                    continue
+                }
+            }
+            _ => (),
+        }
+        index += 1;
+    }
+
+    if !expanded {
+        return Ok(None);
+    }
+    output.push_str(&command[start..]);
+    Ok(Some(output))
+}
+
 /// Contains all history-related functionality for the `Shell`.
 pub trait ShellHistory {
-    /// Prints the commands contained within the history buffers to standard output.
-    fn print_history(&self, _arguments: &[&str]) -> i32;
+    /// Executes the `history` builtin, which prints each command of the history along with its
+    /// number, deletes the Nth command with `-d N`, or clears the history with `-c`.
+    fn history(&mut self, arguments: &[&str]) -> i32;
+
+    /// Expands the references to earlier commands within an interactive command, such as `!!`
+    /// and `!N`. Returns `None` if the command contained no references.
+    fn expand_history(&self, command: &str) -> Result<Option<String>, String>;
 
     /// Sets the history size for the shell context equal to the HISTORY_SIZE shell variable if it
     /// is set otherwise to a default value (1000).
@@ -21,21 +79,63 @@ pub trait ShellHistory {
 }
 
 impl<'a> ShellHistory for Shell<'a> {
-    fn print_history(&self, _arguments: &[&str]) -> i32 {
-        if let Some(context) = self.context.as_ref() {
-            let mut buffer = Vec::with_capacity(8*1024);
-            for command in &context.history.buffers {
-                let _ = writeln!(buffer, "{}", command);
+    fn history(&mut self, arguments: &[&str]) -> i32 {
+        let context = match self.context.as_mut() {
+            Some(context) => context,
+            None => {
+                eprintln!("ion: history: the history is only available within interactive sessions");
+                return FAILURE;
+            }
+        };
+
+        match arguments.get(1) {
+            None => {
+                let mut buffer = Vec::with_capacity(8*1024);
+                for (number, command) in context.history.buffers.iter().enumerate() {
+                    let _ = writeln!(buffer, "{:5}  {}", number + 1, command);
+                }
+                let stdout = io::stdout();
+                let mut stdout = stdout.lock();
+                let _ = stdout.write_all(&buffer);
+                SUCCESS
+            }
+            Some(&"-c") => {
+                context.history.buffers.clear();
+                SUCCESS
+            }
+            Some(&"-d") => {
+                let length = context.history.buffers.len();
+                match arguments.get(2).and_then(|number| number.parse::<usize>().ok()) {
+                    Some(number) if number > 0 && number <= length => {
+                        context.history.buffers.remove(number - 1);
+                        SUCCESS
+                    }
+                    _ => {
+                        eprintln!("ion: history: {}: history position out of range",
+                            arguments.get(2).unwrap_or(&""));
+                        FAILURE
+                    }
+                }
+            }
+            Some(argument) => {
+                eprintln!("ion: history: {}: invalid option\nusage: history [-c | -d N]", argument);
+                BAD_ARG
             }
-            let stdout = io::stdout();
-            let mut stdout = stdout.lock();
-            let _ = stdout.write_all(&buffer);
-            SUCCESS
-        } else {
-            FAILURE
         }
     }
 
+    fn expand_history(&self, command: &str) -> Result<Option<String>, String> {
+        let buffers = match self.context.as_ref() {
+            Some(context) => &context.history.buffers,
+            None => return Ok(None),
+        };
+        expand_events(command, |number| {
+            let index = if number < 0 { buffers.len() as isize + number } else { number - 1 };
+            if index < 0 { return None }
+            buffers.get(index as usize).map(|buffer| buffer.to_string())
+        })
+    }
+
     fn set_context_history_from_vars(&mut self) {
         let context = self.context.as_mut().unwrap();
         let max_history_size = self.variables
@@ -59,3 +159,29 @@ impl<'a> ShellHistory for Shell<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(command: &str) -> Result<Option<String>, String> {
+        let history = ["ls", "cd /tmp", "echo hello"];
+        expand_events(command, |number| {
+            let index = if number < 0 { history.len() as isize + number } else { number - 1 };
+            if index < 0 { return None }
+            history.get(index as usize).map(|command| command.to_string())
+        })
+    }
+
+    #[test]
+    fn history_events() {
+        assert_eq!(expand("!!"), Ok(Some("echo hello".into())));
+        assert_eq!(expand("!1 -l"), Ok(Some("ls -l".into())));
+        assert_eq!(expand("!-2 && !!"), Ok(Some("cd /tmp && echo hello".into())));
+        assert_eq!(expand("echo '!!' $! !"), Ok(None));
+        assert_eq!(expand("test 1 != 2"), Ok(None));
+        assert_eq!(expand("!4"), Err("!4: event not found".into()));
+        assert_eq!(expand("!-4"), Err("!-4: event not found".into()));
+        assert_eq!(expand("!0"), Err("!0: event not found".into()));
+    }
+}
@@ -1,32 +1,150 @@
 use std::fs::File;
 use std::process::{Command, Stdio};
+use std::time::Duration;
 use std::os::unix::io::{FromRawFd, IntoRawFd};
 
 //use glob::glob;
 use parser::{expand_string, Expander};
-use parser::pipelines::RedirectFrom;
+use parser::pipelines::{FdRedirection, RedirectFrom};
+use shell::pipe_exec::limits::Limits;
 use smallstring::SmallString;
 use types::*;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum JobKind { And, Background, Last, Or, Pipe(RedirectFrom) }
 
+/// Restricts the kinds of commands that a job may be resolved to.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Resolution {
+    /// Aliases, functions, builtins, and then external commands are searched in order.
+    Any,
+    /// Set by the `builtin` prefix, which only permits builtins.
+    Builtin,
+    /// Set by the `command` prefix, which bypasses aliases, functions, and builtins.
+    External,
+    /// A group of commands within parenthesis, which is executed within a fork of the shell.
+    /// The only argument of the job is the group itself.
+    Subshell,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Job {
     pub command: Identifier,
     pub args: Array,
     pub kind: JobKind,
+    pub resolution: Resolution,
+    /// Redirections of arbitrary file descriptors, such as `3> file` or `2>&1`, in order.
+    pub redirections: Vec<FdRedirection>,
 }
 
 impl Job {
     pub fn new(args: Array, kind: JobKind) -> Self {
         let command = SmallString::from_str(&args[0]);
-        Job { command, args, kind }
+        Job { command, args, kind, resolution: Resolution::Any, redirections: Vec::new() }
+    }
+
+    /// Removes any leading `builtin` or `command` keywords from the job, restricting how the
+    /// remaining command will be resolved.
+    pub fn strip_resolution_prefixes(&mut self) {
+        while self.args.len() > 1 {
+            self.resolution = match self.args[0].as_str() {
+                "builtin" => Resolution::Builtin,
+                "command" => Resolution::External,
+                _ => return
+            };
+            self.args.remove(0);
+            self.command = SmallString::from_str(&self.args[0]);
+        }
+    }
+
+    /// Removes a leading `timeout DURATION` from the job, returning the duration within which the
+    /// pipeline is expected to complete. A duration which cannot be parsed leaves the job as is,
+    /// so that an external `timeout` command may be executed instead.
+    pub fn strip_timeout(&mut self) -> Option<Duration> {
+        if self.resolution != Resolution::Any || self.args.len() < 3 || self.args[0] != "timeout" {
+            return None;
+        }
+        let duration = match parse_duration(&self.args[1]) {
+            Some(duration) => duration,
+            None => return None,
+        };
+        self.args.remove(0);
+        self.args.remove(0);
+        self.command = SmallString::from_str(&self.args[0]);
+        self.strip_resolution_prefixes();
+        Some(duration)
+    }
+
+    /// Removes a leading `time` from the job, returning whether the times are to be reported in
+    /// the POSIX format of `time -p`. A `time` without a command is left to the builtin.
+    pub fn strip_time(&mut self) -> Option<bool> {
+        if self.resolution != Resolution::Any || self.args.len() < 2 || self.args[0] != "time" {
+            return None;
+        }
+        let posix = self.args[1] == "-p";
+        let count = if posix { 2 } else { 1 };
+        if self.args.len() <= count || self.args[1] == "-h" || self.args[1] == "--help" {
+            return None;
+        }
+        for _ in 0..count {
+            self.args.remove(0);
+        }
+        self.command = SmallString::from_str(&self.args[0]);
+        self.strip_resolution_prefixes();
+        Some(posix)
+    }
+
+    /// Removes a leading `limit` and its options from the job, returning the resource limits that
+    /// the processes of the pipeline are restricted to.
+    pub fn strip_limits(&mut self) -> Result<Option<Limits>, String> {
+        if self.resolution != Resolution::Any || self.args.len() < 2 || self.args[0] != "limit" {
+            return Ok(None);
+        }
+        let (limits, count) = Limits::parse(&self.args[1..])?;
+        for _ in 0..count + 1 {
+            self.args.remove(0);
+        }
+        self.command = SmallString::from_str(&self.args[0]);
+        self.strip_resolution_prefixes();
+        Ok(Some(limits))
+    }
+
+    /// Rewrites a condition of the form `VALUE in ELEMENTS...` into an invocation of the `in`
+    /// builtin. The value must be an expansion, or a word which does not name a command, so that
+    /// commands which are merely given an `in` argument are executed as they are.
+    pub fn membership_test<F: Fn(&str) -> bool>(&mut self, is_command: F) {
+        if self.resolution != Resolution::Any || !self.redirections.is_empty() || self.args.len() < 3
+            || self.args[1] != "in"
+        {
+            return;
+        }
+        let is_expansion = {
+            let value = self.args[0].trim_left_matches('"');
+            value.starts_with('$') || value.starts_with('@')
+        };
+        if !is_expansion && is_command(self.args[0].trim_matches(|c| c == '"' || c == '\'')) {
+            return;
+        }
+        self.args.swap(0, 1);
+        self.command = "in".into();
     }
 
     /// Takes the current job's arguments and expands them, one argument at a
     /// time, returning a new `Job` with the expanded arguments.
     pub fn expand<E: Expander>(&mut self, expanders: &E) {
+        for redirection in &mut self.redirections {
+            match *redirection {
+                FdRedirection::File { path: ref mut value, .. }
+                    | FdRedirection::Duplicate { source: ref mut value, .. } =>
+                {
+                    *value = expand_string(value, expanders, false).join(" ");
+                }
+                FdRedirection::Close(_) => (),
+            }
+        }
+
+        // The commands of a subshell are expanded when the subshell executes them.
+        if self.resolution == Resolution::Subshell { return }
         let mut expanded = Array::new();
         expanded.grow(self.args.len());
         expanded.extend(self.args.drain().flat_map(|arg| {
@@ -42,6 +160,18 @@ impl Job {
 
 }
 
+/// The debug format of a command, without the path of its executable, which precedes the
+/// command within brackets when the command was given another name as its `argv[0]`.
+fn describe(cmd: &Command) -> String {
+    let command = format!("{:?}", cmd);
+    if command.starts_with('[') {
+        if let Some(pos) = command.find("] ") {
+            return command[pos + 2..].to_owned();
+        }
+    }
+    command
+}
+
 /// This represents a job that has been processed and expanded to be run
 /// as part of some pipeline
 pub enum RefinedJob {
@@ -59,6 +189,8 @@ pub enum RefinedJob {
         stdout: Option<File>,
         /// A file corresponding to the standard error for this builtin
         stderr: Option<File>,
+        /// Redirections of the other file descriptors for this builtin
+        redirections: Vec<FdRedirection>,
     },
     /// Functions can act as commands too!
     Function {
@@ -72,6 +204,21 @@ pub enum RefinedJob {
         stdout: Option<File>,
         /// A file corresponding to the standard error for this builtin
         stderr: Option<File>,
+        /// Redirections of the other file descriptors for this builtin
+        redirections: Vec<FdRedirection>,
+    },
+    /// A group of commands which is executed within a fork of the shell
+    Subshell {
+        /// The commands within the parenthesis of the group
+        code: String,
+        /// A file corresponding to the standard input for this subshell
+        stdin: Option<File>,
+        /// A file corresponding to the standard output for this subshell
+        stdout: Option<File>,
+        /// A file corresponding to the standard error for this subshell
+        stderr: Option<File>,
+        /// Redirections of the other file descriptors for this subshell
+        redirections: Vec<FdRedirection>,
     }
 }
 
@@ -83,7 +230,8 @@ macro_rules! set_field {
                     command.$field(Stdio::from_raw_fd($arg.into_raw_fd()));
                 }
             }
-            RefinedJob::Builtin { ref mut $field,  .. } | RefinedJob::Function { ref mut $field, .. } => {
+            RefinedJob::Builtin { ref mut $field,  .. } | RefinedJob::Function { ref mut $field, .. }
+                | RefinedJob::Subshell { ref mut $field, .. } => {
                 *$field = Some($arg);
             }
         }
@@ -97,7 +245,8 @@ impl RefinedJob {
             args,
             stdin: None,
             stdout: None,
-            stderr: None
+            stderr: None,
+            redirections: Vec::new()
         }
     }
 
@@ -107,7 +256,18 @@ impl RefinedJob {
             args,
             stdin: None,
             stdout: None,
-            stderr: None
+            stderr: None,
+            redirections: Vec::new()
+        }
+    }
+
+    pub fn subshell(code: String) -> Self {
+        RefinedJob::Subshell {
+            code,
+            stdin: None,
+            stdout: None,
+            stderr: None,
+            redirections: Vec::new()
         }
     }
 
@@ -128,11 +288,12 @@ impl RefinedJob {
     pub fn short(&self) -> String {
         match *self {
             RefinedJob::External(ref cmd) => {
-                format!("{:?}", cmd).split('"').nth(1).unwrap_or("").to_string()
+                describe(cmd).split('"').nth(1).unwrap_or("").to_string()
             },
             RefinedJob::Builtin { ref name, .. } | RefinedJob::Function { ref name, .. } => {
                 name.to_string()
             }
+            RefinedJob::Subshell { ref code, .. } => format!("({})", code),
         }
     }
 
@@ -140,7 +301,7 @@ impl RefinedJob {
     pub fn long(&self) -> String {
         match *self {
             RefinedJob::External(ref cmd) => {
-                let command = format!("{:?}", cmd);
+                let command = describe(cmd);
                 let mut arg_iter = command.split_whitespace();
                 let command = arg_iter.next().unwrap();
                 let mut output = String::from(&command[1..command.len()-1]);
@@ -157,11 +318,30 @@ impl RefinedJob {
             RefinedJob::Builtin { ref args, .. } | RefinedJob::Function { ref args, .. } => {
                 format!("{}", args.join(" "))
             }
+            RefinedJob::Subshell { ref code, .. } => format!("({})", code),
         }
     }
 
 }
 
+/// Parses a duration such as `5`, `2.5s`, `10m`, `1h`, or `1d`, where seconds are the default unit.
+pub fn parse_duration(input: &str) -> Option<Duration> {
+    let (number, unit) = match input.char_indices().last() {
+        Some((i, 's')) => (&input[..i], 1.0),
+        Some((i, 'm')) => (&input[..i], 60.0),
+        Some((i, 'h')) => (&input[..i], 3600.0),
+        Some((i, 'd')) => (&input[..i], 86400.0),
+        _ => (input, 1.0),
+    };
+    match number.parse::<f64>() {
+        Ok(value) if value >= 0.0 && value.is_finite() => {
+            let seconds = value * unit;
+            Some(Duration::new(seconds as u64, (seconds.fract() * 1e9) as u32))
+        }
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -179,4 +359,53 @@ mod tests {
         assert_eq!(job, expanded);
     }
 
+    #[test]
+    fn subshells_are_not_expanded() {
+        let mut job = Job::new(array!("(echo $x)"), JobKind::Last);
+        job.resolution = Resolution::Subshell;
+        let mut expanded = job.clone();
+        expanded.expand(&Empty);
+        assert_eq!(job, expanded);
+    }
+
+    #[test]
+    fn timeouts() {
+        let mut job = Job::new(array!("timeout", "1.5m", "builtin", "echo", "hi"), JobKind::Last);
+        assert_eq!(job.strip_timeout(), Some(Duration::from_secs(90)));
+        assert_eq!(job.args[0], "echo");
+        assert_eq!(job.resolution, Resolution::Builtin);
+
+        let mut job = Job::new(array!("timeout", "250ms", "sleep", "1"), JobKind::Last);
+        assert_eq!(job.strip_timeout(), None);
+        assert_eq!(job.args[0], "timeout");
+        let mut job = Job::new(array!("timeout", "5"), JobKind::Last);
+        assert_eq!(job.strip_timeout(), None);
+
+        assert_eq!(parse_duration("5"), Some(Duration::from_secs(5)));
+        assert_eq!(parse_duration("0.5s"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("2h"), Some(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("1d"), Some(Duration::from_secs(86400)));
+        assert_eq!(parse_duration("-1"), None);
+        assert_eq!(parse_duration("s"), None);
+    }
+
+    #[test]
+    fn membership_tests() {
+        let is_command = |name: &str| name == "grep" || name == "test";
+        let mut job = Job::new(array!("$x", "in", "@array"), JobKind::Last);
+        job.membership_test(is_command);
+        assert_eq!(job.args, array!("in", "$x", "@array"));
+
+        let mut job = Job::new(array!("apple", "in", "apple", "banana"), JobKind::Last);
+        job.membership_test(is_command);
+        assert_eq!(job.args[0], "in");
+
+        // Commands which are given a literal `in` argument are left unchanged.
+        for args in &[array!("grep", "in", "file"), array!("test", "in", "=", "in"), array!("$x", "in")] {
+            let mut job = Job::new(args.clone(), JobKind::Last);
+            job.membership_test(is_command);
+            assert_eq!(&job.args, args);
+        }
+    }
+
 }
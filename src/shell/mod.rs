@@ -1,10 +1,13 @@
 mod assignments;
 mod binary;
-mod completer;
+mod colors;
+pub mod command_cache;
+pub mod completer;
 mod flow;
 mod history;
 mod job;
 mod pipe_exec;
+mod prompt;
 pub mod directory_stack;
 pub mod flags;
 
@@ -15,33 +18,47 @@ pub mod variables;
 
 pub use self::pipe_exec::{foreground, job_control};
 pub use self::history::ShellHistory;
-pub use self::job::{Job, JobKind};
+pub use self::job::{parse_duration, Job, JobKind, Resolution};
 pub use self::flow::FlowLogic;
 pub use self::binary::Binary;
+pub use self::assignments::VariableStore;
 
 use app_dirs::{AppDataType, AppInfo, app_root};
 use builtins::*;
-use fnv::FnvHashMap;
+use fnv::{FnvHashMap, FnvHashSet};
 use liner::Context;
-use parser::{Expander, ArgumentSplitter, Select};
+use parser::{Expander, ArgumentSplitter, GlobMode, GlobOptions, GlobSort, Select, StatementSplitter,
+    DEFAULT_GLOB_DEPTH, parse_and_validate, expand_string, find_unset_variable};
 use parser::pipelines::Pipeline;
+use self::command_cache::CommandCache;
+use self::completer::Completion;
 use self::directory_stack::DirectoryStack;
 use self::flags::*;
-use self::flow_control::{FlowControl, Function, FunctionError, Type};
+use self::flow_control::{EXPORTED_FUNCTION_PREFIX, FlowControl, Function, FunctionError, Statement, Type,
+    collect_loops};
 use self::foreground::ForegroundSignals;
-use self::job_control::{JobControl, BackgroundProcess};
-use self::pipe_exec::PipelineExecution;
+use self::job_control::{JobControl, BackgroundProcess, Deadline};
+use self::pipe_exec::limits::Limits;
+use self::pipe_exec::timer::Timer;
+use self::pipe_exec::{finish_substitutions, Coproc, PipelineExecution, Substitution};
 use self::status::*;
-use self::variables::Variables;
+use self::variables::{EXPORTED_ARRAY_PREFIX, EXPORTED_BTREEMAP_PREFIX, EXPORTED_HASHMAP_PREFIX, Variables};
 use smallvec::SmallVec;
+use std::cell::{Cell, RefCell};
 use std::env;
 use std::fs::File;
-use std::io::{self, Write};
+use std::mem;
+use std::io::{self, Read, Write};
 use std::ops::Deref;
 use std::process;
 use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex};
-use std::time::SystemTime;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use json;
+use sys;
 use types::*;
 
 /// The shell structure is a megastructure that manages all of the state of the shell throughout the entirety of the
@@ -65,7 +82,7 @@ pub struct Shell<'a> {
     /// The job ID of the previous command sent to the background.
     pub previous_job: u32,
     /// Contains all the boolean flags that control shell behavior.
-    pub flags: u8,
+    pub flags: u16,
     /// A temporary field for storing foreground PIDs used by the pipeline execution.
     foreground: Vec<u32>,
     /// Contains information on all of the active background processes that are being managed by the shell.
@@ -75,7 +92,31 @@ pub struct Shell<'a> {
     /// Set when a signal is received, this will tell the flow control logic to abort.
     pub break_flow: bool,
     /// When the `fg` command is run, this will be used to communicate with the specified background process.
-    pub foreground_signals: Arc<ForegroundSignals>
+    pub foreground_signals: Arc<ForegroundSignals>,
+    /// The number of function calls that are currently being executed.
+    call_depth: usize,
+    /// Files within `FNPATH` which have already been sourced to autoload a function.
+    autoloaded: FnvHashSet<PathBuf>,
+    /// The namespace that functions are currently being declared in, or executed from.
+    namespace: Option<Identifier>,
+    /// Set when a glob pattern fails to match any paths while `FAIL_GLOB` is set.
+    unmatched_glob: Cell<bool>,
+    /// Process substitutions which have been spawned while expanding the current pipeline.
+    substitutions: Vec<Substitution>,
+    /// The deadline of the pipeline that is executing, when it was prefixed with `timeout`.
+    deadline: Option<Deadline>,
+    /// The resource limits of the pipeline that is executing, when it was prefixed with `limit`.
+    limits: Limits,
+    /// The pipes of a coprocess which is about to be spawned by the pipeline that is executing.
+    coproc: Option<Coproc>,
+    /// The shell's ends of the pipes of each coprocess, for reading and writing respectively.
+    coprocs: FnvHashMap<Identifier, (File, File)>,
+    /// The commands which are run when a signal is received, where `0` is the shell's exit.
+    pub traps: FnvHashMap<i32, String>,
+    /// The executables which commands have been resolved to within the `PATH`.
+    pub command_cache: RefCell<CommandCache>,
+    /// The completions of the arguments of commands, which were registered with `complete`.
+    pub completions: FnvHashMap<String, Completion>,
 }
 
 impl<'a> Shell<'a> {
@@ -97,10 +138,26 @@ impl<'a> Shell<'a> {
             background: Arc::new(Mutex::new(Vec::new())),
             is_background_shell: false,
             break_flow: false,
-            foreground_signals: Arc::new(ForegroundSignals::new())
+            foreground_signals: Arc::new(ForegroundSignals::new()),
+            call_depth: 0,
+            autoloaded: FnvHashSet::default(),
+            namespace: None,
+            unmatched_glob: Cell::new(false),
+            substitutions: Vec::new(),
+            deadline: None,
+            limits: Limits::default(),
+            coproc: None,
+            coprocs: FnvHashMap::default(),
+            traps: FnvHashMap::default(),
+            command_cache: RefCell::new(CommandCache::default()),
+            completions: FnvHashMap::default(),
         }
     }
 
+    /// The time which remains before the deadline of the pipeline that is executing, if it was
+    /// prefixed with `timeout`.
+    pub fn time_remaining(&self) -> Option<Duration> { self.deadline.as_ref().map(Deadline::remaining) }
+
     pub fn next_signal(&self) -> Option<i32> {
         for sig in 0..32 {
             if signals::PENDING.fetch_and(!(1 << sig), Ordering::SeqCst) & (1 << sig) == 1 << sig {
@@ -111,7 +168,42 @@ impl<'a> Shell<'a> {
         None
     }
 
+    /// Runs the trap of the given signal, if it has one, without affecting the previous status.
+    /// Returns whether the signal was trapped, which includes signals that are ignored.
+    pub fn run_trap(&mut self, signal: i32) -> bool {
+        let command = match self.traps.get(&signal) {
+            Some(command) => command.clone(),
+            None => return false,
+        };
+        let previous_status = self.previous_status;
+        if let Err(why) = self.evaluate(&command) {
+            eprintln!("ion: trap: {}", why);
+        }
+        self.previous_status = previous_status;
+        true
+    }
+
+    /// Runs the traps of each pending signal which has one, leaving any other pending signals
+    /// to be handled as they otherwise would be.
+    pub fn run_pending_traps(&mut self) {
+        for sig in 1..32 {
+            if !self.traps.contains_key(&sig) {
+                continue
+            }
+            if signals::PENDING.fetch_and(!(1 << sig), Ordering::SeqCst) & (1 << sig) == 1 << sig {
+                self.run_trap(sig);
+            }
+        }
+    }
+
     pub fn exit(&mut self, status: i32) -> ! {
+        // The trap is removed first, so that an `exit` within the trap will not run it again.
+        if let Some(command) = self.traps.remove(&0) {
+            self.previous_status = status;
+            if let Err(why) = self.evaluate(&command) {
+                eprintln!("ion: trap: {}", why);
+            }
+        }
         if let Some(context) = self.context.as_mut() {
             context.history.commit_history();
         }
@@ -135,6 +227,76 @@ impl<'a> Shell<'a> {
         })
     }
 
+    /// Defines each function that was exported to the environment by a parent ion process.
+    pub fn import_functions(&mut self) {
+        for (key, source) in env::vars() {
+            if !key.starts_with(EXPORTED_FUNCTION_PREFIX) {
+                continue
+            }
+
+            let name = &key[EXPORTED_FUNCTION_PREFIX.len()..];
+            let mut statements = source.lines()
+                .flat_map(|line| StatementSplitter::new(line).map(parse_and_validate).collect::<Vec<_>>());
+
+            // Only a single function declaration is accepted, so that the environment may
+            // not be used to execute arbitrary commands.
+            let imported = match statements.next() {
+                Some(Statement::Function { name: fn_name, description, args, statements: mut body, private }) => {
+                    let mut level = 1;
+                    collect_loops(&mut statements, &mut body, &mut level);
+                    if fn_name == name && level == 0 && statements.next().is_none() {
+                        self.functions.insert(fn_name.clone(), Function {
+                            description: description,
+                            name:        fn_name,
+                            args:        args,
+                            statements:  body,
+                            private:     private,
+                            traced:      false
+                        });
+                        true
+                    } else {
+                        false
+                    }
+                },
+                _ => false
+            };
+
+            if !imported {
+                eprintln!("ion: unable to import function '{}' from the environment", name);
+            }
+        }
+    }
+
+    /// Defines each array and map that was exported to the environment by a parent ion process.
+    pub fn import_variables(&mut self) {
+        for (key, source) in env::vars() {
+            let result = if key.starts_with(EXPORTED_ARRAY_PREFIX) {
+                let name = &key[EXPORTED_ARRAY_PREFIX.len()..];
+                json::from_json(&source, false).map(|data| { self.variables.set_array(name, data.flatten()); })
+            } else if key.starts_with(EXPORTED_HASHMAP_PREFIX) {
+                let name = &key[EXPORTED_HASHMAP_PREFIX.len()..];
+                match json::from_json(&source, false) {
+                    Ok(Data::HashMap(map)) => Ok(self.variables.set_hashmap(name, map)),
+                    Ok(_) => Err("expected a JSON object".into()),
+                    Err(why) => Err(why),
+                }
+            } else if key.starts_with(EXPORTED_BTREEMAP_PREFIX) {
+                let name = &key[EXPORTED_BTREEMAP_PREFIX.len()..];
+                match json::from_json(&source, true) {
+                    Ok(Data::BTreeMap(map)) => Ok(self.variables.set_btreemap(name, map)),
+                    Ok(_) => Err("expected a JSON object".into()),
+                    Err(why) => Err(why),
+                }
+            } else {
+                continue
+            };
+
+            if let Err(why) = result {
+                eprintln!("ion: unable to import variable '{}' from the environment: {}", key, why);
+            }
+        }
+    }
+
     /// Evaluates the source init file in the user's home directory.
     pub fn evaluate_init_file(&mut self) {
         match app_root(AppDataType::UserConfig, &AppInfo{ name: "ion", author: "Redox OS Developers" }) {
@@ -155,6 +317,161 @@ impl<'a> Shell<'a> {
         }
     }
 
+    /// Whether the name refers to an alias, a function, a builtin, or an executable.
+    pub fn is_command(&self, name: &str) -> bool {
+        (self.flags & EXPAND_ALIASES != 0 && self.variables.aliases.contains_key(name))
+            || self.resolve_function(name).is_some()
+            || self.builtins.contains_key(name)
+            || if name.contains('/') {
+                command_cache::is_executable(Path::new(name))
+            } else {
+                self.command_cache.borrow_mut().contains(name)
+            }
+    }
+
+    /// Conditions of the form `VALUE in ELEMENTS...` are executed by the `in` builtin, unless the
+    /// value names a command which is given an `in` argument.
+    fn membership_test(&self, pipeline: &mut Pipeline) {
+        if pipeline.jobs.len() == 1 && pipeline.stdin.is_none() && pipeline.stdout.is_none()
+            && pipeline.stderr.is_none()
+        {
+            pipeline.jobs[0].membership_test(|name| self.is_command(name));
+        }
+    }
+
+    /// Prefixes the name of a function that is being declared with the current namespace.
+    fn qualify_function_name(&self, name: &str) -> Identifier {
+        match self.namespace {
+            Some(ref namespace) => [namespace, "::", name].concat().into(),
+            None => name.into()
+        }
+    }
+
+    /// Obtains the full name of the function that a command refers to. Functions within the
+    /// current namespace take precedence, and private functions are only visible from within
+    /// the namespace that they were declared in.
+    pub fn resolve_function(&self, name: &str) -> Option<Identifier> {
+        let current = self.namespace.as_ref().map(|namespace| &**namespace);
+        let qualified = self.qualify_function_name(name);
+        for candidate in &[&*qualified, name] {
+            if let Some(function) = self.functions.get::<str>(candidate) {
+                if !function.private || function.namespace() == current {
+                    return Some(function.name.clone());
+                }
+            }
+        }
+        None
+    }
+
+    /// Searches each directory within `FNPATH` for a file named after the given command, either
+    /// with or without an `.ion` extension, and sources the first match so that the function
+    /// that it defines may be called. Files which have already been sourced are skipped.
+    fn autoload_function(&mut self, name: &str) {
+        if name.contains('/') {
+            return;
+        }
+
+        let fnpath = match self.variables.get_var("FNPATH") {
+            Some(fnpath) => fnpath,
+            None => return
+        };
+
+        let file = self.command_cache.borrow_mut().function_file(&fnpath, name);
+        if let Some(path) = file {
+            if !self.autoloaded.contains(&path) {
+                self.autoloaded.insert(path.clone());
+                self.execute_script(&path);
+            }
+        }
+    }
+
+    /// If the `NO_UNSET` flag is set, or the `NO_UNSET` variable is set to `1`, checks whether the
+    /// input references an undefined variable, in which case an error naming the variable is
+    /// printed.
+    pub fn references_unset_variable(&self, input: &str) -> bool {
+        if self.flags & NO_UNSET == 0 && &*self.variables.get_var_or_empty("NO_UNSET") != "1" {
+            return false
+        }
+        match find_unset_variable(input, self) {
+            Some(name) => {
+                eprintln!("ion: {}: undefined variable", name);
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Determines whether a glob pattern failed to match any paths since this was last called.
+    pub fn glob_failed(&self) -> bool { self.unmatched_glob.replace(false) }
+
+    /// Forgets the glob patterns which failed to match before an expansion is checked, as those
+    /// of expansions which are not checked, like that of the prompt, would otherwise linger.
+    pub fn forget_failed_globs(&self) { self.unmatched_glob.set(false) }
+
+    /// Executes the command with the standard output redirected into a pipe, returning
+    /// everything that was written to it before the command returned.
+    fn capture_output(&mut self, command: &str) -> Option<Value> {
+        let (reader, writer) = match sys::pipe2(sys::O_CLOEXEC) {
+            Ok(fds) => fds,
+            Err(why) => {
+                eprintln!("ion: unable to create pipe: {}", why);
+                return None;
+            }
+        };
+        // Background jobs of the command may keep the pipe open after the command has returned,
+        // and so the reader is also woken through a second pipe once the command has returned.
+        let (wake_reader, wake_writer) = match sys::pipe2(sys::O_CLOEXEC) {
+            Ok(fds) => fds,
+            Err(why) => {
+                eprintln!("ion: unable to create pipe: {}", why);
+                let _ = sys::close(reader);
+                let _ = sys::close(writer);
+                return None;
+            }
+        };
+        let stdout_backup = match sys::dup_cloexec(sys::STDOUT_FILENO) {
+            Ok(fd) => fd,
+            Err(why) => {
+                eprintln!("ion: unable to duplicate stdout: {}", why);
+                for &fd in &[reader, writer, wake_reader, wake_writer] {
+                    let _ = sys::close(fd);
+                }
+                return None;
+            }
+        };
+
+        // The pipe is read from another thread so that large outputs can't fill the pipe.
+        let handle = thread::spawn(move || {
+            let mut output: Vec<u8> = Vec::new();
+            let mut file = unsafe { File::from_raw_fd(reader) };
+            let mut wake = unsafe { File::from_raw_fd(wake_reader) };
+            if let Err(why) = read_captured_output(&mut file, &mut wake, &mut output) {
+                eprintln!("ion: unable to read the output of a function: {}", why);
+            }
+            String::from_utf8_lossy(&output).into_owned()
+        });
+
+        // Process substitutions of the word being expanded must outlive the pipelines of the command.
+        let substitutions = mem::replace(&mut self.substitutions, Vec::new());
+        let _ = io::stdout().flush();
+        let _ = sys::dup2(writer, sys::STDOUT_FILENO);
+        let _ = sys::close(writer);
+        self.on_command(command);
+        let _ = io::stdout().flush();
+        let remaining = mem::replace(&mut self.substitutions, substitutions);
+        self.substitutions.extend(remaining);
+        let _ = sys::dup2(stdout_backup, sys::STDOUT_FILENO);
+        let _ = sys::close(stdout_backup);
+        let _ = unsafe { File::from_raw_fd(wake_writer) }.write_all(b"\n");
+
+        handle.join().ok().map(|mut output| {
+            if output.ends_with('\n') || output.ends_with('\0') {
+                output.pop();
+            }
+            output.into()
+        })
+    }
+
     /// Executes a pipeline and returns the final exit status of the pipeline.
     /// To avoid infinite recursion when using aliases, the noalias boolean will be set the true
     /// if an alias branch was executed.
@@ -162,8 +479,15 @@ impl<'a> Shell<'a> {
         let command_start_time = SystemTime::now();
         let builtins = self.builtins;
 
-        // Expand any aliases found
+        // Strip the `builtin` and `command` prefixes, which restrict how the command is resolved.
+        for job in &mut pipeline.jobs {
+            job.strip_resolution_prefixes();
+        }
+
+        // Expand any aliases found, which scripts must enable with `set -o expand_aliases`.
+        let expand_aliases = self.flags & EXPAND_ALIASES != 0;
         for job_no in 0..pipeline.jobs.len() {
+            if !expand_aliases || pipeline.jobs[job_no].resolution != Resolution::Any { continue }
             if let Some(alias) = {
                 let key: &str = pipeline.jobs[job_no].command.as_ref();
                 self.variables.aliases.get(key)
@@ -176,32 +500,93 @@ impl<'a> Shell<'a> {
             }
         }
 
-        pipeline.expand(self);
-        // Branch if -> input == shell command i.e. echo
-        let exit_status = if let Some(command) = {
-            let key: &str = pipeline.jobs[0].command.as_ref();
-            builtins.get(key)
-        } {
-            // Run the 'main' of the command and set exit_status
-            if !pipeline.requires_piping() {
-                if self.flags & PRINT_COMMS != 0 { eprintln!("> {}", pipeline.to_string()); }
-                let borrowed = &pipeline.jobs[0].args;
-                let small: SmallVec<[&str; 4]> = borrowed.iter()
-                    .map(|x| x as &str)
-                    .collect();
-                Some((command.main)(&small, self))
-            } else {
-                Some(self.execute_pipeline(pipeline))
+        // Autoload any commands that are neither builtins nor defined functions.
+        for job in pipeline.jobs.iter().filter(|job| job.resolution == Resolution::Any) {
+            let name: &str = job.command.as_ref();
+            if !builtins.contains_key(name) && self.resolve_function(name).is_none() {
+                self.autoload_function(name);
+            }
+        }
+
+        // The commands of a subshell are only checked and expanded once the subshell executes them.
+        let references_unset_variable = pipeline.jobs.iter()
+            .filter(|job| job.resolution != Resolution::Subshell)
+            .flat_map(|job| job.args.iter().map(|arg| arg.as_str()))
+            .chain(pipeline.redirection_words())
+            .any(|word| self.references_unset_variable(word));
+        if references_unset_variable {
+            self.variables.set_var("?", &FAILURE.to_string());
+            self.previous_status = FAILURE;
+            return Some(FAILURE);
+        }
+
+        self.forget_failed_globs();
+        pipeline.expand(&FunctionExpander::new(self));
+        let substitutions = mem::replace(&mut self.substitutions, Vec::new());
+        let background = pipeline.jobs[pipeline.jobs.len() - 1].kind == JobKind::Background;
+        if self.glob_failed() {
+            finish_substitutions(substitutions, background);
+            self.variables.set_var("?", &FAILURE.to_string());
+            self.previous_status = FAILURE;
+            return Some(FAILURE);
+        }
+        // A leading `time` reports how long the pipeline took once it has completed.
+        let timer = pipeline.jobs[0].strip_time()
+            .and_then(|posix| if background { None } else { Some(Timer::start(posix)) });
+        // A leading `timeout` gives the pipeline a deadline, after which its processes are terminated.
+        let outer_deadline = pipeline.jobs[0].strip_timeout()
+            .map(|duration| mem::replace(&mut self.deadline, Some(Deadline::new(duration))));
+        // A leading `limit` restricts the resources that the processes of the pipeline may use.
+        let outer_limits = match pipeline.jobs[0].strip_limits() {
+            Ok(limits) => limits.map(|limits| mem::replace(&mut self.limits, limits)),
+            Err(why) => {
+                eprintln!("ion: limit: {}", why);
+                if let Some(deadline) = outer_deadline {
+                    self.deadline = deadline;
+                }
+                finish_substitutions(substitutions, background);
+                self.variables.set_var("?", &BAD_ARG.to_string());
+                self.previous_status = BAD_ARG;
+                return Some(BAD_ARG);
             }
-        // Branch else if -> input == shell function and set the exit_status
-        } else if let Some(function) = self.functions.get(&pipeline.jobs[0].command).cloned() {
+        };
+        let resolution = pipeline.jobs[0].resolution;
+        let function = match resolution {
+            Resolution::Any => self.resolve_function(pipeline.jobs[0].command.as_ref())
+                .and_then(|name| self.functions.get(&name).cloned()),
+            _ => None
+        };
+        let builtin = match resolution {
+            Resolution::External => None,
+            _ => builtins.get::<str>(pipeline.jobs[0].command.as_ref())
+        };
+
+        // Branch if -> input == shell function and set the exit_status
+        let exit_status = if let Some(function) = function {
             if !pipeline.requires_piping() {
+                if self.flags & PRINT_COMMS != 0 { eprintln!("> {}", pipeline.to_string()); }
                 let args: &[String] = pipeline.jobs[0].args.deref();
                 let args: Vec<&str> = args.iter().map(AsRef::as_ref).collect();
                 match function.execute(self, &args) {
                     Ok(()) => None,
-                    Err(FunctionError::InvalidArgumentCount) => {
-                        eprintln!("ion: invalid number of function arguments supplied");
+                    Err(FunctionError::InvalidArgumentCount(usage)) => {
+                        eprintln!("ion: invalid number of function arguments supplied\nusage: {}", usage);
+                        Some(FAILURE)
+                    },
+                    Err(FunctionError::RecursionLimit(limit)) => {
+                        eprintln!("ion: maximum recursion depth exceeded ({})", limit);
+                        Some(FAILURE)
+                    },
+                    Err(FunctionError::UnknownArgument(name)) => {
+                        eprintln!("ion: function has no parameter named '{}'", name);
+                        Some(FAILURE)
+                    },
+                    Err(FunctionError::DuplicateArgument(name)) => {
+                        eprintln!("ion: function argument '{}' was supplied more than once", name);
+                        Some(FAILURE)
+                    },
+                    Err(FunctionError::ReadonlyArgument(name)) => {
+                        eprintln!("ion: cannot assign to readonly variable '{}'", name);
                         Some(FAILURE)
                     },
                     Err(FunctionError::InvalidArgumentType(expected_type, value)) => {
@@ -218,9 +603,35 @@ impl<'a> Shell<'a> {
             } else {
                 Some(self.execute_pipeline(pipeline))
             }
+        // Branch else if -> input == shell command i.e. echo
+        } else if let Some(command) = builtin {
+            // Run the 'main' of the command and set exit_status
+            if !pipeline.requires_piping() {
+                if self.flags & PRINT_COMMS != 0 { eprintln!("> {}", pipeline.to_string()); }
+                let borrowed = &pipeline.jobs[0].args;
+                let small: SmallVec<[&str; 4]> = borrowed.iter()
+                    .map(|x| x as &str)
+                    .collect();
+                Some((command.main)(&small, self))
+            } else {
+                Some(self.execute_pipeline(pipeline))
+            }
+        } else if resolution == Resolution::Builtin {
+            eprintln!("ion: builtin: {}: not a shell builtin", pipeline.jobs[0].command);
+            Some(FAILURE)
         } else {
             Some(self.execute_pipeline(pipeline))
         };
+        if let Some(deadline) = outer_deadline {
+            self.deadline = deadline;
+        }
+        if let Some(limits) = outer_limits {
+            self.limits = limits;
+        }
+        finish_substitutions(substitutions, background);
+        if let Some(timer) = timer {
+            timer.finish(&mut self.variables);
+        }
 
         // If `RECORD_SUMMARY` is set to "1" (True, Yes), then write a summary of the pipline
         // just executed to the the file and context histories. At the moment, this means
@@ -241,15 +652,38 @@ impl<'a> Shell<'a> {
 
         // Retrieve the exit_status and set the $? variable and history.previous_status
         if let Some(code) = exit_status {
-            self.variables.set_var("?", &code.to_string());
-            self.previous_status = code;
+            self.set_previous_status(code);
         }
         exit_status
     }
 
+    /// Records the exit status of the last statement, which is also exposed as `$?`.
+    pub fn set_previous_status(&mut self, status: i32) {
+        self.variables.set_var("?", &status.to_string());
+        self.previous_status = status;
+    }
+
 
 }
 
+/// Applies the final subscript of a chained subscript expansion to a nested value.
+fn select_data(data: &Data, selection: Select) -> Option<Array> {
+    match (data, selection) {
+        (_, Select::None) => None,
+        (_, Select::All) => Some(data.flatten()),
+        (_, Select::Key(ref key)) => data.get(key.get()).map(Data::flatten),
+        (&Data::Array(ref array), Select::Index(index)) => {
+            index.resolve(array.len()).and_then(|n| array.get(n)).map(Data::flatten)
+        },
+        (&Data::Array(ref array), Select::Range(range)) => {
+            range.select(array.iter(), array.len()).map(|values| {
+                values.into_iter().flat_map(Data::flatten).collect()
+            })
+        },
+        _ => None
+    }
+}
+
 impl<'a> Expander for Shell<'a> {
     fn tilde(&self, input: &str) -> Option<String> {
         /// XXX: This is a silly implementation: the `Variables` struct
@@ -260,6 +694,24 @@ impl<'a> Expander for Shell<'a> {
     /// Expand an array variable with some selection
     fn array(&self, array: &str, selection: Select) -> Option<Array> {
         use std::iter::FromIterator;
+        // Chained subscripts, such as `@config[servers][0]`, select values which are nested
+        // within maps. Every subscript but the last is kept within the name of the variable.
+        if let Some(pos) = array.find('[') {
+            let root = match self.variables.get_data(&array[..pos]) {
+                Some(root) => root,
+                None => return None
+            };
+            let mut data = &root;
+            for key in array[pos+1..array.len()-1].split("][") {
+                let key = expand_string(key, self, false).join(" ");
+                data = match data.get(&key) {
+                    Some(value) => value,
+                    None => return None
+                };
+            }
+            return select_data(data, selection);
+        }
+
         let mut found = match self.variables.get_array(array) {
             Some(array) => match selection {
                 Select::None  => None,
@@ -270,10 +722,8 @@ impl<'a> Expander for Shell<'a> {
                         .map(|x| Array::from_iter(Some(x.to_owned())))
                 },
                 Select::Range(range) => {
-                    if let Some((start, length)) = range.bounds(array.len()) {
-                        let array = array.iter()
-                            .skip(start)
-                            .take(length)
+                    if let Some(array) = range.select(array.iter(), array.len()) {
+                        let array = array.into_iter()
                             .map(|x| x.to_owned())
                             .collect::<Array>();
                         if array.is_empty() {
@@ -291,20 +741,24 @@ impl<'a> Expander for Shell<'a> {
             },
             None => None
         };
+        if found.is_none() {
+            found = match self.variables.get_btreemap(array) {
+                Some(map) => match selection {
+                    Select::All => Some(map.values().flat_map(Data::flatten).collect()),
+                    Select::Key(ref key) => {
+                        Some(map.get(key.get()).map_or_else(|| array![""], Data::flatten))
+                    },
+                    _ => None
+                },
+                None => None
+            }
+        }
         if found.is_none() {
             found = match self.variables.get_map(array) {
                 Some(map) => match selection {
-                    Select::All => {
-                        let mut arr = Array::new();
-                        for (_, value) in map {
-                            arr.push(value.clone());
-                        }
-                        Some(arr)
-                    }
+                    Select::All => Some(map.values().flat_map(Data::flatten).collect()),
                     Select::Key(ref key) => {
-                        Some(array![
-                            map.get(key.get()).unwrap_or(&"".into()).clone()
-                        ])
+                        Some(map.get(key.get()).map_or_else(|| array![""], Data::flatten))
                     },
                     _ => None
                 },
@@ -323,6 +777,37 @@ impl<'a> Expander for Shell<'a> {
                 .map(|x| x.ascii_replace('\n', ' ').into())
         }
     }
+    fn map(&self, name: &str) -> Option<Vec<(Value, Array)>> {
+        self.variables.get_map_entries(name)
+    }
+    fn glob_options(&self) -> GlobOptions {
+        GlobOptions {
+            depth: self.variables.get_var("GLOB_DEPTH")
+                .and_then(|depth| depth.parse::<usize>().ok())
+                .unwrap_or(DEFAULT_GLOB_DEPTH),
+            extended: self.flags & EXT_GLOB != 0,
+            mode: if self.flags & FAIL_GLOB != 0 {
+                GlobMode::Fail
+            } else if self.flags & NULL_GLOB != 0 {
+                GlobMode::Null
+            } else {
+                GlobMode::Literal
+            },
+            sort: self.variables.get_var("GLOB_SORT")
+                .and_then(|sort| GlobSort::from_name(&sort))
+                .unwrap_or(GlobSort::Name),
+        }
+    }
+    /// The shell is forked to execute a process substitution, which requires it to be borrowed
+    /// mutably, as it is by the `FunctionExpander` that the words of statements are expanded by.
+    fn process_substitution(&self, _command: &str, _writes: bool) -> Option<String> {
+        eprintln!("ion: process substitutions may not be used here");
+        None
+    }
+    fn unmatched_glob(&self, pattern: &str) {
+        eprintln!("ion: no matches found: {}", pattern);
+        self.unmatched_glob.set(true);
+    }
     /// Expand a subshell expression
     fn command(&self, command: &str) -> Option<Value> {
         /// XXX: This is a silly implementation: the `Variables` struct
@@ -330,3 +815,106 @@ impl<'a> Expander for Shell<'a> {
         self.variables.command_expansion(command)
     }
 }
+
+/// Expands words on behalf of the shell, except that the functions which are called by process
+/// expansions are executed within the shell itself at the point at which they are expanded, so
+/// that their side effects on variables are preserved.
+struct FunctionExpander<'s, 'a: 's> {
+    shell: RefCell<&'s mut Shell<'a>>,
+}
+
+impl<'s, 'a: 's> FunctionExpander<'s, 'a> {
+    fn new(shell: &'s mut Shell<'a>) -> FunctionExpander<'s, 'a> {
+        FunctionExpander { shell: RefCell::new(shell) }
+    }
+}
+
+impl<'s, 'a: 's> Expander for FunctionExpander<'s, 'a> {
+    fn tilde(&self, input: &str) -> Option<String> { self.shell.borrow().tilde(input) }
+    fn array(&self, array: &str, selection: Select) -> Option<Array> {
+        self.shell.borrow().array(array, selection)
+    }
+    fn variable(&self, variable: &str, quoted: bool) -> Option<Value> {
+        self.shell.borrow().variable(variable, quoted)
+    }
+    fn map(&self, name: &str) -> Option<Vec<(Value, Array)>> { self.shell.borrow().map(name) }
+    fn glob_options(&self) -> GlobOptions { self.shell.borrow().glob_options() }
+    fn process_substitution(&self, command: &str, writes: bool) -> Option<String> {
+        let mut shell = self.shell.borrow_mut();
+        match Substitution::spawn(&mut shell, command, writes) {
+            Ok(substitution) => {
+                let path = substitution.path();
+                shell.substitutions.push(substitution);
+                Some(path)
+            },
+            Err(why) => {
+                eprintln!("ion: unable to spawn process substitution: {}", why);
+                None
+            }
+        }
+    }
+    fn unmatched_glob(&self, pattern: &str) { self.shell.borrow().unmatched_glob(pattern) }
+    fn command(&self, command: &str) -> Option<Value> {
+        let is_function = command.split_whitespace().next()
+            .map_or(false, |name| self.shell.borrow().resolve_function(name).is_some());
+        if is_function {
+            self.shell.borrow_mut().capture_output(command)
+        } else {
+            self.shell.borrow().command(command)
+        }
+    }
+}
+
+/// Reads the output of a function from the pipe until either every writer of the pipe has closed
+/// it, or the function has returned, as signalled through the wake pipe, and the pipe is empty.
+/// Background jobs of the function may otherwise keep the pipe open long after it has returned.
+fn read_captured_output(file: &mut File, wake: &mut File, output: &mut Vec<u8>) -> io::Result<()> {
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let ready = match sys::poll_inputs(&[file.as_raw_fd(), wake.as_raw_fd()]) {
+            Ok(ready) => ready,
+            Err(ref why) if why.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => return read_captured_output_without_polling(file, wake, output),
+        };
+        // Whatever remains within the pipe is read before the return of the function is noticed.
+        if ready[0] {
+            match file.read(&mut buffer) {
+                Ok(0) => return Ok(()),
+                Ok(read) => output.extend_from_slice(&buffer[..read]),
+                Err(ref why) if why.kind() == io::ErrorKind::Interrupted => (),
+                Err(why) => return Err(why),
+            }
+        } else if ready[1] {
+            return Ok(());
+        }
+    }
+}
+
+/// Where descriptors can't be polled, both pipes are instead read without blocking, sleeping
+/// briefly whenever neither of them has anything to be read.
+fn read_captured_output_without_polling(file: &mut File, wake: &mut File, output: &mut Vec<u8>)
+    -> io::Result<()>
+{
+    sys::set_nonblocking(file.as_raw_fd())?;
+    sys::set_nonblocking(wake.as_raw_fd())?;
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut returned = false;
+    loop {
+        match file.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(read) => {
+                output.extend_from_slice(&buffer[..read]);
+                continue
+            },
+            Err(ref why) if why.kind() == io::ErrorKind::WouldBlock => if returned {
+                return Ok(());
+            },
+            Err(ref why) if why.kind() == io::ErrorKind::Interrupted => continue,
+            Err(why) => return Err(why),
+        }
+        match wake.read(&mut buffer[..1]) {
+            Ok(_) => returned = true,
+            Err(_) => thread::sleep(Duration::from_millis(10)),
+        }
+    }
+}
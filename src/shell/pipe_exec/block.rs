@@ -0,0 +1,153 @@
+//! The output of an `if`, `for`, or `while` block may be redirected or piped as a whole, as in
+//! `for f in * ... end > log.txt` or `while ... end | sort`, and its input may be redirected, as
+//! in `while read line ... end < file`. The shell's own standard streams are redirected while the
+//! block executes, so that every command within the block inherits them.
+
+use super::{open_output, pipe, redir, redirect_fds, redirect_output, stdin_of, PipelineExecution};
+use super::job_control::JobControl;
+use super::super::Shell;
+use super::super::flags::NO_CLOBBER;
+use parser::pipelines::{Input, Pipeline, RedirectFrom};
+use std::fs::File;
+use std::io::{self, Write};
+use std::mem;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::process::exit;
+use std::thread::JoinHandle;
+use sys;
+
+pub struct BlockOutput {
+    /// Each descriptor of the shell which was redirected, along with a copy of the original.
+    backups: Vec<(RawFd, RawFd)>,
+    /// The forked shell which executes the commands that the block is piped into.
+    consumer: Option<(u32, String)>,
+    /// The threads which write the output into each file of a list of targets.
+    fanouts: Vec<JoinHandle<()>>,
+}
+
+impl BlockOutput {
+    /// Redirects the standard output, and possibly the standard error, of the shell according
+    /// to the `output` of a block, spawning the commands that it is piped into, if any. Returns
+    /// `None` if the output could not be redirected.
+    pub fn redirect(shell: &mut Shell, mut output: Pipeline) -> Option<BlockOutput> {
+        output.expand(shell);
+        let mut block = BlockOutput { backups: Vec::new(), consumer: None, fanouts: Vec::new() };
+        let result = if output.jobs.is_empty() {
+            block.redirect_streams(&output, shell.flags & NO_CLOBBER != 0)
+        } else {
+            block.pipe_into(shell, output)
+        };
+
+        match result {
+            Ok(()) => Some(block),
+            Err(why) => {
+                if !why.is_empty() {
+                    eprintln!("ion: {}", why);
+                }
+                block.finish(shell);
+                None
+            }
+        }
+    }
+
+    /// Makes `fd` refer to the same file as `file`, keeping a copy of the original descriptor.
+    fn replace(&mut self, fd: RawFd, file: &File) -> Result<(), String> {
+        let backup = sys::dup_cloexec(fd)
+            .map_err(|why| format!("failed to duplicate descriptor {}: {}", fd, why))?;
+        self.backups.push((fd, backup));
+        sys::dup2(file.as_raw_fd(), fd)
+            .map(|_| ())
+            .map_err(|why| format!("failed to redirect descriptor {}: {}", fd, why))
+    }
+
+    fn redirect_streams(&mut self, output: &Pipeline, noclobber: bool) -> Result<(), String> {
+        if let Some(ref input) = output.stdin {
+            let file = match *input {
+                Input::File(ref path) => File::open(path)
+                    .map_err(|why| format!("failed to redirect '{}' into stdin: {}", path, why))?,
+                Input::HereString(ref string) | Input::Literal(ref string) => {
+                    let mut string = string.clone();
+                    if !string.ends_with('\n') {
+                        string.push('\n');
+                    }
+                    let fd = unsafe { stdin_of(&string) }
+                        .map_err(|why| format!("failed to redirect herestring into stdin: {}", why))?;
+                    unsafe { File::from_raw_fd(fd) }
+                }
+            };
+            self.replace(sys::STDIN_FILENO, &file)?;
+        }
+        for redirection in output.stdout.iter().chain(output.stderr.iter()) {
+            let file = open_output(redirection, noclobber, &mut self.fanouts)?;
+            if redirection.from != RedirectFrom::Stderr {
+                self.replace(sys::STDOUT_FILENO, &file)?;
+            }
+            if redirection.from != RedirectFrom::Stdout {
+                self.replace(sys::STDERR_FILENO, &file)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Forks the shell to execute the commands which read the output of the block, and
+    /// redirects the standard output of the shell into their standard input.
+    fn pipe_into(&mut self, shell: &mut Shell, mut output: Pipeline) -> Result<(), String> {
+        let command = output.to_string();
+        if output.stdin.is_some() {
+            return Err(format!("the input of {} is already the output of the block", command));
+        }
+        let redirections = output.jobs.iter_mut()
+            .map(|job| mem::replace(&mut job.redirections, Vec::new()))
+            .collect();
+        let mut commands = shell.generate_commands(&mut output);
+        let noclobber = shell.flags & NO_CLOBBER != 0;
+        // The errors of these redirections have already been printed.
+        if let Some(stdout) = output.stdout.take() {
+            if redirect_output(stdout, noclobber, &mut commands, &mut self.fanouts) { return Err(String::new()) }
+        }
+        if let Some(stderr) = output.stderr.take() {
+            if redirect_output(stderr, noclobber, &mut commands, &mut self.fanouts) { return Err(String::new()) }
+        }
+        if redirect_fds(redirections, &mut commands) { return Err(String::new()) }
+
+        let (reader, writer) = sys::pipe2(sys::O_CLOEXEC)
+            .map_err(|why| format!("failed to create a pipe for {}: {}", command, why))?;
+        let (reader, writer) = unsafe { (File::from_raw_fd(reader), File::from_raw_fd(writer)) };
+        commands[0].0.stdin(reader);
+
+        match unsafe { sys::fork() } {
+            Ok(0) => {
+                // The commands would otherwise never receive an EOF.
+                drop(writer);
+                shell.is_background_shell = true;
+                let _ = sys::reset_signal(sys::SIGINT);
+                let _ = sys::reset_signal(sys::SIGHUP);
+                let _ = sys::reset_signal(sys::SIGTERM);
+                exit(pipe(shell, commands, false));
+            }
+            Ok(pid) => {
+                // Dropping the commands closes the shell's copy of the reading end.
+                drop(commands);
+                self.consumer = Some((pid, command));
+                self.replace(sys::STDOUT_FILENO, &writer)
+            }
+            Err(why) => Err(format!("failed to fork {}: {}", command, why)),
+        }
+    }
+
+    /// Restores the outputs of the shell once the block has executed, and waits for every
+    /// target to have received the output. Returns the status of the commands that the block
+    /// was piped into, if any.
+    pub fn finish(self, shell: &mut Shell) -> Option<i32> {
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+        for (fd, backup) in self.backups.into_iter().rev() {
+            redir(backup, fd);
+            let _ = sys::close(backup);
+        }
+        for thread in self.fanouts {
+            let _ = thread.join();
+        }
+        self.consumer.map(|(pid, command)| shell.watch_foreground(pid, pid, move || command, |_, _| false))
+    }
+}
@@ -0,0 +1,58 @@
+//! Coprocesses are pipelines which execute in the background, whose standard input and output
+//! are connected to pipes that the shell holds the other ends of, so that a script may write to
+//! and read from a persistent helper process, such as a database client.
+
+use super::super::Shell;
+use super::super::job::{JobKind, RefinedJob};
+use std::fs::File;
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use sys;
+use types::{Array, Identifier};
+
+pub struct Coproc {
+    name: Identifier,
+    /// The ends of the pipes which are given to the coprocess, until it has been spawned.
+    ends: Option<(File, File)>,
+    /// The output of the coprocess is read from this end.
+    reader: File,
+    /// The input of the coprocess is written to this end.
+    writer: File,
+}
+
+fn pipe() -> io::Result<(File, File)> {
+    let (reader, writer) = sys::pipe2(sys::O_CLOEXEC)?;
+    unsafe { Ok((File::from_raw_fd(reader), File::from_raw_fd(writer))) }
+}
+
+impl Coproc {
+    pub fn new(name: &str) -> io::Result<Coproc> {
+        let (input, writer) = pipe()?;
+        let (reader, output) = pipe()?;
+        Ok(Coproc { name: name.into(), ends: Some((input, output)), reader, writer })
+    }
+
+    /// Connects the standard input of the first command, and the standard output of the last
+    /// command, to the pipes of the coprocess.
+    pub fn connect(&mut self, commands: &mut [(RefinedJob, JobKind)]) {
+        if let Some((input, output)) = self.ends.take() {
+            if let Some(&mut (ref mut job, _)) = commands.first_mut() {
+                job.stdin(input);
+            }
+            if let Some(&mut (ref mut job, _)) = commands.last_mut() {
+                job.stdout(output);
+            }
+        }
+    }
+
+    /// Keeps the shell's ends of the pipes open once the coprocess has been forked as `pid`,
+    /// where `@NAME[0]` is the descriptor to read from, `@NAME[1]` is the descriptor to write
+    /// to, and `$NAME_PID` is the process ID of the coprocess.
+    pub fn register(self, shell: &mut Shell, pid: u32) {
+        let descriptors = array![self.reader.as_raw_fd().to_string(), self.writer.as_raw_fd().to_string()];
+        shell.variables.set_array(&self.name, descriptors);
+        shell.variables.set_var(&format!("{}_PID", &*self.name), &pid.to_string());
+        // Replacing an earlier coprocess of the same name closes the shell's ends of its pipes.
+        shell.coprocs.insert(self.name, (self.reader, self.writer));
+    }
+}
@@ -0,0 +1,55 @@
+//! Writes the output of a command to several files at once, as with `cmd > [a b -]`, without
+//! the need for `tee`. The command is given the writing end of a pipe, which is read by a
+//! thread of the shell that copies everything it reads into each of the files.
+
+use super::create;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::io::FromRawFd;
+use std::thread::{self, JoinHandle};
+use sys;
+
+/// Opens each of the targets, where `-` refers to the standard output of the shell.
+fn open(target: &str, append: bool, clobber: bool) -> io::Result<File> {
+    if target == "-" {
+        sys::dup_cloexec(sys::STDOUT_FILENO).map(|fd| unsafe { File::from_raw_fd(fd) })
+    } else if append {
+        OpenOptions::new().create(true).write(true).append(true).open(target)
+    } else {
+        create(target, clobber)
+    }
+}
+
+/// Opens every target, and spawns the thread which copies the output into them. Returns the
+/// end of the pipe which the command is to write to, and the thread, which exits once every
+/// copy of that end has been closed. Existing files are only truncated if `clobber` is set.
+pub fn spawn(targets: &[String], append: bool, clobber: bool) -> Result<(File, JoinHandle<()>), String> {
+    let mut outputs = Vec::with_capacity(targets.len());
+    for target in targets {
+        let output = open(target, append, clobber)
+            .map_err(|why| format!("failed to redirect output into {}: {}", target, why))?;
+        outputs.push(output);
+    }
+
+    let (reader, writer) = sys::pipe2(sys::O_CLOEXEC)
+        .map_err(|why| format!("failed to create a pipe for the redirection: {}", why))?;
+    let (mut reader, writer) = unsafe { (File::from_raw_fd(reader), File::from_raw_fd(writer)) };
+
+    let thread = thread::spawn(move || {
+        let mut buffer = [0u8; 8192];
+        loop {
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(ref why) if why.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            };
+            // A target which can no longer be written to does not stop the others.
+            for output in &mut outputs {
+                let _ = output.write_all(&buffer[..read]);
+            }
+        }
+    });
+
+    Ok((writer, thread))
+}
@@ -0,0 +1,135 @@
+//! Applies the redirections of the arbitrary file descriptors of a job, such as `3> file`, `2>&1`,
+//! and `<&4`, after the standard input and outputs of the job have been set up.
+
+use parser::pipelines::FdRedirection;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::io::{AsRawFd, IntoRawFd, RawFd};
+use sys;
+
+/// Opens the file of a redirection, for reading, writing, or appending.
+fn open(path: &str, fd: i32, write: bool, append: bool) -> Result<File, String> {
+    let file = if append {
+        OpenOptions::new().create(true).append(true).open(path)
+    } else if write {
+        File::create(path)
+    } else {
+        File::open(path)
+    };
+    file.map_err(|why| format!("failed to redirect file descriptor {} to '{}': {}", fd, path, why))
+}
+
+/// Parses the descriptor that a descriptor is to be made a duplicate of.
+fn descriptor(source: &str) -> Result<RawFd, String> {
+    source.parse::<RawFd>().map_err(|_| format!("'{}' is not a file descriptor", source))
+}
+
+/// Makes `fd` refer to the same file as `source`, where the resulting descriptor is inherited
+/// by the commands that are executed.
+fn duplicate(source: RawFd, fd: RawFd) -> io::Result<()> {
+    if source == fd {
+        sys::clear_cloexec(fd)
+    } else {
+        sys::dup2(source, fd).map(|_| ())
+    }
+}
+
+/// The redirections of an external command, whose files are opened before the command is
+/// spawned, so that the redirections may be applied within the child without allocating.
+pub struct Prepared {
+    /// The files are kept open until the command has been spawned.
+    _files: Vec<File>,
+    /// Each descriptor, along with the descriptor that it will duplicate, or `None` if the
+    /// descriptor is to be closed.
+    actions: Vec<(RawFd, Option<RawFd>)>,
+}
+
+impl Prepared {
+    pub fn new(redirections: &[FdRedirection]) -> Result<Prepared, String> {
+        let mut files = Vec::new();
+        let mut actions = Vec::with_capacity(redirections.len());
+        for redirection in redirections {
+            match *redirection {
+                FdRedirection::File { fd, ref path, write, append } => {
+                    let file = open(path, fd, write, append)?;
+                    actions.push((fd as RawFd, Some(file.as_raw_fd())));
+                    files.push(file);
+                }
+                FdRedirection::Duplicate { fd, ref source } => actions.push((fd as RawFd, Some(descriptor(source)?))),
+                FdRedirection::Close(fd) => actions.push((fd as RawFd, None)),
+            }
+        }
+        Ok(Prepared { _files: files, actions })
+    }
+
+    /// Applies the redirections from within the child, before the command is executed.
+    pub fn apply(&self) -> io::Result<()> {
+        for &(fd, source) in &self.actions {
+            match source {
+                Some(source) => duplicate(source, fd)?,
+                None => {
+                    let _ = sys::close(fd);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The prior state of each descriptor which was redirected by `redirect`: either a copy of the
+/// descriptor, or `None` if the descriptor was not open.
+pub type Backups = Vec<(RawFd, Option<RawFd>)>;
+
+/// Applies the redirections within the shell itself, for builtins, functions, and subshells,
+/// returning the backups that are needed to restore the descriptors afterwards.
+pub fn redirect(redirections: &[FdRedirection]) -> Result<Backups, String> {
+    let mut backups = Vec::with_capacity(redirections.len());
+    for redirection in redirections {
+        let result = match *redirection {
+            FdRedirection::File { fd, ref path, write, append } => {
+                // The backup is made first, as the file may be opened onto the descriptor.
+                backups.push((fd as RawFd, sys::dup_cloexec(fd as RawFd).ok()));
+                open(path, fd, write, append).and_then(|file| {
+                    let source = file.into_raw_fd();
+                    let result = duplicate(source, fd as RawFd);
+                    if source != fd as RawFd {
+                        let _ = sys::close(source);
+                    }
+                    result.map_err(|why| format!("failed to redirect file descriptor {}: {}", fd, why))
+                })
+            }
+            FdRedirection::Duplicate { fd, ref source } => descriptor(source).and_then(|source| {
+                backups.push((fd as RawFd, sys::dup_cloexec(fd as RawFd).ok()));
+                duplicate(source, fd as RawFd).map_err(|why| {
+                    format!("failed to duplicate file descriptor {} to {}: {}", source, fd, why)
+                })
+            }),
+            FdRedirection::Close(fd) => {
+                backups.push((fd as RawFd, sys::dup_cloexec(fd as RawFd).ok()));
+                let _ = sys::close(fd as RawFd);
+                Ok(())
+            }
+        };
+
+        if let Err(why) = result {
+            restore(backups);
+            return Err(why);
+        }
+    }
+    Ok(backups)
+}
+
+/// Restores the descriptors which were redirected by `redirect`, in the reverse order.
+pub fn restore(backups: Backups) {
+    for (fd, backup) in backups.into_iter().rev() {
+        match backup {
+            Some(backup) => {
+                let _ = sys::dup2(backup, fd);
+                let _ = sys::close(backup);
+            }
+            None => {
+                let _ = sys::close(fd);
+            }
+        }
+    }
+}
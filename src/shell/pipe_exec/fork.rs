@@ -3,6 +3,7 @@ use sys;
 /// Ensures that the forked child is given a unique process ID.
 pub fn create_process_group(pgid: u32) { let _ = sys::setpgid(0, pgid); }
 
+use super::coproc::Coproc;
 use super::job_control::{JobControl, ProcessState};
 use super::pipe;
 use super::super::Shell;
@@ -11,22 +12,32 @@ use super::super::status::*;
 use std::process::exit;
 
 /// Forks the shell, adding the child to the parent's background list, and executing
-/// the given commands in the child fork.
-pub fn fork_pipe(shell: &mut Shell, commands: Vec<(RefinedJob, JobKind)>, command_name: String) -> i32 {
+/// the given commands in the child fork. If the commands are a coprocess, the parent keeps
+/// its ends of the coprocess's pipes.
+pub fn fork_pipe(shell: &mut Shell, commands: Vec<(RefinedJob, JobKind)>, command_name: String,
+    coproc: Option<Coproc>) -> i32
+{
     match unsafe { sys::fork() } {
         Ok(0) => {
+            // The coprocess would otherwise never receive an EOF when the shell closes its ends.
+            drop(coproc);
             shell.is_background_shell = true;
             let _ = sys::reset_signal(sys::SIGINT);
             let _ = sys::reset_signal(sys::SIGHUP);
             let _ = sys::reset_signal(sys::SIGTERM);
             // This ensures that the child fork has a unique PGID.
             create_process_group(0);
+            shell.limits.apply_or_exit();
             // After execution of it's commands, exit with the last command's status.
             exit(pipe(shell, commands, false));
         }
         Ok(pid) => {
             // The parent process should add the child fork's PID to the background.
             shell.send_to_background(pid, ProcessState::Running, command_name);
+            shell.variables.set_var("!", &pid.to_string());
+            if let Some(coproc) = coproc {
+                coproc.register(shell, pid);
+            }
             SUCCESS
         }
         Err(why) => {
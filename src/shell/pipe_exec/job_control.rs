@@ -4,13 +4,20 @@ use super::super::signals;
 use super::super::status::*;
 use std::fmt;
 use std::sync::{Arc, Mutex};
+use std::io::{stderr, Write};
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
 use std::thread::{sleep, spawn};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use sys;
 
 use sys::job_control as self_sys;
 pub use sys::job_control::watch_background;
 
+/// Mirrors the `NOTIFY` flag of the shell for the threads which watch the background jobs, which
+/// report a job as soon as it exits when the flag is set.
+pub static NOTIFY_NOW: AtomicBool = ATOMIC_BOOL_INIT;
+
 /// When given a process ID, that process's group will be assigned as the foreground process group.
 pub fn set_foreground_as(pid: u32) {
     signals::block();
@@ -18,6 +25,16 @@ pub fn set_foreground_as(pid: u32) {
     signals::unblock();
 }
 
+/// Restores the modes of the terminal to the modes that the shell had before a job was given the
+/// foreground, if the job was stopped, and so may have left the terminal with modes of its own.
+pub fn restore_modes(status: i32, modes: Option<sys::Termios>) {
+    if status == get_signal_code(sys::SIGTSTP) {
+        if let Some(ref modes) = modes {
+            let _ = sys::tcsetattr(0, modes);
+        }
+    }
+}
+
 pub trait JobControl {
     /// Waits for background jobs to finish before returning.
     fn wait_for_background(&mut self);
@@ -25,13 +42,62 @@ pub trait JobControl {
     /// and sets it as the foreground process. Once the task exits or stops, the exit status will
     /// be returned, and ownership of the TTY given back to the shell.
     fn set_bg_task_in_foreground(&self, pid: u32, cont: bool) -> i32;
+    /// Reports each background job which has exited since the last prompt, and then removes the
+    /// job from the list of background jobs.
+    fn report_finished_jobs(&self);
     fn handle_signal(&self, signal: i32) -> bool;
     fn foreground_send(&self, signal: i32);
     fn background_send(&self, signal: i32);
+    /// Waits for the processes of a foreground job to exit, returning the exit status of the
+    /// last process. The exit status of each process is passed to `drop_command` as it exits,
+    /// which returns whether any of the job's processes are still running.
     fn watch_foreground<F, D>(&mut self, pid: u32, last_pid: u32, get_command: F, drop_command: D) -> i32
         where F: FnOnce() -> String,
-              D: FnMut(i32);
+              D: FnMut(i32, i32) -> bool;
     fn send_to_background(&mut self, child: u32, state: ProcessState, command: String);
+    /// Whether the deadline of the pipeline that is executing, if it has one, has passed, such
+    /// that its processes were terminated by the shell.
+    fn timed_out(&self) -> bool;
+}
+
+/// The deadline of a pipeline that was prefixed with `timeout`, after which the process group
+/// of each of its foreground jobs is sent a `SIGTERM`.
+pub struct Deadline {
+    instant: Instant,
+    expired: Arc<AtomicBool>,
+}
+
+impl Deadline {
+    pub fn new(duration: Duration) -> Deadline {
+        Deadline { instant: Instant::now() + duration, expired: Arc::new(AtomicBool::new(false)) }
+    }
+
+    pub fn expired(&self) -> bool { self.expired.load(Ordering::SeqCst) }
+
+    pub fn remaining(&self) -> Duration {
+        let now = Instant::now();
+        if self.instant > now { self.instant - now } else { Duration::from_secs(0) }
+    }
+
+    /// Spawns a timer which terminates the process group of `pgid` once the deadline passes,
+    /// unless the returned `Timer` is dropped first.
+    pub fn arm(&self, pgid: u32) -> Timer {
+        let (cancel, cancelled) = channel();
+        let remaining = self.remaining();
+        let expired = self.expired.clone();
+        spawn(move || {
+            if let Err(RecvTimeoutError::Timeout) = cancelled.recv_timeout(remaining) {
+                expired.store(true, Ordering::SeqCst);
+                let _ = sys::killpg(pgid, sys::SIGTERM);
+            }
+        });
+        Timer { _cancel: cancel }
+    }
+}
+
+/// Cancels the timer of a `Deadline` when dropped, by disconnecting from the timer's thread.
+pub struct Timer {
+    _cancel: Sender<()>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -39,19 +105,67 @@ pub trait JobControl {
 pub enum ProcessState {
     Running,
     Stopped,
+    /// The process has exited with the given status, which has yet to be reported by `jobs`.
+    Done(i32),
     Empty,
 }
 
+impl ProcessState {
+    /// Whether the process has yet to exit.
+    pub fn is_alive(self) -> bool {
+        self == ProcessState::Running || self == ProcessState::Stopped
+    }
+}
+
 impl fmt::Display for ProcessState {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             ProcessState::Running => write!(f, "Running"),
             ProcessState::Stopped => write!(f, "Stopped"),
+            ProcessState::Done(SUCCESS) => write!(f, "Done"),
+            ProcessState::Done(status) => write!(f, "Exit {}", status),
             ProcessState::Empty => write!(f, "Empty"),
         }
     }
 }
 
+/// Job IDs begin from 1 when they are displayed or given to the job control builtins, whereas
+/// jobs are stored by their index within the background list.
+pub fn job_id(index: u32) -> u32 { index + 1 }
+
+/// Parses a job ID that was given to a job control builtin, returning the index of the job.
+pub fn parse_job_id(id: &str) -> Option<u32> {
+    match id.parse::<u32>() {
+        Ok(id) if id > 0 => Some(id - 1),
+        _ => None,
+    }
+}
+
+/// Resolves a job specification that was given to a job control builtin to the index of the job.
+/// Jobs may be given by their ID, which may be prefixed with `%`, whereas `%+`, `%%`, and `%` refer
+/// to the current job, `%name` to the first job whose command begins with `name`, and `%?text` to
+/// the first job whose command contains `text`.
+pub fn parse_jobspec(shell: &Shell, spec: &str) -> Option<u32> {
+    if !spec.starts_with('%') {
+        return parse_job_id(spec);
+    }
+
+    let spec = &spec[1..];
+    match spec {
+        "" | "+" | "%" => if shell.previous_job == !0 { None } else { Some(shell.previous_job) },
+        _ => parse_job_id(spec).or_else(|| {
+            let matches = |name: &str| if spec.starts_with('?') {
+                name.contains(&spec[1..])
+            } else {
+                name.starts_with(spec)
+            };
+            shell.background.lock().unwrap().iter()
+                .position(|process| process.is_active() && matches(&process.name))
+                .map(|id| id as u32)
+        }),
+    }
+}
+
 pub fn add_to_background(
     processes: Arc<Mutex<Vec<BackgroundProcess>>>,
     pid: u32,
@@ -67,6 +181,7 @@ pub fn add_to_background(
             (*processes)[id] = BackgroundProcess {
                 pid:           pid,
                 ignore_sighup: false,
+                disowned:      false,
                 state:         state,
                 name:          command,
             };
@@ -77,6 +192,7 @@ pub fn add_to_background(
             (*processes).push(BackgroundProcess {
                 pid:           pid,
                 ignore_sighup: false,
+                disowned:      false,
                 state:         state,
                 name:          command,
             });
@@ -93,18 +209,28 @@ pub fn add_to_background(
 pub struct BackgroundProcess {
     pub pid:           u32,
     pub ignore_sighup: bool,
+    /// A disowned job is neither listed nor signaled by the shell, but keeps its place within
+    /// the list until it exits, as the thread which watches the job refers to it by its index.
+    pub disowned:      bool,
     pub state:         ProcessState,
     pub name:          String,
 }
 
+impl BackgroundProcess {
+    /// Whether the job is still alive, and has not been disowned by the shell.
+    pub fn is_active(&self) -> bool { !self.disowned && self.state.is_alive() }
+}
+
 impl<'a> JobControl for Shell<'a> {
     fn set_bg_task_in_foreground(&self, pid: u32, cont: bool) -> i32 {
+        let modes = sys::tcgetattr(0).ok();
+        // Pass the TTY to the background job before it resumes, so that a stopped job which
+        // reads from the terminal will not immediately be stopped again.
+        set_foreground_as(pid);
         // Resume the background task, if needed.
         if cont {
             signals::resume(pid);
         }
-        // Pass the TTY to the background job
-        set_foreground_as(pid);
         // Signal the background thread that is waiting on this process to stop waiting.
         self.foreground_signals.signal_to_grab(pid);
         let status = loop {
@@ -119,39 +245,60 @@ impl<'a> JobControl for Shell<'a> {
         };
         // Have the shell reclaim the TTY
         set_foreground_as(sys::getpid().unwrap());
+        restore_modes(status, modes);
         status
     }
 
+    fn report_finished_jobs(&self) {
+        // Jobs were already reported as they exited if the `NOTIFY` flag is set.
+        let notified = NOTIFY_NOW.load(Ordering::Relaxed);
+        let stderr = stderr();
+        let mut stderr = stderr.lock();
+        for (id, process) in self.background.lock().unwrap().iter_mut().enumerate() {
+            if let ProcessState::Done(_) = process.state {
+                if !notified {
+                    let mark = if id as u32 == self.previous_job { '+' } else { ' ' };
+                    let _ = writeln!(stderr, "[{}]{} {}\t{}", job_id(id as u32), mark, process.state, process.name);
+                }
+                process.state = ProcessState::Empty;
+            }
+        }
+    }
+
     /// Waits until all running background tasks have completed, and listens for signals in the
     /// event that a signal is sent to kill the running tasks.
     fn wait_for_background(&mut self) {
         let sigcode;
         'event: loop {
-            for process in self.background.lock().unwrap().iter() {
-                if let ProcessState::Running = process.state {
-                    while let Some(signal) = self.next_signal() {
-                        if signal != sys::SIGTSTP {
-                            self.background_send(signal);
-                            sigcode = get_signal_code(signal);
-                            break 'event;
-                        }
-                    }
-                    sleep(Duration::from_millis(100));
-                    continue 'event;
+            let running = self.background.lock().unwrap().iter()
+                .any(|process| process.state == ProcessState::Running && !process.disowned);
+            if !running {
+                return;
+            }
+            // The lock must not be held while a trap is run, as the trap may start jobs of its own.
+            while let Some(signal) = self.next_signal() {
+                if signal != sys::SIGTSTP && !self.run_trap(signal) {
+                    self.background_send(signal);
+                    sigcode = get_signal_code(signal);
+                    break 'event;
                 }
             }
-            return;
+            sleep(Duration::from_millis(100));
         }
         self.exit(sigcode);
     }
 
     fn watch_foreground<F, D>(&mut self, pid: u32, last_pid: u32, get_command: F, drop_command: D) -> i32
         where F: FnOnce() -> String,
-              D: FnMut(i32)
+              D: FnMut(i32, i32) -> bool
     {
+        // The job's processes are terminated if the deadline of the pipeline passes first.
+        let _timer = self.deadline.as_ref().map(|deadline| deadline.arm(pid));
         self_sys::watch_foreground(self, pid, last_pid, get_command, drop_command)
     }
 
+    fn timed_out(&self) -> bool { self.deadline.as_ref().map_or(false, Deadline::expired) }
+
     /// Send a kill signal to all running foreground tasks.
     fn foreground_send(&self, signal: i32) {
         for &process in self.foreground.iter() {
@@ -163,13 +310,13 @@ impl<'a> JobControl for Shell<'a> {
     fn background_send(&self, signal: i32) {
         if signal == sys::SIGHUP {
             for process in self.background.lock().unwrap().iter() {
-                if !process.ignore_sighup {
+                if process.is_active() && !process.ignore_sighup {
                     let _ = sys::killpg(process.pid, signal);
                 }
             }
         } else {
             for process in self.background.lock().unwrap().iter() {
-                if let ProcessState::Running = process.state {
+                if process.state == ProcessState::Running && !process.disowned {
                     let _ = sys::killpg(process.pid, signal);
                 }
             }
@@ -186,7 +333,7 @@ impl<'a> JobControl for Shell<'a> {
         // the previous job in the shell (in case fg/bg is executed w/ no args).
         let njob = add_to_background(processes.clone(), pid, state, command);
         self.previous_job = njob;
-        eprintln!("ion: bg [{}] {}", njob, pid);
+        eprintln!("[{}] {}", job_id(njob), pid);
 
         // Spawn a background thread that will monitor the progress of the
         // background process, updating it's state changes until it finally
@@ -0,0 +1,129 @@
+//! Resource limits of a pipeline that was prefixed with `limit`, such as
+//! `limit --cpu 60 --mem 512M command`, which are applied within each process of the pipeline
+//! before its command is executed.
+
+use super::super::status::COULD_NOT_EXEC;
+use std::io;
+use std::process::exit;
+use sys;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Limits {
+    /// The CPU time of each process, in seconds.
+    cpu: Option<u64>,
+    /// The size of the address space of each process, in bytes.
+    memory: Option<u64>,
+    /// The size of the largest file which may be written, in bytes.
+    file_size: Option<u64>,
+    /// The number of files which each process may have open.
+    files: Option<u64>,
+    /// The number of processes which the user may have.
+    processes: Option<u64>,
+}
+
+/// Parses a size such as `4096`, `64K`, `512M`, or `2G`, where bytes are the default unit.
+fn parse_size(input: &str) -> Option<u64> {
+    let (number, unit) = match input.char_indices().last() {
+        Some((i, 'K')) | Some((i, 'k')) => (&input[..i], 1 << 10),
+        Some((i, 'M')) | Some((i, 'm')) => (&input[..i], 1 << 20),
+        Some((i, 'G')) | Some((i, 'g')) => (&input[..i], 1 << 30),
+        _ => (input, 1),
+    };
+    number.parse::<u64>().ok().and_then(|number| number.checked_mul(unit))
+}
+
+impl Limits {
+    /// Parses the options which follow `limit`, returning the limits along with the number of
+    /// arguments which were taken by the options, after which the command begins.
+    pub fn parse(args: &[String]) -> Result<(Limits, usize), String> {
+        let mut limits = Limits::default();
+        let mut index = 0;
+        while index < args.len() && args[index].starts_with("--") {
+            let option = &args[index][2..];
+            let value = match args.get(index + 1) {
+                Some(value) => value,
+                None => return Err(format!("--{} requires a value", option)),
+            };
+            let (limit, parsed) = match option {
+                "cpu" => (&mut limits.cpu, value.parse::<u64>().ok()),
+                "mem" => (&mut limits.memory, parse_size(value)),
+                "fsize" => (&mut limits.file_size, parse_size(value)),
+                "files" => (&mut limits.files, value.parse::<u64>().ok()),
+                "procs" => (&mut limits.processes, value.parse::<u64>().ok()),
+                _ => return Err(format!("unknown resource: --{}", option)),
+            };
+            match parsed {
+                Some(parsed) => *limit = Some(parsed),
+                None => return Err(format!("invalid limit for --{}: '{}'", option, value)),
+            }
+            index += 2;
+        }
+
+        if index == args.len() {
+            Err("no command was given".into())
+        } else {
+            Ok((limits, index))
+        }
+    }
+
+    /// Applies the limits to the current process, from within the child before the command is
+    /// executed, so that they are also inherited by any processes that the command spawns.
+    pub fn apply(&self) -> io::Result<()> {
+        let resources = [
+            (sys::RLIMIT_CPU, self.cpu),
+            (sys::RLIMIT_AS, self.memory),
+            (sys::RLIMIT_FSIZE, self.file_size),
+            (sys::RLIMIT_NOFILE, self.files),
+            (sys::RLIMIT_NPROC, self.processes),
+        ];
+        for &(resource, limit) in &resources {
+            if let Some(limit) = limit {
+                sys::setrlimit(resource, limit, limit)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the limits within a fork of the shell, which exits if they could not be applied.
+    pub fn apply_or_exit(&self) {
+        if let Err(why) = self.apply() {
+            eprintln!("ion: failed to apply resource limits: {}", why);
+            exit(COULD_NOT_EXEC);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> { args.iter().map(|&arg| arg.into()).collect() }
+
+    #[test]
+    fn parse_limits() {
+        let (limits, count) = Limits::parse(&args(&["--cpu", "60", "--mem", "512M", "cmd"])).unwrap();
+        assert_eq!(count, 4);
+        assert_eq!(limits.cpu, Some(60));
+        assert_eq!(limits.memory, Some(512 << 20));
+        assert_eq!(limits.files, None);
+
+        let (limits, count) = Limits::parse(&args(&["--files", "64", "cmd", "--cpu", "1"])).unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(limits.files, Some(64));
+        assert_eq!(limits.cpu, None);
+
+        assert!(Limits::parse(&args(&["--cpu", "60"])).is_err());
+        assert!(Limits::parse(&args(&["--cpu"])).is_err());
+        assert!(Limits::parse(&args(&["--mem", "lots", "cmd"])).is_err());
+        assert!(Limits::parse(&args(&["--stack", "1M", "cmd"])).is_err());
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size("64K"), Some(64 << 10));
+        assert_eq!(parse_size("2g"), Some(2 << 30));
+        assert_eq!(parse_size("M"), None);
+        assert_eq!(parse_size("-1"), None);
+    }
+}
@@ -3,27 +3,40 @@
 //! IDs, watching foreground and background tasks, sending foreground tasks to the background,
 //! handling pipeline and conditional operators, and std{in,out,err} redirections.
 
+mod block;
+mod coproc;
+mod fanout;
+mod fds;
 pub mod foreground;
 mod fork;
 pub mod job_control;
+pub mod limits;
+pub mod timer;
 
+pub use self::block::BlockOutput;
+pub use self::coproc::Coproc;
+use self::fds::Prepared;
 use self::fork::{create_process_group, fork_pipe};
-use self::job_control::JobControl;
-use super::{JobKind, Shell};
+use self::job_control::{restore_modes, JobControl};
+use super::{FlowLogic, JobKind, Shell};
 use super::flags::*;
-use super::job::RefinedJob;
+use super::job::{RefinedJob, Resolution};
 use super::signals::{self, SignalHandler};
 use super::status::*;
 use super::flow_control::{FunctionError, Type};
-use parser::pipelines::{Input, Pipeline, Redirection, RedirectFrom};
-use std::fs::{File, OpenOptions};
+use parser::pipelines::{FdRedirection, Input, Pipeline, Redirection, RedirectFrom};
+use std::fs::{self, File, OpenOptions};
 use std::io::{self, Error, Write};
 use std::iter;
+use std::mem;
 use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
 use std::os::unix::process::CommandExt;
 use std::path::Path;
 use std::process::{exit, Command};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
 use sys;
+use types::Array;
 
 /// Use dup2 to replace `old` with `new` using `old`s file descriptor ID
 fn redir(old: RawFd, new: RawFd) {
@@ -32,21 +45,98 @@ fn redir(old: RawFd, new: RawFd) {
     }
 }
 
+/// The largest input which is written directly into a pipe by `stdin_of`. This is the smallest
+/// capacity of a pipe that POSIX guarantees, so writing this much can never block.
+const PIPE_CAPACITY: usize = 4096;
+
 /// Create an OS pipe and write the contents of a byte slice to one end
 /// such that reading from this pipe will produce the byte slice. Return
 /// A file descriptor representing the read end of the pipe.
 pub unsafe fn stdin_of<T: AsRef<[u8]>>(input: T) -> Result<RawFd, Error> {
     let (reader, writer) = sys::pipe2(sys::O_CLOEXEC)?;
     let mut infile = File::from_raw_fd(writer);
-    // Write the contents; make sure to use write_all so that we block until
-    // the entire string is written
-    infile.write_all(input.as_ref())?;
-    infile.flush()?;
+    let input = input.as_ref();
+    if input.len() <= PIPE_CAPACITY {
+        // Write the contents; make sure to use write_all so that we block until
+        // the entire string is written
+        infile.write_all(input)?;
+        infile.flush()?;
+    } else {
+        // Larger inputs would fill the pipe before the command which reads them has been
+        // spawned, so they are written from another thread, which closes the pipe when done.
+        let input = input.to_vec();
+        thread::spawn(move || { let _ = infile.write_all(&input); });
+    }
     // `infile` currently owns the writer end RawFd. If we just return the reader end
     // and let `infile` go out of scope, it will be closed, sending EOF to the reader!
     Ok(reader)
 }
 
+/// The command of a process substitution, such as `<(sort file)`, which runs within a fork of
+/// the shell, along with the shell's end of the pipe that is connected to it.
+pub struct Substitution {
+    pid: u32,
+    fd:  RawFd,
+}
+
+impl Substitution {
+    /// Forks the shell to execute the command with its standard output connected to a pipe, or
+    /// its standard input if `writes` is set. The shell's end of the pipe is left open across
+    /// `exec`, so that the commands of the pipeline may open it through its `/dev/fd` path.
+    pub fn spawn(shell: &mut Shell, command: &str, writes: bool) -> io::Result<Substitution> {
+        let (reader, writer) = sys::pipe2(sys::O_CLOEXEC)?;
+        let (ours, theirs) = if writes { (writer, reader) } else { (reader, writer) };
+
+        match unsafe { sys::fork() } {
+            Ok(0) => {
+                signals::unblock();
+                let _ = sys::reset_signal(sys::SIGINT);
+                let _ = sys::reset_signal(sys::SIGHUP);
+                let _ = sys::reset_signal(sys::SIGTERM);
+                // The substitutions of the pipeline being expanded are for the parent to finish.
+                shell.substitutions.clear();
+                let theirs = Some(unsafe { File::from_raw_fd(theirs) });
+                let status = if writes {
+                    shell.exec_subshell(command, &None, &None, &theirs, &[])
+                } else {
+                    shell.exec_subshell(command, &theirs, &None, &None, &[])
+                };
+                exit(status)
+            }
+            Ok(pid) => {
+                let _ = sys::close(theirs);
+                // A duplicated descriptor does not inherit the close-on-exec flag.
+                let result = sys::dup(ours).map(|fd| Substitution { pid: pid, fd: fd });
+                let _ = sys::close(ours);
+                result
+            }
+            Err(why) => {
+                let _ = sys::close(theirs);
+                let _ = sys::close(ours);
+                Err(why)
+            }
+        }
+    }
+
+    pub fn path(&self) -> String { format!("/dev/fd/{}", self.fd) }
+}
+
+/// Closes the shell's end of each substitution's pipe, and then waits for their commands to
+/// exit. The pipes must all be closed before waiting, as each command may hold the pipes of the
+/// substitutions which were spawned before it. Background pipelines are waited on from another
+/// thread, so that the shell does not block on them.
+pub fn finish_substitutions(substitutions: Vec<Substitution>, background: bool) {
+    if substitutions.is_empty() { return }
+    let mut pids = Vec::with_capacity(substitutions.len());
+    for substitution in substitutions {
+        let _ = sys::close(substitution.fd);
+        pids.push(substitution.pid);
+    }
+
+    let wait = move || for pid in pids { let _ = sys::waitpid(pid); };
+    if background { thread::spawn(wait); } else { wait(); }
+}
+
 /// This function serves three purposes:
 /// 1. If the result is `Some`, then we will fork the pipeline executing into the background.
 /// 2. The value stored within `Some` will be that background job's command name.
@@ -80,8 +170,8 @@ fn is_implicit_cd(argument: &str) -> bool {
 /// This function is to be executed when a stdin value is supplied to a pipeline job.
 ///
 /// Using that value, the stdin of the first command will be mapped to either a `File`,
-/// or `HereString`, which may be either a herestring or heredoc. Returns `true` if
-/// the input error occurred.
+/// or `HereString`, which may be either a herestring or heredoc, or the `Literal` text of a
+/// heredoc. Returns `true` if the input error occurred.
 fn redirect_input(mut input: Input, piped_commands: &mut Vec<(RefinedJob, JobKind)>) -> bool {
     match input {
         Input::File(ref filename) => if let Some(command) = piped_commands.first_mut() {
@@ -93,7 +183,8 @@ fn redirect_input(mut input: Input, piped_commands: &mut Vec<(RefinedJob, JobKin
                 },
             }
         },
-        Input::HereString(ref mut string) => if let Some(command) = piped_commands.first_mut() {
+        Input::HereString(ref mut string) |
+        Input::Literal(ref mut string) => if let Some(command) = piped_commands.first_mut() {
             if !string.ends_with('\n') {
                 string.push('\n');
             }
@@ -111,22 +202,53 @@ fn redirect_input(mut input: Input, piped_commands: &mut Vec<(RefinedJob, JobKin
     false
 }
 
+/// Creates the file that output is redirected into, truncating it if it exists. Unless `clobber`
+/// is set, an existing regular file is left untouched, and an error is returned instead.
+fn create(path: &str, clobber: bool) -> io::Result<File> {
+    if !clobber && fs::metadata(path).map(|metadata| metadata.is_file()).unwrap_or(false) {
+        return Err(Error::new(io::ErrorKind::AlreadyExists, "cannot overwrite existing file"));
+    }
+    File::create(path)
+}
+
+/// Opens the file that output is redirected into, where existing files are not truncated if
+/// `noclobber` is set, unless the redirection is forced. When the target is a list of files, the
+/// output is instead written into a pipe, whose contents are copied into each of the files by a
+/// thread, which is pushed onto `fanouts`.
+fn open_output(redirection: &Redirection, noclobber: bool, fanouts: &mut Vec<JoinHandle<()>>)
+    -> Result<File, String>
+{
+    let clobber = redirection.force || !noclobber;
+    if redirection.is_list() {
+        let (writer, thread) = fanout::spawn(&redirection.targets, redirection.append, clobber)?;
+        fanouts.push(thread);
+        return Ok(writer);
+    }
+
+    let file = if redirection.append {
+        OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(true)
+            .open(&redirection.file)
+    } else {
+        create(&redirection.file, clobber)
+    };
+    file.map_err(|err| format!("failed to redirect output into {}: {}", redirection.file, err))
+}
+
 /// This function is to be executed when a stdout/stderr value is supplied to a pipeline job.
 ///
 /// Using that value, the stdout and/or stderr of the last command will be redirected accordingly
 /// to the designated output. Returns `true` if the outputs couldn't be redirected.
-fn redirect_output(stdout: Redirection, piped_commands: &mut Vec<(RefinedJob, JobKind)>) -> bool {
+fn redirect_output(
+    stdout: Redirection,
+    noclobber: bool,
+    piped_commands: &mut Vec<(RefinedJob, JobKind)>,
+    fanouts: &mut Vec<JoinHandle<()>>,
+) -> bool {
     if let Some(mut command) = piped_commands.last_mut() {
-        let file = if stdout.append {
-            OpenOptions::new()
-                .create(true)
-                .write(true)
-                .append(true)
-                .open(&stdout.file)
-        } else {
-            File::create(&stdout.file)
-        };
-        match file {
+        match open_output(&stdout, noclobber, fanouts) {
             Ok(f) => match stdout.from {
                 RedirectFrom::Both => match f.try_clone() {
                     Ok(f_copy) => {
@@ -141,10 +263,8 @@ fn redirect_output(stdout: Redirection, piped_commands: &mut Vec<(RefinedJob, Jo
                 RedirectFrom::Stderr => command.0.stderr(f),
                 RedirectFrom::Stdout => command.0.stdout(f),
             },
-            Err(err) => {
-                let stderr = io::stderr();
-                let mut stderr = stderr.lock();
-                let _ = writeln!(stderr, "ion: failed to redirect stdout into {}: {}", stdout.file, err);
+            Err(why) => {
+                eprintln!("ion: {}", why);
                 return true;
             }
         }
@@ -152,6 +272,30 @@ fn redirect_output(stdout: Redirection, piped_commands: &mut Vec<(RefinedJob, Jo
     false
 }
 
+/// Applies the redirections of the arbitrary file descriptors of each job, such as `3> file` or
+/// `2>&1`. The files of external commands are opened now, whereas the redirections of builtins,
+/// functions, and subshells are applied when they are executed. Returns `true` if a file could
+/// not be opened.
+fn redirect_fds(redirections: Vec<Vec<FdRedirection>>, piped_commands: &mut Vec<(RefinedJob, JobKind)>) -> bool {
+    for (list, command) in redirections.into_iter().zip(piped_commands.iter_mut()) {
+        if list.is_empty() { continue }
+        match command.0 {
+            RefinedJob::External(ref mut command) => match Prepared::new(&list) {
+                Ok(prepared) => {
+                    command.before_exec(move || prepared.apply());
+                }
+                Err(why) => {
+                    eprintln!("ion: {}", why);
+                    return true;
+                }
+            },
+            RefinedJob::Builtin { ref mut redirections, .. } | RefinedJob::Function { ref mut redirections, .. }
+                | RefinedJob::Subshell { ref mut redirections, .. } => *redirections = list,
+        }
+    }
+    false
+}
+
 pub trait PipelineExecution {
     /// Given a pipeline, generates commands and executes them.
     ///
@@ -178,7 +322,9 @@ pub trait PipelineExecution {
     fn generate_commands(&self, pipeline: &mut Pipeline) -> Vec<(RefinedJob, JobKind)>;
 
     /// Waits for all of the children within a pipe to finish exuecting, returning the
-    /// exit status of the last process in the queue.
+    /// exit status of the last process in the queue, or with `pipefail` set, the first
+    /// non-zero exit status. The exit status of every process is stored within the
+    /// `PIPESTATUS` array.
     fn wait(&mut self, children: Vec<u32>, commands: Vec<RefinedJob>) -> i32;
 
     /// Executes a `RefinedJob` that was created in the `generate_commands` method.
@@ -202,6 +348,7 @@ pub trait PipelineExecution {
         stdout: &Option<File>,
         stderr: &Option<File>,
         stdin: &Option<File>,
+        redirections: &[FdRedirection],
     ) -> i32;
 
     fn exec_function(
@@ -211,6 +358,18 @@ pub trait PipelineExecution {
         stdout: &Option<File>,
         stderr: &Option<File>,
         stdin: &Option<File>,
+        redirections: &[FdRedirection],
+    ) -> i32;
+
+    /// Executes the commands of a subshell within the current process, which is expected to be
+    /// a fork of the shell, so that changes to the shell's state do not leak into the parent.
+    fn exec_subshell(
+        &mut self,
+        code: &str,
+        stdout: &Option<File>,
+        stderr: &Option<File>,
+        stdin: &Option<File>,
+        redirections: &[FdRedirection],
     ) -> i32;
 }
 
@@ -220,6 +379,10 @@ impl<'a> PipelineExecution for Shell<'a> {
         self.foreground.clear();
         // If the supplied pipeline is a background, a string representing the command will be stored here.
         let possible_background_name = gen_background_string(&pipeline, self.flags & PRINT_COMMS != 0);
+        // The redirections of each job's file descriptors are applied after its other redirections.
+        let redirections = pipeline.jobs.iter_mut()
+            .map(|job| mem::replace(&mut job.redirections, Vec::new()))
+            .collect();
         // Generates commands for execution, differentiating between external and builtin commands.
         let mut piped_commands = self.generate_commands(pipeline);
         // Redirect the inputs if a custom redirect value was given.
@@ -227,21 +390,40 @@ impl<'a> PipelineExecution for Shell<'a> {
             if redirect_input(stdin, &mut piped_commands) { return COULD_NOT_EXEC; }
         }
         // Redirect the outputs if a custom redirect value was given.
+        let mut fanouts = Vec::new();
+        let noclobber = self.flags & NO_CLOBBER != 0;
         if let Some(stdout) = pipeline.stdout.take() {
-            if redirect_output(stdout, &mut piped_commands) { return COULD_NOT_EXEC; }
+            if redirect_output(stdout, noclobber, &mut piped_commands, &mut fanouts) { return COULD_NOT_EXEC; }
         }
+        if let Some(stderr) = pipeline.stderr.take() {
+            if redirect_output(stderr, noclobber, &mut piped_commands, &mut fanouts) { return COULD_NOT_EXEC; }
+        }
+        if redirect_fds(redirections, &mut piped_commands) { return COULD_NOT_EXEC; }
         // If the given pipeline is a background task, fork the shell.
         if let Some(command_name) = possible_background_name {
-            fork_pipe(self, piped_commands, command_name)
+            // A coprocess is connected to its pipes, before any redirections of its own.
+            let mut coproc = self.coproc.take();
+            if let Some(ref mut coproc) = coproc {
+                coproc.connect(&mut piped_commands);
+            }
+            fork_pipe(self, piped_commands, command_name, coproc)
         } else {
             // While active, the SIGTTOU signal will be ignored.
             let _sig_ignore = SignalHandler::new();
             let foreground = !self.is_background_shell;
+            // A job such as an editor may alter the modes of the terminal, which are restored
+            // if the job is stopped before it is able to restore them itself.
+            let modes = if foreground { sys::tcgetattr(0).ok() } else { None };
             // Execute each command in the pipeline, giving each command the foreground.
             let exit_status = pipe(self, piped_commands, foreground);
             // Set the shell as the foreground process again to regain the TTY.
             if foreground {
                 let _ = sys::tcsetpgrp(0, sys::getpid().unwrap());
+                restore_modes(exit_status, modes);
+            }
+            // Wait for the output to have been written to every target of the redirections.
+            for thread in fanouts {
+                let _ = thread.join();
             }
             exit_status
         }
@@ -252,15 +434,37 @@ impl<'a> PipelineExecution for Shell<'a> {
             .jobs
             .drain(..)
             .map(|mut job| {
+                let function = match job.resolution {
+                    Resolution::Any => self.resolve_function(job.command.as_ref()),
+                    _ => None
+                };
                 let refined = {
-                    if is_implicit_cd(&job.args[0]) {
+                    if job.resolution == Resolution::Subshell {
+                        let group = &job.args[0];
+                        RefinedJob::subshell(group[1..group.len() - 1].to_owned())
+                    } else if is_implicit_cd(&job.args[0]) {
                         RefinedJob::builtin("cd".into(), iter::once("cd".into()).chain(job.args.drain()).collect())
-                    } else if self.functions.contains_key::<str>(job.command.as_ref()) {
-                        RefinedJob::function(job.command, job.args.drain().collect())
-                    } else if self.builtins.contains_key::<str>(job.command.as_ref()) {
+                    } else if let Some(name) = function {
+                        RefinedJob::function(name, job.args.drain().collect())
+                    } else if job.resolution != Resolution::External
+                        && self.builtins.contains_key::<str>(job.command.as_ref())
+                    {
                         RefinedJob::builtin(job.command, job.args.drain().collect())
                     } else {
-                        let mut command = Command::new(job.command);
+                        // The cache of the `PATH` is consulted before its directories are searched.
+                        let path = {
+                            let name: &str = job.command.as_ref();
+                            if name.contains('/') { None } else { self.command_cache.borrow_mut().resolve(name) }
+                        };
+                        // The command is still given its name, rather than its path, as `argv[0]`.
+                        let mut command = match path {
+                            Some(path) => {
+                                let mut command = Command::new(path);
+                                command.arg0(job.command.as_ref() as &str);
+                                command
+                            }
+                            None => Command::new(job.command),
+                        };
                         for arg in job.args.drain().skip(1) {
                             command.arg(arg);
                         }
@@ -283,19 +487,46 @@ impl<'a> PipelineExecution for Shell<'a> {
         // Each process in the pipe has the same PGID, which is the first process's PID.
         let pgid = children[0];
 
-        // If the last process exits, we know that all processes should exit.
+        // The status of the job is the status of the last process.
         let last_pid = children[children.len() - 1];
 
+        // The status of each process, in the order that the processes appear in the pipe.
+        let pids = children.clone();
+        let mut statuses: Vec<Option<i32>> = vec![None; pids.len()];
+
         // Watch the foreground group, dropping all commands that exit as they exit.
-        self.watch_foreground(
-            pgid,
-            last_pid,
-            move || as_string,
-            move |pid| if let Some(id) = children.iter().position(|&x| x as i32 == pid) {
-                commands.remove(id);
-                children.remove(id);
-            },
-        )
+        let status = {
+            let statuses = &mut statuses;
+            self.watch_foreground(
+                pgid,
+                last_pid,
+                move || as_string,
+                move |pid, status| {
+                    if let Some(id) = children.iter().position(|&x| x as i32 == pid) {
+                        commands.remove(id);
+                        children.remove(id);
+                    }
+                    if let Some(id) = pids.iter().position(|&x| x as i32 == pid) {
+                        statuses[id] = Some(status);
+                    }
+                    !children.is_empty()
+                },
+            )
+        };
+
+        // With `pipefail`, the first process to fail determines the status of a job which exited.
+        let status = if self.flags & PIPE_FAIL != 0 && statuses.iter().all(Option::is_some) {
+            statuses.iter().filter_map(|&value| value).find(|&value| value != SUCCESS).unwrap_or(status)
+        } else {
+            status
+        };
+
+        // Processes which did not exit, such as those that were stopped, take the job's status.
+        let statuses = statuses.into_iter()
+            .map(|value| value.unwrap_or(status).to_string())
+            .collect();
+        self.variables.set_array("PIPESTATUS", statuses);
+        status
     }
 
     fn exec_job(&mut self, job: &mut RefinedJob, foreground: bool) -> i32 {
@@ -303,11 +534,12 @@ impl<'a> PipelineExecution for Shell<'a> {
         let long = job.long();
         match *job {
             RefinedJob::External(ref mut command) => match {
+                let limits = self.limits;
                 command
                     .before_exec(move || {
                         signals::unblock();
                         create_process_group(0);
-                        Ok(())
+                        limits.apply()
                     })
                     .spawn()
             } {
@@ -315,7 +547,7 @@ impl<'a> PipelineExecution for Shell<'a> {
                     if foreground {
                         let _ = sys::tcsetpgrp(0, child.id());
                     }
-                    self.watch_foreground(child.id(), child.id(), move || long, |_| ())
+                    self.watch_foreground(child.id(), child.id(), move || long, |_, _| false)
                 }
                 Err(e) => {
                     if e.kind() == io::ErrorKind::NotFound {
@@ -333,12 +565,17 @@ impl<'a> PipelineExecution for Shell<'a> {
                 ref stdin,
                 ref stdout,
                 ref stderr,
+                ref redirections,
             } => {
-                if let Ok(stdout_bk) = sys::dup(sys::STDOUT_FILENO) {
-                    if let Ok(stderr_bk) = sys::dup(sys::STDERR_FILENO) {
-                        if let Ok(stdin_bk) = sys::dup(sys::STDIN_FILENO) {
+                // Without a command, the redirections of `exec` apply to the shell itself.
+                if name == "exec" && args.len() == 1 {
+                    return redirect_shell(stdout, stderr, stdin, redirections);
+                }
+                if let Ok(stdout_bk) = sys::dup_cloexec(sys::STDOUT_FILENO) {
+                    if let Ok(stderr_bk) = sys::dup_cloexec(sys::STDERR_FILENO) {
+                        if let Ok(stdin_bk) = sys::dup_cloexec(sys::STDIN_FILENO) {
                             let args: Vec<&str> = args.iter().map(|x| x as &str).collect();
-                            let code = self.exec_builtin(name, &args, stdout, stderr, stdin);
+                            let code = self.exec_builtin(name, &args, stdout, stderr, stdin, redirections);
                             redir(stdout_bk, sys::STDOUT_FILENO);
                             redir(stderr_bk, sys::STDERR_FILENO);
                             redir(stdin_bk, sys::STDIN_FILENO);
@@ -357,12 +594,13 @@ impl<'a> PipelineExecution for Shell<'a> {
                 ref stdin,
                 ref stdout,
                 ref stderr,
+                ref redirections,
             } => {
-                if let Ok(stdout_bk) = sys::dup(sys::STDOUT_FILENO) {
-                    if let Ok(stderr_bk) = sys::dup(sys::STDERR_FILENO) {
-                        if let Ok(stdin_bk) = sys::dup(sys::STDIN_FILENO) {
+                if let Ok(stdout_bk) = sys::dup_cloexec(sys::STDOUT_FILENO) {
+                    if let Ok(stderr_bk) = sys::dup_cloexec(sys::STDERR_FILENO) {
+                        if let Ok(stdin_bk) = sys::dup_cloexec(sys::STDIN_FILENO) {
                             let args: Vec<&str> = args.iter().map(|x| x as &str).collect();
-                            let code = self.exec_function(name, &args, stdout, stderr, stdin);
+                            let code = self.exec_function(name, &args, stdout, stderr, stdin, redirections);
                             redir(stdout_bk, sys::STDOUT_FILENO);
                             redir(stderr_bk, sys::STDERR_FILENO);
                             redir(stdin_bk, sys::STDIN_FILENO);
@@ -375,6 +613,33 @@ impl<'a> PipelineExecution for Shell<'a> {
                 eprintln!("ion: failed to `dup` STDOUT, STDIN, or STDERR: not running '{}'", long);
                 COULD_NOT_EXEC
             }
+            RefinedJob::Subshell {
+                ref code,
+                ref stdin,
+                ref stdout,
+                ref stderr,
+                ref redirections,
+            } => match unsafe { sys::fork() } {
+                Ok(0) => {
+                    signals::unblock();
+                    let _ = sys::reset_signal(sys::SIGINT);
+                    let _ = sys::reset_signal(sys::SIGHUP);
+                    let _ = sys::reset_signal(sys::SIGTERM);
+                    create_process_group(0);
+                    self.limits.apply_or_exit();
+                    exit(self.exec_subshell(code, stdout, stderr, stdin, redirections))
+                }
+                Ok(pid) => {
+                    if foreground {
+                        let _ = sys::tcsetpgrp(0, pid);
+                    }
+                    self.watch_foreground(pid, pid, move || long, |_, _| false)
+                }
+                Err(e) => {
+                    eprintln!("ion: failed to fork {}: {}", short, e);
+                    COULD_NOT_EXEC
+                }
+            },
         }
     }
 
@@ -385,6 +650,7 @@ impl<'a> PipelineExecution for Shell<'a> {
         stdout: &Option<File>,
         stderr: &Option<File>,
         stdin: &Option<File>,
+        redirections: &[FdRedirection],
     ) -> i32 {
         if let Some(ref file) = *stdin {
             redir(file.as_raw_fd(), sys::STDIN_FILENO);
@@ -395,10 +661,19 @@ impl<'a> PipelineExecution for Shell<'a> {
         if let Some(ref file) = *stderr {
             redir(file.as_raw_fd(), sys::STDERR_FILENO);
         }
+        let backups = match fds::redirect(redirections) {
+            Ok(backups) => backups,
+            Err(why) => {
+                eprintln!("ion: {}", why);
+                return COULD_NOT_EXEC;
+            }
+        };
         // The precondition for this function asserts that there exists some `builtin`
         // in the shell named `name`, so we unwrap here.
         let builtin = self.builtins.get(name).unwrap();
-        (builtin.main)(args, self)
+        let status = (builtin.main)(args, self);
+        fds::restore(backups);
+        status
     }
 
     fn exec_function(
@@ -408,6 +683,7 @@ impl<'a> PipelineExecution for Shell<'a> {
         stdout: &Option<File>,
         stderr: &Option<File>,
         stdin: &Option<File>,
+        redirections: &[FdRedirection],
     ) -> i32 {
         if let Some(ref file) = *stdin {
             redir(file.as_raw_fd(), sys::STDIN_FILENO);
@@ -418,12 +694,35 @@ impl<'a> PipelineExecution for Shell<'a> {
         if let Some(ref file) = *stderr {
             redir(file.as_raw_fd(), sys::STDERR_FILENO);
         }
+        let backups = match fds::redirect(redirections) {
+            Ok(backups) => backups,
+            Err(why) => {
+                eprintln!("ion: {}", why);
+                return COULD_NOT_EXEC;
+            }
+        };
 
         let function = self.functions.get(name).cloned().unwrap();
-        match function.execute(self, args) {
+        let status = match function.execute(self, args) {
             Ok(()) => SUCCESS,
-            Err(FunctionError::InvalidArgumentCount) => {
-                eprintln!("ion: invalid number of function arguments supplied");
+            Err(FunctionError::InvalidArgumentCount(usage)) => {
+                eprintln!("ion: invalid number of function arguments supplied\nusage: {}", usage);
+                FAILURE
+            },
+            Err(FunctionError::RecursionLimit(limit)) => {
+                eprintln!("ion: maximum recursion depth exceeded ({})", limit);
+                FAILURE
+            },
+            Err(FunctionError::UnknownArgument(name)) => {
+                eprintln!("ion: function has no parameter named '{}'", name);
+                FAILURE
+            },
+            Err(FunctionError::DuplicateArgument(name)) => {
+                eprintln!("ion: function argument '{}' was supplied more than once", name);
+                FAILURE
+            },
+            Err(FunctionError::ReadonlyArgument(name)) => {
+                eprintln!("ion: cannot assign to readonly variable '{}'", name);
                 FAILURE
             },
             Err(FunctionError::InvalidArgumentType(expected_type, value)) => {
@@ -436,24 +735,113 @@ impl<'a> PipelineExecution for Shell<'a> {
                 eprintln!("ion: function argument has invalid type: expected {}, found value \'{}\'", type_, value);
                 FAILURE
             }
+        };
+        fds::restore(backups);
+        status
+    }
+
+    fn exec_subshell(
+        &mut self,
+        code: &str,
+        stdout: &Option<File>,
+        stderr: &Option<File>,
+        stdin: &Option<File>,
+        redirections: &[FdRedirection],
+    ) -> i32 {
+        if let Some(ref file) = *stdin {
+            redir(file.as_raw_fd(), sys::STDIN_FILENO);
+        }
+        if let Some(ref file) = *stdout {
+            redir(file.as_raw_fd(), sys::STDOUT_FILENO);
+        }
+        if let Some(ref file) = *stderr {
+            redir(file.as_raw_fd(), sys::STDERR_FILENO);
+        }
+        // Being a fork of the shell, the subshell has no need to restore the descriptors.
+        if let Err(why) = fds::redirect(redirections) {
+            eprintln!("ion: {}", why);
+            return COULD_NOT_EXEC;
         }
+
+        // The pipes of the other commands in the pipeline would otherwise be held open, as the
+        // fork is never replaced by an `exec`.
+        sys::close_cloexec_fds();
+        // The history, background jobs, and traps belong to the parent, so the subshell must
+        // neither commit the history, signal the jobs, nor run the parent's traps when it exits.
+        self.context = None;
+        self.background = Arc::new(Mutex::new(Vec::new()));
+        self.traps.clear();
+        self.on_command(code);
+        let _ = io::stdout().flush();
+        self.previous_status
     }
 }
 
-/// This function will panic if called with an empty slice
-pub fn pipe(shell: &mut Shell, commands: Vec<(RefinedJob, JobKind)>, foreground: bool) -> i32 {
-
-    fn close(file: &Option<File>) {
-        if let &Some(ref file) = file {
-            if let Err(e) = sys::close(file.as_raw_fd()) {
-                eprintln!("ion: failed to close file '{:?}': {}", file, e);
+/// Applies the redirections of an `exec` which was given no command, which, unlike those of
+/// other builtins, are kept for the remainder of the shell's life.
+fn redirect_shell(
+    stdout: &Option<File>,
+    stderr: &Option<File>,
+    stdin: &Option<File>,
+    redirections: &[FdRedirection],
+) -> i32 {
+    if let Some(ref file) = *stdin {
+        redir(file.as_raw_fd(), sys::STDIN_FILENO);
+    }
+    if let Some(ref file) = *stdout {
+        redir(file.as_raw_fd(), sys::STDOUT_FILENO);
+    }
+    if let Some(ref file) = *stderr {
+        redir(file.as_raw_fd(), sys::STDERR_FILENO);
+    }
+    match fds::redirect(redirections) {
+        Ok(backups) => {
+            for (_, backup) in backups {
+                if let Some(backup) = backup {
+                    let _ = sys::close(backup);
+                }
             }
+            SUCCESS
+        }
+        Err(why) => {
+            eprintln!("ion: {}", why);
+            COULD_NOT_EXEC
         }
     }
+}
 
+/// Prepares a fork of the shell which executes a builtin or a function as part of a pipeline, by
+/// applying its redirections, and then closing the pipes of every other command in the pipeline.
+/// As the fork is never replaced by an `exec`, it would otherwise hold those pipes open, so that
+/// a command reading from the fork would never receive an EOF, and a fork writing into a pipe
+/// whose reader has exited would block forever, rather than receiving a `SIGPIPE`.
+fn isolate_fork(
+    stdout: &Option<File>,
+    stderr: &Option<File>,
+    stdin: &Option<File>,
+    redirections: &[FdRedirection],
+) -> Result<(), String> {
+    if let Some(ref file) = *stdin {
+        redir(file.as_raw_fd(), sys::STDIN_FILENO);
+    }
+    if let Some(ref file) = *stdout {
+        redir(file.as_raw_fd(), sys::STDOUT_FILENO);
+    }
+    if let Some(ref file) = *stderr {
+        redir(file.as_raw_fd(), sys::STDERR_FILENO);
+    }
+    fds::redirect(redirections)?;
+    sys::close_cloexec_fds();
+    Ok(())
+}
+
+/// This function will panic if called with an empty slice
+pub fn pipe(shell: &mut Shell, commands: Vec<(RefinedJob, JobKind)>, foreground: bool) -> i32 {
     let mut previous_status = SUCCESS;
     let mut previous_kind = JobKind::And;
     let mut commands = commands.into_iter();
+    // The resource limits which each process of the pipeline is restricted to.
+    let limits = shell.limits;
     loop {
         if let Some((mut parent, mut kind)) = commands.next() {
             // When an `&&` or `||` operator is utilized, execute commands based on the previous status.
@@ -495,7 +883,7 @@ pub fn pipe(shell: &mut Shell, commands: Vec<(RefinedJob, JobKind)>, foreground:
                                         command.before_exec(move || {
                                             signals::unblock();
                                             create_process_group(pgid);
-                                            Ok(())
+                                            limits.apply()
                                         }).spawn()
                                     } {
                                         Ok(child) => {
@@ -523,7 +911,8 @@ pub fn pipe(shell: &mut Shell, commands: Vec<(RefinedJob, JobKind)>, foreground:
                                                       ref args,
                                                       ref stdout,
                                                       ref stderr,
-                                                      ref stdin, } =>
+                                                      ref stdin,
+                                                      ref redirections, } =>
                                 {
                                     match unsafe { sys::fork() } {
                                         Ok(0) => {
@@ -532,17 +921,18 @@ pub fn pipe(shell: &mut Shell, commands: Vec<(RefinedJob, JobKind)>, foreground:
                                             let _ = sys::reset_signal(sys::SIGHUP);
                                             let _ = sys::reset_signal(sys::SIGTERM);
                                             create_process_group(pgid);
+                                            limits.apply_or_exit();
                                             let args: Vec<&str> = args
                                                 .iter()
                                                 .map(|x| x as &str).collect();
-                                            let ret = shell.exec_builtin(name,
-                                                              &args,
-                                                              stdout,
-                                                              stderr,
-                                                              stdin);
-                                            close(stdout);
-                                            close(stderr);
-                                            close(stdin);
+                                            let ret = match isolate_fork(stdout, stderr, stdin, redirections) {
+                                                Ok(()) => shell.exec_builtin(name, &args, &None, &None, &None, &[]),
+                                                Err(why) => {
+                                                    eprintln!("ion: {}", why);
+                                                    COULD_NOT_EXEC
+                                                }
+                                            };
+                                            let _ = io::stdout().flush();
                                             exit(ret)
                                         },
                                         Ok(pid) => {
@@ -562,11 +952,45 @@ pub fn pipe(shell: &mut Shell, commands: Vec<(RefinedJob, JobKind)>, foreground:
                                         }
                                     }
                                 }
+                                RefinedJob::Subshell { ref code,
+                                                       ref stdout,
+                                                       ref stderr,
+                                                       ref stdin,
+                                                       ref redirections, } =>
+                                {
+                                    match unsafe { sys::fork() } {
+                                        Ok(0) => {
+                                            signals::unblock();
+                                            let _ = sys::reset_signal(sys::SIGINT);
+                                            let _ = sys::reset_signal(sys::SIGHUP);
+                                            let _ = sys::reset_signal(sys::SIGTERM);
+                                            create_process_group(pgid);
+                                            limits.apply_or_exit();
+                                            exit(shell.exec_subshell(code, stdout, stderr, stdin, redirections))
+                                        },
+                                        Ok(pid) => {
+                                            if pgid == 0 {
+                                                pgid = pid;
+                                                if foreground {
+                                                    let _ = sys::tcsetpgrp(0, pgid);
+                                                }
+                                            }
+                                            shell.foreground.push(pid);
+                                            children.push(pid);
+                                        },
+                                        Err(e) => {
+                                            eprintln!("ion: failed to fork {}: {}",
+                                                      short,
+                                                      e);
+                                        }
+                                    }
+                                }
                                 RefinedJob::Function { ref name,
                                                       ref args,
                                                       ref stdout,
                                                       ref stderr,
-                                                      ref stdin, } =>
+                                                      ref stdin,
+                                                      ref redirections, } =>
                                 {
                                     match unsafe { sys::fork() } {
                                         Ok(0) => {
@@ -575,17 +999,18 @@ pub fn pipe(shell: &mut Shell, commands: Vec<(RefinedJob, JobKind)>, foreground:
                                             let _ = sys::reset_signal(sys::SIGHUP);
                                             let _ = sys::reset_signal(sys::SIGTERM);
                                             create_process_group(pgid);
+                                            limits.apply_or_exit();
                                             let args: Vec<&str> = args
                                                 .iter()
                                                 .map(|x| x as &str).collect();
-                                            let ret = shell.exec_function(name,
-                                                              &args,
-                                                              stdout,
-                                                              stderr,
-                                                              stdin);
-                                            close(stdout);
-                                            close(stderr);
-                                            close(stdin);
+                                            let ret = match isolate_fork(stdout, stderr, stdin, redirections) {
+                                                Ok(()) => shell.exec_function(name, &args, &None, &None, &None, &[]),
+                                                Err(why) => {
+                                                    eprintln!("ion: {}", why);
+                                                    COULD_NOT_EXEC
+                                                }
+                                            };
+                                            let _ = io::stdout().flush();
                                             exit(ret)
                                         },
                                         Ok(pid) => {
@@ -665,6 +1090,7 @@ pub fn pipe(shell: &mut Shell, commands: Vec<(RefinedJob, JobKind)>, foreground:
                 }
                 _ => {
                     previous_status = shell.exec_job(&mut parent, foreground);
+                    shell.variables.set_array("PIPESTATUS", array![previous_status.to_string()]);
                     previous_kind = kind;
                 }
             }
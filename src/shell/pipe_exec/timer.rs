@@ -0,0 +1,53 @@
+//! Times a pipeline that was prefixed with `time`, such as `time make | tail`, reporting the real,
+//! user, and system time of the whole pipeline once it has completed.
+
+use super::super::variables::Variables;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+use sys;
+
+pub struct Timer {
+    start: Instant,
+    /// The user and system time which had been used before the pipeline was executed.
+    cpu: (Duration, Duration),
+    /// Set by `time -p`, which reports the times in the format given by POSIX.
+    posix: bool,
+}
+
+fn cpu_times() -> (Duration, Duration) {
+    sys::cpu_times().unwrap_or((Duration::from_secs(0), Duration::from_secs(0)))
+}
+
+fn seconds(duration: Duration) -> String {
+    format!("{}.{:03}", duration.as_secs(), duration.subsec_nanos() / 1_000_000)
+}
+
+impl Timer {
+    pub fn start(posix: bool) -> Timer {
+        Timer { start: Instant::now(), cpu: cpu_times(), posix }
+    }
+
+    /// Reports the times to the standard error, and stores them, in seconds, within the
+    /// `TIME_REAL`, `TIME_USER`, and `TIME_SYS` variables.
+    pub fn finish(self, variables: &mut Variables) {
+        let real = self.start.elapsed();
+        let (user, system) = cpu_times();
+        let times = [("real", real), ("user", user - self.cpu.0), ("sys", system - self.cpu.1)];
+
+        let stderr = io::stderr();
+        let mut stderr = stderr.lock();
+        if !self.posix {
+            let _ = stderr.write_all(b"\n");
+        }
+        for &(name, duration) in &times {
+            let _ = if self.posix {
+                writeln!(stderr, "{} {}.{:02}", name, duration.as_secs(), duration.subsec_nanos() / 10_000_000)
+            } else {
+                let millis = duration.subsec_nanos() / 1_000_000;
+                let secs = duration.as_secs();
+                writeln!(stderr, "{}\t{}m{}.{:03}s", name, secs / 60, secs % 60, millis)
+            };
+            variables.set_var(&["TIME_", &name.to_uppercase()].concat(), &seconds(duration));
+        }
+    }
+}
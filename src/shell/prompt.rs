@@ -0,0 +1,137 @@
+//! Expands the backslash escapes of the `PROMPT` variable, such as `\w` for the current working
+//! directory, which are evaluated each time that the prompt is drawn.
+
+use parser::expand_string;
+use std::char;
+use std::env;
+use sys;
+use sys::variables as self_sys;
+use super::Shell;
+
+/// The characters which may follow a backslash to form a prompt escape.
+const ESCAPES: &str = "wWuhH?jtA$ne\\";
+
+/// Marks the position of each escape while the rest of the prompt is being expanded. Characters
+/// from the private use area are used, because the expansion leaves them untouched, with the
+/// offset from this character being the index of the escape within `ESCAPES`.
+const MARKER: u32 = 0xE000;
+
+/// Replaces each supported escape with a marker that will survive the expansion of the prompt.
+/// Unsupported escapes are kept, to be handled by the expansion as usual.
+fn mark_escapes(prompt: &str) -> String {
+    let mut output = String::with_capacity(prompt.len());
+    let mut chars = prompt.chars();
+    while let Some(character) = chars.next() {
+        if character != '\\' {
+            output.push(character);
+            continue
+        }
+
+        match chars.next() {
+            Some(escape) => match ESCAPES.find(escape) {
+                Some(index) => output.push(char::from_u32(MARKER + index as u32).unwrap()),
+                None => {
+                    output.push('\\');
+                    output.push(escape);
+                }
+            },
+            None => output.push('\\'),
+        }
+    }
+    output
+}
+
+/// Replaces each marked escape with the value returned by `lookup`.
+fn replace_markers<F: Fn(char) -> String>(text: &str, lookup: F) -> String {
+    let mut output = String::with_capacity(text.len());
+    for character in text.chars() {
+        let code = character as u32;
+        if code >= MARKER && code < MARKER + ESCAPES.len() as u32 {
+            output.push_str(&lookup(ESCAPES.as_bytes()[(code - MARKER) as usize] as char));
+        } else {
+            output.push(character);
+        }
+    }
+    output
+}
+
+/// Abbreviates the home directory at the start of the path with a `~`.
+fn shorten_home(path: &str, home: &str) -> String {
+    if home.is_empty() || !path.starts_with(home) {
+        path.to_owned()
+    } else if path.len() == home.len() {
+        "~".to_owned()
+    } else if path.as_bytes()[home.len()] == b'/' {
+        ["~", &path[home.len()..]].concat()
+    } else {
+        path.to_owned()
+    }
+}
+
+fn current_dir(shell: &Shell) -> String {
+    let cwd = env::current_dir().map(|path| path.to_string_lossy().into_owned()).unwrap_or_else(|_| "?".into());
+    shorten_home(&cwd, &shell.variables.get_var_or_empty("HOME"))
+}
+
+fn escape_value(shell: &Shell, escape: char) -> String {
+    match escape {
+        'w' => current_dir(shell),
+        'W' => {
+            let cwd = current_dir(shell);
+            match cwd.rfind('/') {
+                Some(pos) if cwd.len() > 1 => cwd[pos + 1..].to_owned(),
+                _ => cwd,
+            }
+        }
+        'u' => shell.variables.get_var_or_empty("USER"),
+        'h' => self_sys::get_host_name().map_or_else(String::new, |host| {
+            host.split('.').next().unwrap_or("").to_owned()
+        }),
+        'H' => self_sys::get_host_name().unwrap_or_default(),
+        '?' => shell.previous_status.to_string(),
+        'j' => {
+            let processes = shell.background.lock().unwrap();
+            processes.iter().filter(|process| process.is_active()).count().to_string()
+        }
+        't' => {
+            let (hour, minute, second) = self_sys::local_time();
+            format!("{:02}:{:02}:{:02}", hour, minute, second)
+        }
+        'A' => {
+            let (hour, minute, _) = self_sys::local_time();
+            format!("{:02}:{:02}", hour, minute)
+        }
+        '$' => if sys::geteuid() == 0 { "#".into() } else { "$".into() },
+        'n' => "\n".into(),
+        'e' => "\x1B".into(),
+        '\\' => "\\".into(),
+        _ => String::new(),
+    }
+}
+
+/// Expands the prompt, substituting the current values for each of its escapes.
+pub fn expand_prompt(shell: &Shell, prompt: &str) -> String {
+    let expanded = expand_string(&mark_escapes(prompt), shell, false).join(" ");
+    replace_markers(&expanded, |escape| escape_value(shell, escape))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes() {
+        let marked = mark_escapes("\\u@\\h:\\w \\x\\\\ \\");
+        assert_eq!("\u{E002}@\u{E003}:\u{E000} \\x\u{E00C} \\", marked);
+        let replaced = replace_markers(&marked, |escape| escape.to_string().repeat(2));
+        assert_eq!("uu@hh:ww \\x\\\\ \\", replaced);
+    }
+
+    #[test]
+    fn home_directory() {
+        assert_eq!("~", shorten_home("/home/ion", "/home/ion"));
+        assert_eq!("~/src", shorten_home("/home/ion/src", "/home/ion"));
+        assert_eq!("/home/ionic", shorten_home("/home/ionic", "/home/ion"));
+        assert_eq!("/tmp", shorten_home("/tmp", ""));
+    }
+}
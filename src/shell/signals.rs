@@ -2,7 +2,7 @@
 //! will be used to block signals in the shell at startup, and unblock signals for each of the forked
 //! children of the shell.
 
-use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT};
+use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
 
 use sys;
 
@@ -10,16 +10,51 @@ pub use sys::signals::{block, unblock};
 
 pub static PENDING: AtomicUsize = ATOMIC_USIZE_INIT;
 
+/// Marks a received signal as pending, so that it is handled once the shell is able to.
+pub extern "C" fn handler(signal: i32) {
+    if signal < 32 {
+        PENDING.fetch_or(1 << signal, Ordering::SeqCst);
+    }
+}
+
 /// Suspends a given process by it's process ID.
 pub fn suspend(pid: u32) {
     let _ = sys::killpg(pid, sys::SIGSTOP);
 }
 
+/// Suspends the shell itself with a `SIGTSTP`, which the shell otherwise blocks, until it is
+/// resumed by the shell which started it.
+pub fn suspend_shell() {
+    if let Ok(pid) = sys::getpid() {
+        let _ = sys::kill(pid, sys::SIGTSTP);
+        // The pending signal is delivered once it is unblocked, stopping the shell right here.
+        unblock();
+        block();
+    }
+}
+
 /// Resumes a given process by it's process ID.
 pub fn resume(pid: u32) {
     let _ = sys::killpg(pid, sys::SIGCONT);
 }
 
+/// Parses a signal which was given by its name, with or without the `SIG` prefix, or by its number,
+/// where the signal `0` only checks whether a process exists.
+pub fn parse(signal: &str) -> Option<i32> {
+    if let Ok(signal) = signal.parse::<i32>() {
+        let known = signal == 0 || sys::SIGNALS.iter().any(|&(_, number)| number == signal);
+        return if known { Some(signal) } else { None };
+    }
+    let signal = signal.to_uppercase();
+    let name = if signal.starts_with("SIG") { &signal[3..] } else { &signal[..] };
+    sys::SIGNALS.iter().find(|&&(other, _)| other == name).map(|&(_, number)| number)
+}
+
+/// The name of a signal, without its `SIG` prefix.
+pub fn name(signal: i32) -> Option<&'static str> {
+    sys::SIGNALS.iter().find(|&&(_, number)| number == signal).map(|&(name, _)| name)
+}
+
 /// The purpose of the signal handler is to ignore signals when it is active, and then continue
 /// listening to signals once the handler is dropped.
 pub struct SignalHandler;
@@ -36,3 +71,20 @@ impl Drop for SignalHandler {
         unblock();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_signals() {
+        assert_eq!(parse("TERM"), Some(sys::SIGTERM));
+        assert_eq!(parse("sigterm"), Some(sys::SIGTERM));
+        assert_eq!(parse("SIGHUP"), Some(sys::SIGHUP));
+        assert_eq!(parse(&sys::SIGINT.to_string()), Some(sys::SIGINT));
+        assert_eq!(parse("0"), Some(0));
+        assert_eq!(parse("NOTASIGNAL"), None);
+        assert_eq!(parse("-1"), None);
+        assert_eq!(name(sys::SIGTSTP), Some("TSTP"));
+    }
+}
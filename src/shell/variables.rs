@@ -1,13 +1,15 @@
-use fnv::FnvHashMap;
+use fnv::{FnvHashMap, FnvHashSet};
+use std::cell::Cell;
 use std::env;
-use std::io::{self, BufRead};
 use std::process;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
+use super::colors;
 use super::directory_stack::DirectoryStack;
-use super::status::{FAILURE, SUCCESS};
 use app_dirs::{AppDataType, AppInfo, app_root};
-use liner::Context;
-use types::{Array, ArrayVariableContext, HashMap, HashMapVariableContext, Identifier, Key, Value, VariableContext};
+use parser::assignments::Primitive;
+use types::{Array, ArrayVariableContext, BTreeMap, BTreeMapVariableContext, Data, HashMap, HashMapVariableContext,
+            Identifier, Key, Value, VariableContext};
 
 #[cfg(target_os = "redox")]
 use sys::getpid;
@@ -15,15 +17,85 @@ use sys::getpid;
 #[cfg(all(unix, not(target_os = "unix")))]
 use sys::getpid;
 
-use sys;
 use sys::variables as self_sys;
 
+/// Stores the values which were shadowed by local assignments made within a function call,
+/// so that they may be restored when the function returns.
+#[derive(Debug, Default)]
+pub struct Scope {
+    hashmaps: FnvHashMap<Identifier, Option<HashMap>>,
+    btreemaps: FnvHashMap<Identifier, Option<BTreeMap>>,
+    arrays: FnvHashMap<Identifier, Option<Array>>,
+    variables: FnvHashMap<Identifier, Option<Value>>,
+    types: FnvHashMap<Identifier, Option<Primitive>>,
+    /// Names which were declared with `const` within this scope.
+    constants: FnvHashSet<Identifier>,
+    /// Names which were local to this scope when they were made readonly.
+    readonly: FnvHashSet<Identifier>,
+}
+
+/// Arrays and maps which are exported are stored in the environment as JSON, within variables
+/// whose names begin with these prefixes, so that they may be imported by child ion processes.
+pub const EXPORTED_ARRAY_PREFIX: &str = "ION_ARRAY_";
+pub const EXPORTED_HASHMAP_PREFIX: &str = "ION_HMAP_";
+pub const EXPORTED_BTREEMAP_PREFIX: &str = "ION_BMAP_";
+
+/// Removes the JSON representations of the arrays and maps which were exported under the name,
+/// except for the one with the given prefix, so that child ion processes will not import a stale
+/// value alongside the current one.
+pub fn remove_exported_data(name: &str, keep: Option<&str>) {
+    for prefix in &[EXPORTED_ARRAY_PREFIX, EXPORTED_HASHMAP_PREFIX, EXPORTED_BTREEMAP_PREFIX] {
+        if keep != Some(*prefix) {
+            env::remove_var([*prefix, name].concat());
+        }
+    }
+}
+
+/// Removes the variable from the environment, along with any array or map which was exported
+/// under its name, returning whether any of them had been exported.
+pub fn unexport(name: &str) -> bool {
+    // Names which the environment can not contain would cause `env::remove_var` to panic.
+    if name.is_empty() || name.contains('=') || name.contains('\0') { return false }
+    let exported = env::var_os(name).is_some()
+        || [EXPORTED_ARRAY_PREFIX, EXPORTED_HASHMAP_PREFIX, EXPORTED_BTREEMAP_PREFIX].iter()
+            .any(|prefix| env::var_os([*prefix, name].concat()).is_some());
+    env::remove_var(name);
+    remove_exported_data(name, None);
+    exported
+}
+
+/// Resolves the variables within a namespace, such as `HOME` in `$env::HOME`.
+pub type NamespaceProvider = fn(&str) -> Option<Value>;
+
+fn env_namespace(name: &str) -> Option<Value> { env::var(name).ok() }
+
 #[derive(Debug)]
 pub struct Variables {
     pub hashmaps: HashMapVariableContext,
+    pub btreemaps: BTreeMapVariableContext,
     pub arrays: ArrayVariableContext,
     pub variables: VariableContext,
     pub aliases: VariableContext,
+    /// The directories which were named with `hash -d`, and which `~name` expands to.
+    pub named_dirs: VariableContext,
+    /// The types of variables which were declared with a type annotation.
+    pub types: FnvHashMap<Identifier, Primitive>,
+    /// Variables which may no longer be assigned to, or unset, outside of any function call.
+    readonly: FnvHashSet<Identifier>,
+    /// The providers of each variable namespace, which are keyed by the name of the namespace.
+    namespaces: FnvHashMap<&'static str, NamespaceProvider>,
+    scopes: Vec<Scope>,
+    /// The number of the line which was most recently read by the shell, exposed as `$LINENO`.
+    pub line_number: usize,
+    /// The names of the functions which are currently being executed, the innermost being last.
+    pub function_names: Vec<Identifier>,
+    /// The time at which the shell was started, or `$SECONDS` was last assigned to, which
+    /// `$SECONDS` is measured from.
+    started: Instant,
+    /// The value of `$SECONDS` at the time that it was last assigned to.
+    seconds: u64,
+    /// The state of the generator which `$RANDOM` is drawn from.
+    random_state: Cell<u32>,
 }
 
 impl Default for Variables {
@@ -32,6 +104,9 @@ impl Default for Variables {
         map.insert("DIRECTORY_STACK_SIZE".into(), "1000".into());
         map.insert("HISTORY_SIZE".into(), "1000".into());
         map.insert("HISTFILE_SIZE".into(), "1000".into());
+        map.insert("RECURSION_LIMIT".into(), "100".into());
+        map.insert("GLOB_DEPTH".into(), "64".into());
+        map.insert("GLOB_SORT".into(), "name".into());
         map.insert("PROMPT".into(), "\x1B\']\'0;${USER}: ${PWD}\x07\x1B\'[\'0m\x1B\'[\'1;38;5;85m${USER}\x1B\'[\'37m:\x1B\'[\'38;5;75m${PWD}\x1B\'[\'37m#\x1B\'[\'0m ".into());
         // Set the PID variable to the PID of the shell
         let pid = getpid().map(|p| p.to_string()).unwrap_or_else(
@@ -39,6 +114,11 @@ impl Default for Variables {
         );
         map.insert("PID".into(), pid.into());
 
+        // Seed `$RANDOM` with the current time and the PID, so that each shell differs.
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|time| time.subsec_nanos() ^ time.as_secs() as u32)
+            .unwrap_or(0) ^ getpid().unwrap_or(0);
+
         // Initialize the HISTFILE variable
         if let Ok(mut home_path) =
             app_root(
@@ -69,88 +149,218 @@ impl Default for Variables {
                 env::set_var("HOME", path.to_str().unwrap_or("?"))
             },
         );
-        Variables {
+        let mut variables = Variables {
             hashmaps: FnvHashMap::with_capacity_and_hasher(64, Default::default()),
+            btreemaps: FnvHashMap::with_capacity_and_hasher(64, Default::default()),
             arrays: FnvHashMap::with_capacity_and_hasher(64, Default::default()),
             variables: map,
             aliases: FnvHashMap::with_capacity_and_hasher(64, Default::default()),
-        }
+            named_dirs: FnvHashMap::default(),
+            types: FnvHashMap::default(),
+            readonly: FnvHashSet::default(),
+            namespaces: FnvHashMap::default(),
+            scopes: Vec::new(),
+            line_number: 0,
+            function_names: Vec::new(),
+            started: Instant::now(),
+            seconds: 0,
+            // The state of a xorshift generator must never be zero.
+            random_state: Cell::new(seed | 1),
+        };
+        variables.register_namespace("color", colors::color);
+        variables.register_namespace("env", env_namespace);
+        variables
     }
 }
 
 impl Variables {
-    pub fn read<I: IntoIterator>(&mut self, args: I) -> i32
-        where I::Item: AsRef<str>
-    {
-        if sys::isatty(sys::STDIN_FILENO) {
-            let mut con = Context::new();
-            for arg in args.into_iter().skip(1) {
-                match con.read_line(format!("{}=", arg.as_ref().trim()), &mut |_| {}) {
-                    Ok(buffer) => self.set_var(arg.as_ref(), buffer.trim()),
-                    Err(_) => return FAILURE,
+    /// Enters a new local scope, which is entered whenever a function is called.
+    pub fn new_scope(&mut self) { self.scopes.push(Scope::default()); }
+
+    /// Exits the current local scope, restoring every variable that was shadowed within it.
+    pub fn pop_scope(&mut self) {
+        if let Some(scope) = self.scopes.pop() {
+            for (name, value) in scope.variables {
+                match value {
+                    Some(value) => { self.variables.insert(name, value); },
+                    None => { self.variables.remove(&name); },
                 }
             }
-        } else {
-            let stdin = io::stdin();
-            let handle = stdin.lock();
-            let mut lines = handle.lines();
-            for arg in args.into_iter().skip(1) {
-                if let Some(Ok(line)) = lines.next() {
-                    self.set_var(arg.as_ref(), line.trim());
+            for (name, array) in scope.arrays {
+                match array {
+                    Some(array) => { self.arrays.insert(name, array); },
+                    None => { self.arrays.remove(&name); },
+                }
+            }
+            for (name, map) in scope.hashmaps {
+                match map {
+                    Some(map) => { self.hashmaps.insert(name, map); },
+                    None => { self.hashmaps.remove(&name); },
+                }
+            }
+            for (name, map) in scope.btreemaps {
+                match map {
+                    Some(map) => { self.btreemaps.insert(name, map); },
+                    None => { self.btreemaps.remove(&name); },
+                }
+            }
+            for (name, primitive) in scope.types {
+                match primitive {
+                    Some(primitive) => { self.types.insert(name, primitive); },
+                    None => { self.types.remove(&name); },
                 }
             }
         }
-        SUCCESS
     }
 
-    pub fn set_var(&mut self, name: &str, value: &str) {
-        if !name.is_empty() {
-            if value.is_empty() {
-                self.variables.remove(name);
-            } else {
-                self.variables.insert(name.into(), value.into());
+    /// Marks the given name as local to the current scope, if any, so that any variable, array,
+    /// or map of that name will be restored when the scope is exited.
+    pub fn shadow(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            if !scope.variables.contains_key(name) {
+                scope.variables.insert(name.into(), self.variables.get(name).cloned());
+                scope.arrays.insert(name.into(), self.arrays.get(name).cloned());
+                scope.hashmaps.insert(name.into(), self.hashmaps.get(name).cloned());
+                scope.btreemaps.insert(name.into(), self.btreemaps.get(name).cloned());
+                scope.types.insert(name.into(), self.types.get(name).cloned());
             }
         }
     }
 
-    pub fn set_array(&mut self, name: &str, value: Array) {
-        if !name.is_empty() {
-            if value.is_empty() {
-                self.arrays.remove(name);
-            } else {
-                self.arrays.insert(name.into(), value);
-            }
+    /// Assigns the value to the variable, where an empty value still defines the variable, so
+    /// that it is distinct from a variable which has been unset. Assignments to readonly
+    /// variables are reported, and refused.
+    pub fn set_var(&mut self, name: &str, value: &str) -> bool {
+        if name.is_empty() { return true }
+        if self.is_readonly(name) {
+            self.refuse_assignment(name);
+            return false;
         }
+        if let Some(assigned) = self.assign_computed(name, value) {
+            return assigned;
+        }
+        self.variables.insert(name.into(), value.into());
+        true
     }
 
-    pub fn set_hashmap_value(&mut self, name: &str, key: &str, value: &str) {
+    /// Assigning to `$RANDOM` seeds its generator, and assigning to `$SECONDS` restarts its
+    /// count from the given number of seconds. Returns `None` if the variable is not one of these.
+    fn assign_computed(&mut self, name: &str, value: &str) -> Option<bool> {
+        let assigned = match name {
+            "RANDOM" => value.parse::<u32>().map(|seed| self.seed_random(seed)).is_ok(),
+            "SECONDS" => value.parse::<u64>().map(|seconds| {
+                self.seconds = seconds;
+                self.started = Instant::now();
+            }).is_ok(),
+            _ => return None,
+        };
+        if !assigned {
+            eprintln!("ion: cannot assign '{}' to {}, which only accepts whole numbers", value, name);
+        }
+        Some(assigned)
+    }
+
+    /// Assigns the array to the variable, where an empty array still defines the variable.
+    /// Assignments to readonly variables are reported, and refused.
+    pub fn set_array(&mut self, name: &str, value: Array) -> bool {
+        if name.is_empty() { return true }
+        if self.is_readonly(name) {
+            self.refuse_assignment(name);
+            return false;
+        }
+        self.arrays.insert(name.into(), value);
+        true
+    }
+
+    fn refuse_assignment(&self, name: &str) {
+        let kind = if self.is_constant(name) { "constant" } else { "readonly variable" };
+        eprintln!("ion: cannot assign to {} '{}'", kind, name);
+    }
+
+    pub fn set_hashmap_value(&mut self, name: &str, key: &str, value: Data) {
         if !name.is_empty() {
+            if let Some(map) = self.btreemaps.get_mut(name) {
+                map.insert(key.into(), value.sorted());
+                return;
+            }
+
             if let Some(map) = self.hashmaps.get_mut(name) {
-                map.insert(key.into(), value.into());
+                map.insert(key.into(), value);
                 return;
             }
 
             let mut map = HashMap::with_capacity_and_hasher(4, Default::default());
-            map.insert(key.into(), value.into());
+            map.insert(key.into(), value);
             self.hashmaps.insert(name.into(), map);
         }
     }
 
+    /// Replaces the map of the given name, along with any array or map that shares its name.
+    pub fn set_hashmap(&mut self, name: &str, map: HashMap) {
+        if !name.is_empty() {
+            self.arrays.remove(name);
+            self.btreemaps.remove(name);
+            self.hashmaps.insert(name.into(), map);
+        }
+    }
+
+    /// Replaces the sorted map of the given name, along with any array or map that shares its name.
+    pub fn set_btreemap(&mut self, name: &str, map: BTreeMap) {
+        if !name.is_empty() {
+            self.arrays.remove(name);
+            self.hashmaps.remove(name);
+            self.btreemaps.insert(name.into(), map);
+        }
+    }
+
     pub fn get_map(&self, name: &str) -> Option<&HashMap> { self.hashmaps.get(name) }
 
+    pub fn get_btreemap(&self, name: &str) -> Option<&BTreeMap> { self.btreemaps.get(name) }
+
+    /// Obtains the keys of the map of the given name, along with their flattened values. The
+    /// entries of hash maps are sorted by key, so that they are iterated in a consistent order.
+    pub fn get_map_entries(&self, name: &str) -> Option<Vec<(Value, Array)>> {
+        if let Some(map) = self.btreemaps.get(name) {
+            Some(map.iter().map(|(key, value)| (String::from(&**key), value.flatten())).collect())
+        } else if let Some(map) = self.hashmaps.get(name) {
+            let mut entries: Vec<(Value, Array)> = map.iter()
+                .map(|(key, value)| (String::from(&**key), value.flatten()))
+                .collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            Some(entries)
+        } else {
+            None
+        }
+    }
+
+    /// Obtains a copy of the map or array of the given name, so that values nested within it
+    /// may be selected.
+    pub fn get_data(&self, name: &str) -> Option<Data> {
+        self.btreemaps.get(name).map(|map| Data::BTreeMap(map.clone()))
+            .or_else(|| self.hashmaps.get(name).map(|map| Data::HashMap(map.clone())))
+            .or_else(|| self.arrays.get(name).map(|array| {
+                Data::Array(array.iter().cloned().map(Data::Str).collect())
+            }))
+    }
+
     pub fn get_array(&self, name: &str) -> Option<&Array> { self.arrays.get(name) }
 
-    pub fn unset_array(&mut self, name: &str) -> Option<Array> { self.arrays.remove(name) }
+    pub fn unset_array(&mut self, name: &str) -> Option<Array> {
+        if self.is_readonly(name) { return None }
+        self.arrays.remove(name)
+    }
 
     pub fn get_var(&self, name: &str) -> Option<Value> {
         if let Some((namespace, variable)) = name.find("::").map(|pos| (&name[..pos], &name[pos+2..])) {
-            match namespace {
-                "env" => env::var(variable).map(Into::into).ok(),
-                _ => {
+            match self.namespaces.get(namespace) {
+                Some(provider) => provider(variable),
+                None => {
                     eprintln!("ion: unsupported namespace: '{}'", namespace);
                     None
                 }
             }
+        } else if let Some(value) = self.computed_var(name) {
+            Some(value)
         } else {
             self.variables.get(name).cloned().or_else(|| {
                 env::var(name).map(Into::into).ok()
@@ -158,9 +368,99 @@ impl Variables {
         }
     }
 
+    /// Computes the value of a special variable, whose value is derived from the state of the
+    /// shell each time that it is expanded.
+    fn computed_var(&self, name: &str) -> Option<Value> {
+        match name {
+            "LINENO" => Some(self.line_number.to_string()),
+            "SECONDS" => Some((self.seconds + self.started.elapsed().as_secs()).to_string()),
+            "RANDOM" => Some((self.random() % 32768).to_string()),
+            "FUNCNAME" => self.function_names.last().map(|name| String::from(&**name)),
+            _ => None,
+        }
+    }
+
+    /// Draws the next number from the shell's generator, which `$RANDOM` and the `random` builtin
+    /// share.
+    pub fn random(&self) -> u32 {
+        let mut state = self.random_state.get();
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        self.random_state.set(state);
+        state
+    }
+
+    /// Seeds the shell's generator, so that the same sequence of numbers may be drawn again.
+    pub fn seed_random(&self, seed: u32) {
+        // The generator would only ever produce zeroes from a state of zero.
+        self.random_state.set(if seed == 0 { 0x9E37_79B9 } else { seed });
+    }
+
+    /// Registers a provider which will resolve every variable within the given namespace.
+    pub fn register_namespace(&mut self, namespace: &'static str, provider: NamespaceProvider) {
+        self.namespaces.insert(namespace, provider);
+    }
+
     pub fn get_var_or_empty(&self, name: &str) -> Value { self.get_var(name).unwrap_or_default() }
 
-    pub fn unset_var(&mut self, name: &str) -> Option<Value> { self.variables.remove(name) }
+    pub fn unset_var(&mut self, name: &str) -> Option<Value> {
+        if self.is_readonly(name) { return None }
+        self.types.remove(name);
+        self.variables.remove(name)
+    }
+
+    /// Prevents the variable from being assigned to, or unset. A variable which is local to the
+    /// current function call is only readonly until the call returns.
+    pub fn set_readonly(&mut self, name: &str) {
+        if name.is_empty() { return }
+        if let Some(scope) = self.scopes.last_mut() {
+            if scope.variables.contains_key(name) {
+                scope.readonly.insert(name.into());
+                return
+            }
+        }
+        self.readonly.insert(name.into());
+    }
+
+    pub fn is_readonly(&self, name: &str) -> bool {
+        self.readonly.contains(name)
+            || self.scopes.last().map_or(false, |scope| scope.readonly.contains(name))
+            || self.is_constant(name)
+    }
+
+    /// The names of the readonly variables, excluding constants, sorted by name.
+    pub fn readonly_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.readonly.iter()
+            .chain(self.scopes.last().into_iter().flat_map(|scope| scope.readonly.iter()))
+            .map(|name| &**name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Prevents the variable from being reassigned until the current scope is exited. Constants
+    /// that are declared outside of a function call are treated as readonly variables.
+    pub fn set_constant(&mut self, name: &str) {
+        if name.is_empty() { return }
+        match self.scopes.last_mut() {
+            Some(scope) => { scope.constants.insert(name.into()); },
+            None => self.set_readonly(name),
+        }
+    }
+
+    /// Whether the variable was declared with `const` within the current function call. Calls
+    /// which are made from within the function may declare their own variables of the same name.
+    pub fn is_constant(&self, name: &str) -> bool {
+        self.scopes.last().map_or(false, |scope| scope.constants.contains(name))
+    }
+
+    /// Records the type of a variable, which will be enforced by subsequent assignments.
+    pub fn set_type(&mut self, name: &str, primitive: Primitive) {
+        if !name.is_empty() {
+            self.types.insert(name.into(), primitive);
+        }
+    }
 
     pub fn get_vars(&self) -> Vec<Identifier> {
         self.variables
@@ -196,7 +496,9 @@ impl Variables {
 
         match tilde_prefix {
             "" => {
-                if let Some(home) = env::home_dir() {
+                if let Some(home) = self.get_var("HOME") {
+                    return Some(home.to_string() + remainder);
+                } else if let Some(home) = env::home_dir() {
                     return Some(home.to_string_lossy().to_string() + remainder);
                 }
             }
@@ -228,15 +530,21 @@ impl Variables {
                 }
 
                 match tilde_num.parse() {
+                    // As with `dirs`, `~N` and `~+N` count from the top of the directory stack,
+                    // and `~-N` counts from its bottom.
                     Ok(num) => {
-                        let res = if neg { dir_stack.dir_from_top(num) } else { dir_stack.dir_from_bottom(num) };
+                        let res = if neg { dir_stack.dir_from_bottom(num) } else { dir_stack.dir_from_top(num) };
 
                         if let Some(path) = res {
-                            return Some(path.to_str().unwrap().to_string());
+                            return Some(path.to_string_lossy().to_string() + remainder);
                         }
                     }
+                    // Any other prefix is the name of a named directory, or of a user, whose home
+                    // is found in the passwd database.
                     Err(_) => {
-                        if let Some(home) = self_sys::get_user_home(tilde_prefix) {
+                        if let Some(path) = self.named_dirs.get(tilde_prefix) {
+                            return Some(path.to_string() + remainder);
+                        } else if let Some(home) = self_sys::get_user_home(tilde_prefix) {
                             return Some(home + remainder);
                         }
                     }
@@ -250,7 +558,9 @@ impl Variables {
         if let Ok(exe) = env::current_exe() {
             if let Ok(output) = process::Command::new(exe).arg("-c").arg(command).output() {
                 if let Ok(mut stdout) = String::from_utf8(output.stdout) {
-                    if stdout.ends_with('\n') {
+                    // Output that is terminated by a NUL, such as from `find -print0`, is
+                    // trimmed just as output that is terminated by a newline.
+                    if stdout.ends_with('\n') || stdout.ends_with('\0') {
                         stdout.pop();
                     }
 
@@ -309,6 +619,191 @@ mod tests {
         assert_eq!("BAR", &expanded);
     }
 
+    #[test]
+    fn empty_values_are_defined() {
+        let mut variables = Variables::default();
+        variables.set_var("EMPTY", "");
+        assert_eq!(Some("".into()), variables.get_var("EMPTY"));
+        variables.unset_var("EMPTY");
+        assert_eq!(None, variables.get_var("EMPTY"));
+        variables.set_array("EMPTY", Array::new());
+        assert_eq!(Some(&Array::new()), variables.get_array("EMPTY"));
+    }
+
+    #[test]
+    fn scopes_restore_shadowed_variables() {
+        let mut variables = Variables::default();
+        variables.set_var("FOO", "BAR");
+        variables.new_scope();
+        variables.shadow("FOO");
+        variables.set_var("FOO", "BAZ");
+        variables.shadow("QUX");
+        variables.set_array("QUX", array!["a", "b"]);
+        assert_eq!(Some("BAZ".into()), variables.get_var("FOO"));
+        variables.pop_scope();
+        assert_eq!(Some("BAR".into()), variables.get_var("FOO"));
+        assert_eq!(None, variables.get_array("QUX"));
+    }
+
+    #[test]
+    fn constants_may_be_shadowed_by_inner_scopes() {
+        let mut variables = Variables::default();
+        variables.new_scope();
+        variables.shadow("LIMIT");
+        variables.set_var("LIMIT", "10");
+        variables.set_constant("LIMIT");
+        variables.new_scope();
+        assert!(!variables.is_constant("LIMIT"));
+        variables.shadow("LIMIT");
+        assert!(variables.set_var("LIMIT", "20"));
+        variables.pop_scope();
+        assert!(variables.is_constant("LIMIT"));
+        assert_eq!(Some("10".into()), variables.get_var("LIMIT"));
+    }
+
+    #[test]
+    fn constants_are_dropped_with_their_scope() {
+        let mut variables = Variables::default();
+        variables.new_scope();
+        variables.shadow("LIMIT");
+        variables.set_var("LIMIT", "10");
+        variables.set_constant("LIMIT");
+        variables.set_var("LIMIT", "20");
+        assert!(variables.is_constant("LIMIT"));
+        assert_eq!(Some("10".into()), variables.get_var("LIMIT"));
+        variables.pop_scope();
+        assert!(!variables.is_readonly("LIMIT"));
+        assert_eq!(None, variables.get_var("LIMIT"));
+        variables.set_var("LIMIT", "30");
+        assert_eq!(Some("30".into()), variables.get_var("LIMIT"));
+    }
+
+    #[test]
+    fn special_variables() {
+        let mut variables = Variables::default();
+        assert_eq!(Some("0".into()), variables.get_var("SECONDS"));
+        assert_eq!(None, variables.get_var("FUNCNAME"));
+        variables.function_names.push("outer".into());
+        variables.function_names.push("inner".into());
+        assert_eq!(Some("inner".into()), variables.get_var("FUNCNAME"));
+        variables.line_number = 12;
+        assert_eq!(Some("12".into()), variables.get_var("LINENO"));
+        for _ in 0..100 {
+            let random = variables.get_var("RANDOM").unwrap().parse::<u32>().unwrap();
+            assert!(random < 32768);
+        }
+        variables.seed_random(42);
+        let first = variables.get_var("RANDOM");
+        variables.seed_random(42);
+        assert_eq!(first, variables.get_var("RANDOM"));
+        assert!(variables.set_var("RANDOM", "42"));
+        assert_eq!(first, variables.get_var("RANDOM"));
+        assert!(!variables.set_var("RANDOM", "seed"));
+        assert!(variables.set_var("SECONDS", "120"));
+        assert_eq!(Some("120".into()), variables.get_var("SECONDS"));
+        assert!(!variables.set_var("SECONDS", "-1"));
+        assert_eq!(None, variables.variables.get("SECONDS"));
+    }
+
+    #[test]
+    fn tilde_expansion() {
+        let mut variables = Variables::default();
+        let dir_stack = DirectoryStack::new();
+        variables.set_var("HOME", "/home/ion");
+        variables.set_var("PWD", "/tmp/current");
+        variables.set_var("OLDPWD", "/tmp/previous");
+        assert_eq!(Some("/home/ion/docs".into()), variables.tilde_expansion("~/docs", &dir_stack));
+        assert_eq!(Some("/tmp/current/file".into()), variables.tilde_expansion("~+/file", &dir_stack));
+        assert_eq!(Some("/tmp/previous".into()), variables.tilde_expansion("~-", &dir_stack));
+        assert_eq!(
+            self_sys::get_user_home("root").map(|home| home + "/docs"),
+            variables.tilde_expansion("~root/docs", &dir_stack)
+        );
+        assert_eq!(None, variables.tilde_expansion("~no_such_user_for_ion/docs", &dir_stack));
+        variables.named_dirs.insert("root".into(), "/src/project".into());
+        assert_eq!(Some("/src/project/docs".into()), variables.tilde_expansion("~root/docs", &dir_stack));
+    }
+
+    #[test]
+    fn set_hashmap_replaces_array() {
+        let mut variables = Variables::default();
+        variables.set_array("MAP", array!["a", "b"]);
+        let mut map = HashMap::with_capacity_and_hasher(4, Default::default());
+        map.insert("key".into(), Data::Str("value".into()));
+        variables.set_hashmap("MAP", map);
+        assert_eq!(None, variables.get_array("MAP"));
+        assert_eq!(Some(&Data::Str("value".into())), variables.get_map("MAP").and_then(|map| map.get("key")));
+    }
+
+    #[test]
+    fn set_hashmap_value_prefers_btreemap() {
+        let mut variables = Variables::default();
+        variables.set_btreemap("MAP", BTreeMap::new());
+        variables.set_hashmap_value("MAP", "key", Data::Str("value".into()));
+        assert_eq!(None, variables.get_map("MAP"));
+        assert_eq!(Some(&Data::Str("value".into())), variables.get_btreemap("MAP").and_then(|map| map.get("key")));
+    }
+
+    #[test]
+    fn map_entries() {
+        let mut variables = Variables::default();
+        let mut map = HashMap::with_capacity_and_hasher(4, Default::default());
+        map.insert("b".into(), Data::Array(vec![Data::Str("1".into()), Data::Str("2".into())]));
+        map.insert("a".into(), Data::Str("value".into()));
+        variables.set_hashmap("MAP", map);
+        let expected = vec![("a".to_owned(), array!["value"]), ("b".to_owned(), array!["1", "2"])];
+        assert_eq!(Some(expected), variables.get_map_entries("MAP"));
+        assert_eq!(None, variables.get_map_entries("UNDEFINED"));
+    }
+
+    #[test]
+    fn select_nested_data() {
+        let mut variables = Variables::default();
+        let mut server = HashMap::with_capacity_and_hasher(4, Default::default());
+        server.insert("port".into(), Data::Str("80".into()));
+        variables.set_hashmap_value("config", "servers", Data::Array(vec![Data::HashMap(server)]));
+        let config = variables.get_data("config").unwrap();
+        let port = config.get("servers").and_then(|servers| servers.get("-1")).and_then(|server| server.get("port"));
+        assert_eq!(Some(&Data::Str("80".into())), port);
+        assert_eq!(array!["80"], config.flatten());
+    }
+
+    #[test]
+    fn namespaced_variables() {
+        fn upper(name: &str) -> Option<Value> { Some(name.to_uppercase()) }
+        let mut variables = Variables::default();
+        assert_eq!(None, variables.get_var("upper::abc"));
+        variables.register_namespace("upper", upper);
+        assert_eq!(Some("ABC".into()), variables.get_var("upper::abc"));
+        assert_eq!(Some("\x1B[32m".into()), variables.get_var("color::green"));
+    }
+
+    #[test]
+    fn local_readonly_variables_are_dropped_with_their_scope() {
+        let mut variables = Variables::default();
+        variables.set_var("FOO", "BAR");
+        variables.new_scope();
+        variables.shadow("FOO");
+        variables.set_var("FOO", "BAZ");
+        variables.set_readonly("FOO");
+        assert!(!variables.set_var("FOO", "QUX"));
+        assert_eq!(vec!["FOO"], variables.readonly_names());
+        variables.pop_scope();
+        assert!(variables.readonly_names().is_empty());
+        assert!(variables.set_var("FOO", "QUX"));
+        assert_eq!(Some("QUX".into()), variables.get_var("FOO"));
+    }
+
+    #[test]
+    fn readonly_variables_can_not_be_modified() {
+        let mut variables = Variables::default();
+        variables.set_var("FOO", "BAR");
+        variables.set_readonly("FOO");
+        variables.set_var("FOO", "BAZ");
+        assert_eq!(None, variables.unset_var("FOO"));
+        assert_eq!(Some("BAR".into()), variables.get_var("FOO"));
+    }
+
     #[test]
     fn decompose_map_reference() {
         if let Some((map_name, inner_key)) = Variables::is_hashmap_reference("map[\'key\']") {
@@ -1,6 +1,7 @@
 extern crate syscall;
 
 use std::{io, mem, slice};
+use std::time::Duration;
 use std::os::unix::io::RawFd;
 
 use syscall::SigAction;
@@ -15,6 +16,38 @@ pub const SIGCONT: i32 = syscall::SIGCONT as i32;
 pub const SIGSTOP: i32 = syscall::SIGSTOP as i32;
 pub const SIGTSTP: i32 = syscall::SIGTSTP as i32;
 
+/// The signals which may be given by their names, without their `SIG` prefix.
+pub const SIGNALS: &[(&str, i32)] = &[
+    ("HUP", syscall::SIGHUP as i32), ("INT", syscall::SIGINT as i32),
+    ("QUIT", syscall::SIGQUIT as i32), ("ILL", syscall::SIGILL as i32),
+    ("TRAP", syscall::SIGTRAP as i32), ("ABRT", syscall::SIGABRT as i32),
+    ("BUS", syscall::SIGBUS as i32), ("FPE", syscall::SIGFPE as i32),
+    ("KILL", syscall::SIGKILL as i32), ("USR1", syscall::SIGUSR1 as i32),
+    ("SEGV", syscall::SIGSEGV as i32), ("USR2", syscall::SIGUSR2 as i32),
+    ("PIPE", syscall::SIGPIPE as i32), ("ALRM", syscall::SIGALRM as i32),
+    ("TERM", syscall::SIGTERM as i32), ("CHLD", syscall::SIGCHLD as i32),
+    ("CONT", syscall::SIGCONT as i32), ("STOP", syscall::SIGSTOP as i32),
+    ("TSTP", syscall::SIGTSTP as i32), ("TTIN", syscall::SIGTTIN as i32),
+    ("TTOU", syscall::SIGTTOU as i32), ("URG", syscall::SIGURG as i32),
+    ("XCPU", syscall::SIGXCPU as i32), ("XFSZ", syscall::SIGXFSZ as i32),
+    ("VTALRM", syscall::SIGVTALRM as i32), ("PROF", syscall::SIGPROF as i32),
+    ("WINCH", syscall::SIGWINCH as i32), ("IO", syscall::SIGIO as i32),
+    ("SYS", syscall::SIGSYS as i32),
+];
+
+pub const RLIMIT_CPU: i32 = 0;
+pub const RLIMIT_AS: i32 = 1;
+pub const RLIMIT_FSIZE: i32 = 2;
+pub const RLIMIT_NOFILE: i32 = 3;
+pub const RLIMIT_NPROC: i32 = 4;
+pub const RLIMIT_CORE: i32 = 5;
+pub const RLIMIT_DATA: i32 = 6;
+pub const RLIMIT_MEMLOCK: i32 = 7;
+pub const RLIMIT_RSS: i32 = 8;
+pub const RLIMIT_STACK: i32 = 9;
+/// The limit of a resource which is not limited.
+pub const RLIM_INFINITY: u64 = !0;
+
 pub const STDIN_FILENO: RawFd = 0;
 pub const STDOUT_FILENO: RawFd = 1;
 pub const STDERR_FILENO: RawFd = 2;
@@ -27,6 +60,14 @@ pub fn getpid() -> io::Result<u32> {
     cvt(syscall::getpid()).map(|pid| pid as u32)
 }
 
+/// Waits for the child process to exit, returning its raw wait status.
+pub fn waitpid(pid: u32) -> io::Result<i32> {
+    let mut status = 0;
+    cvt(syscall::waitpid(pid as usize, &mut status, 0)).map(|_| status as i32)
+}
+
+pub fn geteuid() -> u32 { syscall::geteuid().unwrap_or(0) as u32 }
+
 pub fn kill(pid: u32, signal: i32) -> io::Result<()> {
     cvt(syscall::kill(pid as usize, signal as usize)).and(Ok(()))
 }
@@ -79,10 +120,74 @@ pub fn tcsetpgrp(tty_fd: RawFd, pgid: u32) -> io::Result<()> {
     cvt(res).and(Ok(()))
 }
 
+/// Redox does not yet support resource limits.
+pub fn getrlimit(_resource: i32) -> io::Result<(u64, u64)> {
+    Err(io::Error::new(io::ErrorKind::Other, "resource limits are not supported"))
+}
+
+pub fn setrlimit(_resource: i32, _soft: u64, _hard: u64) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "resource limits are not supported"))
+}
+
+/// Redox does not yet support file creation masks.
+pub fn umask(_mask: u32) -> io::Result<u32> {
+    Err(io::Error::new(io::ErrorKind::Other, "file creation masks are not supported"))
+}
+
+pub fn cpu_times() -> io::Result<(Duration, Duration)> {
+    Err(io::Error::new(io::ErrorKind::Other, "resource usage is not supported"))
+}
+
+/// The modes of a terminal, as they are read from its `termios` handle.
+pub type Termios = Vec<u8>;
+
+pub fn tcgetattr(fd: RawFd) -> io::Result<Termios> {
+    let tfd = cvt(syscall::dup(fd, b"termios"))?;
+    let mut modes = vec![0; 256];
+    let res = syscall::read(tfd, &mut modes);
+    let _ = syscall::close(tfd);
+    cvt(res).map(|count| {
+        modes.truncate(count);
+        modes
+    })
+}
+
+pub fn tcsetattr(fd: RawFd, modes: &Termios) -> io::Result<()> {
+    let tfd = cvt(syscall::dup(fd, b"termios"))?;
+    let res = syscall::write(tfd, modes);
+    let _ = syscall::close(tfd);
+    cvt(res).and(Ok(()))
+}
+
+pub fn set_input_modes(_fd: RawFd, _echo: bool, _canonical: bool) -> io::Result<Termios> {
+    Err(io::Error::new(io::ErrorKind::Other, "terminal modes are not supported"))
+}
+
+pub fn poll_input(_fd: RawFd, _timeout: i32) -> io::Result<bool> {
+    Err(io::Error::new(io::ErrorKind::Other, "timeouts are not supported"))
+}
+
+pub fn poll_inputs(_fds: &[RawFd]) -> io::Result<Vec<bool>> {
+    Err(io::Error::new(io::ErrorKind::Other, "polling is not supported"))
+}
+
 pub fn dup(fd: RawFd) -> io::Result<RawFd> {
     cvt(syscall::dup(fd, &[]))
 }
 
+/// Duplicates the descriptor onto one which is closed on `exec`, so that the copies of the
+/// shell's descriptors that it keeps for itself are not inherited by the commands it executes.
+pub fn dup_cloexec(fd: RawFd) -> io::Result<RawFd> {
+    let duplicate = cvt(syscall::dup(fd, &[]))?;
+    match cvt(syscall::fcntl(duplicate, syscall::F_SETFD, syscall::O_CLOEXEC)) {
+        Ok(_) => Ok(duplicate),
+        Err(why) => {
+            let _ = syscall::close(duplicate);
+            Err(why)
+        }
+    }
+}
+
 pub fn dup2(old: RawFd, new: RawFd) -> io::Result<RawFd> {
     cvt(syscall::dup2(old, new, &[]))
 }
@@ -100,6 +205,30 @@ pub fn isatty(fd: RawFd) -> bool {
     }
 }
 
+/// Clears the close-on-exec flag of the descriptor, so that it is inherited by commands.
+pub fn clear_cloexec(fd: RawFd) -> io::Result<()> {
+    let flags = cvt(syscall::fcntl(fd, syscall::F_GETFD, 0))?;
+    cvt(syscall::fcntl(fd, syscall::F_SETFD, flags & !syscall::O_CLOEXEC)).and(Ok(()))
+}
+
+/// Sets the descriptor to return immediately from reads for which there is no input.
+pub fn set_nonblocking(fd: RawFd) -> io::Result<()> {
+    let flags = cvt(syscall::fcntl(fd, syscall::F_GETFL, 0))?;
+    cvt(syscall::fcntl(fd, syscall::F_SETFL, flags | syscall::O_NONBLOCK)).and(Ok(()))
+}
+
+/// Closes each descriptor above the standard streams which is marked as close-on-exec, as an
+/// `exec` would, so that a fork which continues to run the shell does not hold them open.
+pub fn close_cloexec_fds() {
+    for fd in 3..1024 {
+        if let Ok(flags) = syscall::fcntl(fd, syscall::F_GETFD, 0) {
+            if flags & syscall::O_CLOEXEC != 0 {
+                let _ = syscall::close(fd);
+            }
+        }
+    }
+}
+
 // Support function for converting syscall error to io error
 fn cvt(result: Result<usize, syscall::Error>) -> io::Result<usize> {
     result.map_err(|err| io::Error::from_raw_os_error(err.errno))
@@ -123,7 +252,7 @@ pub mod job_control {
     use std::sync::{Arc, Mutex};
     use syscall;
     use shell::foreground::ForegroundSignals;
-    use shell::status::TERMINATED;
+    use shell::status::{TERMINATED, TIMED_OUT};
     use shell::Shell;
 
     pub fn watch_background(
@@ -137,31 +266,49 @@ pub mod job_control {
 
 
     pub fn watch_foreground<'a, F, D>(
-        _shell: &mut Shell<'a>,
-        pid: u32,
-        _last_pid: u32,
+        shell: &mut Shell<'a>,
+        _pid: u32,
+        last_pid: u32,
         _get_command: F,
         mut drop_command: D,
     ) -> i32
     where
         F: FnOnce() -> String,
-        D: FnMut(i32),
+        D: FnMut(i32, i32) -> bool,
     {
+        let mut exit_status = 0;
+        // The status of the last process, which is returned once every process has exited.
+        let mut last_status = None;
         loop {
             let mut status_raw = 0;
-            match syscall::waitpid(pid as usize, &mut status_raw, 0) {
+            // Every process of the pipeline is waited upon, rather than only the first.
+            match syscall::waitpid(0, &mut status_raw, 0) {
                 Ok(0) => (),
-                Ok(_pid) => {
+                Ok(pid) => {
                     let status = ExitStatus::from_raw(status_raw as i32);
-                    if let Some(code) = status.code() {
-                        break code;
+                    let code = match status.code() {
+                        Some(code) => code,
+                        None if shell.timed_out() => TIMED_OUT,
+                        None => {
+                            let stderr = io::stderr();
+                            let mut stderr = stderr.lock();
+                            let _ = stderr.write_all(b"ion: child ended by signal\n");
+                            break TERMINATED;
+                        }
+                    };
+                    let running = drop_command(pid as i32, code);
+                    if pid == last_pid as usize {
+                        last_status = Some(code);
                     } else {
-                        let stderr = io::stderr();
-                        let mut stderr = stderr.lock();
-                        let _ = stderr.write_all(b"ion: child ended by signal\n");
-                        break TERMINATED;
+                        exit_status = code;
+                    }
+                    match last_status {
+                        Some(status) if !running => break status,
+                        _ => (),
                     }
                 }
+                // ECHILD signifies that all children have exited
+                Err(ref err) if err.errno == syscall::ECHILD => break last_status.unwrap_or(exit_status),
                 Err(err) => {
                     let stderr = io::stderr();
                     let mut stderr = stderr.lock();
@@ -174,8 +321,44 @@ pub mod job_control {
 }
 
 pub mod variables {
-    pub fn get_user_home(_username: &str) -> Option<String> {
-        // TODO
+    use std::fs::File;
+    use std::io::{BufRead, BufReader, Read};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    pub fn get_host_name() -> Option<String> {
+        let mut hostname = String::new();
+        match File::open("/etc/hostname").and_then(|mut file| file.read_to_string(&mut hostname)) {
+            Ok(_) => Some(hostname.trim().to_owned()),
+            Err(_) => None,
+        }
+    }
+
+    /// Returns the hour, minute, and second of the current time. Redox has no time zones, so
+    /// this is always UTC.
+    pub fn local_time() -> (u32, u32, u32) {
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+        let seconds = seconds % 86400;
+        ((seconds / 3600) as u32, (seconds / 60 % 60) as u32, (seconds % 60) as u32)
+    }
+
+    /// Finds the home directory of the given user within `/etc/passwd`, where each entry is of
+    /// the form `user;uid;gid;name;home;shell`.
+    pub fn get_user_home(username: &str) -> Option<String> {
+        let passwd = match File::open("/etc/passwd") {
+            Ok(file) => BufReader::new(file),
+            Err(_) => return None,
+        };
+
+        for line in passwd.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => return None,
+            };
+            let mut fields = line.split(';');
+            if fields.next() == Some(username) {
+                return fields.nth(3).map(String::from);
+            }
+        }
         None
     }
 }
@@ -1,7 +1,8 @@
 extern crate libc;
 
 use libc::{c_int, pid_t, sighandler_t};
-use std::io;
+use std::{io, mem};
+use std::time::Duration;
 use std::os::unix::io::RawFd;
 
 pub const PATH_SEPARATOR: &str = ":";
@@ -14,6 +15,32 @@ pub const SIGCONT: i32 = libc::SIGCONT;
 pub const SIGSTOP: i32 = libc::SIGSTOP;
 pub const SIGTSTP: i32 = libc::SIGTSTP;
 
+/// The signals which may be given by their names, without their `SIG` prefix.
+pub const SIGNALS: &[(&str, i32)] = &[
+    ("HUP", libc::SIGHUP), ("INT", libc::SIGINT), ("QUIT", libc::SIGQUIT), ("ILL", libc::SIGILL),
+    ("TRAP", libc::SIGTRAP), ("ABRT", libc::SIGABRT), ("BUS", libc::SIGBUS), ("FPE", libc::SIGFPE),
+    ("KILL", libc::SIGKILL), ("USR1", libc::SIGUSR1), ("SEGV", libc::SIGSEGV),
+    ("USR2", libc::SIGUSR2), ("PIPE", libc::SIGPIPE), ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM), ("CHLD", libc::SIGCHLD), ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP), ("TSTP", libc::SIGTSTP), ("TTIN", libc::SIGTTIN),
+    ("TTOU", libc::SIGTTOU), ("URG", libc::SIGURG), ("XCPU", libc::SIGXCPU),
+    ("XFSZ", libc::SIGXFSZ), ("VTALRM", libc::SIGVTALRM), ("PROF", libc::SIGPROF),
+    ("WINCH", libc::SIGWINCH), ("IO", libc::SIGIO), ("SYS", libc::SIGSYS),
+];
+
+pub const RLIMIT_CPU: i32 = libc::RLIMIT_CPU as i32;
+pub const RLIMIT_AS: i32 = libc::RLIMIT_AS as i32;
+pub const RLIMIT_FSIZE: i32 = libc::RLIMIT_FSIZE as i32;
+pub const RLIMIT_NOFILE: i32 = libc::RLIMIT_NOFILE as i32;
+pub const RLIMIT_NPROC: i32 = libc::RLIMIT_NPROC as i32;
+pub const RLIMIT_CORE: i32 = libc::RLIMIT_CORE as i32;
+pub const RLIMIT_DATA: i32 = libc::RLIMIT_DATA as i32;
+pub const RLIMIT_MEMLOCK: i32 = libc::RLIMIT_MEMLOCK as i32;
+pub const RLIMIT_RSS: i32 = libc::RLIMIT_RSS as i32;
+pub const RLIMIT_STACK: i32 = libc::RLIMIT_STACK as i32;
+/// The limit of a resource which is not limited.
+pub const RLIM_INFINITY: u64 = libc::RLIM_INFINITY as u64;
+
 pub const STDOUT_FILENO: i32 = libc::STDOUT_FILENO;
 pub const STDERR_FILENO: i32 = libc::STDERR_FILENO;
 pub const STDIN_FILENO: i32 = libc::STDIN_FILENO;
@@ -26,6 +53,14 @@ pub fn getpid() -> io::Result<u32> {
     cvt(unsafe { libc::getpid() }).map(|pid| pid as u32)
 }
 
+/// Waits for the child process to exit, returning its raw wait status.
+pub fn waitpid(pid: u32) -> io::Result<i32> {
+    let mut status = 0;
+    cvt(unsafe { libc::waitpid(pid as pid_t, &mut status, 0) }).map(|_| status)
+}
+
+pub fn geteuid() -> u32 { unsafe { libc::geteuid() } }
+
 pub fn kill(pid: u32, signal: i32) -> io::Result<()> {
     cvt(unsafe { libc::kill(pid as pid_t, signal as c_int) }).and(Ok(()))
 }
@@ -70,10 +105,94 @@ pub fn tcsetpgrp(fd: RawFd, pgrp: u32) -> io::Result<()> {
     cvt(unsafe { libc::tcsetpgrp(fd as c_int, pgrp as pid_t) }).and(Ok(()))
 }
 
+/// Gets the soft and the hard limit of a resource of the current process.
+pub fn getrlimit(resource: i32) -> io::Result<(u64, u64)> {
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    cvt(unsafe { libc::getrlimit(resource as _, &mut limit) })?;
+    Ok((limit.rlim_cur as u64, limit.rlim_max as u64))
+}
+
+/// Sets the soft and the hard limit of a resource of the current process.
+pub fn setrlimit(resource: i32, soft: u64, hard: u64) -> io::Result<()> {
+    let limit = libc::rlimit { rlim_cur: soft as libc::rlim_t, rlim_max: hard as libc::rlim_t };
+    cvt(unsafe { libc::setrlimit(resource as _, &limit) }).and(Ok(()))
+}
+
+/// Sets the file creation mask of the shell, which its children inherit, returning the prior mask.
+pub fn umask(mask: u32) -> io::Result<u32> {
+    Ok(unsafe { libc::umask(mask as libc::mode_t) } as u32)
+}
+
+/// The user and system CPU time of the shell combined with that of its waited-for children.
+pub fn cpu_times() -> io::Result<(Duration, Duration)> {
+    fn usage(who: c_int) -> io::Result<(Duration, Duration)> {
+        let mut usage: libc::rusage = unsafe { mem::zeroed() };
+        cvt(unsafe { libc::getrusage(who, &mut usage) })?;
+        let duration = |time: libc::timeval| {
+            Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000)
+        };
+        Ok((duration(usage.ru_utime), duration(usage.ru_stime)))
+    }
+
+    let (user, system) = usage(libc::RUSAGE_SELF)?;
+    let (children_user, children_system) = usage(libc::RUSAGE_CHILDREN)?;
+    Ok((user + children_user, system + children_system))
+}
+
+/// The modes of a terminal.
+pub type Termios = libc::termios;
+
+pub fn tcgetattr(fd: RawFd) -> io::Result<Termios> {
+    let mut modes = unsafe { mem::zeroed() };
+    cvt(unsafe { libc::tcgetattr(fd as c_int, &mut modes) }).map(|_| modes)
+}
+
+pub fn tcsetattr(fd: RawFd, modes: &Termios) -> io::Result<()> {
+    cvt(unsafe { libc::tcsetattr(fd as c_int, libc::TCSADRAIN, modes) }).and(Ok(()))
+}
+
+/// Changes whether the terminal echoes its input, and whether its input is read a line at a time,
+/// returning the prior modes of the terminal so that they may be restored.
+pub fn set_input_modes(fd: RawFd, echo: bool, canonical: bool) -> io::Result<Termios> {
+    let previous = tcgetattr(fd)?;
+    let mut modes = previous;
+    if !echo {
+        modes.c_lflag &= !(libc::ECHO | libc::ECHONL);
+    }
+    if !canonical {
+        modes.c_lflag &= !libc::ICANON;
+        modes.c_cc[libc::VMIN] = 1;
+        modes.c_cc[libc::VTIME] = 0;
+    }
+    tcsetattr(fd, &modes).map(|_| previous)
+}
+
+/// Waits for up to `timeout` milliseconds for the descriptor to have input to be read.
+pub fn poll_input(fd: RawFd, timeout: i32) -> io::Result<bool> {
+    let mut pollfd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+    cvt(unsafe { libc::poll(&mut pollfd, 1, timeout as c_int) }).map(|ready| ready > 0)
+}
+
+/// Waits for any of the descriptors to have input to be read, or to be closed, returning whether
+/// each of them is ready.
+pub fn poll_inputs(fds: &[RawFd]) -> io::Result<Vec<bool>> {
+    let mut pollfds: Vec<libc::pollfd> = fds.iter()
+        .map(|&fd| libc::pollfd { fd: fd, events: libc::POLLIN, revents: 0 })
+        .collect();
+    cvt(unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, -1) })?;
+    Ok(pollfds.iter().map(|pollfd| pollfd.revents != 0).collect())
+}
+
 pub fn dup(fd: RawFd) -> io::Result<RawFd> {
     cvt(unsafe { libc::dup(fd) })
 }
 
+/// Duplicates the descriptor onto one which is closed on `exec`, so that the copies of the
+/// shell's descriptors that it keeps for itself are not inherited by the commands it executes.
+pub fn dup_cloexec(fd: RawFd) -> io::Result<RawFd> {
+    cvt(unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) })
+}
+
 pub fn dup2(old: RawFd, new: RawFd) -> io::Result<RawFd> {
     cvt(unsafe { libc::dup2(old, new) })
 }
@@ -86,6 +205,36 @@ pub fn isatty(fd: RawFd) -> bool {
     unsafe { libc::isatty(fd) == 1 }
 }
 
+/// Clears the close-on-exec flag of the descriptor, so that it is inherited by commands.
+pub fn clear_cloexec(fd: RawFd) -> io::Result<()> {
+    let flags = cvt(unsafe { libc::fcntl(fd, libc::F_GETFD) })?;
+    cvt(unsafe { libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) }).and(Ok(()))
+}
+
+/// Sets the descriptor to return immediately from reads for which there is no input.
+pub fn set_nonblocking(fd: RawFd) -> io::Result<()> {
+    let flags = cvt(unsafe { libc::fcntl(fd, libc::F_GETFL) })?;
+    cvt(unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) }).and(Ok(()))
+}
+
+/// Closes each descriptor above the standard streams which is marked as close-on-exec, as an
+/// `exec` would, so that a fork which continues to run the shell does not hold them open.
+pub fn close_cloexec_fds() {
+    let fds: Vec<RawFd> = match ::std::fs::read_dir("/proc/self/fd") {
+        Ok(entries) => entries.filter_map(Result::ok)
+            .filter_map(|entry| entry.file_name().to_str().and_then(|name| name.parse().ok()))
+            .collect(),
+        Err(_) => (3..1024).collect(),
+    };
+
+    for fd in fds.into_iter().filter(|&fd| fd > 2) {
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+        if flags != -1 && flags & libc::FD_CLOEXEC != 0 {
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
 // Support functions for converting libc return values to io errors {
 trait IsMinusOne {
     fn is_minus_one(&self) -> bool;
@@ -159,8 +308,10 @@ pub mod job_control {
     use std::thread::sleep;
     use std::time::Duration;
     use std::sync::{Arc, Mutex};
+    use std::sync::atomic::Ordering;
     use shell::foreground::ForegroundSignals;
-    use shell::status::{FAILURE, TERMINATED};
+    use shell::signals;
+    use shell::status::{get_signal_code, FAILURE, TERMINATED, TIMED_OUT};
     use shell::Shell;
     use libc::{self, pid_t};
 
@@ -192,40 +343,38 @@ pub mod job_control {
 
             match waitpid(-(pid as pid_t), opts) {
                 Ok(WaitStatus::Exited(_, status)) => {
-                    if !fg_was_grabbed {
-                        eprintln!("ion: ([{}] {}) exited with {}", njob, pid, status);
-                    }
-                    let mut processes = processes.lock().unwrap();
-                    let process = &mut processes.iter_mut().nth(njob).unwrap();
-                    process.state = ProcessState::Empty;
-                    if fg_was_grabbed {
-                        fg.reply_with(status);
-                    }
+                    exited(&fg, &processes, njob, fg_was_grabbed, status as i32);
+                    break;
+                }
+                // The status of a job that was killed by a signal is kept, so that it may be
+                // reported by `wait`.
+                Ok(WaitStatus::Signaled(_, signal, _)) => {
+                    exited(&fg, &processes, njob, fg_was_grabbed, get_signal_code(signal as i32));
                     break;
                 }
                 Ok(WaitStatus::Stopped(pid, _)) => {
-                    if !fg_was_grabbed {
-                        eprintln!("ion: ([{}] {}) Stopped", njob, pid);
-                    }
                     let mut processes = processes.lock().unwrap();
                     let process = &mut processes.iter_mut().nth(njob).unwrap();
+                    if !fg_was_grabbed && !process.disowned {
+                        eprintln!("ion: ([{}] {}) Stopped", job_id(njob as u32), pid);
+                    }
                     if fg_was_grabbed {
-                        fg.reply_with(TERMINATED as i8);
+                        fg.reply_with(get_signal_code(libc::SIGTSTP) as i8);
                         fg_was_grabbed = false;
                     }
                     process.state = ProcessState::Stopped;
                 }
                 Ok(WaitStatus::Continued(pid)) => {
-                    if !fg_was_grabbed {
-                        eprintln!("ion: ([{}] {}) Running", njob, pid);
-                    }
                     let mut processes = processes.lock().unwrap();
                     let process = &mut processes.iter_mut().nth(njob).unwrap();
+                    if !fg_was_grabbed && !process.disowned {
+                        eprintln!("ion: ([{}] {}) Running", job_id(njob as u32), pid);
+                    }
                     process.state = ProcessState::Running;
                 }
                 Ok(_) => (),
                 Err(why) => {
-                    eprintln!("ion: ([{}] {}) errored: {}", njob, pid, why);
+                    eprintln!("ion: ([{}] {}) errored: {}", job_id(njob as u32), pid, why);
                     let mut processes = processes.lock().unwrap();
                     let process = &mut processes.iter_mut().nth(njob).unwrap();
                     process.state = ProcessState::Empty;
@@ -239,25 +388,77 @@ pub mod job_control {
         }
     }
 
+    /// Records the exit status of a background job which has exited.
+    fn exited(
+        fg: &ForegroundSignals,
+        processes: &Mutex<Vec<BackgroundProcess>>,
+        njob: usize,
+        fg_was_grabbed: bool,
+        status: i32,
+    ) {
+        let mut processes = processes.lock().unwrap();
+        let process = &mut processes.iter_mut().nth(njob).unwrap();
+        // Jobs are otherwise reported before the next prompt is displayed.
+        if !fg_was_grabbed && !process.disowned && NOTIFY_NOW.load(Ordering::Relaxed) {
+            eprintln!("\n[{}]  {}\t{}", job_id(njob as u32), ProcessState::Done(status), process.name);
+        }
+        // A job that was brought into the foreground, or which was disowned, has no need to be
+        // reported.
+        process.state = if fg_was_grabbed || process.disowned {
+            ProcessState::Empty
+        } else {
+            ProcessState::Done(status)
+        };
+        if fg_was_grabbed {
+            fg.reply_with(status as i8);
+        }
+    }
+
     pub fn watch_foreground<'a, F, D>(
         shell: &mut Shell<'a>,
-        _pid: u32,
+        pid: u32,
         last_pid: u32,
         get_command: F,
         mut drop_command: D,
     ) -> i32
     where
         F: FnOnce() -> String,
-        D: FnMut(i32),
+        D: FnMut(i32, i32) -> bool,
     {
         let mut exit_status = 0;
+        // The status of the last process, which is returned once every process has exited.
+        let mut last_status = None;
         loop {
             match waitpid(-1, Some(WUNTRACED)) {
-                Ok(WaitStatus::Exited(pid, status)) => if pid == (last_pid as i32) {
-                    break status as i32;
-                } else {
-                    drop_command(pid);
+                Ok(WaitStatus::Exited(pid, status)) => {
+                    let status = status as i32;
+                    let running = drop_command(pid, status);
+                    if pid == (last_pid as i32) {
+                        last_status = Some(status);
+                    } else {
+                        exit_status = status;
+                    }
+                    match last_status {
+                        Some(status) if !running => break status,
+                        _ => (),
+                    }
+                },
+                // Earlier processes in a pipe are expected to be ended by a SIGPIPE when a
+                // later process exits without reading all of their output.
+                Ok(WaitStatus::Signaled(pid, Signal::SIGPIPE, _)) if pid != (last_pid as i32) => {
+                    let status = get_signal_code(libc::SIGPIPE);
+                    let running = drop_command(pid, status);
                     exit_status = status;
+                    match last_status {
+                        Some(status) if !running => break status,
+                        _ => (),
+                    }
+                },
+                // The processes of a job whose deadline passed were terminated by the shell.
+                Ok(WaitStatus::Signaled(pid, Signal::SIGTERM, _)) if shell.timed_out() => {
+                    if !drop_command(pid, TIMED_OUT) {
+                        break TIMED_OUT;
+                    }
                 },
                 Ok(WaitStatus::Signaled(_, signal, _)) => {
                     eprintln!("ion: process ended by signal");
@@ -269,18 +470,26 @@ pub mod job_control {
                         shell.exit(TERMINATED);
                     } else if signal == Signal::SIGINT {
                         shell.foreground_send(libc::SIGINT as i32);
-                        shell.break_flow = true;
+                        if shell.traps.contains_key(&libc::SIGINT) {
+                            // The trap is run in place of aborting, once the statement has ended.
+                            signals::PENDING.fetch_or(1 << libc::SIGINT, Ordering::SeqCst);
+                        } else {
+                            shell.break_flow = true;
+                        }
                     }
                     break TERMINATED;
                 }
-                Ok(WaitStatus::Stopped(pid, _)) => {
-                    shell.send_to_background(pid as u32, ProcessState::Stopped, get_command());
+                // The whole process group of the job is stopped and sent to the background, where
+                // it may later be resumed by `fg` or `bg`.
+                Ok(WaitStatus::Stopped(..)) => {
+                    signals::suspend(pid);
+                    shell.send_to_background(pid, ProcessState::Stopped, get_command());
                     shell.break_flow = true;
-                    break TERMINATED;
+                    break get_signal_code(libc::SIGTSTP);
                 }
                 Ok(_) => (),
                 // ECHILD signifies that all children have exited
-                Err(Error::Sys(Errno::ECHILD)) => break exit_status as i32,
+                Err(Error::Sys(Errno::ECHILD)) => break last_status.unwrap_or(exit_status),
                 Err(why) => {
                     eprintln!("ion: process doesn't exist: {}", why);
                     break FAILURE;
@@ -291,9 +500,28 @@ pub mod job_control {
 }
 
 pub mod variables {
+    use super::libc;
+    use std::time::{SystemTime, UNIX_EPOCH};
     use users_unix::get_user_by_name;
     use users_unix::os::unix::UserExt;
 
+    pub fn get_host_name() -> Option<String> {
+        let mut buffer = [0u8; 256];
+        let result = unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) };
+        if result != 0 { return None }
+        let length = buffer.iter().position(|&byte| byte == 0).unwrap_or(buffer.len());
+        Some(String::from_utf8_lossy(&buffer[..length]).into_owned())
+    }
+
+    /// Returns the hour, minute, and second of the current local time.
+    pub fn local_time() -> (u32, u32, u32) {
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+        let seconds = seconds as libc::time_t;
+        let mut time: libc::tm = unsafe { ::std::mem::zeroed() };
+        unsafe { libc::localtime_r(&seconds, &mut time) };
+        (time.tm_hour as u32, time.tm_min as u32, time.tm_sec as u32)
+    }
+
     pub fn get_user_home(username: &str) -> Option<String> {
         match get_user_by_name(username) {
             Some(user) => Some(user.home_dir().to_string_lossy().into_owned()),
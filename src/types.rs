@@ -1,15 +1,19 @@
 use smallvec::SmallVec;
 use fnv::FnvHashMap;
 use smallstring::SmallString;
+use std::collections::BTreeMap as StdBTreeMap;
+use std::fmt;
 
 pub type Array = SmallVec<[Value; 4]>;
-pub type HashMap = FnvHashMap<Key, Value>;
+pub type HashMap = FnvHashMap<Key, Data>;
+pub type BTreeMap = StdBTreeMap<Key, Data>;
 pub type Identifier = SmallString;
 pub type Key = SmallString;
 pub type Value = String;
 pub type VariableContext = FnvHashMap<Identifier, Value>;
 pub type ArrayVariableContext = FnvHashMap<Identifier, Array>;
 pub type HashMapVariableContext = FnvHashMap<Identifier, HashMap>;
+pub type BTreeMapVariableContext = FnvHashMap<Identifier, BTreeMap>;
 
 /// Construct a new Array containing the given arguments
 ///
@@ -36,3 +40,83 @@ macro_rules! array [
         _arr
     })
 ];
+
+/// A value that is stored within a map. As the values of a map may themselves be arrays or
+/// maps, data structures may be nested within each other.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Data {
+    Str(Value),
+    Array(Vec<Data>),
+    HashMap(HashMap),
+    BTreeMap(BTreeMap),
+}
+
+impl Data {
+    /// Obtains the value stored at the given key of a map, or the given index of an array.
+    /// Negative indices are counted from the end of the array.
+    pub fn get(&self, key: &str) -> Option<&Data> {
+        match *self {
+            Data::Str(_) => None,
+            Data::Array(ref array) => key.parse::<isize>().ok().and_then(|index| {
+                let index = if index < 0 { array.len() as isize + index } else { index };
+                if index < 0 { None } else { array.get(index as usize) }
+            }),
+            Data::HashMap(ref map) => map.get(key),
+            Data::BTreeMap(ref map) => map.get(key),
+        }
+    }
+
+    /// Collects every string within the value, including those nested within its arrays and
+    /// the values of its maps.
+    pub fn flatten(&self) -> Array {
+        match *self {
+            Data::Str(ref value) => array![value.as_str()],
+            Data::Array(ref array) => array.iter().flat_map(Data::flatten).collect(),
+            Data::HashMap(ref map) => map.values().flat_map(Data::flatten).collect(),
+            Data::BTreeMap(ref map) => map.values().flat_map(Data::flatten).collect(),
+        }
+    }
+
+    /// Converts every map nested within the value into a map that is sorted by its keys.
+    pub fn sorted(self) -> Data {
+        match self {
+            Data::Str(value) => Data::Str(value),
+            Data::Array(array) => Data::Array(array.into_iter().map(Data::sorted).collect()),
+            Data::HashMap(map) => Data::BTreeMap(map.into_iter().map(|(key, value)| (key, value.sorted())).collect()),
+            Data::BTreeMap(map) => Data::BTreeMap(map.into_iter().map(|(key, value)| (key, value.sorted())).collect()),
+        }
+    }
+}
+
+/// Writes the value in the literal syntax that is accepted by `let`.
+impl fmt::Display for Data {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Data::Str(ref value) if value.is_empty() || value.contains(char::is_whitespace) => {
+                write!(f, "'{}'", value)
+            },
+            Data::Str(ref value) => write!(f, "{}", value),
+            Data::Array(ref array) => {
+                write!(f, "[")?;
+                for value in array {
+                    write!(f, " {}", value)?;
+                }
+                write!(f, " ]")
+            },
+            Data::HashMap(ref map) => {
+                write!(f, "[")?;
+                for (key, value) in map {
+                    write!(f, " {}={}", key, value)?;
+                }
+                write!(f, " ]")
+            },
+            Data::BTreeMap(ref map) => {
+                write!(f, "[")?;
+                for (key, value) in map {
+                    write!(f, " {}={}", key, value)?;
+                }
+                write!(f, " ]")
+            },
+        }
+    }
+}